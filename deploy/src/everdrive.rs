@@ -0,0 +1,199 @@
+//! Uploads a ROM to an EverDrive-64 X7 over its USB port. Every command is
+//! 16 bytes, `cmd`, the command letter, then a big endian address, size in
+//! 512 byte blocks and argument.
+
+use std::error::Error;
+use std::io::{ErrorKind, Read, Write};
+
+const ROM_ADDRESS: u32 = 0x1000_0000;
+const BLOCK_SIZE: usize = 512;
+const CHUNK_SIZE: usize = 0x8000;
+
+const COMMAND_TEST: u8 = b't';
+const COMMAND_WRITE_ROM: u8 = b'W';
+const COMMAND_START: u8 = b's';
+const TEST_REPLY: &[u8; 4] = b"cmdr";
+
+/// The EverDrive only takes whole blocks.
+pub fn pad_rom(rom: &mut Vec<u8>, min_size: usize) {
+    let size = rom.len().max(min_size);
+    rom.resize(size + (BLOCK_SIZE - size % BLOCK_SIZE) % BLOCK_SIZE, 0);
+}
+
+fn command(
+    port: &mut impl Write,
+    command: u8,
+    address: u32,
+    size: usize,
+    arg: u32,
+) -> Result<(), Box<dyn Error>> {
+    let mut buf = [0; 16];
+    buf[..3].copy_from_slice(b"cmd");
+    buf[3] = command;
+    buf[4..8].copy_from_slice(&address.to_be_bytes());
+    buf[8..12].copy_from_slice(&((size / BLOCK_SIZE) as u32).to_be_bytes());
+    buf[12..16].copy_from_slice(&arg.to_be_bytes());
+
+    port.write_all(&buf)?;
+    Ok(())
+}
+
+/// Checks that an EverDrive in USB mode is listening.
+pub fn test(port: &mut (impl Read + Write)) -> Result<(), Box<dyn Error>> {
+    command(port, COMMAND_TEST, 0, 0, 0)?;
+
+    let mut reply = [0; 16];
+    port.read_exact(&mut reply).map_err(|e| match e.kind() {
+        ErrorKind::TimedOut | ErrorKind::UnexpectedEof => {
+            "The EverDrive did not answer, is it on its menu?".into()
+        }
+        _ => Box::<dyn Error>::from(e),
+    })?;
+
+    if &reply[..4] != TEST_REPLY {
+        return Err("The EverDrive sent an unexpected reply".into());
+    }
+
+    Ok(())
+}
+
+/// Writes `rom`, which `pad_rom` has padded, to the cartridge and boots it
+/// if `start` is set. `progress` gets the bytes sent so far and the total.
+pub fn upload(
+    port: &mut (impl Read + Write),
+    rom: &[u8],
+    start: bool,
+    mut progress: impl FnMut(usize, usize),
+) -> Result<(), Box<dyn Error>> {
+    if !rom.chunks_exact(BLOCK_SIZE).remainder().is_empty() {
+        return Err("ROM size is not a whole number of blocks".into());
+    }
+
+    test(port)?;
+    command(port, COMMAND_WRITE_ROM, ROM_ADDRESS, rom.len(), 0)?;
+
+    let mut sent = 0;
+    for chunk in rom.chunks(CHUNK_SIZE) {
+        port.write_all(chunk)?;
+        sent += chunk.len();
+        progress(sent, rom.len());
+    }
+
+    port.flush()?;
+
+    if start {
+        command(port, COMMAND_START, 0, 0, 0)?;
+        port.flush()?;
+    }
+
+    Ok(())
+}
+
+/// Stands in for the cartridge end of the USB port.
+#[cfg(test)]
+#[derive(Default)]
+struct MockEverDrive {
+    input: Vec<u8>,
+    reply: std::collections::VecDeque<u8>,
+    rom: Vec<u8>,
+    rom_len: usize,
+    started: bool,
+}
+
+#[cfg(test)]
+impl Write for MockEverDrive {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        for &byte in buf {
+            if self.rom.len() < self.rom_len {
+                self.rom.push(byte);
+                continue;
+            }
+
+            self.input.push(byte);
+
+            if self.input.len() == 16 {
+                assert_eq!(&self.input[..3], b"cmd");
+                let size = u32::from_be_bytes([
+                    self.input[8],
+                    self.input[9],
+                    self.input[10],
+                    self.input[11],
+                ]);
+
+                match self.input[3] {
+                    COMMAND_TEST => self.reply.extend(b"cmdr\0\0\0\0\0\0\0\0\0\0\0\0"),
+                    COMMAND_WRITE_ROM => self.rom_len = size as usize * BLOCK_SIZE,
+                    COMMAND_START => self.started = true,
+                    command => panic!("Unknown command {}", command),
+                }
+
+                self.input.clear();
+            }
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl Read for MockEverDrive {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.reply.is_empty() {
+            return Err(ErrorKind::TimedOut.into());
+        }
+
+        let len = buf.len().min(self.reply.len());
+        for byte in &mut buf[..len] {
+            *byte = self.reply.pop_front().unwrap();
+        }
+
+        Ok(len)
+    }
+}
+
+#[test]
+fn roms_are_padded_uploaded_and_started() {
+    let mut rom: Vec<u8> = (0..100_000).map(|i| i as u8).collect();
+    pad_rom(&mut rom, 0);
+    assert_eq!(rom.len(), 100_352);
+
+    let mut everdrive = MockEverDrive::default();
+    let mut reports = Vec::new();
+    upload(&mut everdrive, &rom, true, |sent, total| {
+        reports.push((sent, total))
+    })
+    .unwrap();
+
+    assert_eq!(everdrive.rom, rom);
+    assert!(everdrive.started);
+    assert_eq!(reports.len(), 4);
+    assert_eq!(reports.last(), Some(&(rom.len(), rom.len())));
+}
+
+#[test]
+fn a_silent_port_is_an_error() {
+    struct Silent;
+
+    impl Read for Silent {
+        fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+            Err(ErrorKind::TimedOut.into())
+        }
+    }
+
+    impl Write for Silent {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let error = upload(&mut Silent, &[0; 512], false, |_, _| {}).unwrap_err();
+    assert!(error.to_string().contains("did not answer"));
+}
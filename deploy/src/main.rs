@@ -1,37 +1,126 @@
+use everdrive_port::{find_everdrive, BAUD_RATE};
+use n64_rom::{parse_size, AssetSizes, Budget, Cic, RomOptions, SizeReport, DEFAULT_RDRAM_SIZE};
 use std::env;
 use std::error::Error;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
 use std::process::{Command, Stdio};
+use std::time::Duration;
+
+mod everdrive;
+mod symbols;
+
+const TARGET_SPEC: &str = "mips-nintendo64-none.json";
+const BOOTCODE_PATH: &str = "bootcode.bin";
+const ELF_PATH: &str = "target/mips-nintendo64-none/release/game";
+const ROM_PATH: &str = "target/mips-nintendo64-none/release/game.z64";
+const ASSET_SIZES_PATH: &str = "game/asset_sizes.tsv";
+const TITLE: &str = "LOKA";
 
 fn main() -> Result<(), Box<dyn Error>> {
-    if !env::current_dir()?.ends_with("loka-n64") {
-        env::set_current_dir("../")?;
+    let mut port_name = None;
+    let mut start = false;
+    let mut budget = Budget::default();
+    let mut args = env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        let mut size = || -> Result<_, Box<dyn Error>> {
+            parse_size(&args.next().ok_or_else(|| format!("{} needs a size", arg))?)
+        };
+
+        match arg.as_str() {
+            "--port" => port_name = Some(args.next().ok_or("--port needs a serial device")?),
+            "--start" => start = true,
+            "--max-rom" => budget.max_rom = Some(size()?),
+            "--max-program" => budget.max_program = Some(size()?),
+            "--max-assets" => budget.max_assets = Some(size()?),
+            "--min-heap" => budget.min_heap = Some(size()?),
+            _ => {
+                println!(
+                    "Usage: {} [--port PORT] [--start] [--max-rom SIZE] [--max-program SIZE] \
+                     [--max-assets SIZE] [--min-heap SIZE]",
+                    env::args().next().unwrap()
+                );
+                return Ok(());
+            }
+        }
     }
 
+    env::set_current_dir(Path::new(env!("CARGO_MANIFEST_DIR")).join(".."))?;
+
     assert!(Command::new("cargo")
-        .args(&[
+        .args([
             "+nightly",
-            "n64",
             "build",
-            "--ipl3",
-            "bootcode.bin",
-            "--",
+            "--release",
             "--package",
             "game",
+            "--target",
+            TARGET_SPEC,
+            "-Z",
+            "build-std=core,alloc",
+            "-Z",
+            "build-std-features=compiler-builtins-mem",
         ])
         .stdout(Stdio::inherit())
         .stderr(Stdio::inherit())
         .status()?
         .success());
 
-    assert!(Command::new("tools/usb64.exe")
-        .args(&[
-            "-rom=target/mips-nintendo64-none/release/game.n64",
-            "-start"
-        ])
-        .stdout(Stdio::inherit())
-        .stderr(Stdio::inherit())
-        .status()?
-        .success());
+    let elf = fs::read(ELF_PATH)?;
+    let bootcode = fs::read(BOOTCODE_PATH).map_err(|e| {
+        format!(
+            "{}: {}, extract it with n64-rom extract-ipl3",
+            BOOTCODE_PATH, e
+        )
+    })?;
+    let (mut rom, report) = n64_rom::build_rom(
+        &elf,
+        &RomOptions {
+            bootcode: &bootcode,
+            title: TITLE,
+            files: &[],
+        },
+    )?;
+    print!("{}", report);
+
+    let symbol_count = symbols::embed_symbols(&elf, &mut rom)?;
+    println!("Embedded {} symbols", symbol_count);
+
+    everdrive::pad_rom(&mut rom, n64_rom::MIN_ROM_SIZE);
+    let cic = Cic::identify_rom(&rom).unwrap_or(Cic::Cic6102);
+    if n64_rom::fix_checksum(&mut rom, cic)? {
+        println!("Fixed the header checksum");
+    }
+
+    let assets = AssetSizes::parse(&fs::read_to_string(ASSET_SIZES_PATH)?)?;
+    let over_budget =
+        SizeReport::new(&elf, assets, Some(rom.len()), DEFAULT_RDRAM_SIZE)?.over_budget(&budget)?;
+    if !over_budget.is_empty() {
+        return Err(format!("Over budget: {}", over_budget.join(", ")).into());
+    }
+
+    fs::write(ROM_PATH, &rom)?;
+
+    let port_name = match port_name {
+        Some(name) => name,
+        None => find_everdrive("pass its port with --port")?,
+    };
+
+    let mut port = serialport::new(&port_name, BAUD_RATE)
+        .timeout(Duration::from_millis(500))
+        .open()?;
+
+    everdrive::upload(&mut port, &rom, start, |sent, total| {
+        print!("\rUploading to {} {}%", port_name, sent * 100 / total);
+        io::stdout().flush().ok();
+    })?;
+    println!();
+
+    if start {
+        println!("Started");
+    }
 
     Ok(())
 }
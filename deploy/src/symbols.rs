@@ -0,0 +1,217 @@
+//! Appends the function symbols of the game ELF to the ROM, where the crash
+//! screen looks them up.
+
+use n64_rom::Elf;
+use n64_types::{encode_symbol_table, SYMBOL_TABLE_HEADER_OFFSET, SYMBOL_TABLE_MAGIC};
+use std::convert::TryInto;
+use std::error::Error;
+
+// The boot code checksums the first megabyte after the header, the table
+// must not end up in it.
+const MIN_TABLE_OFFSET: usize = 0x10_1000;
+
+/// Address, size and name.
+type Function = (u32, u32, String);
+
+/// Replaces any symbol table already in `rom` with the functions in `elf`
+/// and returns how many there were.
+pub fn embed_symbols(elf: &[u8], rom: &mut Vec<u8>) -> Result<usize, Box<dyn Error>> {
+    let symbols = function_symbols(elf)?;
+    let mut symbols: Vec<_> = symbols
+        .iter()
+        .map(|(address, size, name)| (*address, *size, name.as_str()))
+        .collect();
+    let table = encode_symbol_table(&mut symbols);
+
+    if rom.len() < SYMBOL_TABLE_HEADER_OFFSET + 4 {
+        return Err("ROM is too small to have a header".into());
+    }
+
+    let old_offset = read_u32(rom, SYMBOL_TABLE_HEADER_OFFSET)? as usize;
+    if old_offset != 0 && read_u32(rom, old_offset).ok() == Some(SYMBOL_TABLE_MAGIC) {
+        rom.truncate(old_offset);
+    }
+
+    let offset = (rom.len().max(MIN_TABLE_OFFSET) + 3) & !3;
+    rom.resize(offset, 0);
+    rom.extend_from_slice(&table);
+    rom[SYMBOL_TABLE_HEADER_OFFSET..SYMBOL_TABLE_HEADER_OFFSET + 4]
+        .copy_from_slice(&(offset as u32).to_be_bytes());
+
+    Ok(symbols.len())
+}
+
+/// Every function in the ELF, with demangled names.
+fn function_symbols(elf: &[u8]) -> Result<Vec<Function>, Box<dyn Error>> {
+    Ok(Elf::parse(elf)?
+        .symbols()?
+        .iter()
+        .filter(|symbol| symbol.is_function())
+        .map(|symbol| (symbol.address, symbol.size, demangle(symbol.name)))
+        .collect())
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, Box<dyn Error>> {
+    let bytes = data.get(offset..offset + 4).ok_or("Read past the end")?;
+    Ok(u32::from_be_bytes(bytes.try_into()?))
+}
+
+/// Demangles legacy Rust symbols, `_ZN4game4main17h0123456789abcdefE` is
+/// `game::main`. Anything else is returned as is.
+fn demangle(symbol: &str) -> String {
+    let mut rest = match symbol.strip_prefix("_ZN") {
+        Some(rest) => rest,
+        None => return symbol.to_string(),
+    };
+
+    let mut parts = Vec::new();
+
+    while !rest.starts_with('E') {
+        let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+        let len: usize = match rest[..digits].parse() {
+            Ok(len) if digits + len <= rest.len() => len,
+            _ => return symbol.to_string(),
+        };
+
+        parts.push(&rest[digits..digits + len]);
+        rest = &rest[digits + len..];
+    }
+
+    if let Some(hash) = parts.last() {
+        if hash.len() == 17 && hash.starts_with('h') {
+            parts.pop();
+        }
+    }
+
+    parts
+        .iter()
+        .map(|part| {
+            // A leading `$` escape is prefixed with `_` to keep it an identifier.
+            if part.starts_with("_$") {
+                unescape(&part[1..])
+            } else {
+                unescape(part)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("::")
+}
+
+fn unescape(part: &str) -> String {
+    const ESCAPES: [(&str, &str); 12] = [
+        ("$LT$", "<"),
+        ("$GT$", ">"),
+        ("$RF$", "&"),
+        ("$BP$", "*"),
+        ("$C$", ","),
+        ("$SP$", "@"),
+        ("$u20$", " "),
+        ("$u27$", "'"),
+        ("$u5b$", "["),
+        ("$u5d$", "]"),
+        ("$u7b$", "{"),
+        ("$u7d$", "}"),
+    ];
+
+    let mut part = part.replace("..", "::");
+
+    for (escape, text) in ESCAPES.iter() {
+        part = part.replace(escape, text);
+    }
+
+    part
+}
+
+#[cfg(test)]
+const SHT_SYMTAB: u32 = 2;
+#[cfg(test)]
+const STT_FUNC: u8 = 2;
+#[cfg(test)]
+const SECTION_HEADER_SIZE: usize = 40;
+#[cfg(test)]
+const SYMBOL_SIZE: usize = 16;
+
+#[cfg(test)]
+fn test_elf(symbols: &[(&str, u32, u32, u8)]) -> Vec<u8> {
+    let mut strings = vec![0];
+    let mut symtab = vec![0; SYMBOL_SIZE];
+
+    for (name, address, size, info) in symbols {
+        symtab.extend_from_slice(&(strings.len() as u32).to_be_bytes());
+        symtab.extend_from_slice(&address.to_be_bytes());
+        symtab.extend_from_slice(&size.to_be_bytes());
+        symtab.extend_from_slice(&[*info, 0, 0, 1]);
+        strings.extend_from_slice(name.as_bytes());
+        strings.push(0);
+    }
+
+    let mut elf = vec![0; 0x34];
+    elf[..6].copy_from_slice(b"\x7fELF\x01\x02");
+
+    let symtab_offset = elf.len();
+    elf.extend_from_slice(&symtab);
+    let strings_offset = elf.len();
+    elf.extend_from_slice(&strings);
+
+    let section_offset = elf.len();
+    elf[0x20..0x24].copy_from_slice(&(section_offset as u32).to_be_bytes());
+    elf[0x30..0x32].copy_from_slice(&3u16.to_be_bytes());
+
+    let mut section = |kind: u32, offset: usize, size: usize, link: u32| {
+        let mut header = [0; SECTION_HEADER_SIZE];
+        header[4..8].copy_from_slice(&kind.to_be_bytes());
+        header[16..20].copy_from_slice(&(offset as u32).to_be_bytes());
+        header[20..24].copy_from_slice(&(size as u32).to_be_bytes());
+        header[24..28].copy_from_slice(&link.to_be_bytes());
+        elf.extend_from_slice(&header);
+    };
+
+    section(0, 0, 0, 0);
+    section(SHT_SYMTAB, symtab_offset, symtab.len(), 2);
+    section(3, strings_offset, strings.len(), 0);
+
+    elf
+}
+
+#[test]
+fn rust_symbols_are_demangled() {
+    assert_eq!(demangle("_ZN4game4main17h0123456789abcdefE"), "game::main");
+    assert_eq!(
+        demangle("_ZN45_$LT$game..player..Player$u20$as$u20$Drop$GT$4drop17h0123456789abcdefE"),
+        "<game::player::Player as Drop>::drop"
+    );
+    assert_eq!(demangle("memcpy"), "memcpy");
+    assert_eq!(demangle("_ZN99broken"), "_ZN99broken");
+}
+
+#[test]
+fn functions_are_embedded_after_the_checksummed_area() {
+    let elf = test_elf(&[
+        (
+            "_ZN4game4main17h0123456789abcdefE",
+            0x8000_1000,
+            0x20,
+            STT_FUNC,
+        ),
+        ("DATA", 0x8010_0000, 4, 1),
+        ("_start", 0x8000_0400, 0x40, 0x10 | STT_FUNC),
+    ]);
+    let mut rom = vec![0; 0x2000];
+
+    assert_eq!(embed_symbols(&elf, &mut rom).unwrap(), 2);
+    assert_eq!(
+        read_u32(&rom, SYMBOL_TABLE_HEADER_OFFSET).unwrap(),
+        0x10_1000
+    );
+    assert_eq!(read_u32(&rom, 0x10_1000).unwrap(), SYMBOL_TABLE_MAGIC);
+
+    let len = rom.len();
+    embed_symbols(&elf, &mut rom).unwrap();
+    assert_eq!(rom.len(), len, "embedding again replaces the old table");
+
+    let table = n64_types::SymbolTable::new(|offset| read_u32(&rom, 0x10_1000 + offset).unwrap());
+    assert_eq!(
+        table.unwrap().lookup(0x8000_1010).unwrap().name(),
+        "game::main"
+    );
+}
@@ -0,0 +1,27 @@
+//! Finding the EverDrive-64 among the serial ports of the host.
+
+use serialport::SerialPortType;
+use std::error::Error;
+
+pub const FTDI_VENDOR_ID: u16 = 0x0403;
+
+// The EverDrive USB port is a FIFO, the baud rate is ignored.
+pub const BAUD_RATE: u32 = 115_200;
+
+/// The name of the first FTDI port. `hint` tells how to pass a port by hand
+/// when there is none.
+pub fn find_everdrive(hint: &str) -> Result<String, Box<dyn Error>> {
+    let ports = serialport::available_ports()?;
+
+    ports
+        .iter()
+        .find(|port| match &port.port_type {
+            SerialPortType::UsbPort(usb) => usb.vid == FTDI_VENDOR_ID,
+            _ => false,
+        })
+        .map(|port| port.port_name.clone())
+        .ok_or_else(|| {
+            let names: Vec<_> = ports.iter().map(|port| port.port_name.as_str()).collect();
+            format!("No EverDrive found, {}. Ports: {}", hint, names.join(", ")).into()
+        })
+}
@@ -0,0 +1,189 @@
+//! Bullet patterns and the emitters firing them. A pattern is a list of
+//! volleys firing on their own timers, so patterns are composed by listing
+//! the volleys of simpler ones. Enemies pick a pattern by name with their
+//! `bullets` property. The patterns are defined in `bullets/patterns.toml`.
+
+pub use crate::bullet_patterns::PATTERNS;
+use core::f32::consts::PI;
+use n64_math::{rand::Rng, Vec2};
+
+pub enum Aim {
+    /// Straight down the screen.
+    Down,
+    /// At the player, where it is when the volley fires.
+    Player,
+}
+
+pub enum Shape {
+    /// `count` bullets evenly round a circle.
+    Radial { count: u32 },
+    /// `ways` bullets `angle` degrees apart, centered on the aim.
+    Spread { ways: u32, angle: f32 },
+}
+
+pub struct Volley {
+    pub shape: Shape,
+    pub aim: Aim,
+    /// Seconds before the first volley and between volleys.
+    pub start: f32,
+    pub interval: f32,
+    /// Degrees the volley turns every time it fires, for spirals.
+    pub turn: f32,
+    /// Degrees each bullet may turn at random.
+    pub jitter: f32,
+    /// Pixels per second, and per second squared once it moves.
+    pub speed: f32,
+    pub acceleration: f32,
+    /// Seconds the bullets hang still before they move.
+    pub delay: f32,
+}
+
+pub struct BulletPattern {
+    pub name: &'static str,
+    pub volleys: &'static [Volley],
+}
+
+pub fn pattern_by_name(name: &str) -> Option<&'static BulletPattern> {
+    PATTERNS.iter().find(|pattern| pattern.name == name)
+}
+
+/// A bullet as it leaves the emitter.
+pub struct Shot {
+    /// Of length one.
+    pub direction: Vec2,
+    pub speed: f32,
+    pub acceleration: f32,
+    pub delay: f32,
+}
+
+/// Fires a pattern. The jitter comes from the seed, so the same emitter
+/// fires the same bullets every time.
+pub struct Emitter {
+    pattern: &'static BulletPattern,
+    /// How much faster than the pattern it fires.
+    rate: f32,
+    time: f32,
+    rng: Rng,
+}
+
+impl Emitter {
+    pub fn new(pattern: &'static BulletPattern, rate: f32, seed: u32) -> Self {
+        Self {
+            pattern,
+            rate,
+            time: 0.0,
+            rng: Rng::new(seed),
+        }
+    }
+
+    /// Fires the volleys due in the next `dt` seconds. `aim` is where the
+    /// player is relative to the emitter.
+    pub fn update(&mut self, dt: f32, aim: Vec2, mut fire: impl FnMut(Shot)) {
+        let from = self.time;
+        let to = self.time + dt * self.rate;
+        self.time = to;
+
+        for volley in self.pattern.volleys {
+            // Volley `n` fires at `start + n * interval`, fire those from
+            // `from` up to `to`.
+            let mut n = if from <= volley.start || volley.interval <= 0.0 {
+                0
+            } else {
+                libm::ceilf((from - volley.start) / volley.interval) as u32
+            };
+
+            loop {
+                let fire_time = volley.start + n as f32 * volley.interval;
+
+                if fire_time >= to {
+                    break;
+                }
+
+                // Rounding can put `n` on the last volley, fired already.
+                if fire_time >= from {
+                    self.fire_volley(volley, n, aim, &mut fire);
+                }
+
+                if volley.interval <= 0.0 {
+                    break;
+                }
+
+                n += 1;
+            }
+        }
+    }
+
+    fn fire_volley(&mut self, volley: &Volley, n: u32, aim: Vec2, fire: &mut impl FnMut(Shot)) {
+        let aim_angle = match volley.aim {
+            Aim::Player if aim.length_squared() > 0.0 => libm::atan2f(aim.y(), aim.x()),
+            _ => PI / 2.0,
+        };
+        let volley_angle = aim_angle + (volley.turn * n as f32).to_radians();
+
+        let (count, step, first) = match volley.shape {
+            Shape::Radial { count } => (count, 2.0 * PI / count as f32, 0.0),
+            Shape::Spread { ways, angle } => {
+                let step = angle.to_radians();
+                (ways, step, -step * (ways as f32 - 1.0) / 2.0)
+            }
+        };
+
+        for i in 0..count {
+            let jitter = (self.rng.next_f32() - 0.5) * volley.jitter.to_radians();
+            let angle = volley_angle + first + step * i as f32 + jitter;
+
+            fire(Shot {
+                direction: Vec2::new(libm::cosf(angle), libm::sinf(angle)),
+                speed: volley.speed,
+                acceleration: volley.acceleration,
+                delay: volley.delay,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+fn fired(emitter: &mut Emitter, dt: f32, steps: usize) -> alloc::vec::Vec<Vec2> {
+    let mut directions = alloc::vec::Vec::new();
+
+    for _ in 0..steps {
+        emitter.update(dt, Vec2::new(1.0, 0.0), |shot| {
+            directions.push(shot.direction)
+        });
+    }
+
+    directions
+}
+
+#[test]
+fn volleys_fire_on_time_whatever_the_step() {
+    let spread = pattern_by_name("aimed_spread").unwrap();
+
+    // 0, 1.5 and 3 seconds in.
+    let big_steps = fired(&mut Emitter::new(spread, 1.0, 1), 1.0, 4);
+    let small_steps = fired(&mut Emitter::new(spread, 1.0, 1), 0.01, 400);
+    assert_eq!(big_steps.len(), 3 * 5);
+    assert_eq!(small_steps.len(), 3 * 5);
+
+    // Aimed to the right, the middle bullet flies straight at the player.
+    assert!((big_steps[2] - Vec2::new(1.0, 0.0)).length() < 0.001);
+
+    let faster = fired(&mut Emitter::new(spread, 2.0, 1), 1.0, 4);
+    assert_eq!(faster.len(), 6 * 5);
+}
+
+#[test]
+fn spirals_turn_and_jitter_follows_the_seed() {
+    let spiral = pattern_by_name("spiral").unwrap();
+    let directions = fired(&mut Emitter::new(spiral, 1.0, 1), 0.15, 2);
+    let turned =
+        directions[4].y().atan2(directions[4].x()) - directions[0].y().atan2(directions[0].x());
+    assert!((turned - 11.0_f32.to_radians()).abs() < 0.001);
+
+    let burst = pattern_by_name("burst").unwrap();
+    let a = fired(&mut Emitter::new(burst, 1.0, 7), 1.0, 1);
+    let b = fired(&mut Emitter::new(burst, 1.0, 7), 1.0, 1);
+    let c = fired(&mut Emitter::new(burst, 1.0, 8), 1.0, 1);
+    assert_eq!(a, b);
+    assert_ne!(a, c);
+}
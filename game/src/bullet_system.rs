@@ -1,32 +1,44 @@
+use crate::bullet_pattern::Shot;
 use crate::components::box_drawable::BoxDrawableComponent;
+use crate::components::collider::{
+    Collider, ColliderComponent, LAYER_ENEMY, LAYER_ENEMY_BULLET, LAYER_PLAYER, LAYER_PLAYER_BULLET,
+};
 use crate::components::movable::MovableComponent;
-use crate::enemy_system::EnemySystem;
 use crate::entity::OwnedEntity;
-use crate::{camera::Camera, world::World, Player, SHIP_SIZE};
+use crate::map::Map;
+use crate::{camera::Camera, pixel_size, world::World, VIDEO_MODE};
 use alloc::vec::Vec;
-use n64_math::{self, Aabb2, Color, Vec2};
+use n64_alloc::Arena;
+use n64_math::{self, rand::Rng, Aabb2, Broadphase, Color, Vec2};
 
 const BULLET_SIZE: Vec2 = Vec2::new(0.00825, 0.00825);
 
 struct Bullet {
     entity: OwnedEntity,
-    can_hit_player: bool,
-    can_hit_enemy: bool,
+    /// Seconds left before the bullet moves at `speed`.
+    delay: f32,
+    speed: Vec2,
+    acceleration: Vec2,
 }
 
 pub struct BulletSystem {
     bullets: Vec<Bullet>,
+    /// For the spread of the player's bullets.
+    rng: Rng,
+    broadphase: Broadphase<Collider>,
 }
 
 impl BulletSystem {
     pub fn new() -> Self {
         Self {
             bullets: Vec::new(),
+            rng: Rng::new_unseeded(),
+            broadphase: Broadphase::new(),
         }
     }
 
     pub fn shoot_bullet(&mut self, world: &mut World, pos: Vec2, speed: Vec2) {
-        let spread = (n64_math::random_f32() - 0.5) * 0.05;
+        let spread = (self.rng.next_f32() - 0.5) * 0.05;
 
         let entity = world.entity.create();
         world.movable.add(
@@ -43,17 +55,39 @@ impl BulletSystem {
                 color: Color::from_rgb(0.2, 0.2, 0.9),
             },
         );
+        world.collider.add(
+            &entity,
+            ColliderComponent {
+                size: BULLET_SIZE,
+                layer: LAYER_PLAYER_BULLET,
+                mask: LAYER_ENEMY,
+            },
+        );
 
         self.bullets.push(Bullet {
             entity,
-            can_hit_player: false,
-            can_hit_enemy: true,
+            delay: 0.0,
+            speed,
+            acceleration: Vec2::zero(),
         });
     }
 
-    pub fn shoot_bullet_enemy(&mut self, world: &mut World, pos: Vec2, speed: Vec2) {
+    /// Fires an enemy bullet from `pos`.
+    pub fn shoot_bullet_enemy(&mut self, world: &mut World, pos: Vec2, shot: Shot) {
+        let speed = shot.direction * shot.speed * pixel_size();
+
         let entity = world.entity.create();
-        world.movable.add(&entity, MovableComponent { pos, speed });
+        world.movable.add(
+            &entity,
+            MovableComponent {
+                pos,
+                speed: if shot.delay > 0.0 {
+                    Vec2::zero()
+                } else {
+                    speed
+                },
+            },
+        );
         world.box_drawable.add(
             &entity,
             BoxDrawableComponent {
@@ -61,22 +95,48 @@ impl BulletSystem {
                 color: Color::from_rgb(0.9, 0.2, 0.2),
             },
         );
+        world.collider.add(
+            &entity,
+            ColliderComponent {
+                size: BULLET_SIZE,
+                layer: LAYER_ENEMY_BULLET,
+                mask: LAYER_PLAYER,
+            },
+        );
 
         self.bullets.push(Bullet {
             entity,
-            can_hit_player: true,
-            can_hit_enemy: false,
+            delay: shot.delay,
+            speed,
+            acceleration: shot.direction * shot.acceleration * pixel_size(),
         });
     }
 
     pub fn update(
         &mut self,
         world: &mut World,
-        enemy_system: &mut EnemySystem,
-        player: &mut Player,
         camera: &Camera,
+        map: &Map,
+        frame_arena: &Arena,
+        dt: f32,
     ) {
-        let mut delete_list = Vec::new();
+        let mut delete_list = frame_arena.vec(self.bullets.len());
+
+        for bullet in self.bullets.iter_mut() {
+            if let Some(movable) = world.movable.lookup_mut(&bullet.entity) {
+                if bullet.delay > 0.0 {
+                    bullet.delay -= dt;
+
+                    if bullet.delay <= 0.0 {
+                        movable.speed = bullet.speed;
+                    }
+                } else {
+                    movable.speed += bullet.acceleration * dt;
+                }
+            }
+        }
+
+        world.collider.rebuild(&world.movable, &mut self.broadphase);
 
         let camera_bb: Aabb2 = Aabb2::new(camera.pos, camera.pos + Vec2::new(1.0, 1.0));
 
@@ -85,46 +145,24 @@ impl BulletSystem {
                 let mut delete = false;
                 let bullet_bb = Aabb2::from_center_size(movable.pos, BULLET_SIZE);
 
-                if !bullet_bb.collides(&camera_bb) {
+                if !bullet_bb.collides(&camera_bb) || map.is_solid(movable.pos, &VIDEO_MODE) {
                     delete = true;
                 }
 
-                if bullet.can_hit_enemy {
-                    for enemy in enemy_system.enemies_mut() {
-                        if let Some(sprite_drawable) = world.sprite_drawable.lookup(enemy.entity())
-                        {
-                            let enemy_bb = Aabb2::from_center_size(
-                                world.movable.pos(enemy.entity()).unwrap_or_else(Vec2::zero),
-                                sprite_drawable.size,
-                            );
-
-                            if bullet_bb.collides(&enemy_bb) {
-                                world.health.damage(
-                                    enemy.entity(),
-                                    50 + (n64_math::random_f32() * 20.0) as i32,
-                                );
-                                delete = true;
-                            }
-                        }
-                    }
-                }
-
-                if bullet.can_hit_player {
-                    let player_bb = Aabb2::from_center_size(
-                        world
-                            .movable
-                            .pos(player.entity())
-                            .unwrap_or_else(Vec2::zero),
-                        SHIP_SIZE,
-                    );
-
-                    if bullet_bb.collides(&player_bb) {
-                        world
-                            .health
-                            .damage(player.entity(), 50 + (n64_math::random_f32() * 20.0) as i32);
+                let health = &mut world.health;
+
+                world.collider.hits(
+                    &bullet.entity,
+                    &world.movable,
+                    &self.broadphase,
+                    |collider| {
+                        health.damage(
+                            &collider.entity,
+                            50 + (n64_math::random_f32() * 20.0) as i32,
+                        );
                         delete = true;
-                    }
-                }
+                    },
+                );
 
                 if delete {
                     delete_list.push(i);
@@ -0,0 +1,69 @@
+use crate::components::movable;
+use crate::entity::Entity;
+use crate::impl_system;
+use n64_math::{Aabb2, Broadphase, Vec2};
+
+/// Collision layers, one bit each so a mask can hold several.
+pub const LAYER_PLAYER: u8 = 1 << 0;
+pub const LAYER_ENEMY: u8 = 1 << 1;
+pub const LAYER_PLAYER_BULLET: u8 = 1 << 2;
+pub const LAYER_ENEMY_BULLET: u8 = 1 << 3;
+pub const LAYER_PICKUP: u8 = 1 << 4;
+
+#[derive(Copy, Clone)]
+pub struct ColliderComponent {
+    /// Centered on the movable position.
+    pub size: Vec2,
+    pub layer: u8,
+    /// The layers this collider hits.
+    pub mask: u8,
+}
+
+/// What the broadphase knows about a collider.
+#[derive(Copy, Clone)]
+pub struct Collider {
+    pub entity: Entity,
+    pub layer: u8,
+}
+
+impl System {
+    /// Rebuilds `broadphase` with every collider where it is now.
+    pub fn rebuild(&self, movable: &movable::System, broadphase: &mut Broadphase<Collider>) {
+        broadphase.rebuild(
+            self.components_and_entities()
+                .filter_map(|(component, entity)| {
+                    let pos = movable.pos(&entity)?;
+
+                    Some((
+                        Aabb2::from_center_size(pos, component.size),
+                        Collider {
+                            entity,
+                            layer: component.layer,
+                        },
+                    ))
+                }),
+        );
+    }
+
+    /// Calls `f` with every collider overlapping `entity` on a layer in its
+    /// mask.
+    pub fn hits(
+        &self,
+        entity: &Entity,
+        movable: &movable::System,
+        broadphase: &Broadphase<Collider>,
+        mut f: impl FnMut(Collider),
+    ) {
+        if let (Some(component), Some(pos)) = (self.lookup(entity), movable.pos(entity)) {
+            let aabb = Aabb2::from_center_size(pos, component.size);
+
+            broadphase.query(&aabb, |_, collider| {
+                if component.mask & collider.layer != 0 && collider.entity != *entity {
+                    f(collider);
+                }
+            });
+        }
+    }
+}
+
+impl_system!(ColliderComponent);
@@ -1,4 +1,5 @@
 pub mod box_drawable;
+pub mod collider;
 pub mod health;
 pub mod movable;
 pub mod sprite_drawable;
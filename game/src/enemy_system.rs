@@ -1,45 +1,54 @@
+use crate::components::collider::{ColliderComponent, LAYER_ENEMY, LAYER_PLAYER_BULLET};
 use crate::components::health::HealthComponent;
 use crate::components::movable::MovableComponent;
 use crate::entity::{Entity, OwnedEntity};
+use crate::{bullet_pattern::Emitter, pixel_size, Player};
 use crate::{bullet_system::BulletSystem, components::sprite_drawable::SpriteDrawableComponent};
-use crate::{sound_mixer::SoundMixer, sounds::EXPLOSION_0, world::World, Player};
+use crate::{movement::Movement, sound_mixer::SoundMixer, sounds::EXPLOSION_0, world::World};
 use alloc::vec::Vec;
-use n64::{current_time_us, gfx::Texture};
+use n64::gfx::Texture;
+use n64_alloc::Arena;
 use n64_math::{self, Vec2};
 
-static ENEMY_WAYPOINT: [Vec2; 4] = [
-    Vec2::new(0.4, 0.4),
-    Vec2::new(0.6, 0.4),
-    Vec2::new(0.6, 0.6),
-    Vec2::new(0.4, 0.6),
-];
+fn ai(world: &mut World, enemy: &mut Enemy, player_pos: Vec2, dt: f32) {
+    // Movements are in map pixels.
+    let pixel_size = pixel_size();
 
-fn ai(world: &mut World, enemy: &mut Enemy, dt: f32) {
-    if let Some(movable) = world.movable.lookup_mut(&enemy.entity) {
-        if enemy.waypoint_step >= 1.0 {
-            enemy.waypoint_step -= 1.0;
-            enemy.waypoint += 1;
-            if enemy.waypoint >= ENEMY_WAYPOINT.len() {
-                enemy.waypoint = 0;
-            }
-        }
+    if enemy.movement.aiming(enemy.time) {
+        enemy.aim = (player_pos - enemy.spawn_pos) / pixel_size;
+    }
 
-        let a_waypoint = (enemy.waypoint + 1) % ENEMY_WAYPOINT.len();
-        let speed_a = ENEMY_WAYPOINT[a_waypoint] - ENEMY_WAYPOINT[enemy.waypoint];
-        let b_waypoint = (a_waypoint + 1) % ENEMY_WAYPOINT.len();
-        let speed_b = ENEMY_WAYPOINT[b_waypoint] - ENEMY_WAYPOINT[a_waypoint];
+    enemy.time += dt;
 
-        movable.speed = (1.0 - enemy.waypoint_step) * speed_a + enemy.waypoint_step * speed_b;
-        enemy.waypoint_step += dt;
+    if let Some(movable) = world.movable.lookup_mut(&enemy.entity) {
+        // Lands on the pattern when the movables are simulated.
+        let pos = enemy.spawn_pos + enemy.movement.offset(enemy.time, enemy.aim) * pixel_size;
+
+        if dt > 0.0 {
+            movable.speed = (pos - movable.pos) / dt;
+        }
     }
 }
 
+/// What the map sets about an enemy.
+pub struct EnemySettings {
+    pub health: i32,
+    pub emitter: Emitter,
+    pub score: i32,
+    pub movement: Movement,
+}
+
 pub struct Enemy {
     entity: OwnedEntity,
-    shoot_speed: i32,
-    last_shoot_time: i64,
-    waypoint: usize,
-    waypoint_step: f32,
+    emitter: Emitter,
+    score: i32,
+    movement: Movement,
+    spawn_pos: Vec2,
+    /// Seconds since the enemy spawned.
+    time: f32,
+    /// Where the player is relative to `spawn_pos`, in pixels, until the
+    /// movement stops aiming.
+    aim: Vec2,
 }
 
 impl Enemy {
@@ -59,30 +68,51 @@ impl EnemySystem {
         }
     }
 
-    pub fn spawn_enemy(&mut self, world: &mut World, pos: Vec2, texture: Texture<'static>) {
+    pub fn spawn_enemy(
+        &mut self,
+        world: &mut World,
+        pos: Vec2,
+        texture: Texture<'static>,
+        settings: EnemySettings,
+    ) {
+        let EnemySettings {
+            health,
+            emitter,
+            score,
+            movement,
+        } = settings;
+
         let entity = world.entity.create();
         world.movable.add(
             &entity,
             MovableComponent {
-                pos,
+                pos: pos + movement.offset(0.0, Vec2::zero()) * pixel_size(),
                 speed: Vec2::zero(),
             },
         );
-        world.sprite_drawable.add(
+        let size = Vec2::new(texture.width as f32 / 320.0, texture.height as f32 / 240.0);
+
+        world
+            .sprite_drawable
+            .add(&entity, SpriteDrawableComponent { size, texture });
+        world.health.add(&entity, HealthComponent { health });
+        world.collider.add(
             &entity,
-            SpriteDrawableComponent {
-                size: Vec2::new(texture.width as f32 / 320.0, texture.height as f32 / 240.0),
-                texture,
+            ColliderComponent {
+                size,
+                layer: LAYER_ENEMY,
+                mask: LAYER_PLAYER_BULLET,
             },
         );
-        world.health.add(&entity, HealthComponent { health: 100 });
 
         self.enemies.push(Enemy {
             entity,
-            shoot_speed: 500 + (n64_math::random_f32() * 200.0) as i32,
-            last_shoot_time: 0,
-            waypoint: 0,
-            waypoint_step: 1.0,
+            emitter,
+            score,
+            movement,
+            spawn_pos: pos,
+            time: 0.0,
+            aim: Vec2::zero(),
         });
     }
 
@@ -92,35 +122,38 @@ impl EnemySystem {
         bullet_system: &mut BulletSystem,
         player: &mut Player,
         sound_mixer: &mut SoundMixer,
+        frame_arena: &Arena,
         dt: f32,
     ) {
-        let mut delete_list = Vec::new();
+        let mut delete_list = frame_arena.vec(self.enemies.len());
 
-        let now = current_time_us();
+        let player_pos = world
+            .movable
+            .pos(player.entity())
+            .unwrap_or_else(Vec2::zero);
 
         for (i, enemy) in self.enemies_mut().iter_mut().enumerate() {
             if !world.health.is_alive(&enemy.entity) {
                 sound_mixer.play_sound(EXPLOSION_0.as_sound_data());
-                player.add_score(1000);
+                player.add_score(enemy.score);
                 delete_list.push(i);
             }
 
-            if now - enemy.last_shoot_time > enemy.shoot_speed as i64 * 1000 {
-                if let (Some(movable), Some(sprite_drawable)) = (
-                    world.movable.lookup(&enemy.entity).copied(),
-                    world.sprite_drawable.lookup(&enemy.entity).copied(),
-                ) {
-                    //sound_mixer.play_sound(SHOOT_0.as_sound_data());
-                    bullet_system.shoot_bullet_enemy(
-                        world,
-                        movable.pos + Vec2::new(0.0, sprite_drawable.size.y() / 2.0),
-                        Vec2::new(0.0, 1.25),
-                    );
-                    enemy.last_shoot_time = now;
-                }
+            if let (Some(movable), Some(sprite_drawable)) = (
+                world.movable.lookup(&enemy.entity).copied(),
+                world.sprite_drawable.lookup(&enemy.entity).copied(),
+            ) {
+                let muzzle = movable.pos + Vec2::new(0.0, sprite_drawable.size.y() / 2.0);
+
+                //sound_mixer.play_sound(SHOOT_0.as_sound_data());
+                enemy
+                    .emitter
+                    .update(dt, (player_pos - muzzle) / pixel_size(), |shot| {
+                        bullet_system.shoot_bullet_enemy(world, muzzle, shot)
+                    });
             }
 
-            ai(world, enemy, dt);
+            ai(world, enemy, player_pos, dt);
         }
 
         {
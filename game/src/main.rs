@@ -1,7 +1,6 @@
 #![cfg_attr(target_vendor = "nintendo64", feature(alloc_error_handler))]
 #![cfg_attr(target_vendor = "nintendo64", feature(global_asm))]
 #![cfg_attr(target_vendor = "nintendo64", feature(lang_items))]
-#![cfg_attr(target_vendor = "nintendo64", feature(panic_info_message))]
 #![cfg_attr(target_vendor = "nintendo64", feature(start))]
 #![cfg_attr(target_vendor = "nintendo64", no_std)]
 #![allow(clippy::inconsistent_digit_grouping)]
@@ -14,15 +13,21 @@ use enemy_system::EnemySystem;
 use map::Map;
 use maps::MAP_1;
 use n64::{
-    self, current_time_us,
-    gfx::{CommandBuffer, CommandBufferCache},
-    ipl3font, slow_cpu_clear, VideoMode, N64,
+    self,
+    app::{self, App, Control, FrameBudget},
+    gfx::{CommandBuffer, CommandBufferCache, FrameStats},
+    ipl3font, profiler, slow_cpu_clear,
+    time::Duration,
+    VideoMode, N64,
 };
+use n64_alloc::Arena;
 use n64_math::{vec2, vec3, Color, Vec2, Vec3};
-use player::{Player, SHIP_SIZE};
+use player::Player;
 use sound_mixer::SoundMixer;
 use world::World;
 
+mod bullet_pattern;
+mod bullet_patterns;
 mod bullet_system;
 mod camera;
 mod components;
@@ -31,6 +36,7 @@ mod entity;
 mod font;
 mod map;
 mod maps;
+mod movement;
 mod player;
 mod sound;
 mod sound_mixer;
@@ -45,207 +51,357 @@ const VIDEO_MODE: VideoMode = VideoMode::Pal {
     height: 240,
 };
 
-fn main() {
-    let mut n64 = N64::new(VIDEO_MODE);
+/// The size of a map pixel in screen units, where the screen is one by one.
+fn pixel_size() -> Vec2 {
+    Vec2::new(
+        1.0 / VIDEO_MODE.width() as f32,
+        1.0 / VIDEO_MODE.height() as f32,
+    )
+}
 
-    let mut world = World::new();
-    let map = Map::load(MAP_1);
+const FRAME_ARENA_SIZE: usize = 16 * 1024;
+
+struct Game {
+    world: World,
+    map: Map,
+    sound_mixer: SoundMixer,
+    camera: Camera,
+    player: Player,
+    bullet_system: BulletSystem,
+    enemy_system: EnemySystem,
+    /// Where the camera was at the last checkpoint trigger, the player
+    /// starts over there.
+    checkpoint: Option<Vec2>,
+    command_buffer_cache: CommandBufferCache,
+    last_frame_stats: FrameStats,
+    // Reset at the start of every update, for allocations that do not
+    // outlive it.
+    frame_arena: Arena,
+}
 
-    let start_pos = Vec2::new(
-        map.get_start_pos().0 / VIDEO_MODE.width() as f32,
-        map.get_start_pos().1 / VIDEO_MODE.height() as f32 - 1.0,
-    );
+impl Game {
+    fn new() -> Self {
+        let mut world = World::new();
+        let map = Map::load(MAP_1);
 
-    let mut sound_mixer = SoundMixer::new();
-    let mut camera = Camera::new(start_pos);
-    let mut player = Player::new(&mut world, start_pos);
-    let mut bullet_system = BulletSystem::new();
-    let mut enemy_system = EnemySystem::new();
-    let mut command_buffer_cache = CommandBufferCache::new();
+        let start_pos = Vec2::new(
+            map.get_start_pos().0 / VIDEO_MODE.width() as f32,
+            map.get_start_pos().1 / VIDEO_MODE.height() as f32 - 1.0,
+        );
 
-    map.spawn_enemies(&mut world, &mut enemy_system, &VIDEO_MODE);
+        let sound_mixer = SoundMixer::new();
+        let camera = Camera::new(start_pos);
+        let player = Player::new(&mut world, start_pos);
+        let bullet_system = BulletSystem::new();
+        let enemy_system = EnemySystem::new();
+        let command_buffer_cache = CommandBufferCache::new();
+        let frame_arena = Arena::new("frame", FRAME_ARENA_SIZE).unwrap();
+
+        Self {
+            world,
+            map,
+            sound_mixer,
+            camera,
+            player,
+            bullet_system,
+            enemy_system,
+            checkpoint: None,
+            command_buffer_cache,
+            last_frame_stats: FrameStats::default(),
+            frame_arena,
+        }
+    }
+}
 
-    let mut frame_begin_time;
-    let mut last_frame_begin_time = current_time_us();
-    let mut frame_used_time = 0;
-    let mut dt;
+impl app::Game for Game {
+    fn update(&mut self, n64: &mut N64, dt: f32) -> Control {
+        self.frame_arena.reset();
+
+        let world = &mut self.world;
+        let frame_arena = &self.frame_arena;
+
+        self.camera.update(&n64.controllers, dt, &VIDEO_MODE);
+        self.map.update(dt, &self.camera, &VIDEO_MODE);
+        self.map
+            .spawn_enemies(world, &mut self.enemy_system, &VIDEO_MODE);
+
+        while let Some(trigger) = self.map.next_trigger() {
+            match trigger.kind {
+                "music" => match trigger.string("sound").and_then(sounds::by_name) {
+                    Some(sound) => self.sound_mixer.play_sound(sound.as_sound_data()),
+                    None => n64::warn!("Trigger {} has no known `sound`", trigger.name),
+                },
+                "checkpoint" => self.checkpoint = Some(self.camera.pos),
+                _ => n64::warn!("Unknown trigger type {:?}", trigger.kind),
+            }
+        }
 
-    let mut last_colored_rect_count = 0;
-    let mut last_textured_rect_count = 0;
+        {
+            let _zone = profiler::zone("enemy ai");
+
+            self.enemy_system.update(
+                world,
+                &mut self.bullet_system,
+                &mut self.player,
+                &mut self.sound_mixer,
+                frame_arena,
+                dt,
+            );
+        }
 
-    loop {
-        frame_begin_time = current_time_us();
+        self.player.update(
+            world,
+            &n64.controllers,
+            &mut self.bullet_system,
+            &mut self.sound_mixer,
+            &self.camera,
+        );
 
         {
-            dt = (frame_begin_time - last_frame_begin_time) as f32 / 1e6;
-            last_frame_begin_time = frame_begin_time;
+            let _zone = profiler::zone("bullets");
+
+            self.bullet_system
+                .update(world, &self.camera, &self.map, frame_arena, dt);
         }
 
-        {
-            // Update
+        world.movable.simulate(dt);
+
+        world.entity.gc(&mut [
+            &mut world.movable,
+            &mut world.box_drawable,
+            &mut world.sprite_drawable,
+            &mut world.health,
+            &mut world.collider,
+        ]);
+
+        if !world.health.is_alive(self.player.entity()) {
+            match self.checkpoint {
+                Some(checkpoint) => {
+                    self.camera.pos = checkpoint;
+                    self.player.respawn(world, checkpoint);
+                }
+                None => return Control::Exit,
+            }
+        }
 
-            n64.controllers.update(&n64.graphics);
+        Control::Continue
+    }
 
-            camera.update(&n64.controllers, dt, &VIDEO_MODE);
+    fn audio(&mut self, buffer: &mut [i16]) {
+        let _zone = profiler::zone("mix");
+        self.sound_mixer.mix(buffer);
+    }
 
-            enemy_system.update(
-                &mut world,
-                &mut bullet_system,
-                &mut player,
-                &mut sound_mixer,
-                dt,
-            );
+    fn render(&mut self, n64: &mut N64, _alpha: f32, budget: &FrameBudget) {
+        let world = &self.world;
+
+        let mut fb = n64.framebuffer.next_buffer();
+        let mut cb = CommandBuffer::new(&mut fb, &mut self.command_buffer_cache);
+        let build_zone = profiler::zone("cb build");
+
+        cb.clear();
+
+        self.map.render(&mut cb, VIDEO_MODE, &self.camera);
+        world
+            .box_drawable
+            .draw(&world.movable, &mut cb, VIDEO_MODE, &self.camera);
+        world
+            .sprite_drawable
+            .draw(&world.movable, &mut cb, VIDEO_MODE, &self.camera);
+
+        cb.add_mesh_indexed(
+            &[
+                vec3(0.0, 0.5, 0.0),
+                vec3(-0.5, -0.20, 0.0),
+                vec3(0.5, -0.30, 0.0),
+            ],
+            &[vec2(0.5, 1.0), vec2(0.0, 0.0), vec2(1.0, 0.0)],
+            &[0xff_00_00_ff, 0x00_ff_00_ff, 0x00_00_ff_ff],
+            &[[0, 1, 2]],
+            &[
+                [1.0, 0.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0, 0.0],
+                [0.0, 0.0, 1.0, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+            None,
+        );
 
-            player.update(
-                &mut world,
-                &n64.controllers,
-                &mut bullet_system,
-                &mut sound_mixer,
-                &camera,
-            );
+        if false {
+            font::draw_text(&mut cb, " !\"#$%&", Vec2::new(1.0, 0.0), 0xffffffff);
+            font::draw_text(&mut cb, "'()+,-./", Vec2::new(1.0, 17.0), 0xffffffff);
+            font::draw_text(&mut cb, "0123456789", Vec2::new(1.0, 34.0), 0xffffffff);
+            font::draw_text(&mut cb, ":;<=>?@", Vec2::new(1.0, 51.0), 0xffffffff);
+            font::draw_text(&mut cb, "ABCDEFGHIJ", Vec2::new(1.0, 68.0), 0xffffffff);
+            font::draw_text(&mut cb, "KLMNOPQRST", Vec2::new(1.0, 85.0), 0xffffffff);
+            font::draw_text(&mut cb, "UVWXYZ", Vec2::new(1.0, 102.0), 0xffffffff);
+            font::draw_text(&mut cb, "[\\]^_`", Vec2::new(1.0, 119.0), 0xffffffff);
+            font::draw_text(&mut cb, "abcdefghij", Vec2::new(1.0, 136.0), 0xffffffff);
+            font::draw_text(&mut cb, "klmnopqrst", Vec2::new(1.0, 153.0), 0xffffffff);
+            font::draw_text(&mut cb, "uvwxyz", Vec2::new(1.0, 170.0), 0xffffffff);
+            font::draw_text(&mut cb, "{|}~", Vec2::new(1.0, 187.0), 0xffffffff);
+        }
 
-            bullet_system.update(&mut world, &mut enemy_system, &mut player, &camera);
+        font::draw_number(
+            &mut cb,
+            self.player.score(),
+            Vec2::new(300.0, 10.0),
+            0x0000efff,
+        );
+        font::draw_number(
+            &mut cb,
+            world
+                .health
+                .lookup(self.player.entity())
+                .map(|hc| hc.health)
+                .unwrap_or(0),
+            Vec2::new(300.0, 215.0),
+            0xaf0000ff,
+        );
 
-            world.movable.simulate(dt);
+        if profiler::overlay_enabled() {
+            profiler::draw_overlay(
+                &mut cb,
+                VIDEO_MODE,
+                Duration::from_micros(budget.budget_us as u64),
+            );
+            draw_profiler_legend(&mut cb);
 
-            world.entity.gc(&mut [
-                &mut world.movable,
-                &mut world.box_drawable,
-                &mut world.sprite_drawable,
-                &mut world.health,
-            ]);
+            #[cfg(target_vendor = "nintendo64")]
+            {
+                let heap = ALLOC.stats();
 
-            if !world.health.is_alive(player.entity()) {
-                break;
+                font::draw_number(
+                    &mut cb,
+                    n64_alloc::BYTES_USED.load(core::sync::atomic::Ordering::SeqCst),
+                    Vec2::new(100.0, 80.0),
+                    0xff0000ff,
+                );
+                font::draw_number(
+                    &mut cb,
+                    heap.largest_free_block as i32,
+                    Vec2::new(100.0, 100.0),
+                    0xff0000ff,
+                );
+                font::draw_number(
+                    &mut cb,
+                    (heap.fragmentation() * 100.0) as i32,
+                    Vec2::new(100.0, 120.0),
+                    0xff0000ff,
+                );
             }
-        }
 
-        {
-            // Audio
-            n64.audio.update(|buffer| {
-                sound_mixer.mix(buffer);
-            });
+            font::draw_number(
+                &mut cb,
+                self.frame_arena.stats().peak_bytes as i32,
+                Vec2::new(100.0, 140.0),
+                0xff0000ff,
+            );
+
+            font::draw_number(
+                &mut cb,
+                budget.average_frame_us as i32,
+                Vec2::new(100.0, 10.0),
+                0x00af00ff,
+            );
+            font::draw_number(
+                &mut cb,
+                budget.last_frame_us as i32,
+                Vec2::new(200.0, 10.0),
+                0x00af00ff,
+            );
+            font::draw_number(
+                &mut cb,
+                self.last_frame_stats.colored_rect_count as i32,
+                Vec2::new(100.0, 30.0),
+                0x00af00ff,
+            );
+            font::draw_number(
+                &mut cb,
+                self.last_frame_stats.textured_rect_count as i32,
+                Vec2::new(200.0, 30.0),
+                0x00af00ff,
+            );
+            font::draw_number(
+                &mut cb,
+                self.last_frame_stats.command_count as i32,
+                Vec2::new(300.0, 30.0),
+                0x00af00ff,
+            );
         }
 
-        {
-            // Graphics
-
-            let (colored_rect_count, textured_rect_count) = {
-                let mut fb = n64.framebuffer.next_buffer();
-                let mut cb = CommandBuffer::new(&mut fb, &mut command_buffer_cache);
-
-                cb.clear();
-
-                map.render(&mut cb, VIDEO_MODE, &camera);
-                world
-                    .box_drawable
-                    .draw(&world.movable, &mut cb, VIDEO_MODE, &camera);
-                world
-                    .sprite_drawable
-                    .draw(&world.movable, &mut cb, VIDEO_MODE, &camera);
-
-                cb.add_mesh_indexed(
-                    &[
-                        vec3(0.0, 0.5, 0.0),
-                        vec3(-0.5, -0.20, 0.0),
-                        vec3(0.5, -0.30, 0.0),
-                    ],
-                    &[vec2(0.5, 1.0), vec2(0.0, 0.0), vec2(1.0, 0.0)],
-                    &[0xff_00_00_ff, 0x00_ff_00_ff, 0x00_00_ff_ff],
-                    &[[0, 1, 2]],
-                    &[
-                        [1.0, 0.0, 0.0, 0.0],
-                        [0.0, 1.0, 0.0, 0.0],
-                        [0.0, 0.0, 1.0, 0.0],
-                        [0.0, 0.0, 0.0, 1.0],
-                    ],
-                    None,
-                );
+        drop(build_zone);
 
-                if false {
-                    font::draw_text(&mut cb, " !\"#$%&", Vec2::new(1.0, 0.0), 0xffffffff);
-                    font::draw_text(&mut cb, "'()+,-./", Vec2::new(1.0, 17.0), 0xffffffff);
-                    font::draw_text(&mut cb, "0123456789", Vec2::new(1.0, 34.0), 0xffffffff);
-                    font::draw_text(&mut cb, ":;<=>?@", Vec2::new(1.0, 51.0), 0xffffffff);
-                    font::draw_text(&mut cb, "ABCDEFGHIJ", Vec2::new(1.0, 68.0), 0xffffffff);
-                    font::draw_text(&mut cb, "KLMNOPQRST", Vec2::new(1.0, 85.0), 0xffffffff);
-                    font::draw_text(&mut cb, "UVWXYZ", Vec2::new(1.0, 102.0), 0xffffffff);
-                    font::draw_text(&mut cb, "[\\]^_`", Vec2::new(1.0, 119.0), 0xffffffff);
-                    font::draw_text(&mut cb, "abcdefghij", Vec2::new(1.0, 136.0), 0xffffffff);
-                    font::draw_text(&mut cb, "klmnopqrst", Vec2::new(1.0, 153.0), 0xffffffff);
-                    font::draw_text(&mut cb, "uvwxyz", Vec2::new(1.0, 170.0), 0xffffffff);
-                    font::draw_text(&mut cb, "{|}~", Vec2::new(1.0, 187.0), 0xffffffff);
-                }
+        self.last_frame_stats = {
+            let _zone = profiler::zone("rdp run");
+            cb.run(&mut n64.graphics)
+        };
 
-                font::draw_number(&mut cb, player.score(), Vec2::new(300.0, 10.0), 0x0000efff);
-                font::draw_number(
-                    &mut cb,
-                    world
-                        .health
-                        .lookup(player.entity())
-                        .map(|hc| hc.health)
-                        .unwrap_or(0),
-                    Vec2::new(300.0, 215.0),
-                    0xaf0000ff,
-                );
+        profiler::record_gpu_time(self.last_frame_stats.gpu_time());
+    }
+}
 
-                #[cfg(target_vendor = "nintendo64")]
-                {
-                    font::draw_number(
-                        &mut cb,
-                        n64_alloc::BYTES_USED.load(core::sync::atomic::Ordering::SeqCst),
-                        Vec2::new(100.0, 160.0),
-                        0xff0000ff,
-                    );
-                    font::draw_number(
-                        &mut cb,
-                        n64_alloc::BYTES_LEFT.load(core::sync::atomic::Ordering::SeqCst),
-                        Vec2::new(100.0, 180.0),
-                        0xff0000ff,
-                    );
-                    font::draw_number(
-                        &mut cb,
-                        *n64_alloc::PAGE_OFFSET.lock() as i32,
-                        Vec2::new(100.0, 200.0),
-                        0xff0000ff,
-                    );
-                }
+/// Lists every profiler zone next to its color, with its time in the last
+/// frame in microseconds, followed by the RDP time.
+/// Right of the memory numbers, which end at x 100.
+fn draw_profiler_legend(cb: &mut CommandBuffer) {
+    profiler::with_profiler(|profiler| {
+        let frame = match profiler.last_frame() {
+            Some(frame) => frame,
+            None => return,
+        };
+
+        for (i, name) in profiler.names().iter().enumerate() {
+            let y = 80.0 + i as f32 * 17.0;
+
+            cb.add_colored_rect(
+                Vec2::new(150.0, y + 4.0),
+                Vec2::new(158.0, y + 12.0),
+                profiler::zone_color(i as u8),
+            );
+            font::draw_text(cb, name, Vec2::new(162.0, y), 0xffffffff);
+            font::draw_number(
+                cb,
+                frame.total(i as u8).as_micros() as i32,
+                Vec2::new(310.0, y),
+                0xffffffff,
+            );
+        }
 
-                {
-                    font::draw_number(
-                        &mut cb,
-                        (dt * 1000.0 * 1000.0) as i32,
-                        Vec2::new(100.0, 10.0),
-                        0x00af00ff,
-                    );
-                    font::draw_number(
-                        &mut cb,
-                        frame_used_time as i32,
-                        Vec2::new(200.0, 10.0),
-                        0x00af00ff,
-                    );
-                    font::draw_number(
-                        &mut cb,
-                        last_colored_rect_count,
-                        Vec2::new(100.0, 30.0),
-                        0x00af00ff,
-                    );
-                    font::draw_number(
-                        &mut cb,
-                        last_textured_rect_count,
-                        Vec2::new(200.0, 30.0),
-                        0x00af00ff,
-                    );
-                }
+        let y = 80.0 + profiler.names().len() as f32 * 17.0;
+
+        font::draw_text(cb, "gpu", Vec2::new(162.0, y), 0xffffffff);
+        font::draw_number(
+            cb,
+            frame.gpu_duration.as_micros() as i32,
+            Vec2::new(310.0, y),
+            0xffffffff,
+        );
+    });
+}
+
+fn main() {
+    #[cfg(not(target_vendor = "nintendo64"))]
+    {
+        if std::env::var_os("N64_HOT_RELOAD").is_some() {
+            textures::watch();
+            maps::watch();
+            sounds::watch();
+            bullet_patterns::watch();
+        }
+    }
 
-                cb.run(&mut n64.graphics)
-            };
+    let mut n64 = N64::new(VIDEO_MODE);
+    let mut game = Game::new();
 
-            last_colored_rect_count = colored_rect_count;
-            last_textured_rect_count = textured_rect_count;
+    App::new().run(&mut n64, &mut game);
 
-            let frame_end_time = n64.graphics.swap_buffers(&mut n64.framebuffer);
-            frame_used_time = frame_end_time - frame_begin_time;
+    #[cfg(not(target_vendor = "nintendo64"))]
+    {
+        if let Some(path) = std::env::var_os("N64_PROFILE_TRACE") {
+            if let Err(err) = profiler::write_chrome_trace(&path) {
+                eprintln!("Failed to write profile trace to {:?}: {}", path, err);
+            }
         }
     }
 
@@ -267,6 +423,12 @@ static ALLOC: n64_alloc::N64Alloc = n64_alloc::N64Alloc::INIT;
 #[cfg(target_vendor = "nintendo64")]
 #[start]
 fn start(_argc: isize, _argv: *const *const u8) -> isize {
+    let (heap_start, heap_len) = n64_sys::sys::heap_memory();
+    unsafe { n64_alloc::init_heap(heap_start, heap_len) };
+
+    n64::crash::install(VIDEO_MODE);
+    n64::info!("{} KiB heap", heap_len / 1024);
+
     main();
     0
 }
@@ -274,47 +436,7 @@ fn start(_argc: isize, _argv: *const *const u8) -> isize {
 #[cfg(target_vendor = "nintendo64")]
 #[panic_handler]
 fn panic(info: &core::panic::PanicInfo) -> ! {
-    const GREEN: Color = Color::new(0b00011_10000_00011_1);
-
-    let mut out_tex = n64::gfx::TextureMut::new(VIDEO_MODE.width(), VIDEO_MODE.height(), unsafe {
-        core::slice::from_raw_parts_mut(n64_sys::vi::get_vi_buffer(), VIDEO_MODE.size() as usize)
-    });
-
-    slow_cpu_clear(out_tex.data);
-
-    ipl3font::draw_str(&mut out_tex, 15, 15, RED, b"PANIC!");
-    if let Some(location) = info.location() {
-        ipl3font::draw_str(
-            &mut out_tex,
-            15,
-            30,
-            RED,
-            alloc::format!(
-                "{}:{}",
-                location.file().rsplit("\\").nth(0).unwrap_or(""),
-                location.line()
-            )
-            .as_bytes(),
-        );
-    }
-    if let Some(args) = info.message() {
-        ipl3font::draw_str(
-            &mut out_tex,
-            15,
-            45,
-            GREEN,
-            alloc::format!("{}", args).as_bytes(),
-        );
-    } else {
-        ipl3font::draw_str(&mut out_tex, 15, 45, GREEN, b"No Message");
-    }
-
-    unsafe {
-        n64_sys::sys::data_cache_hit_writeback(out_tex.data);
-        n64_sys::vi::set_vi_buffer(out_tex.data);
-    }
-
-    loop {}
+    n64::crash::show_panic(info)
 }
 
 #[cfg(target_vendor = "nintendo64")]
@@ -1,14 +1,97 @@
-use crate::{camera::Camera, enemy_system::EnemySystem, world::World};
+use crate::bullet_pattern::{self, BulletPattern, Emitter};
+use crate::enemy_system::{EnemySettings, EnemySystem};
+use crate::movement::{Movement, Pattern};
+use crate::{camera::Camera, world::World};
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
 use n64::{
-    gfx::{CommandBuffer, StaticTexture},
-    VideoMode,
+    gfx::{CommandBuffer, StaticTexture, TextureFlip},
+    hot_reload, VideoMode,
 };
 use n64_math::Vec2;
 
+// Cells are big endian `u16`s, the index of the tile plus one (zero is no
+// tile) with the flips of Tiled in the top bits.
+pub const TILE_FLIP_HORIZONTAL: u16 = 0x8000;
+pub const TILE_FLIP_VERTICAL: u16 = 0x4000;
+pub const TILE_FLIP_DIAGONAL: u16 = 0x2000;
+pub const TILE_INDEX_MASK: u16 = 0x1fff;
+
+pub enum StaticProperty {
+    Int(i32),
+    Float(f32),
+    String(&'static str),
+}
+
+pub enum StaticObjectShape {
+    /// A tile object, spawned as an enemy.
+    Sprite(&'static StaticTexture),
+    /// A rectangle, ellipse or point, a trigger named by its type.
+    Region,
+    /// A polyline or polygon in map pixels, a path enemies can follow.
+    Path(&'static [Vec2]),
+}
+
+/// An object of Tiled, with its position and height in map pixels.
 pub struct StaticObject {
+    /// The type in Tiled.
+    pub kind: &'static str,
+    pub name: &'static str,
+    /// The center.
     pub x: f32,
     pub y: f32,
-    pub texture: &'static StaticTexture,
+    pub height: f32,
+    pub shape: StaticObjectShape,
+    /// Custom properties, sorted by name.
+    pub properties: &'static [(&'static str, StaticProperty)],
+}
+
+impl StaticObject {
+    pub fn property(&self, name: &str) -> Option<&'static StaticProperty> {
+        self.properties
+            .iter()
+            .find(|(property, _)| *property == name)
+            .map(|(_, value)| value)
+    }
+
+    /// An int or float property.
+    pub fn number(&self, name: &str) -> Option<f32> {
+        match self.property(name)? {
+            StaticProperty::Int(value) => Some(*value as f32),
+            StaticProperty::Float(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn string(&self, name: &str) -> Option<&'static str> {
+        match self.property(name)? {
+            StaticProperty::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    fn bottom(&self) -> f32 {
+        self.y + self.height / 2.0
+    }
+}
+
+pub struct StaticMapLayer {
+    /// How fast the layer scrolls with the camera, 1.0 is with the map.
+    pub parallax_x: f32,
+    pub parallax_y: f32,
+    pub visible: bool,
+}
+
+pub struct TileFrame {
+    pub tile: u16,
+    pub duration_ms: u32,
+}
+
+/// Cells with `tile` show the frames in turn, looping.
+pub struct TileAnimation {
+    pub tile: u16,
+    pub frames: &'static [TileFrame],
 }
 
 pub struct StaticMapData {
@@ -17,61 +100,301 @@ pub struct StaticMapData {
     pub tile_width: i32,
     pub tile_height: i32,
     pub tiles: &'static [&'static StaticTexture],
-    pub layers: &'static [u8],
-    pub objects: &'static [&'static [StaticObject]],
+    pub layers: &'static [StaticMapLayer],
+    /// The cells of every layer, one after the other.
+    pub cells: &'static [u8],
+    pub animations: &'static [TileAnimation],
+    /// A byte per cell, non-zero where it is solid. Empty without a
+    /// collision layer.
+    pub collision: &'static [u8],
+    pub objects: &'static [StaticObject],
 }
 
 pub struct Map {
     data: &'static StaticMapData,
+    /// Since the map was loaded.
+    time_us: u64,
+    /// The tile each tile index shows right now.
+    tile_frames: Vec<u16>,
+    /// Enemies and triggers the camera has not reached, the lowest last
+    /// since the camera scrolls up.
+    unreached: Vec<&'static StaticObject>,
+    /// Enemies waiting for their `spawn_delay`, with the time they spawn.
+    waiting: Vec<(u64, &'static StaticObject)>,
+    /// Reached triggers, in the order they were reached.
+    triggers: VecDeque<&'static StaticObject>,
+}
+
+fn bullet_pattern(object: &StaticObject) -> &'static BulletPattern {
+    let name = object.string("bullets").unwrap_or("single");
+
+    bullet_pattern::pattern_by_name(name).unwrap_or_else(|| {
+        n64::warn!("Enemy {} has unknown `bullets` {}", object.name, name);
+        &bullet_pattern::PATTERNS[0]
+    })
 }
 
 impl Map {
     pub fn load(data: &'static StaticMapData) -> Self {
-        Self { data }
+        let mut unreached: Vec<&'static StaticObject> = data
+            .objects
+            .iter()
+            .filter(|object| !matches!(object.shape, StaticObjectShape::Path(_)))
+            .collect();
+
+        unreached.sort_by(|a, b| {
+            a.bottom()
+                .partial_cmp(&b.bottom())
+                .unwrap_or(Ordering::Equal)
+        });
+
+        Self {
+            data,
+            time_us: 0,
+            tile_frames: (0..=data.tiles.len() as u16).collect(),
+            unreached,
+            waiting: Vec::new(),
+            triggers: VecDeque::new(),
+        }
+    }
+
+    /// Advances tile animations and reaches the objects that scrolled into
+    /// view.
+    pub fn update(&mut self, dt: f32, camera: &Camera, video_mode: &VideoMode) {
+        self.time_us += (dt * 1_000_000.0).round() as u64;
+
+        let camera_top = camera.pos.y() * video_mode.height() as f32;
+
+        while let Some(&object) = self.unreached.last() {
+            if object.bottom() < camera_top {
+                break;
+            }
+
+            match object.shape {
+                StaticObjectShape::Sprite(_) => {
+                    let delay = object.number("spawn_delay").unwrap_or(0.0);
+                    let delay_us = (delay * 1_000_000.0) as u64;
+                    self.waiting.push((self.time_us + delay_us, object));
+                }
+                StaticObjectShape::Region => self.triggers.push_back(object),
+                StaticObjectShape::Path(_) => {}
+            }
+
+            self.unreached.pop();
+        }
+
+        let time_ms = self.time_us / 1000;
+
+        for animation in self.data.animations {
+            let length_ms: u32 = animation.frames.iter().map(|f| f.duration_ms).sum();
+
+            if length_ms == 0 {
+                continue;
+            }
+
+            let mut frame_time_ms = (time_ms % length_ms as u64) as u32;
+
+            for frame in animation.frames {
+                if frame_time_ms < frame.duration_ms {
+                    self.tile_frames[animation.tile as usize] = frame.tile;
+                    break;
+                }
+
+                frame_time_ms -= frame.duration_ms;
+            }
+        }
+    }
+
+    /// Whether `pos`, in screen units like the camera, is in a solid cell.
+    pub fn is_solid(&self, pos: Vec2, video_mode: &VideoMode) -> bool {
+        let collision = hot_reload::current(self.data.collision);
+
+        let x = (pos.x() * video_mode.width() as f32) as i32 / self.data.tile_width;
+        let y = (pos.y() * video_mode.height() as f32) as i32 / self.data.tile_height;
+
+        if pos.x() < 0.0
+            || pos.y() < 0.0
+            || x >= self.data.width_in_tiles
+            || y >= self.data.height_in_tiles
+        {
+            return false;
+        }
+
+        collision
+            .get((x + y * self.data.width_in_tiles) as usize)
+            .copied()
+            .unwrap_or(0)
+            != 0
     }
 
+    /// Spawns the reached enemies whose delay is over, set up by their
+    /// `health` and `score` properties. They fire the bullet pattern named
+    /// by `bullets`, `fire_rate` times as fast, with jitter from `seed`.
+    /// With `formation` more than one, that many spawn `formation_x` and
+    /// `formation_y` pixels and `formation_delay` seconds apart.
     pub fn spawn_enemies(
-        &self,
+        &mut self,
         world: &mut World,
         enemy_system: &mut EnemySystem,
         video_mode: &VideoMode,
     ) {
-        for objects in self.data.objects {
-            for object in *objects {
+        let mut i = 0;
+
+        while i < self.waiting.len() {
+            let (spawn_time, object) = self.waiting[i];
+
+            if spawn_time > self.time_us {
+                i += 1;
+                continue;
+            }
+
+            self.waiting.swap_remove(i);
+
+            let texture = match object.shape {
+                StaticObjectShape::Sprite(texture) => texture,
+                _ => continue,
+            };
+
+            let pattern = self.pattern(object);
+            let bullets = bullet_pattern(object);
+            let fire_rate = object.number("fire_rate").unwrap_or(1.0);
+            let seed = match object.property("seed") {
+                Some(StaticProperty::Int(seed)) => *seed as u32,
+                seed => {
+                    if seed.is_some() {
+                        n64::warn!("Enemy {} has a `seed` that is not an int", object.name);
+                    }
+
+                    object.x.to_bits() ^ object.y.to_bits().rotate_left(16)
+                }
+            };
+
+            let formation_step = Vec2::new(
+                object.number("formation_x").unwrap_or(0.0),
+                object.number("formation_y").unwrap_or(0.0),
+            );
+            let formation_delay = object.number("formation_delay").unwrap_or(0.0);
+
+            for member in 0..object.number("formation").unwrap_or(1.0) as i32 {
                 enemy_system.spawn_enemy(
                     world,
                     Vec2::new(
                         object.x / video_mode.width() as f32,
                         object.y / video_mode.height() as f32,
                     ),
-                    object.texture.as_texture(),
+                    texture.as_texture(),
+                    EnemySettings {
+                        health: object.number("health").unwrap_or(100.0) as i32,
+                        emitter: Emitter::new(bullets, fire_rate, seed.wrapping_add(member as u32)),
+                        score: object.number("score").unwrap_or(1000.0) as i32,
+                        movement: Movement {
+                            pattern,
+                            formation_offset: formation_step * member as f32,
+                            delay: formation_delay * member as f32,
+                        },
+                    },
                 );
             }
         }
     }
 
-    pub fn render(&self, cb: &mut CommandBuffer, video_mode: VideoMode, camera: &Camera) {
-        let tiles_in_layer = (self.data.width_in_tiles * self.data.height_in_tiles) as usize;
+    /// The movement pattern named by the `ai` property of `object`, with
+    /// its settings from the other properties.
+    fn pattern(&self, object: &StaticObject) -> Pattern {
+        let number = |name: &str, default: f32| object.number(name).unwrap_or(default);
+        // None of these make sense at zero or less, a zero period gives NaN
+        // positions.
+        let positive = |name: &str, default: f32| match object.number(name) {
+            Some(value) if value > 0.0 => value,
+            Some(value) => {
+                n64::warn!(
+                    "Enemy {} has `{}` {}, it must be more than 0",
+                    object.name,
+                    name,
+                    value
+                );
+                default
+            }
+            None => default,
+        };
+        let velocity = Vec2::new(number("velocity_x", 0.0), number("velocity_y", 60.0));
+        let circle = Pattern::Circle {
+            radius: positive("radius", 32.0),
+            period: positive("period", 4.0),
+        };
 
-        let tile_scale: Vec2 = Vec2::new(32.0, 32.0);
+        match object.string("ai").unwrap_or("circle") {
+            "linear" => Pattern::Linear { velocity },
+            "sine" => Pattern::Sine {
+                velocity,
+                amplitude: positive("amplitude", 32.0),
+                period: positive("period", 2.0),
+            },
+            "circle" => circle,
+            "path" => match object.string("path").and_then(|name| self.path(name)) {
+                Some(points) => Pattern::Spline {
+                    points,
+                    speed: positive("speed", 60.0),
+                },
+                None => {
+                    n64::warn!("Enemy {} has no known `path`", object.name);
+                    circle
+                }
+            },
+            "dive" => Pattern::Dive {
+                delay: number("dive_delay", 1.0).max(0.0),
+                speed: positive("speed", 120.0),
+            },
+            ai => {
+                n64::warn!("Enemy {} has unknown `ai` {}", object.name, ai);
+                circle
+            }
+        }
+    }
 
-        let camera_pixel_pos = Vec2::new(
-            camera.pos.0 * video_mode.width() as f32,
-            camera.pos.1 * video_mode.height() as f32,
-        );
+    fn path(&self, name: &str) -> Option<&'static [Vec2]> {
+        self.data
+            .objects
+            .iter()
+            .find_map(|object| match object.shape {
+                StaticObjectShape::Path(points) if object.name == name => Some(points),
+                _ => None,
+            })
+    }
 
-        let camera_tile = Vec2::new(
-            camera_pixel_pos.0 / self.data.tile_width as f32,
-            camera_pixel_pos.1 / self.data.tile_height as f32,
-        );
+    /// The next trigger the camera reached, its type says what it does.
+    pub fn next_trigger(&mut self) -> Option<&'static StaticObject> {
+        self.triggers.pop_front()
+    }
+
+    pub fn render(&self, cb: &mut CommandBuffer, video_mode: VideoMode, camera: &Camera) {
+        let tiles_in_layer = (self.data.width_in_tiles * self.data.height_in_tiles) as usize;
+
+        let tile_size = Vec2::new(self.data.tile_width as f32, self.data.tile_height as f32);
 
         let tiles_on_screen_x = (video_mode.width() / self.data.tile_width) + 1;
         let tiles_on_screen_y = (video_mode.height() / self.data.tile_height) + 2;
 
-        let first_tile_x = camera_tile.x() as i32;
-        let first_tile_y = camera_tile.y() as i32;
+        let cells = hot_reload::current(self.data.cells);
+
+        for (layer, cells) in self
+            .data
+            .layers
+            .iter()
+            .zip(cells.chunks_exact(2 * tiles_in_layer))
+        {
+            if !layer.visible {
+                continue;
+            }
+
+            let camera_pixel_pos = Vec2::new(
+                camera.pos.0 * video_mode.width() as f32 * layer.parallax_x,
+                camera.pos.1 * video_mode.height() as f32 * layer.parallax_y,
+            );
+
+            let first_tile_x = (camera_pixel_pos.x() / tile_size.x()) as i32;
+            let first_tile_y = (camera_pixel_pos.y() / tile_size.y()) as i32;
 
-        for layer in self.data.layers.chunks_exact(tiles_in_layer) {
             for y in first_tile_y..(first_tile_y + tiles_on_screen_y) {
                 if y < 0 || y >= self.data.height_in_tiles {
                     continue;
@@ -82,12 +405,14 @@ impl Map {
                         continue;
                     }
 
-                    let index = x + y * self.data.width_in_tiles;
-                    let tile = layer[index as usize];
-
-                    if tile == 0 {
-                        continue;
-                    }
+                    let index = 2 * (x + y * self.data.width_in_tiles) as usize;
+                    let cell = u16::from_be_bytes([cells[index], cells[index + 1]]);
+                    // Reloaded cells can still point past the tiles of the
+                    // build, those are not drawn.
+                    let tile = match self.tile_frames.get((cell & TILE_INDEX_MASK) as usize) {
+                        Some(0) | None => continue,
+                        Some(tile) => *tile,
+                    };
 
                     let pos = Vec2::new(
                         (x * self.data.tile_width) as f32,
@@ -95,13 +420,18 @@ impl Map {
                     );
 
                     let upper_left = pos;
-                    let lower_right = pos + tile_scale;
+                    let lower_right = pos + tile_size;
 
-                    cb.add_textured_rect(
+                    cb.add_textured_rect_flipped(
                         upper_left - camera_pixel_pos,
                         lower_right - camera_pixel_pos,
                         self.data.tiles[(tile - 1) as usize].as_texture(),
                         None,
+                        TextureFlip {
+                            horizontal: cell & TILE_FLIP_HORIZONTAL != 0,
+                            vertical: cell & TILE_FLIP_VERTICAL != 0,
+                            diagonal: cell & TILE_FLIP_DIAGONAL != 0,
+                        },
                     );
                 }
             }
@@ -0,0 +1,224 @@
+//! Enemy movement patterns. They are pure functions of the time since the
+//! enemy spawned, giving its offset from where it spawned in map pixels.
+
+use core::f32::consts::PI;
+use n64_math::Vec2;
+
+#[derive(Copy, Clone)]
+pub enum Pattern {
+    /// Straight along `velocity`, in pixels per second.
+    Linear { velocity: Vec2 },
+    /// Along `velocity`, swaying `amplitude` pixels to the sides and back
+    /// every `period` seconds.
+    Sine {
+        velocity: Vec2,
+        amplitude: f32,
+        period: f32,
+    },
+    /// Round a circle of `radius` pixels every `period` seconds.
+    Circle { radius: f32, period: f32 },
+    /// A Catmull-Rom spline through `points` at `speed` pixels per second,
+    /// moved to start where the enemy spawned. Stops at the last point.
+    Spline { points: &'static [Vec2], speed: f32 },
+    /// Still for `delay` seconds, then straight along the aim at `speed`
+    /// pixels per second.
+    Dive { delay: f32, speed: f32 },
+}
+
+impl Pattern {
+    /// `aim` is where the enemy dives, relative to where it spawned.
+    pub fn offset(&self, t: f32, aim: Vec2) -> Vec2 {
+        match *self {
+            Pattern::Linear { velocity } => velocity * t,
+            Pattern::Sine {
+                velocity,
+                amplitude,
+                period,
+            } => {
+                let side = if velocity.length_squared() > 0.0 {
+                    Vec2::new(-velocity.y(), velocity.x()).normalize()
+                } else {
+                    Vec2::unit_x()
+                };
+
+                velocity * t + side * amplitude * libm::sinf(2.0 * PI * t / period)
+            }
+            Pattern::Circle { radius, period } => {
+                let angle = 2.0 * PI * t / period;
+
+                Vec2::new(
+                    radius * libm::cosf(angle) - radius,
+                    radius * libm::sinf(angle),
+                )
+            }
+            Pattern::Spline { points, speed } => match points.first() {
+                Some(first) => spline_at(points, speed * t) - *first,
+                None => Vec2::zero(),
+            },
+            Pattern::Dive { delay, speed } => {
+                if t < delay {
+                    return Vec2::zero();
+                }
+
+                let direction = if aim.length_squared() > 0.0 {
+                    aim.normalize()
+                } else {
+                    Vec2::unit_y()
+                };
+
+                direction * speed * (t - delay)
+            }
+        }
+    }
+
+    /// Whether the aim can still change, since the dive has not started.
+    pub fn aiming(&self, t: f32) -> bool {
+        match *self {
+            Pattern::Dive { delay, .. } => t < delay,
+            _ => false,
+        }
+    }
+}
+
+/// A pattern as flown by one enemy of a formation.
+pub struct Movement {
+    pub pattern: Pattern,
+    /// Where the enemy is in the formation, in pixels.
+    pub formation_offset: Vec2,
+    /// How long the enemy waits before it follows the pattern, so the
+    /// formation can fly it one after the other.
+    pub delay: f32,
+}
+
+impl Movement {
+    pub fn offset(&self, t: f32, aim: Vec2) -> Vec2 {
+        self.formation_offset + self.pattern.offset(f32::max(0.0, t - self.delay), aim)
+    }
+
+    pub fn aiming(&self, t: f32) -> bool {
+        self.pattern.aiming(f32::max(0.0, t - self.delay))
+    }
+}
+
+fn catmull_rom(p0: Vec2, p1: Vec2, p2: Vec2, p3: Vec2, u: f32) -> Vec2 {
+    let u2 = u * u;
+    let u3 = u2 * u;
+
+    0.5 * (2.0 * p1
+        + (p2 - p0) * u
+        + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * u2
+        + (3.0 * p1 - p0 - 3.0 * p2 + p3) * u3)
+}
+
+/// The point `distance` along the straight lines between `points`, on the
+/// spline through them.
+fn spline_at(points: &[Vec2], distance: f32) -> Vec2 {
+    let last = points.len() - 1;
+    let mut left = distance;
+
+    for i in 0..last {
+        let length = (points[i + 1] - points[i]).length();
+
+        if left < length {
+            let p0 = points[i.saturating_sub(1)];
+            let p3 = points[usize::min(i + 2, last)];
+
+            return catmull_rom(p0, points[i], points[i + 1], p3, left / length);
+        }
+
+        left -= length;
+    }
+
+    points[last]
+}
+
+#[cfg(test)]
+fn assert_close(a: Vec2, b: Vec2) {
+    assert!((a - b).length() < 0.001, "{} is not {}", a, b);
+}
+
+#[test]
+fn patterns_start_where_the_enemy_spawned() {
+    static PATH: [Vec2; 3] = [
+        Vec2::new(10.0, 10.0),
+        Vec2::new(20.0, 10.0),
+        Vec2::new(20.0, 30.0),
+    ];
+
+    let patterns = [
+        Pattern::Linear {
+            velocity: Vec2::new(0.0, 60.0),
+        },
+        Pattern::Sine {
+            velocity: Vec2::new(0.0, 60.0),
+            amplitude: 16.0,
+            period: 2.0,
+        },
+        Pattern::Circle {
+            radius: 32.0,
+            period: 4.0,
+        },
+        Pattern::Spline {
+            points: &PATH,
+            speed: 10.0,
+        },
+        Pattern::Dive {
+            delay: 1.0,
+            speed: 100.0,
+        },
+    ];
+
+    for pattern in patterns.iter() {
+        assert_close(pattern.offset(0.0, Vec2::new(0.0, 1.0)), Vec2::zero());
+    }
+}
+
+#[test]
+fn patterns_follow_their_shape() {
+    let sine = Pattern::Sine {
+        velocity: Vec2::new(0.0, 60.0),
+        amplitude: 16.0,
+        period: 2.0,
+    };
+    assert_close(sine.offset(0.5, Vec2::zero()), Vec2::new(-16.0, 30.0));
+    assert_close(sine.offset(1.0, Vec2::zero()), Vec2::new(0.0, 60.0));
+
+    let circle = Pattern::Circle {
+        radius: 32.0,
+        period: 4.0,
+    };
+    assert_close(circle.offset(1.0, Vec2::zero()), Vec2::new(-32.0, 32.0));
+    assert_close(circle.offset(2.0, Vec2::zero()), Vec2::new(-64.0, 0.0));
+    assert_close(circle.offset(4.0, Vec2::zero()), Vec2::zero());
+
+    // The spline goes through the points and stops at the last.
+    static PATH: [Vec2; 3] = [
+        Vec2::new(10.0, 10.0),
+        Vec2::new(20.0, 10.0),
+        Vec2::new(20.0, 30.0),
+    ];
+    let spline = Pattern::Spline {
+        points: &PATH,
+        speed: 10.0,
+    };
+    assert_close(spline.offset(1.0, Vec2::zero()), Vec2::new(10.0, 0.0));
+    assert_close(spline.offset(3.0, Vec2::zero()), Vec2::new(10.0, 20.0));
+    assert_close(spline.offset(10.0, Vec2::zero()), Vec2::new(10.0, 20.0));
+}
+
+#[test]
+fn dives_keep_the_aim_from_when_they_start() {
+    let dive = Movement {
+        pattern: Pattern::Dive {
+            delay: 1.0,
+            speed: 100.0,
+        },
+        formation_offset: Vec2::new(8.0, 0.0),
+        delay: 0.5,
+    };
+
+    assert!(dive.aiming(1.4));
+    assert!(!dive.aiming(1.5));
+    assert_close(dive.offset(1.5, Vec2::new(3.0, 4.0)), Vec2::new(8.0, 0.0));
+    assert_close(dive.offset(2.5, Vec2::new(3.0, 4.0)), Vec2::new(68.0, 80.0));
+}
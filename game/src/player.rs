@@ -1,4 +1,7 @@
 use crate::bullet_system::BulletSystem;
+use crate::components::collider::{
+    ColliderComponent, LAYER_ENEMY_BULLET, LAYER_PICKUP, LAYER_PLAYER,
+};
 use crate::components::health::HealthComponent;
 use crate::components::movable::MovableComponent;
 use crate::components::sprite_drawable::SpriteDrawableComponent;
@@ -12,6 +15,7 @@ use n64_math::Vec2;
 const PLAYTER_START_POS: Vec2 = Vec2::new(0.5, 0.8);
 const SHIP_SPEED: f32 = 0.35;
 const SHIP_SHOOT_DELAY_MS: i32 = 150;
+const SHIP_HEALTH: i32 = 10000;
 pub const SHIP_SIZE: Vec2 = Vec2::new(32.0 / 320.0 as f32, 32.0 / 240.0 as f32);
 
 pub struct Player {
@@ -42,13 +46,36 @@ impl Player {
                 texture: SHIP_2_SMALL.as_texture(),
             },
         );
-        world
-            .health
-            .add(&player.entity, HealthComponent { health: 10000 });
+        world.health.add(
+            &player.entity,
+            HealthComponent {
+                health: SHIP_HEALTH,
+            },
+        );
+        world.collider.add(
+            &player.entity,
+            ColliderComponent {
+                size: SHIP_SIZE,
+                layer: LAYER_PLAYER,
+                mask: LAYER_ENEMY_BULLET | LAYER_PICKUP,
+            },
+        );
 
         player
     }
 
+    /// Back to full health at the start position below `camera_pos`.
+    pub fn respawn(&self, world: &mut World, camera_pos: Vec2) {
+        if let Some(movable) = world.movable.lookup_mut(&self.entity) {
+            movable.pos = camera_pos + PLAYTER_START_POS;
+            movable.speed = Vec2::zero();
+        }
+
+        if let Some(health) = world.health.lookup_mut(&self.entity) {
+            health.health = SHIP_HEALTH;
+        }
+    }
+
     pub fn entity(&self) -> &Entity {
         &self.entity
     }
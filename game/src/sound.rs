@@ -1,20 +1,257 @@
+use n64::hot_reload;
+use n64_types::{decode_adpcm_frame, ADPCM_FRAME_BYTES, ADPCM_FRAME_SAMPLES};
 use zerocopy::LayoutVerified;
 
-pub struct StaticSoundData {
-    pub data: &'static [u8],
+/// Loop points in stereo frames. Playback jumps back to `start` when it
+/// reaches `end`, so looping sounds never finish on their own.
+#[derive(Copy, Clone)]
+pub struct SoundLoop {
+    pub start: usize,
+    pub end: usize,
+}
+
+pub enum StaticSoundData {
+    Pcm {
+        data: &'static [u8],
+        looping: Option<SoundLoop>,
+    },
+    Adpcm {
+        data: &'static [u8],
+        codebook: &'static [[i16; 2]],
+        channels: usize,
+        sample_count: usize,
+        looping: Option<SoundLoop>,
+        loop_history: [[i32; 2]; 2],
+    },
 }
 
 impl StaticSoundData {
+    /// The included data, for hot reload.
+    #[cfg(not(target_vendor = "nintendo64"))]
+    pub fn data(&self) -> &'static [u8] {
+        match *self {
+            StaticSoundData::Pcm { data, .. } => data,
+            StaticSoundData::Adpcm { data, .. } => data,
+        }
+    }
+
     pub fn as_sound_data(&self) -> SoundData {
-        let samples = LayoutVerified::<_, [i16]>::new_slice(self.data)
-            .unwrap()
-            .into_slice();
+        match *self {
+            StaticSoundData::Pcm { data, looping } => {
+                let samples = LayoutVerified::<_, [i16]>::new_slice(hot_reload::current(data))
+                    .unwrap()
+                    .into_slice();
+
+                SoundData::Pcm { samples, looping }
+            }
+            StaticSoundData::Adpcm {
+                data,
+                codebook,
+                channels,
+                sample_count,
+                looping,
+                loop_history,
+            } => SoundData::Adpcm {
+                data,
+                codebook,
+                channels,
+                sample_count,
+                looping,
+                loop_history,
+            },
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+pub enum SoundData {
+    Pcm {
+        samples: &'static [i16],
+        looping: Option<SoundLoop>,
+    },
+    Adpcm {
+        data: &'static [u8],
+        codebook: &'static [[i16; 2]],
+        channels: usize,
+        sample_count: usize,
+        looping: Option<SoundLoop>,
+        loop_history: [[i32; 2]; 2],
+    },
+}
 
-        SoundData { samples }
+impl SoundData {
+    pub fn decoder(self) -> SoundDecoder {
+        match self {
+            SoundData::Pcm { samples, looping } => SoundDecoder::Pcm {
+                samples,
+                looping,
+                current_sample: 0,
+            },
+            SoundData::Adpcm {
+                data,
+                codebook,
+                channels,
+                sample_count,
+                looping,
+                loop_history,
+            } => SoundDecoder::Adpcm(AdpcmDecoder {
+                data,
+                codebook,
+                channels,
+                looping,
+                loop_history,
+                samples_left: looping.map(|l| l.end).unwrap_or(sample_count),
+                offset: 0,
+                history: [[0; 2]; 2],
+                frames: [[0; ADPCM_FRAME_SAMPLES]; 2],
+                frame_len: 0,
+                frame_pos: 0,
+            }),
+        }
     }
 }
 
+/// Streams interleaved stereo samples in native endianness out of a `SoundData`.
 #[derive(Copy, Clone)]
-pub struct SoundData {
-    pub samples: &'static [i16],
+pub enum SoundDecoder {
+    Pcm {
+        samples: &'static [i16],
+        looping: Option<SoundLoop>,
+        current_sample: usize,
+    },
+    Adpcm(AdpcmDecoder),
+}
+
+impl SoundDecoder {
+    #[inline]
+    pub fn next_sample(&mut self) -> Option<i16> {
+        match self {
+            SoundDecoder::Pcm {
+                samples,
+                looping,
+                current_sample,
+            } => {
+                if let Some(looping) = looping {
+                    if *current_sample >= 2 * looping.end {
+                        *current_sample = 2 * looping.start;
+                    }
+                }
+
+                let sample = *samples.get(*current_sample)?;
+                *current_sample += 1;
+
+                #[cfg(target_vendor = "nintendo64")]
+                {
+                    Some(sample)
+                }
+
+                #[cfg(not(target_vendor = "nintendo64"))]
+                {
+                    Some(sample.swap_bytes())
+                }
+            }
+            SoundDecoder::Adpcm(decoder) => decoder.next_sample(),
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct AdpcmDecoder {
+    data: &'static [u8],
+    codebook: &'static [[i16; 2]],
+    channels: usize,
+    looping: Option<SoundLoop>,
+    loop_history: [[i32; 2]; 2],
+    samples_left: usize,
+    offset: usize,
+    history: [[i32; 2]; 2],
+    frames: [[i16; ADPCM_FRAME_SAMPLES]; 2],
+    frame_len: usize,
+    frame_pos: usize,
+}
+
+impl AdpcmDecoder {
+    #[inline]
+    fn next_sample(&mut self) -> Option<i16> {
+        if self.frame_pos >= 2 * self.frame_len {
+            self.decode_frames()?;
+        }
+
+        let channel = (self.frame_pos % 2).min(self.channels - 1);
+        let sample = self.frames[channel][self.frame_pos / 2];
+        self.frame_pos += 1;
+
+        Some(sample)
+    }
+
+    fn decode_frames(&mut self) -> Option<()> {
+        let mut skip = 0;
+
+        if self.samples_left == 0 {
+            let looping = self.looping?;
+            let loop_frame = looping.start / ADPCM_FRAME_SAMPLES;
+
+            self.offset = loop_frame * self.channels * ADPCM_FRAME_BYTES;
+            self.history = self.loop_history;
+            self.samples_left = looping.end - loop_frame * ADPCM_FRAME_SAMPLES;
+            skip = looping.start % ADPCM_FRAME_SAMPLES;
+        }
+
+        for channel in 0..self.channels {
+            let frame = self
+                .data
+                .get(self.offset..(self.offset + ADPCM_FRAME_BYTES))?;
+
+            decode_adpcm_frame(
+                frame,
+                self.codebook,
+                &mut self.history[channel],
+                &mut self.frames[channel],
+            );
+
+            self.offset += ADPCM_FRAME_BYTES;
+        }
+
+        self.frame_len = self.samples_left.min(ADPCM_FRAME_SAMPLES);
+        self.frame_pos = 2 * skip;
+        self.samples_left -= self.frame_len;
+
+        Some(())
+    }
+}
+
+#[test]
+fn adpcm_loops_back_into_the_middle_of_a_frame() {
+    use alloc::{boxed::Box, vec::Vec};
+
+    // Without prediction and scale the residuals are the samples, a ramp
+    // from 0 to 47 minus 8 each frame.
+    let mut data = Vec::new();
+
+    for _ in 0..3 {
+        data.push(0);
+        data.extend((0..8).map(|i| ((2 * i) << 4 | (2 * i + 1)) as u8));
+    }
+
+    let data = Box::leak(data.into_boxed_slice());
+    let sound = SoundData::Adpcm {
+        data,
+        codebook: &[[0, 0]],
+        channels: 1,
+        sample_count: 48,
+        looping: Some(SoundLoop { start: 20, end: 40 }),
+        loop_history: [[0; 2]; 2],
+    };
+
+    let mut decoder = sound.decoder();
+    let samples: Vec<i16> = (0..2 * 80).map(|_| decoder.next_sample().unwrap()).collect();
+    let expected = (0..40)
+        .chain(20..40)
+        .chain(20..40)
+        .map(|i| ((i % 16) as i16 ^ 8) - 8);
+
+    for (i, expected) in expected.enumerate() {
+        assert_eq!(samples[2 * i], expected);
+        assert_eq!(samples[2 * i + 1], expected);
+    }
 }
@@ -1,10 +1,9 @@
-use crate::sound::SoundData;
+use crate::sound::{SoundData, SoundDecoder};
 use alloc::vec::Vec;
 
 #[derive(Copy, Clone)]
 struct PlayingSound {
-    sound: SoundData,
-    current_sample: usize,
+    decoder: SoundDecoder,
     done: bool,
 }
 
@@ -21,8 +20,7 @@ impl SoundMixer {
 
     pub fn play_sound(&mut self, sound: SoundData) {
         self.playing_sounds.push(PlayingSound {
-            sound,
-            current_sample: 0,
+            decoder: sound.decoder(),
             done: false,
         });
     }
@@ -32,36 +30,16 @@ impl SoundMixer {
             let mut accumulator: i32 = 0;
 
             for playing_sound in self.playing_sounds.iter_mut() {
-                if playing_sound.current_sample >= playing_sound.sound.samples.len() {
-                    playing_sound.done = true;
+                if let Some(sample) = playing_sound.decoder.next_sample() {
+                    accumulator = accumulator.saturating_add(sample as i32);
                 } else {
-                    #[cfg(target_vendor = "nintendo64")]
-                    {
-                        accumulator = accumulator.saturating_add(
-                            playing_sound.sound.samples[playing_sound.current_sample] as i32,
-                        );
-                    }
-
-                    #[cfg(not(target_vendor = "nintendo64"))]
-                    {
-                        accumulator = accumulator.saturating_add(
-                            playing_sound.sound.samples[playing_sound.current_sample].swap_bytes()
-                                as i32,
-                        );
-                    }
-
-                    playing_sound.current_sample += 1;
+                    playing_sound.done = true;
                 }
             }
 
             *out_sample = accumulator.min(i16::MAX as i32).max(i16::MIN as i32) as i16;
         }
 
-        self.playing_sounds = self
-            .playing_sounds
-            .iter()
-            .copied()
-            .filter(|s| !s.done)
-            .collect();
+        self.playing_sounds.retain(|s| !s.done);
     }
 }
@@ -1,5 +1,5 @@
 use crate::{
-    components::{box_drawable, health, movable, sprite_drawable},
+    components::{box_drawable, collider, health, movable, sprite_drawable},
     entity::EntitySystem,
 };
 
@@ -9,6 +9,7 @@ pub struct World {
     pub box_drawable: box_drawable::System,
     pub sprite_drawable: sprite_drawable::System,
     pub health: health::System,
+    pub collider: collider::System,
 }
 
 impl World {
@@ -19,6 +20,7 @@ impl World {
             box_drawable: box_drawable::System::new(),
             sprite_drawable: sprite_drawable::System::new(),
             health: health::System::new(),
+            collider: collider::System::new(),
         }
     }
 }
@@ -0,0 +1,233 @@
+//! Linear bump arenas for transient allocations. Allocating moves an offset
+//! forward and `reset` frees everything at once, so an arena reset every
+//! frame is a cheap place for data that only lives for that frame.
+
+use alloc::alloc::{alloc, dealloc};
+use core::alloc::Layout;
+use core::cell::Cell;
+use core::marker::PhantomData;
+use core::mem;
+use core::ops::{Deref, DerefMut};
+use core::ptr::{self, NonNull};
+use core::slice;
+
+const ARENA_ALIGN: usize = 16;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ArenaStats {
+    pub name: &'static str,
+    pub capacity: usize,
+    pub used_bytes: usize,
+    /// Most bytes used since the arena was created.
+    pub peak_bytes: usize,
+}
+
+pub struct Arena {
+    name: &'static str,
+    start: NonNull<u8>,
+    capacity: usize,
+    used: Cell<usize>,
+    peak: Cell<usize>,
+    owned: bool,
+}
+
+impl Arena {
+    /// Creates an arena of `capacity` bytes taken from the global allocator,
+    /// returned to it when the arena is dropped.
+    pub fn new(name: &'static str, capacity: usize) -> Option<Arena> {
+        let layout = Layout::from_size_align(capacity.max(1), ARENA_ALIGN).ok()?;
+        let start = NonNull::new(unsafe { alloc(layout) })?;
+
+        Some(Arena {
+            name,
+            start,
+            capacity,
+            used: Cell::new(0),
+            peak: Cell::new(0),
+            owned: true,
+        })
+    }
+
+    /// Creates an arena in memory outside of the heap.
+    ///
+    /// # Safety
+    ///
+    /// `len` bytes at `start` must be valid for the lifetime of the arena
+    /// and not be used for anything else.
+    pub unsafe fn from_raw(name: &'static str, start: NonNull<u8>, len: usize) -> Arena {
+        Arena {
+            name,
+            start,
+            capacity: len,
+            used: Cell::new(0),
+            peak: Cell::new(0),
+            owned: false,
+        }
+    }
+
+    #[inline]
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    #[inline]
+    pub fn stats(&self) -> ArenaStats {
+        ArenaStats {
+            name: self.name,
+            capacity: self.capacity,
+            used_bytes: self.used.get(),
+            peak_bytes: self.peak.get(),
+        }
+    }
+
+    /// Returns `None` when the arena is full.
+    #[inline]
+    pub fn alloc_layout(&self, layout: Layout) -> Option<NonNull<u8>> {
+        let base = self.start.as_ptr() as usize;
+        let offset = (base + self.used.get() + layout.align() - 1) & !(layout.align() - 1);
+        let end = offset.checked_add(layout.size())? - base;
+
+        if end > self.capacity {
+            return None;
+        }
+
+        self.used.set(end);
+        self.peak.set(self.peak.get().max(end));
+
+        NonNull::new(offset as *mut u8)
+    }
+
+    /// A vector of at most `capacity` elements in the arena.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the arena does not have room for `capacity` elements.
+    #[inline]
+    pub fn vec<T>(&self, capacity: usize) -> ArenaVec<'_, T> {
+        let ptr = if mem::size_of::<T>() == 0 || capacity == 0 {
+            NonNull::dangling()
+        } else {
+            Layout::array::<T>(capacity)
+                .ok()
+                .and_then(|layout| self.alloc_layout(layout))
+                .unwrap_or_else(|| panic!("arena {} is out of memory", self.name))
+                .cast()
+        };
+
+        ArenaVec {
+            ptr,
+            len: 0,
+            capacity,
+            _arena: PhantomData,
+        }
+    }
+
+    /// Frees everything allocated in the arena.
+    #[inline]
+    pub fn reset(&mut self) {
+        self.used.set(0);
+    }
+}
+
+impl Drop for Arena {
+    fn drop(&mut self) {
+        if self.owned {
+            unsafe {
+                dealloc(
+                    self.start.as_ptr(),
+                    Layout::from_size_align_unchecked(self.capacity.max(1), ARENA_ALIGN),
+                );
+            }
+        }
+    }
+}
+
+/// A fixed capacity vector living in an `Arena`. Dropping it drops the
+/// elements, the memory is freed when the arena is reset.
+pub struct ArenaVec<'a, T> {
+    ptr: NonNull<T>,
+    len: usize,
+    capacity: usize,
+    _arena: PhantomData<&'a Arena>,
+}
+
+impl<'a, T> ArenaVec<'a, T> {
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// # Panics
+    ///
+    /// Panics if the vector is full.
+    #[inline]
+    pub fn push(&mut self, value: T) {
+        assert!(self.len < self.capacity, "arena vec is full");
+
+        unsafe {
+            ptr::write(self.ptr.as_ptr().add(self.len), value);
+        }
+
+        self.len += 1;
+    }
+
+    #[inline]
+    pub fn pop(&mut self) -> Option<T> {
+        if self.len == 0 {
+            None
+        } else {
+            self.len -= 1;
+            unsafe { Some(ptr::read(self.ptr.as_ptr().add(self.len))) }
+        }
+    }
+}
+
+impl<'a, T> Deref for ArenaVec<'a, T> {
+    type Target = [T];
+
+    #[inline]
+    fn deref(&self) -> &[T] {
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl<'a, T> DerefMut for ArenaVec<'a, T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut [T] {
+        unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl<'a, T> Drop for ArenaVec<'a, T> {
+    fn drop(&mut self) {
+        unsafe {
+            ptr::drop_in_place(&mut **self as *mut [T]);
+        }
+    }
+}
+
+#[test]
+fn arena_allocations_are_aligned_and_reset() {
+    let mut arena = Arena::new("test", 256).unwrap();
+
+    {
+        let mut list = arena.vec::<u32>(4);
+        list.push(1);
+        list.push(2);
+        assert_eq!(&list[..], &[1, 2]);
+
+        let wide = arena.alloc_layout(Layout::from_size_align(8, 64).unwrap());
+        assert_eq!(wide.unwrap().as_ptr().align_offset(64), 0);
+        assert!(arena
+            .alloc_layout(Layout::from_size_align(256, 1).unwrap())
+            .is_none());
+    }
+
+    let used = arena.stats().used_bytes;
+    assert!(used > 16);
+
+    arena.reset();
+
+    assert_eq!(arena.stats().used_bytes, 0);
+    assert_eq!(arena.stats().peak_bytes, used);
+}
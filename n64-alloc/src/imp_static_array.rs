@@ -4,22 +4,75 @@ use core::ptr::NonNull;
 use memory_units::{Bytes, Pages};
 use spin::Mutex;
 
+/// Size of the heap used when `init_heap` was not called before the first
+/// allocation, as on PC and in tests.
+#[cfg(not(target_vendor = "nintendo64"))]
 pub const SCRATCH_LEN_BYTES: usize = 3 * 1024 * 1024 - 512;
 
+#[cfg(not(target_vendor = "nintendo64"))]
 #[repr(align(4096))]
 struct ScratchHeap([u8; SCRATCH_LEN_BYTES]);
 
+#[cfg(not(target_vendor = "nintendo64"))]
 static mut SCRATCH_HEAP: ScratchHeap = ScratchHeap([0; SCRATCH_LEN_BYTES]);
-pub static OFFSET: Mutex<usize> = Mutex::new(0);
+
+/// The memory pages are handed out from, front to back. Pages are never
+/// given back.
+pub(crate) struct Heap {
+    start: usize,
+    len: usize,
+    offset: usize,
+}
+
+impl Heap {
+    #[inline]
+    unsafe fn get_or_init(&mut self) -> &mut Self {
+        #[cfg(not(target_vendor = "nintendo64"))]
+        {
+            if self.len == 0 {
+                self.start = core::ptr::addr_of_mut!(SCRATCH_HEAP.0) as usize;
+                self.len = SCRATCH_LEN_BYTES;
+            }
+        }
+
+        self
+    }
+
+    #[inline]
+    pub(crate) fn len(&mut self) -> usize {
+        unsafe { self.get_or_init().len }
+    }
+
+    #[inline]
+    pub(crate) fn committed(&self) -> usize {
+        self.offset
+    }
+}
+
+pub(crate) static HEAP: Mutex<Heap> = Mutex::new(Heap {
+    start: 0,
+    len: 0,
+    offset: 0,
+});
+
+pub(crate) unsafe fn init(start: *mut u8, len: usize) {
+    let mut heap = HEAP.lock();
+    assert_eq!(heap.offset, 0, "the heap is already in use");
+
+    let aligned_start = (start as usize + 15) & !15;
+    heap.start = aligned_start;
+    heap.len = len.saturating_sub(aligned_start - start as usize);
+}
 
 pub(crate) unsafe fn alloc_pages(pages: Pages) -> Result<NonNull<u8>, AllocErr> {
     let bytes: Bytes = pages.into();
-    let mut offset = OFFSET.lock();
-    let end = bytes.0.checked_add(*offset).ok_or(AllocErr)?;
-    if end < SCRATCH_LEN_BYTES {
-        let ptr = SCRATCH_HEAP.0[*offset..end].as_mut_ptr() as *mut u8;
-        *offset = end;
-        NonNull::new(ptr).ok_or_else(|| AllocErr)
+    let mut heap = HEAP.lock();
+    let heap = heap.get_or_init();
+    let end = bytes.0.checked_add(heap.offset).ok_or(AllocErr)?;
+    if end <= heap.len {
+        let ptr = (heap.start + heap.offset) as *mut u8;
+        heap.offset = end;
+        NonNull::new(ptr).ok_or(AllocErr)
     } else {
         Err(AllocErr)
     }
@@ -5,10 +5,14 @@
 
 extern crate alloc;
 
+mod arena;
 mod const_init;
 mod imp_static_array;
 mod neighbors;
 mod size_classes;
+mod stats;
+#[cfg(feature = "tags")]
+mod tags;
 
 use const_init::ConstInit;
 use core::alloc::{GlobalAlloc, Layout};
@@ -20,6 +24,14 @@ use core::sync::atomic::{AtomicI32, Ordering};
 use imp_static_array as imp;
 use memory_units::{size_of, ByteSize, Bytes, Pages, RoundUpTo, Words};
 use neighbors::Neighbors;
+use stats::{free_list_stats, size_class_index, CellCounters};
+
+pub use arena::{Arena, ArenaStats, ArenaVec};
+#[cfg(not(target_vendor = "nintendo64"))]
+pub use imp_static_array::SCRATCH_LEN_BYTES;
+pub use stats::{HeapStats, SizeClassStats, NUM_SIZE_CLASSES};
+#[cfg(feature = "tags")]
+pub use tags::{set_tag, tag, tag_scope, tag_stats, TagScope, TagStats, MAX_TAGS, UNTAGGED};
 
 pub(crate) struct AllocErr;
 
@@ -414,7 +426,7 @@ where
 {
     // The previous cell in the free list (not to be confused with the current
     // cell's previously _adjacent_ cell).
-    let previous_free = head;
+    let mut previous_free = head;
 
     loop {
         let current_free = previous_free.get();
@@ -456,7 +468,7 @@ where
             return Ok(result);
         }
 
-        previous_free.set(&*(*current_free.get()).next_free_raw.get());
+        previous_free = &(*current_free.get()).next_free_raw;
     }
 }
 
@@ -500,6 +512,7 @@ unsafe fn alloc_with_refill<'a, 'b>(
 pub struct N64Alloc<'a> {
     head: imp::Exclusive<*const FreeCell<'a>>,
     size_classes: size_classes::SizeClasses<'a>,
+    counters: imp::Exclusive<CellCounters>,
 }
 
 unsafe impl<'a> Sync for N64Alloc<'a> {}
@@ -508,6 +521,7 @@ impl<'a> ConstInit for N64Alloc<'a> {
     const INIT: N64Alloc<'a> = N64Alloc {
         head: imp::Exclusive::INIT,
         size_classes: size_classes::SizeClasses::INIT,
+        counters: imp::Exclusive::INIT,
     };
 }
 
@@ -560,9 +574,15 @@ impl<'a> N64Alloc<'a> {
 
         let word_size: Words = checked_round_up_to(size).ok_or(AllocErr)?;
 
-        self.with_free_list_and_policy_for_size(word_size, align, |head, policy| {
+        let result = self.with_free_list_and_policy_for_size(word_size, align, |head, policy| {
             alloc_with_refill(word_size, align, head, policy)
-        })
+        });
+
+        if result.is_ok() {
+            self.count_cell(word_size, align, true);
+        }
+
+        result
     }
 
     unsafe fn dealloc_impl(&self, ptr: NonNull<u8>, layout: Layout) {
@@ -574,6 +594,8 @@ impl<'a> N64Alloc<'a> {
         let size: Words = size.round_up_to();
         let align = Bytes(layout.align());
 
+        self.count_cell(size, align, false);
+
         self.with_free_list_and_policy_for_size(size, align, |head, policy| {
             let cell = (ptr.as_ptr() as *mut CellHeader<'a> as *const CellHeader<'a>).offset(-1);
             let cell = &*cell;
@@ -648,29 +670,199 @@ impl<'a> N64Alloc<'a> {
             let _head = free.insert_into_free_list(head, policy);
         });
     }
+
+    unsafe fn count_cell(&self, size: Words, align: Bytes, allocated: bool) {
+        self.counters
+            .with_exclusive_access(|counters| match size_class_index(size, align) {
+                Some(index) if allocated => counters.size_classes[index] += 1,
+                Some(index) => counters.size_classes[index] -= 1,
+                None if allocated => {
+                    counters.large += 1;
+                    counters.large_bytes += Bytes::from(size).0;
+                }
+                None => {
+                    counters.large -= 1;
+                    counters.large_bytes -= Bytes::from(size).0;
+                }
+            });
+    }
+
+    /// Walks the free lists and returns cell counts, sizes and how
+    /// fragmented the heap is. Takes every free list lock in turn, so it
+    /// should not be called every frame.
+    pub fn stats(&self) -> HeapStats {
+        unsafe {
+            let (free_cells, free_bytes, largest_free_block) = self
+                .head
+                .with_exclusive_access(|head| free_list_stats(*head));
+
+            let mut size_classes = [SizeClassStats::default(); NUM_SIZE_CLASSES];
+            let mut size_class_free_bytes = 0;
+
+            for (class, head) in size_classes.iter_mut().zip(self.size_classes.0.iter()) {
+                let (cells, bytes, _) = head.with_exclusive_access(|head| free_list_stats(*head));
+                class.free_cells = cells;
+                size_class_free_bytes += bytes;
+            }
+
+            let (used_cells, used_bytes) = self.counters.with_exclusive_access(|counters| {
+                for (class, used_cells) in size_classes.iter_mut().zip(counters.size_classes.iter())
+                {
+                    class.used_cells = *used_cells;
+                }
+
+                (counters.large, counters.large_bytes)
+            });
+
+            let (heap_bytes, committed_bytes) = {
+                let mut heap = imp::HEAP.lock();
+                (heap.len(), heap.committed())
+            };
+
+            HeapStats {
+                heap_bytes,
+                committed_bytes,
+                used_cells,
+                used_bytes,
+                free_cells,
+                free_bytes,
+                largest_free_block,
+                size_classes,
+                size_class_free_bytes,
+            }
+        }
+    }
 }
 
-pub static BYTES_LEFT: AtomicI32 = AtomicI32::new(imp::SCRATCH_LEN_BYTES as i32);
 pub static BYTES_USED: AtomicI32 = AtomicI32::new(0);
-pub use imp::OFFSET as PAGE_OFFSET;
+
+/// Gives the heap `len` bytes of memory at `start` to hand out. On N64 this
+/// has to be called before the first allocation, elsewhere a static scratch
+/// heap is used when it is not.
+///
+/// # Safety
+///
+/// The memory must not be used for anything else for the rest of the
+/// program.
+#[inline]
+pub unsafe fn init_heap(start: *mut u8, len: usize) {
+    imp::init(start, len);
+}
 
 unsafe impl GlobalAlloc for N64Alloc<'static> {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        BYTES_LEFT.fetch_sub(layout.size() as i32, Ordering::SeqCst);
         BYTES_USED.fetch_add(layout.size() as i32, Ordering::SeqCst);
 
-        match self.alloc_impl(layout) {
+        #[cfg(feature = "tags")]
+        let result = tags::alloc(layout, |layout| self.alloc_impl(layout));
+        #[cfg(not(feature = "tags"))]
+        let result = self.alloc_impl(layout);
+
+        match result {
             Ok(ptr) => ptr.as_ptr(),
             Err(AllocErr) => ptr::null_mut(),
         }
     }
 
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
-        BYTES_LEFT.fetch_add(layout.size() as i32, Ordering::SeqCst);
         BYTES_USED.fetch_sub(layout.size() as i32, Ordering::SeqCst);
 
         if let Some(ptr) = NonNull::new(ptr) {
+            #[cfg(feature = "tags")]
+            tags::dealloc(ptr, layout, |ptr, layout| self.dealloc_impl(ptr, layout));
+            #[cfg(not(feature = "tags"))]
             self.dealloc_impl(ptr, layout);
         }
     }
 }
+
+#[cfg(test)]
+fn layout(size: usize, align: usize) -> Layout {
+    Layout::from_size_align(size, align).unwrap()
+}
+
+// The tests go around `GlobalAlloc` so the `tags` feature does not change
+// the sizes they see.
+#[cfg(test)]
+impl<'a> N64Alloc<'a> {
+    unsafe fn test_alloc(&self, layout: Layout) -> *mut u8 {
+        self.alloc_impl(layout).ok().unwrap().as_ptr()
+    }
+
+    unsafe fn test_dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.dealloc_impl(NonNull::new(ptr).unwrap(), layout);
+    }
+}
+
+#[test]
+fn stats_count_large_cells_and_fragmentation() {
+    static ALLOC: N64Alloc = N64Alloc::INIT;
+    let block = layout(4096, 8);
+
+    unsafe {
+        let blocks = [
+            ALLOC.test_alloc(block),
+            ALLOC.test_alloc(block),
+            ALLOC.test_alloc(block),
+            ALLOC.test_alloc(block),
+        ];
+        let stats = ALLOC.stats();
+
+        assert_eq!(stats.used_cells, 4);
+        assert_eq!(stats.used_bytes, 4 * 4096);
+        assert_eq!(stats.free_cells, 1);
+        assert_eq!(stats.largest_free_block, stats.free_bytes);
+        assert_eq!(stats.fragmentation(), 0.0);
+        assert!(stats.committed_bytes >= 4 * 4096);
+
+        // Freeing a block between two allocated ones leaves a hole.
+        ALLOC.test_dealloc(blocks[1], block);
+        let stats = ALLOC.stats();
+
+        assert_eq!(stats.used_cells, 3);
+        assert_eq!(stats.free_cells, 2);
+        assert!(stats.largest_free_block < stats.free_bytes);
+        assert!(stats.fragmentation() > 0.0 && stats.fragmentation() < 1.0);
+
+        for &ptr in &[blocks[0], blocks[2], blocks[3]] {
+            ALLOC.test_dealloc(ptr, block);
+        }
+
+        let stats = ALLOC.stats();
+
+        assert_eq!(stats.used_cells, 0);
+        assert_eq!(stats.used_bytes, 0);
+    }
+}
+
+#[test]
+fn stats_count_size_class_cells() {
+    static ALLOC: N64Alloc = N64Alloc::INIT;
+    let small = layout(
+        3 * core::mem::size_of::<usize>(),
+        core::mem::size_of::<usize>(),
+    );
+    let mut ptrs = [ptr::null_mut(); 10];
+
+    unsafe {
+        for ptr in ptrs.iter_mut() {
+            *ptr = ALLOC.test_alloc(small);
+        }
+
+        let before = ALLOC.stats();
+        assert_eq!(before.size_classes[2].used_cells, 10);
+        assert_eq!(before.size_class_used_cells(), 10);
+        assert_eq!(before.used_cells, 0);
+
+        for &ptr in &ptrs[..4] {
+            ALLOC.test_dealloc(ptr, small);
+        }
+
+        let after = ALLOC.stats();
+        assert_eq!(after.size_classes[2].used_cells, 6);
+        assert_eq!(
+            after.size_classes[2].free_cells,
+            before.size_classes[2].free_cells + 4
+        );
+    }
+}
@@ -0,0 +1,110 @@
+use crate::const_init::ConstInit;
+use crate::size_classes::SizeClasses;
+use crate::FreeCell;
+use memory_units::{size_of, Bytes, Words};
+
+pub const NUM_SIZE_CLASSES: usize = SizeClasses::NUM_SIZE_CLASSES;
+
+/// Live cell counts, updated on every alloc and dealloc.
+pub(crate) struct CellCounters {
+    pub(crate) size_classes: [u32; NUM_SIZE_CLASSES],
+    pub(crate) large: u32,
+    pub(crate) large_bytes: usize,
+}
+
+impl ConstInit for CellCounters {
+    const INIT: CellCounters = CellCounters {
+        size_classes: [0; NUM_SIZE_CLASSES],
+        large: 0,
+        large_bytes: 0,
+    };
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SizeClassStats {
+    pub used_cells: u32,
+    pub free_cells: u32,
+}
+
+/// A snapshot of the heap, see `N64Alloc::stats`.
+#[derive(Clone, Debug)]
+pub struct HeapStats {
+    /// Size of the memory the allocator gets pages from.
+    pub heap_bytes: usize,
+    /// Bytes of the heap handed out as pages so far.
+    pub committed_bytes: usize,
+    /// Allocated cells outside the size classes and their size in bytes.
+    pub used_cells: u32,
+    pub used_bytes: usize,
+    /// Cells in the main free list, which large allocations and new size
+    /// class cells are carved out of.
+    pub free_cells: u32,
+    pub free_bytes: usize,
+    pub largest_free_block: usize,
+    /// Indexed by size in words minus one.
+    pub size_classes: [SizeClassStats; NUM_SIZE_CLASSES],
+    /// Bytes sitting in size class free lists, only usable for that size.
+    pub size_class_free_bytes: usize,
+}
+
+impl HeapStats {
+    /// How much of the free memory can not be used for one allocation,
+    /// 0 when all free memory is one block and approaching 1 as it is split
+    /// into many small blocks.
+    #[inline]
+    pub fn fragmentation(&self) -> f32 {
+        if self.free_bytes == 0 {
+            0.0
+        } else {
+            1.0 - self.largest_free_block as f32 / self.free_bytes as f32
+        }
+    }
+
+    #[inline]
+    pub fn size_class_used_cells(&self) -> u32 {
+        self.size_classes.iter().map(|class| class.used_cells).sum()
+    }
+
+    #[inline]
+    pub fn size_class_free_cells(&self) -> u32 {
+        self.size_classes.iter().map(|class| class.free_cells).sum()
+    }
+}
+
+/// Free cells and bytes in a free list, and the largest block. Walks without
+/// merging anything, but counts a cell waiting to be merged with the next
+/// cell in the list as part of that cell.
+pub(crate) unsafe fn free_list_stats<'a>(head: *const FreeCell<'a>) -> (u32, usize, usize) {
+    let mut cells = 0;
+    let mut bytes = 0;
+    let mut largest = 0;
+    let mut pending = 0;
+    let mut current = head;
+
+    while !current.is_null() {
+        let cell = &*current;
+        let size = cell.header.size().0;
+
+        if cell.next_free_can_merge() {
+            pending += size + size_of::<crate::CellHeader>().0;
+        } else {
+            cells += 1;
+            largest = largest.max(size + pending);
+            pending = 0;
+        }
+
+        bytes += size;
+        current = cell.next_free();
+    }
+
+    (cells, bytes, largest)
+}
+
+#[inline]
+pub(crate) fn size_class_index(size: Words, align: Bytes) -> Option<usize> {
+    if align <= size_of::<usize>() && size.0 >= 1 && size.0 <= NUM_SIZE_CLASSES {
+        Some(size.0 - 1)
+    } else {
+        None
+    }
+}
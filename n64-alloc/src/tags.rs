@@ -0,0 +1,149 @@
+//! Attributes live bytes to tags, set around code whose allocations should
+//! be tracked together. Each allocation gets a hidden word in front of it
+//! holding its tag, so enabling the `tags` feature costs memory.
+
+use crate::AllocErr;
+use core::alloc::Layout;
+use core::cmp;
+use core::ptr::NonNull;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::Mutex;
+
+pub const MAX_TAGS: usize = 16;
+pub const UNTAGGED: usize = 0;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct TagStats {
+    pub bytes: usize,
+    pub peak_bytes: usize,
+    pub allocations: u32,
+}
+
+impl TagStats {
+    const ZERO: TagStats = TagStats {
+        bytes: 0,
+        peak_bytes: 0,
+        allocations: 0,
+    };
+}
+
+static CURRENT_TAG: AtomicUsize = AtomicUsize::new(UNTAGGED);
+static TAG_STATS: Mutex<[TagStats; MAX_TAGS]> = Mutex::new([TagStats::ZERO; MAX_TAGS]);
+
+/// Sets the tag for following allocations and returns the previous one.
+#[inline]
+pub fn set_tag(tag: usize) -> usize {
+    assert!(tag < MAX_TAGS);
+    CURRENT_TAG.swap(tag, Ordering::SeqCst)
+}
+
+#[inline]
+pub fn tag() -> usize {
+    CURRENT_TAG.load(Ordering::SeqCst)
+}
+
+/// Restores the previous tag when dropped.
+pub struct TagScope {
+    previous: usize,
+}
+
+impl Drop for TagScope {
+    #[inline]
+    fn drop(&mut self) {
+        set_tag(self.previous);
+    }
+}
+
+/// Tags allocations until the returned scope is dropped.
+#[inline]
+pub fn tag_scope(tag: usize) -> TagScope {
+    TagScope {
+        previous: set_tag(tag),
+    }
+}
+
+/// Live bytes and allocations per tag. Allocations still counted under a
+/// tag after the code using it is done are leaks.
+#[inline]
+pub fn tag_stats() -> [TagStats; MAX_TAGS] {
+    *TAG_STATS.lock()
+}
+
+// Room in front of the allocation for the tag, keeping the data aligned.
+#[inline]
+fn prefix_len(layout: Layout) -> usize {
+    cmp::max(layout.align(), core::mem::size_of::<usize>())
+}
+
+pub(crate) unsafe fn alloc(
+    layout: Layout,
+    alloc: impl FnOnce(Layout) -> Result<NonNull<u8>, AllocErr>,
+) -> Result<NonNull<u8>, AllocErr> {
+    let prefix = prefix_len(layout);
+    let size = layout.size().checked_add(prefix).ok_or(AllocErr)?;
+    let tagged_layout = Layout::from_size_align(size, layout.align()).map_err(|_| AllocErr)?;
+    let tag = tag();
+
+    let ptr = alloc(tagged_layout)?.as_ptr().add(prefix);
+    (ptr as *mut usize).sub(1).write(tag);
+
+    let mut stats = TAG_STATS.lock();
+    let stats = &mut stats[tag];
+    stats.bytes += layout.size();
+    stats.peak_bytes = cmp::max(stats.peak_bytes, stats.bytes);
+    stats.allocations += 1;
+
+    Ok(NonNull::new_unchecked(ptr))
+}
+
+pub(crate) unsafe fn dealloc(
+    ptr: NonNull<u8>,
+    layout: Layout,
+    dealloc: impl FnOnce(NonNull<u8>, Layout),
+) {
+    let prefix = prefix_len(layout);
+    let tag = (ptr.as_ptr() as *const usize).sub(1).read();
+
+    {
+        let mut stats = TAG_STATS.lock();
+        let stats = &mut stats[tag];
+        stats.bytes -= layout.size();
+        stats.allocations -= 1;
+    }
+
+    let tagged_layout = Layout::from_size_align_unchecked(layout.size() + prefix, layout.align());
+    dealloc(
+        NonNull::new_unchecked(ptr.as_ptr().sub(prefix)),
+        tagged_layout,
+    );
+}
+
+#[test]
+fn allocations_remember_their_tag() {
+    use crate::N64Alloc;
+    use core::alloc::GlobalAlloc;
+
+    static ALLOC: N64Alloc = N64Alloc::INIT;
+    let layout = Layout::from_size_align(100, 16).unwrap();
+    let tag_of = |ptr: *mut u8| unsafe { (ptr as *const usize).sub(1).read() };
+
+    unsafe {
+        let scope = tag_scope(3);
+        let tagged = [ALLOC.alloc(layout), ALLOC.alloc(layout)];
+        drop(scope);
+        let untagged = ALLOC.alloc(layout);
+
+        assert_eq!(tag(), UNTAGGED);
+        assert_eq!(tag_of(tagged[0]), 3);
+        assert_eq!(tag_of(tagged[1]), 3);
+        assert_eq!(tag_of(untagged), UNTAGGED);
+        assert!(tagged.iter().all(|ptr| ptr.align_offset(16) == 0));
+        // Other tests may allocate concurrently, so only check what they
+        // can not lower.
+        assert!(tag_stats()[3].peak_bytes >= 200);
+
+        for &ptr in tagged.iter().chain(Some(&untagged)) {
+            ALLOC.dealloc(ptr, layout);
+        }
+    }
+}
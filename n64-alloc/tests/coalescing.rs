@@ -0,0 +1,100 @@
+//! Large cells are carved from the end of a free cell, so each allocation
+//! below sits just before the previous one in memory.
+
+use n64_alloc::N64Alloc;
+use std::alloc::{GlobalAlloc, Layout};
+
+const BLOCK: usize = 4096;
+
+fn block() -> Layout {
+    Layout::from_size_align(BLOCK, 8).unwrap()
+}
+
+#[test]
+fn freed_cells_merge_into_previous_free_neighbor() {
+    static ALLOC: N64Alloc = N64Alloc::INIT;
+
+    unsafe {
+        let blocks: Vec<_> = (0..4).map(|_| ALLOC.alloc(block())).collect();
+
+        for &ptr in blocks.iter().rev() {
+            ALLOC.dealloc(ptr, block());
+        }
+
+        let stats = ALLOC.stats();
+
+        assert_eq!(stats.used_cells, 0);
+        assert_eq!(stats.free_cells, 1);
+        assert_eq!(stats.fragmentation(), 0.0);
+    }
+}
+
+#[test]
+fn freed_cells_merge_with_next_free_neighbor() {
+    static ALLOC: N64Alloc = N64Alloc::INIT;
+
+    unsafe {
+        let blocks: Vec<_> = (0..3).map(|_| ALLOC.alloc(block())).collect();
+
+        // The first block is freed alone, the second one then has a free
+        // next neighbor and is merged with it on the next free list walk.
+        ALLOC.dealloc(blocks[0], block());
+        ALLOC.dealloc(blocks[1], block());
+
+        let stats = ALLOC.stats();
+
+        assert_eq!(stats.used_cells, 1);
+        assert_eq!(stats.free_cells, 2);
+        assert!(stats.largest_free_block >= 2 * BLOCK || stats.free_bytes > 3 * BLOCK);
+
+        let double = Layout::from_size_align(2 * BLOCK, 8).unwrap();
+        let ptr = ALLOC.alloc(double);
+
+        assert_eq!(ptr, blocks[1], "the merged cell was reused");
+
+        ALLOC.dealloc(ptr, double);
+        ALLOC.dealloc(blocks[2], block());
+    }
+}
+
+#[test]
+fn size_class_cells_are_reused() {
+    static ALLOC: N64Alloc = N64Alloc::INIT;
+    let small = Layout::from_size_align(16, 8).unwrap();
+
+    unsafe {
+        let first = ALLOC.alloc(small);
+        ALLOC.dealloc(first, small);
+
+        assert_eq!(ALLOC.alloc(small), first);
+
+        ALLOC.dealloc(first, small);
+    }
+}
+
+#[test]
+fn cells_skipped_by_first_fit_stay_free() {
+    static ALLOC: N64Alloc = N64Alloc::INIT;
+
+    unsafe {
+        let blocks: Vec<_> = (0..4).map(|_| ALLOC.alloc(block())).collect();
+
+        // Two free cells that are too small for the next allocation and sit
+        // in front of the rest of the page in the free list.
+        ALLOC.dealloc(blocks[1], block());
+        ALLOC.dealloc(blocks[3], block());
+
+        let before = ALLOC.stats();
+        let double = Layout::from_size_align(2 * BLOCK, 8).unwrap();
+        let ptr = ALLOC.alloc(double);
+        let after = ALLOC.stats();
+
+        assert_eq!(after.committed_bytes, before.committed_bytes);
+        assert_eq!(after.free_cells, before.free_cells);
+        assert!(after.free_bytes + 2 * BLOCK + 64 >= before.free_bytes);
+
+        ALLOC.dealloc(ptr, double);
+        ALLOC.dealloc(blocks[0], block());
+        ALLOC.dealloc(blocks[2], block());
+    }
+}
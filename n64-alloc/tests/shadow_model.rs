@@ -0,0 +1,227 @@
+//! Runs random alloc, realloc and dealloc sequences against the allocator
+//! on the host and checks every result against a model of what should be
+//! live.
+
+use n64_alloc::{HeapStats, N64Alloc, NUM_SIZE_CLASSES};
+use proptest::{collection::vec, prelude::*};
+use std::{
+    alloc::{GlobalAlloc, Layout},
+    collections::BTreeMap,
+    mem::size_of,
+};
+
+static ALLOC: N64Alloc = N64Alloc::INIT;
+
+#[derive(Clone, Debug)]
+enum Op {
+    Alloc { size: usize, align: usize },
+    Dealloc { index: usize },
+    Realloc { index: usize, size: usize },
+}
+
+// Every size class takes a cell of at least size squared words from the
+// heap the first time it is used, so medium sizes would run the 3 MB heap
+// out of memory long before anything interesting happens.
+fn size() -> impl Strategy<Value = usize> {
+    prop_oneof![
+        4 => 1..128usize,
+        2 => 2049..16384usize,
+    ]
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        4 => (size(), 0..10u32).prop_map(|(size, shift)| Op::Alloc {
+            size,
+            align: 1 << shift,
+        }),
+        3 => any::<usize>().prop_map(|index| Op::Dealloc { index }),
+        1 => (any::<usize>(), size()).prop_map(|(index, size)| Op::Realloc { index, size }),
+    ]
+}
+
+struct Allocation {
+    layout: Layout,
+    fill: u8,
+}
+
+/// What should be live, keyed by address.
+struct Shadow {
+    live: BTreeMap<usize, Allocation>,
+    next_fill: u8,
+    // Stats from before the sequence started, a failed earlier case can
+    // leave allocations behind.
+    baseline: HeapStats,
+}
+
+impl Shadow {
+    fn new() -> Self {
+        Self {
+            live: BTreeMap::new(),
+            next_fill: 0,
+            baseline: ALLOC.stats(),
+        }
+    }
+
+    unsafe fn alloc(&mut self, layout: Layout) {
+        let ptr = ALLOC.alloc(layout);
+        assert!(!ptr.is_null(), "out of memory for {:?}", layout);
+        self.insert(ptr, layout);
+    }
+
+    unsafe fn dealloc(&mut self, index: usize) {
+        if let Some((ptr, allocation)) = self.take(index) {
+            ALLOC.dealloc(ptr, allocation.layout);
+        }
+    }
+
+    unsafe fn realloc(&mut self, index: usize, size: usize) {
+        if let Some((ptr, allocation)) = self.take(index) {
+            let new_ptr = ALLOC.realloc(ptr, allocation.layout, size);
+            assert!(!new_ptr.is_null());
+
+            let kept = allocation.layout.size().min(size);
+            let data = std::slice::from_raw_parts(new_ptr, kept);
+            assert!(
+                data.iter().all(|b| *b == allocation.fill),
+                "realloc lost data"
+            );
+
+            let layout = Layout::from_size_align(size, allocation.layout.align()).unwrap();
+            self.insert(new_ptr, layout);
+        }
+    }
+
+    unsafe fn insert(&mut self, ptr: *mut u8, layout: Layout) {
+        let start = ptr as usize;
+        let end = start + layout.size();
+
+        assert_eq!(start % layout.align(), 0, "misaligned {:?}", layout);
+
+        if let Some((&before, allocation)) = self.live.range(..=start).next_back() {
+            assert!(
+                before + allocation.layout.size() <= start,
+                "{:#x} overlaps the allocation at {:#x}",
+                start,
+                before
+            );
+        }
+
+        if let Some((&after, _)) = self.live.range(start..).next() {
+            assert!(
+                end <= after,
+                "{:#x} overlaps the allocation at {:#x}",
+                start,
+                after
+            );
+        }
+
+        self.next_fill = self.next_fill.wrapping_add(1);
+        std::ptr::write_bytes(ptr, self.next_fill, layout.size());
+        self.live.insert(
+            start,
+            Allocation {
+                layout,
+                fill: self.next_fill,
+            },
+        );
+    }
+
+    /// Removes the allocation at `index` modulo the live count and checks
+    /// nothing else wrote to it.
+    unsafe fn take(&mut self, index: usize) -> Option<(*mut u8, Allocation)> {
+        if self.live.is_empty() {
+            return None;
+        }
+
+        let start = *self.live.keys().nth(index % self.live.len()).unwrap();
+        let allocation = self.live.remove(&start).unwrap();
+        let data = std::slice::from_raw_parts(start as *const u8, allocation.layout.size());
+
+        assert!(
+            data.iter().all(|b| *b == allocation.fill),
+            "allocation at {:#x} was overwritten",
+            start
+        );
+
+        Some((start as *mut u8, allocation))
+    }
+
+    fn check_stats(&self) {
+        // With tags every allocation grows by a hidden prefix.
+        if cfg!(feature = "tags") {
+            return;
+        }
+
+        let stats = ALLOC.stats();
+        let mut size_class_used = [0; NUM_SIZE_CLASSES];
+        let mut used_cells = self.baseline.used_cells;
+
+        for (used, class) in size_class_used
+            .iter_mut()
+            .zip(self.baseline.size_classes.iter())
+        {
+            *used = class.used_cells;
+        }
+
+        for allocation in self.live.values() {
+            let words = allocation.layout.size().div_ceil(size_of::<usize>());
+
+            if allocation.layout.align() <= size_of::<usize>() && words <= NUM_SIZE_CLASSES {
+                size_class_used[words - 1] += 1;
+            } else {
+                used_cells += 1;
+            }
+        }
+
+        assert_eq!(stats.used_cells, used_cells);
+
+        for (class, expected) in stats.size_classes.iter().zip(size_class_used.iter()) {
+            assert_eq!(class.used_cells, *expected);
+        }
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(128))]
+
+    #[test]
+    fn random_sequences_match_shadow_model(ops in vec(op(), 1..200)) {
+        let mut shadow = Shadow::new();
+
+        unsafe {
+            for op in ops {
+                match op {
+                    Op::Alloc { size, align } => {
+                        shadow.alloc(Layout::from_size_align(size, align).unwrap())
+                    }
+                    Op::Dealloc { index } => shadow.dealloc(index),
+                    Op::Realloc { index, size } => shadow.realloc(index, size),
+                }
+
+                shadow.check_stats();
+            }
+
+            while !shadow.live.is_empty() {
+                shadow.dealloc(0);
+            }
+
+            shadow.check_stats();
+
+            // Freed cells have been merged back far enough that the largest
+            // free block can be handed out again without asking for pages.
+            let before = ALLOC.stats();
+            let largest = before.largest_free_block;
+
+            if !cfg!(feature = "tags") && largest > NUM_SIZE_CLASSES * size_of::<usize>() {
+                let layout = Layout::from_size_align(largest, size_of::<usize>()).unwrap();
+                let ptr = ALLOC.alloc(layout);
+
+                prop_assert!(!ptr.is_null());
+                prop_assert_eq!(ALLOC.stats().committed_bytes, before.committed_bytes);
+
+                ALLOC.dealloc(ptr, layout);
+            }
+        }
+    }
+}
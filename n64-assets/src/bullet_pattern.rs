@@ -0,0 +1,375 @@
+use crate::error::InFile;
+use crate::{AssetError, Output, Processor};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[rustfmt::skip]
+macro_rules! PATTERN_TEMPLATE { () => {
+r##"    BulletPattern {{
+        name: {name:?},
+        volleys: &[
+{volleys}        ],
+    }},
+"##
+}; }
+
+#[rustfmt::skip]
+macro_rules! VOLLEY_TEMPLATE { () => {
+r##"            Volley {{
+                shape: {shape},
+                aim: Aim::{aim},
+                start: {start}_f32,
+                interval: {interval}_f32,
+                turn: {turn}_f32,
+                jitter: {jitter}_f32,
+                speed: {speed}_f32,
+                acceleration: {acceleration}_f32,
+                delay: {delay}_f32,
+            }},
+"##
+}; }
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum ShapeKind {
+    Radial,
+    Spread,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum Aim {
+    Down,
+    Player,
+}
+
+/// A volley as written, the fields it leaves out come from `base`.
+#[derive(Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct VolleyToml {
+    base: Option<String>,
+    shape: Option<ShapeKind>,
+    count: Option<u32>,
+    ways: Option<u32>,
+    angle: Option<f32>,
+    aim: Option<Aim>,
+    start: Option<f32>,
+    interval: Option<f32>,
+    turn: Option<f32>,
+    jitter: Option<f32>,
+    speed: Option<f32>,
+    acceleration: Option<f32>,
+    delay: Option<f32>,
+}
+
+impl VolleyToml {
+    /// `self` with the fields it leaves out taken from `base`.
+    fn over(&self, base: &VolleyToml) -> VolleyToml {
+        VolleyToml {
+            base: None,
+            shape: self.shape.or(base.shape),
+            count: self.count.or(base.count),
+            ways: self.ways.or(base.ways),
+            angle: self.angle.or(base.angle),
+            aim: self.aim.or(base.aim),
+            start: self.start.or(base.start),
+            interval: self.interval.or(base.interval),
+            turn: self.turn.or(base.turn),
+            jitter: self.jitter.or(base.jitter),
+            speed: self.speed.or(base.speed),
+            acceleration: self.acceleration.or(base.acceleration),
+            delay: self.delay.or(base.delay),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PatternToml {
+    name: String,
+    volleys: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BulletsToml {
+    #[serde(default)]
+    volley: BTreeMap<String, VolleyToml>,
+    #[serde(default)]
+    pattern: Vec<PatternToml>,
+}
+
+/// The volleys of all files by name, with the file each is in.
+struct Volleys {
+    by_name: BTreeMap<String, (VolleyToml, PathBuf)>,
+}
+
+impl Volleys {
+    /// `name` with everything it inherits, `chain` holds the volleys that
+    /// led here to catch loops.
+    fn resolve(&self, name: &str, chain: &mut Vec<String>) -> Result<VolleyToml, String> {
+        let (volley, _) = self
+            .by_name
+            .get(name)
+            .ok_or_else(|| format!("Unknown volley `{}`", name))?;
+
+        if chain.iter().any(|n| n == name) {
+            chain.push(name.to_string());
+            return Err(format!("Volley bases loop: {}", chain.join(" -> ")));
+        }
+
+        chain.push(name.to_string());
+
+        Ok(match &volley.base {
+            Some(base) => volley.over(&self.resolve(base, chain)?),
+            None => volley.clone(),
+        })
+    }
+
+    /// The `Volley` expression of `name` for the generated code.
+    fn code(&self, name: &str) -> Result<String, String> {
+        let volley = self.resolve(name, &mut Vec::new())?;
+        let positive = |field: &str, value: Option<u32>| match value {
+            Some(value) if value > 0 => Ok(value),
+            _ => Err(format!("Volley `{}` needs a `{}` above zero", name, field)),
+        };
+
+        let shape = match volley.shape {
+            Some(ShapeKind::Radial) => format!(
+                "Shape::Radial {{ count: {} }}",
+                positive("count", volley.count)?
+            ),
+            Some(ShapeKind::Spread) => format!(
+                "Shape::Spread {{ ways: {}, angle: {}_f32 }}",
+                positive("ways", volley.ways)?,
+                volley.angle.unwrap_or(0.0)
+            ),
+            None => return Err(format!("Volley `{}` has no `shape`", name)),
+        };
+
+        let speed = volley
+            .speed
+            .ok_or_else(|| format!("Volley `{}` has no `speed`", name))?;
+
+        if volley.interval.unwrap_or(0.0) < 0.0 {
+            return Err(format!("Volley `{}` has a negative `interval`", name));
+        }
+
+        Ok(format!(
+            VOLLEY_TEMPLATE!(),
+            shape = shape,
+            aim = format!("{:?}", volley.aim.unwrap_or(Aim::Down)),
+            start = volley.start.unwrap_or(0.0),
+            interval = volley.interval.unwrap_or(0.0),
+            turn = volley.turn.unwrap_or(0.0),
+            jitter = volley.jitter.unwrap_or(0.0),
+            speed = speed,
+            acceleration = volley.acceleration.unwrap_or(0.0),
+            delay = volley.delay.unwrap_or(0.0),
+        ))
+    }
+}
+
+/// Bullet patterns for the enemies, from toml files of named volleys and
+/// the patterns firing them:
+///
+/// ```toml
+/// [volley.spread]
+/// shape = "spread" # `ways` bullets `angle` degrees apart, or "radial" with `count`
+/// ways = 5
+/// angle = 15.0
+/// interval = 1.5
+/// speed = 150.0
+///
+/// [volley.aimed_spread]
+/// base = "spread"
+/// aim = "player"
+///
+/// [[pattern]]
+/// name = "aimed_spread"
+/// volleys = ["aimed_spread"]
+/// ```
+///
+/// A volley takes what it leaves out from its `base`. `aim` is "down" by
+/// default, `start`, `interval`, `turn`, `jitter`, `acceleration` and
+/// `delay` are zero. The patterns of all files become one `PATTERNS` table
+/// in the order they are written, the first one is the default.
+pub struct BulletPatternProcessor;
+
+impl Processor for BulletPatternProcessor {
+    fn header(&self) -> &str {
+        "use crate::bullet_pattern::{Aim, BulletPattern, Shape, Volley};
+"
+    }
+
+    fn version(&self) -> String {
+        "bullets 1".to_string()
+    }
+
+    fn batch(&self) -> bool {
+        true
+    }
+
+    fn process(&self, inputs: &[PathBuf], _out_dir: &Path) -> Result<Output, AssetError> {
+        let mut volleys = Volleys {
+            by_name: BTreeMap::new(),
+        };
+        let mut patterns = Vec::new();
+
+        for path in inputs {
+            let text = fs::read_to_string(path).in_file(path)?;
+            let bullets: BulletsToml = toml::from_str(&text).in_file(path)?;
+
+            for (name, volley) in bullets.volley {
+                if let Some((_, other)) = volleys.by_name.get(&name) {
+                    return Err(AssetError::new(
+                        path,
+                        format!("Volley `{}` is in {} as well", name, other.display()),
+                    ));
+                }
+
+                volleys.by_name.insert(name, (volley, path.clone()));
+            }
+
+            patterns.extend(bullets.pattern.into_iter().map(|p| (p, path)));
+        }
+
+        if patterns.is_empty() {
+            let path = inputs.first().map_or(Path::new(""), |path| path.as_path());
+            return Err(AssetError::new(path, "There are no bullet patterns"));
+        }
+
+        let mut names = HashSet::new();
+        let mut code = String::from("pub static PATTERNS: &[BulletPattern] = &[\n");
+
+        for (pattern, path) in patterns.iter() {
+            if !names.insert(&pattern.name) {
+                return Err(AssetError::new(
+                    path,
+                    format!("There are two patterns named `{}`", pattern.name),
+                ));
+            }
+
+            if pattern.volleys.is_empty() {
+                return Err(AssetError::new(
+                    path,
+                    format!("Pattern `{}` fires no volleys", pattern.name),
+                ));
+            }
+
+            let mut volley_code = String::new();
+            for name in pattern.volleys.iter() {
+                let error = |message| {
+                    let file = volleys.by_name.get(name).map_or(*path, |(_, file)| file);
+                    AssetError::new(file, format!("Pattern `{}`: {}", pattern.name, message))
+                };
+                volley_code.push_str(&volleys.code(name).map_err(error)?);
+            }
+
+            code.push_str(&format!(
+                PATTERN_TEMPLATE!(),
+                name = pattern.name,
+                volleys = volley_code
+            ));
+        }
+
+        code.push_str("];\n");
+
+        Ok(Output {
+            code,
+            ..Output::default()
+        })
+    }
+}
+
+#[cfg(test)]
+fn process(name: &str, toml: &str) -> Result<String, AssetError> {
+    let path = std::env::temp_dir().join(format!(
+        "n64-assets-bullets-{}-{}.toml",
+        name,
+        std::process::id()
+    ));
+    fs::write(&path, toml).unwrap();
+    let output = BulletPatternProcessor.process(std::slice::from_ref(&path), Path::new(""));
+    fs::remove_file(path).ok();
+    output.map(|output| output.code)
+}
+
+#[test]
+fn volleys_take_what_they_leave_out_from_their_base() {
+    let code = process(
+        "base",
+        "[volley.spread]
+shape = \"spread\"
+ways = 5
+angle = 15.0
+interval = 1.5
+speed = 150.0
+
+[volley.aimed_spread]
+base = \"spread\"
+aim = \"player\"
+start = 0.5
+
+[[pattern]]
+name = \"single\"
+volleys = [\"spread\"]
+
+[[pattern]]
+name = \"aimed_spread\"
+volleys = [\"spread\", \"aimed_spread\"]
+",
+    )
+    .unwrap();
+
+    assert!(code.starts_with(
+        "pub static PATTERNS: &[BulletPattern] = &[
+    BulletPattern {
+        name: \"single\",
+        volleys: &[
+            Volley {
+                shape: Shape::Spread { ways: 5, angle: 15_f32 },
+                aim: Aim::Down,
+                start: 0_f32,
+                interval: 1.5_f32,
+                turn: 0_f32,
+                jitter: 0_f32,
+                speed: 150_f32,
+                acceleration: 0_f32,
+                delay: 0_f32,
+            },
+        ],
+    },
+"
+    ));
+    assert!(code.contains(
+        "                shape: Shape::Spread { ways: 5, angle: 15_f32 },
+                aim: Aim::Player,
+                start: 0.5_f32,
+                interval: 1.5_f32,
+"
+    ));
+    assert!(code.ends_with("];\n"));
+}
+
+#[test]
+fn broken_patterns_are_errors() {
+    let error = |toml: &str| process("broken", toml).err().unwrap().to_string();
+
+    assert!(error("[[pattern]]\nname = \"a\"\nvolleys = [\"b\"]\n")
+        .ends_with("Pattern `a`: Unknown volley `b`"));
+    assert!(error(
+        "[volley.a]\nbase = \"b\"\n[volley.b]\nbase = \"a\"\n\
+         [[pattern]]\nname = \"a\"\nvolleys = [\"a\"]\n"
+    )
+    .ends_with("Volley bases loop: a -> b -> a"));
+    assert!(error(
+        "[volley.a]\nshape = \"radial\"\nspeed = 1.0\n\
+         [[pattern]]\nname = \"a\"\nvolleys = [\"a\"]\n"
+    )
+    .ends_with("Volley `a` needs a `count` above zero"));
+    assert!(error("[volley.a]\nshape = \"round\"\n").contains("unknown variant `round`"));
+    assert!(error("[volley.a]\n").ends_with("There are no bullet patterns"));
+}
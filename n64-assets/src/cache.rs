@@ -0,0 +1,209 @@
+//! Processed results are kept in the out directory under a hash of
+//! everything that went into them, so only changed assets are processed
+//! again. Results that were not used by the last run are removed.
+
+use crate::{AssetError, Output, OutputFile};
+use std::collections::HashSet;
+use std::convert::TryInto;
+use std::fs;
+use std::path::PathBuf;
+
+/// 64 bit FNV-1a, stable between runs and compilers unlike the hasher of
+/// the standard library.
+pub(crate) struct ContentHash(u64);
+
+impl ContentHash {
+    #[inline]
+    pub(crate) fn new() -> ContentHash {
+        ContentHash(0xcbf2_9ce4_8422_2325)
+    }
+
+    /// Prefixed with the length so two writes never hash like one.
+    pub(crate) fn write(&mut self, bytes: &[u8]) {
+        for byte in (bytes.len() as u64)
+            .to_le_bytes()
+            .iter()
+            .chain(bytes.iter())
+        {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    #[inline]
+    pub(crate) fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+pub(crate) struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    pub(crate) fn new(dir: PathBuf) -> Cache {
+        Cache { dir }
+    }
+
+    fn path(&self, key: u64) -> PathBuf {
+        self.dir.join(format!("{:016x}.bin", key))
+    }
+
+    /// A missing or unreadable entry is a miss, it is processed again.
+    pub(crate) fn get(&self, key: u64) -> Option<Output> {
+        decode(&fs::read(self.path(key)).ok()?)
+    }
+
+    pub(crate) fn put(&self, key: u64, output: &Output) -> Result<(), AssetError> {
+        fs::create_dir_all(&self.dir)
+            .and_then(|_| fs::write(self.path(key), encode(output)))
+            .map_err(|e| AssetError::new(&self.dir, format!("Unable to write the cache: {}", e)))
+    }
+
+    /// Removes every entry not in `used`.
+    pub(crate) fn retain(&self, used: &HashSet<u64>) {
+        let keep: HashSet<PathBuf> = used.iter().map(|key| self.path(*key)).collect();
+
+        if let Ok(entries) = fs::read_dir(&self.dir) {
+            for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+                if !keep.contains(&path) {
+                    fs::remove_file(path).ok();
+                }
+            }
+        }
+    }
+}
+
+fn encode(output: &Output) -> Vec<u8> {
+    let mut out = Vec::new();
+    let bytes = |out: &mut Vec<u8>, bytes: &[u8]| {
+        out.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+        out.extend_from_slice(bytes);
+    };
+
+    bytes(&mut out, output.code.as_bytes());
+    bytes(&mut out, output.records.join("\n").as_bytes());
+    out.extend_from_slice(&(output.files.len() as u32).to_le_bytes());
+
+    for file in output.files.iter() {
+        bytes(&mut out, file.path.to_string_lossy().as_bytes());
+        bytes(&mut out, file.category.as_bytes());
+        bytes(&mut out, file.asset.as_bytes());
+        bytes(&mut out, &file.data);
+        bytes(
+            &mut out,
+            file.included_as.as_deref().unwrap_or("").as_bytes(),
+        );
+        bytes(
+            &mut out,
+            file.requires
+                .as_ref()
+                .map(|path| path.to_string_lossy())
+                .unwrap_or_default()
+                .as_bytes(),
+        );
+    }
+
+    out
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn word(&mut self) -> Option<usize> {
+        let bytes = self.data.get(self.offset..self.offset + 4)?;
+        self.offset += 4;
+        Some(u32::from_le_bytes(bytes.try_into().ok()?) as usize)
+    }
+
+    fn bytes(&mut self) -> Option<&'a [u8]> {
+        let len = self.word()?;
+        let bytes = self.data.get(self.offset..self.offset + len)?;
+        self.offset += len;
+        Some(bytes)
+    }
+
+    fn string(&mut self) -> Option<String> {
+        String::from_utf8(self.bytes()?.to_vec()).ok()
+    }
+}
+
+fn decode(data: &[u8]) -> Option<Output> {
+    let mut reader = Reader { data, offset: 0 };
+
+    let code = reader.string()?;
+    let records = reader.string()?;
+    let file_count = reader.word()?;
+    let mut files = Vec::new();
+
+    for _ in 0..file_count {
+        files.push(OutputFile {
+            path: PathBuf::from(reader.string()?),
+            category: reader.string()?,
+            asset: reader.string()?,
+            data: reader.bytes()?.to_vec(),
+            included_as: Some(reader.string()?).filter(|expr| !expr.is_empty()),
+            requires: Some(reader.string()?)
+                .filter(|path| !path.is_empty())
+                .map(PathBuf::from),
+        });
+    }
+
+    if reader.offset != data.len() {
+        return None;
+    }
+
+    Some(Output {
+        code,
+        files,
+        records: records
+            .split('\n')
+            .filter(|record| !record.is_empty())
+            .map(str::to_string)
+            .collect(),
+    })
+}
+
+#[test]
+fn outputs_survive_the_cache() {
+    let output = Output {
+        code: "pub static A: u8 = 1;\n".to_string(),
+        files: vec![OutputFile {
+            path: PathBuf::from("/out/textures/a.ntex"),
+            category: "textures".to_string(),
+            asset: "A".to_string(),
+            data: vec![1, 2, 3],
+            included_as: Some("A.data".to_string()),
+            requires: Some(PathBuf::from("/out/a.ntab")),
+        }],
+        records: vec!["map\tMAP_1\t1\t1\t1\t1\t1\t1".to_string()],
+    };
+
+    let decoded = decode(&encode(&output)).unwrap();
+    assert_eq!(decoded.code, output.code);
+    assert_eq!(decoded.records, output.records);
+    assert_eq!(decoded.files[0].path, output.files[0].path);
+    assert_eq!(decoded.files[0].data, [1, 2, 3]);
+    assert_eq!(decoded.files[0].included_as.as_deref(), Some("A.data"));
+    assert_eq!(decoded.files[0].requires, output.files[0].requires);
+
+    let encoded = encode(&output);
+    assert!(decode(&encoded[..encoded.len() - 1]).is_none());
+}
+
+#[test]
+fn content_hash_separates_writes() {
+    let hash = |parts: &[&[u8]]| {
+        let mut hash = ContentHash::new();
+        for part in parts {
+            hash.write(part);
+        }
+        hash.finish()
+    };
+
+    assert_eq!(hash(&[b"ab"]), hash(&[b"ab"]));
+    assert_ne!(hash(&[b"ab", b"c"]), hash(&[b"a", b"bc"]));
+}
@@ -0,0 +1,60 @@
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Something wrong with an asset, the manifest or writing the results,
+/// with the file and line to look at.
+pub struct AssetError {
+    pub path: PathBuf,
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl AssetError {
+    pub fn new(path: impl AsRef<Path>, message: impl Into<String>) -> AssetError {
+        AssetError {
+            path: path.as_ref().to_path_buf(),
+            line: None,
+            message: message.into(),
+        }
+    }
+
+    /// `line` counts from 1.
+    pub fn at_line(path: impl AsRef<Path>, line: usize, message: impl Into<String>) -> AssetError {
+        AssetError {
+            line: Some(line),
+            ..AssetError::new(path, message)
+        }
+    }
+}
+
+impl fmt::Display for AssetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.path.display(), line, self.message),
+            None => write!(f, "{}: {}", self.path.display(), self.message),
+        }
+    }
+}
+
+// Build scripts print the `Debug` of the error they return, which should be
+// as readable as the `Display`.
+impl fmt::Debug for AssetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl Error for AssetError {}
+
+/// Blames errors from reading or decoding a file on that file.
+pub(crate) trait InFile<T> {
+    fn in_file(self, path: &Path) -> Result<T, AssetError>;
+}
+
+impl<T, E: fmt::Display> InFile<T> for Result<T, E> {
+    #[inline]
+    fn in_file(self, path: &Path) -> Result<T, AssetError> {
+        self.map_err(|e| AssetError::new(path, e.to_string()))
+    }
+}
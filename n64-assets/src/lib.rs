@@ -0,0 +1,87 @@
+//! Turns the textures, maps, sounds and bullet patterns of a game into
+//! binary files and the Rust modules that include them, as listed in a
+//! manifest. Results are cached by content and assets are processed in
+//! parallel.
+
+pub use bullet_pattern::BulletPatternProcessor;
+pub use error::AssetError;
+pub use manifest::{Manifest, Stage};
+pub use map::MapProcessor;
+pub use pipeline::{Pipeline, Report};
+pub use sound::SoundProcessor;
+pub use texture::TextureProcessor;
+
+mod bullet_pattern;
+mod cache;
+mod error;
+mod manifest;
+mod map;
+mod pipeline;
+mod sound;
+mod texture;
+
+use std::path::{Path, PathBuf};
+
+/// A binary file written by a processor, included by its code.
+pub struct OutputFile {
+    pub path: PathBuf,
+    /// Groups assets in the size report, like `textures` or `map tiles`.
+    pub category: String,
+    /// What the code calls it.
+    pub asset: String,
+    pub data: Vec<u8>,
+    /// The `&'static [u8]` the code includes the file as, so the PC build
+    /// can reload it. `None` when the code depends on more than its size.
+    pub included_as: Option<String>,
+    /// Another file that must be as built for this one to reload, like the
+    /// tile table the cells of a map index.
+    pub requires: Option<PathBuf>,
+}
+
+/// What a processor made of its inputs.
+#[derive(Default)]
+pub struct Output {
+    /// Rust items for the generated module.
+    pub code: String,
+    pub files: Vec<OutputFile>,
+    /// More lines for the size report, like the tile usage of maps.
+    pub records: Vec<String>,
+}
+
+/// Turns input files into binary files and the Rust code including them.
+pub trait Processor: Send + Sync {
+    /// The top of the generated module, usually `use` items.
+    fn header(&self) -> &str;
+
+    /// Changes whenever the output for the same inputs does, settings
+    /// included, so results cached before are not used.
+    fn version(&self) -> String;
+
+    /// Whether all inputs of a stage go to one `process`, instead of one
+    /// each in parallel.
+    fn batch(&self) -> bool {
+        false
+    }
+
+    /// Files besides the inputs the output depends on.
+    fn dependencies(&self, _inputs: &[PathBuf]) -> Result<Vec<PathBuf>, AssetError> {
+        Ok(Vec::new())
+    }
+
+    /// Binary files go in `out_dir`.
+    fn process(&self, inputs: &[PathBuf], out_dir: &Path) -> Result<Output, AssetError>;
+
+    /// Code after all the assets of a stage, given their names in order,
+    /// like a lookup by name.
+    fn footer(&self, _assets: &[String]) -> String {
+        String::new()
+    }
+}
+
+/// Name of the Rust static for an asset file, `ship_1.png` is `SHIP_1`.
+pub(crate) fn asset_ident(path: &Path) -> Result<String, AssetError> {
+    path.file_stem()
+        .and_then(|stem| stem.to_str())
+        .map(|stem| stem.to_uppercase())
+        .ok_or_else(|| AssetError::new(path, "File name is not valid UTF-8"))
+}
@@ -0,0 +1,230 @@
+//! The manifest lists the stages of the pipeline, which files go into each,
+//! the processor that turns them into binary files and where the Rust module
+//! including them is written:
+//!
+//! ```toml
+//! # Written for `n64-rom size`.
+//! sizes = "asset_sizes.tsv"
+//!
+//! [textures]
+//! processor = "texture"
+//! inputs = "textures/*.png"
+//! output = "src/textures.rs"
+//!
+//! [maps]
+//! processor = "map"
+//! inputs = "maps/*.tmx"
+//! exclude = ["map_2"]
+//! output = "src/maps.rs"
+//! ```
+//!
+//! Every table is a stage, in the order they are written. Paths are relative
+//! to the manifest and `exclude` skips inputs with any of the given strings in
+//! their file name.
+
+use crate::{error::InFile, AssetError};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub struct Stage {
+    pub name: String,
+    pub processor: String,
+    /// A directory followed by a file pattern with at most one `*`, like
+    /// `sounds/*.wav`.
+    pub inputs: String,
+    pub exclude: Vec<String>,
+    pub output: PathBuf,
+    /// Where the stage starts in the manifest, for errors about it.
+    pub line: usize,
+}
+
+pub struct Manifest {
+    pub path: PathBuf,
+    /// The directory the paths in the manifest are relative to.
+    pub root: PathBuf,
+    pub sizes: Option<PathBuf>,
+    pub stages: Vec<Stage>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StageToml {
+    processor: String,
+    inputs: String,
+    #[serde(default)]
+    exclude: Vec<String>,
+    output: String,
+}
+
+impl Manifest {
+    pub fn load(path: impl AsRef<Path>) -> Result<Manifest, AssetError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .map_err(|e| AssetError::new(path, format!("Unable to read the manifest: {}", e)))?;
+
+        Manifest::parse(path, &text)
+    }
+
+    /// `path` is only used for errors and to find the root directory.
+    pub fn parse(path: &Path, text: &str) -> Result<Manifest, AssetError> {
+        let root = path.parent().unwrap_or_else(|| Path::new("")).to_path_buf();
+        let table: toml::value::Table = toml::from_str(text).in_file(path)?;
+
+        let mut sizes = None;
+        let mut stages = Vec::new();
+
+        for (key, value) in table {
+            match value {
+                toml::Value::Table(_) => {
+                    let line = find_line(text, &format!("[{}]", key)).unwrap_or(1);
+                    let stage: StageToml = value
+                        .try_into()
+                        .map_err(|e| AssetError::at_line(path, line, format!("[{}] {}", key, e)))?;
+
+                    stages.push(Stage {
+                        name: key,
+                        processor: stage.processor,
+                        inputs: stage.inputs,
+                        exclude: stage.exclude,
+                        output: root.join(stage.output),
+                        line,
+                    });
+                }
+                toml::Value::String(value) if key == "sizes" => sizes = Some(root.join(value)),
+                _ => {
+                    let line = find_line(text, &key).unwrap_or(1);
+                    return Err(AssetError::at_line(
+                        path,
+                        line,
+                        format!("Unknown or bad setting `{}`", key),
+                    ));
+                }
+            }
+        }
+
+        // The tables come sorted by name.
+        stages.sort_by_key(|stage| stage.line);
+
+        Ok(Manifest {
+            path: path.to_path_buf(),
+            root,
+            sizes,
+            stages,
+        })
+    }
+
+    /// The files matching the `inputs` of `stage`, sorted so the generated
+    /// code is the same every time.
+    pub fn inputs(&self, stage: &Stage) -> Result<Vec<PathBuf>, AssetError> {
+        let pattern = self.root.join(&stage.inputs);
+        let dir = pattern.parent().unwrap_or_else(|| Path::new(""));
+        let file_pattern = pattern
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| AssetError::at_line(&self.path, stage.line, "Bad `inputs` pattern"))?;
+        let (prefix, suffix) = match file_pattern.find('*') {
+            Some(star) => (&file_pattern[..star], &file_pattern[star + 1..]),
+            None => (file_pattern, ""),
+        };
+
+        let entries = fs::read_dir(dir).map_err(|e| {
+            AssetError::new(
+                dir,
+                format!("Unable to list [{}] inputs: {}", stage.name, e),
+            )
+        })?;
+        let mut inputs = Vec::new();
+
+        for entry in entries {
+            let path = entry
+                .map_err(|e| AssetError::new(dir, e.to_string()))?
+                .path();
+            let name = match path.file_name().and_then(|name| name.to_str()) {
+                Some(name) => name,
+                None => continue,
+            };
+
+            let matches = if file_pattern.contains('*') {
+                name.len() >= prefix.len() + suffix.len()
+                    && name.starts_with(prefix)
+                    && name.ends_with(suffix)
+            } else {
+                name == file_pattern
+            };
+
+            if matches && path.is_file() && !stage.exclude.iter().any(|e| name.contains(e.as_str()))
+            {
+                inputs.push(path);
+            }
+        }
+
+        inputs.sort();
+
+        Ok(inputs)
+    }
+}
+
+/// The first line, counting from 1, starting with `start`.
+fn find_line(text: &str, start: &str) -> Option<usize> {
+    text.lines()
+        .position(|line| line.trim_start().starts_with(start))
+        .map(|index| index + 1)
+}
+
+#[test]
+fn stages_are_read_with_their_settings() {
+    let manifest = Manifest::parse(
+        Path::new("game/assets.toml"),
+        "sizes = \"asset_sizes.tsv\"
+
+[sounds]
+processor = \"sound\" # 16 bit PCM
+inputs = \"sounds/#1/*.wav\"
+output = \"src/sounds.rs\"
+
+# Every map but the unfinished one
+[maps]
+processor = \"map\"
+inputs = \"maps/*.tmx\"
+exclude = [
+    \"map_2\",
+    \"test, old\",
+]
+output = \"src/maps.rs\"
+",
+    )
+    .unwrap();
+
+    assert_eq!(manifest.sizes, Some(PathBuf::from("game/asset_sizes.tsv")));
+    assert_eq!(manifest.stages.len(), 2);
+    assert_eq!(manifest.stages[0].processor, "sound");
+    assert_eq!(manifest.stages[0].inputs, "sounds/#1/*.wav");
+    assert_eq!(
+        manifest.stages[0].output,
+        PathBuf::from("game/src/sounds.rs")
+    );
+    assert_eq!(manifest.stages[1].name, "maps");
+    assert_eq!(manifest.stages[1].exclude, ["map_2", "test, old"]);
+    assert_eq!(manifest.stages[1].line, 9);
+}
+
+#[test]
+fn manifest_errors_point_at_the_line() {
+    let error = |text: &str| {
+        Manifest::parse(Path::new("assets.toml"), text)
+            .err()
+            .unwrap()
+            .to_string()
+    };
+
+    assert!(error("[sounds]\nprocessor = sound\n").contains("line 2"));
+    assert_eq!(
+        error("\n[sounds]\nprocessor = \"sound\"\n"),
+        "assets.toml:2: [sounds] missing field `inputs`"
+    );
+    assert_eq!(
+        error("\nspeed = \"fast\""),
+        "assets.toml:2: Unknown or bad setting `speed`"
+    );
+}
@@ -0,0 +1,914 @@
+use crate::error::InFile;
+use crate::texture::{load_png, Image};
+use crate::{asset_ident, AssetError, Output, OutputFile, Processor};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use tiled::{
+    LayerData, Map, Object, ObjectShape, ObjectTemplate, Properties, PropertyValue, Tileset,
+};
+
+// Layer cells are big endian `u16`s, these flags over the index of the
+// tile plus one, or zero for no tile.
+const TILE_FLIP_HORIZONTAL: u16 = 0x8000;
+const TILE_FLIP_VERTICAL: u16 = 0x4000;
+const TILE_FLIP_DIAGONAL: u16 = 0x2000;
+const MAX_TILES: usize = 0x1fff;
+
+#[rustfmt::skip]
+macro_rules! TILE_TEMPLATE { () => {
+r##"static {tile_ident}: StaticTexture = StaticTexture::from_static({width}, {height}, include_bytes_align_as!(Color, {tile_path:?}));
+"##
+}; }
+
+#[rustfmt::skip]
+macro_rules! TILE_IDENT_TEMPLATE { () => {
+r##"    &{tile_ident},
+"##
+}; }
+
+#[rustfmt::skip]
+macro_rules! OBJECT_TEXTURE_TEMPLATE { () => {
+r##"static {object_texture_ident}: StaticTexture = StaticTexture::from_static({width}, {height}, include_bytes_align_as!(Color, {object_texture_path:?}));
+"##
+}; }
+
+#[rustfmt::skip]
+macro_rules! OBJECT_TEMPLATE { () => {
+r##"    StaticObject {{
+        kind: {kind:?},
+        name: {name:?},
+        x: {x}_f32,
+        y: {y}_f32,
+        height: {height}_f32,
+        shape: {shape},
+        properties: &[{properties}],
+    }},
+"##
+}; }
+
+#[rustfmt::skip]
+macro_rules! LAYER_TEMPLATE { () => {
+r##"    StaticMapLayer {{ parallax_x: {parallax_x}_f32, parallax_y: {parallax_y}_f32, visible: {visible} }},
+"##
+}; }
+
+#[rustfmt::skip]
+macro_rules! ANIMATION_TEMPLATE { () => {
+r##"    TileAnimation {{ tile: {tile}, frames: &[{frames}] }},
+"##
+}; }
+
+#[rustfmt::skip]
+macro_rules! FRAME_TEMPLATE { () => {
+r##"TileFrame {{ tile: {tile}, duration_ms: {duration_ms} }}, "##
+}; }
+
+#[rustfmt::skip]
+macro_rules! MAP_TEMPLATE { () => {
+r##"static {tiles_name_ident}: &[&StaticTexture] = &[
+{map_tile_refs}];
+
+static {layers_name_ident}: &[StaticMapLayer] = &[
+{layers}];
+
+static {animations_name_ident}: &[TileAnimation] = &[
+{animations}];
+
+{object_textures}
+pub static {objects_name_ident}: &[StaticObject] = &[
+{objects}];
+
+pub static {map_name_ident}: &StaticMapData = &StaticMapData {{
+    width_in_tiles: {map_width},
+    height_in_tiles: {map_height},
+    tile_width: {tile_width},
+    tile_height: {tile_height},
+    tiles: {tiles_name_ident},
+    layers: {layers_name_ident},
+    cells: include_bytes!({map_data_path:?}),
+    animations: {animations_name_ident},
+    collision: {collision},
+    objects: {objects_name_ident},
+}};"##
+}; }
+
+/// Indices of the tiles the maps use, shared by all of them since every
+/// map includes the tiles of the maps before it. Zero is no tile.
+struct TileIds {
+    by_gid: HashMap<u32, u16>,
+    gids: Vec<u32>,
+}
+
+impl TileIds {
+    fn new() -> TileIds {
+        let mut by_gid = HashMap::new();
+        by_gid.insert(0, 0);
+
+        TileIds {
+            by_gid,
+            gids: vec![0],
+        }
+    }
+
+    fn index(&mut self, gid: u32, map_path: &Path) -> Result<u16, AssetError> {
+        if let Some(index) = self.by_gid.get(&gid) {
+            return Ok(*index);
+        }
+
+        if self.gids.len() > MAX_TILES {
+            return Err(AssetError::new(
+                map_path,
+                format!("The maps use more than {} tiles", MAX_TILES),
+            ));
+        }
+
+        let index = self.gids.len() as u16;
+        self.by_gid.insert(gid, index);
+        self.gids.push(gid);
+
+        Ok(index)
+    }
+}
+
+fn cell(index: u16, flip_horizontal: bool, flip_vertical: bool, flip_diagonal: bool) -> u16 {
+    let mut cell = index;
+
+    if flip_horizontal {
+        cell |= TILE_FLIP_HORIZONTAL;
+    }
+
+    if flip_vertical {
+        cell |= TILE_FLIP_VERTICAL;
+    }
+
+    if flip_diagonal {
+        cell |= TILE_FLIP_DIAGONAL;
+    }
+
+    cell
+}
+
+fn float_property(
+    properties: &Properties,
+    name: &str,
+    default: f32,
+    map_path: &Path,
+    layer: &str,
+) -> Result<f32, AssetError> {
+    match properties.get(name) {
+        None => Ok(default),
+        Some(PropertyValue::FloatValue(value)) => Ok(*value),
+        Some(PropertyValue::IntValue(value)) => Ok(*value as f32),
+        Some(_) => Err(AssetError::new(
+            map_path,
+            format!("`{}` of layer {} must be a number", name, layer),
+        )),
+    }
+}
+
+fn bool_property(
+    properties: &Properties,
+    name: &str,
+    map_path: &Path,
+    layer: &str,
+) -> Result<bool, AssetError> {
+    match properties.get(name) {
+        None => Ok(false),
+        Some(PropertyValue::BoolValue(value)) => Ok(*value),
+        Some(_) => Err(AssetError::new(
+            map_path,
+            format!("`{}` of layer {} must be a bool", name, layer),
+        )),
+    }
+}
+
+/// The frames of `gid` as gids and milliseconds, when its tileset animates
+/// it.
+fn tile_animation(
+    gid: u32,
+    map: &Map,
+    map_path: &Path,
+) -> Result<Option<Vec<(u32, u32)>>, AssetError> {
+    let tileset = find_tileset_with_gid(gid, &map.tilesets, map_path)?;
+    let id = gid - tileset.first_gid;
+
+    Ok(tileset
+        .tiles
+        .iter()
+        .find(|tile| tile.id == id)
+        .and_then(|tile| tile.animation.as_ref())
+        .map(|frames| {
+            frames
+                .iter()
+                .map(|frame| (tileset.first_gid + frame.tile_id, frame.duration))
+                .collect()
+        }))
+}
+
+/// A custom property as a `(name, StaticProperty)` in the generated code.
+fn property_code(name: &str, value: &PropertyValue) -> String {
+    let value = match value {
+        // The game has no use for bools yet, they read as 0 or 1.
+        PropertyValue::BoolValue(value) => format!("Int({})", *value as i32),
+        PropertyValue::FloatValue(value) => format!("Float({}_f32)", value),
+        PropertyValue::IntValue(value) => format!("Int({})", value),
+        // ARGB, the game has no use for colors yet.
+        PropertyValue::ColorValue(value) => format!("Int({})", *value as i32),
+        PropertyValue::StringValue(value) => format!("String({:?})", value),
+    };
+
+    format!("({:?}, StaticProperty::{}), ", name, value)
+}
+
+/// Everything the maps share while they are processed in order.
+struct MapState<'a> {
+    out_dir: &'a Path,
+    tileset_images: HashMap<PathBuf, Image>,
+    emitted_object_textures: HashSet<String>,
+    output: Output,
+}
+
+impl<'a> MapState<'a> {
+    fn tileset_image(&mut self, path: &Path, rotate_180: bool) -> Result<&Image, AssetError> {
+        if !self.tileset_images.contains_key(path) {
+            let image = load_png(path, rotate_180, None)?;
+            self.tileset_images.insert(path.to_path_buf(), image);
+        }
+
+        Ok(&self.tileset_images[path])
+    }
+
+    fn file(
+        &mut self,
+        path: PathBuf,
+        category: &str,
+        asset: &str,
+        data: Vec<u8>,
+        field: &str,
+    ) -> &mut OutputFile {
+        self.output.files.push(OutputFile {
+            path,
+            category: category.to_string(),
+            asset: asset.to_string(),
+            data,
+            included_as: Some(format!("{}.{}", asset, field)),
+            requires: None,
+        });
+        self.output.files.last_mut().unwrap()
+    }
+}
+
+fn find_tileset_with_gid<'a>(
+    gid: u32,
+    tilesets: &'a [Tileset],
+    map_path: &Path,
+) -> Result<&'a Tileset, AssetError> {
+    for tileset in tilesets {
+        let effective_gid = gid as i32 - tileset.first_gid as i32;
+        let tilecount = tileset.tilecount.ok_or_else(|| {
+            AssetError::new(
+                map_path,
+                format!("Tileset {} needs a tilecount", tileset.name),
+            )
+        })?;
+
+        if effective_gid >= 0 && (effective_gid as u32) < tilecount {
+            return Ok(tileset);
+        }
+    }
+
+    Err(AssetError::new(
+        map_path,
+        format!("No tileset has tile {}", gid),
+    ))
+}
+
+fn tileset_file(map_path: &Path, tileset: &Tileset, source: &str) -> PathBuf {
+    tileset
+        .source
+        .as_ref()
+        .map(Path::new)
+        .unwrap_or(map_path)
+        .with_file_name(source)
+}
+
+fn load_tile_image(
+    state: &mut MapState,
+    gid: u32,
+    map_path: &Path,
+    tileset: &Tileset,
+    width: i32,
+    height: i32,
+    rotate_180: bool,
+) -> Result<Vec<u8>, AssetError> {
+    let mut effective_gid = gid - tileset.first_gid;
+
+    let tile_width = tileset.tile_width;
+    let tile_height = tileset.tile_height;
+    let tile_size = tile_width * tile_height;
+
+    for tileset_image in tileset.images.iter() {
+        let image_size = (tileset_image.width * tileset_image.height) as u32;
+        let image_tiles = image_size / tile_size;
+
+        let image_path = tileset_file(map_path, tileset, &tileset_image.source);
+
+        if effective_gid < image_tiles {
+            if tile_width != width as u32 || tile_height != height as u32 {
+                return Err(AssetError::new(
+                    &image_path,
+                    format!(
+                        "Tiles are {}x{}, not {}x{} as the map needs",
+                        tile_width, tile_height, width, height
+                    ),
+                ));
+            }
+
+            let image = state.tileset_image(&image_path, rotate_180)?;
+
+            let mut res = vec![0; 2 * tile_size as usize];
+
+            let image_width_tiles = image.width as u32 / tile_width;
+
+            let tile_x = effective_gid % image_width_tiles;
+            let tile_y = effective_gid / image_width_tiles;
+
+            let start_x = tile_x * tile_width;
+            let start_y = tile_y * tile_height;
+
+            let image_stride = image.width as u32;
+
+            for y in 0..tile_height {
+                for x in 0..tile_width {
+                    let out_index = 2 * (x + tile_width * y) as usize;
+                    let image_index = 2 * ((start_x + x) + image_stride * (start_y + y)) as usize;
+
+                    let pixel = image
+                        .data
+                        .get(image_index..image_index + 2)
+                        .ok_or_else(|| {
+                            AssetError::new(
+                                &image_path,
+                                format!("Tile {} is outside of the image", gid),
+                            )
+                        })?;
+
+                    res[out_index..out_index + 2].copy_from_slice(pixel);
+                }
+            }
+
+            return Ok(res);
+        }
+
+        effective_gid -= image_tiles;
+    }
+
+    for tile in &tileset.tiles {
+        if let Some(image) = tile.images.first() {
+            if tile.id == effective_gid {
+                let image_path = tileset_file(map_path, tileset, &image.source);
+                let image = load_png(&image_path, rotate_180, Some((width, height)))?;
+
+                return Ok(image.data);
+            }
+        }
+    }
+
+    Err(AssetError::new(
+        map_path,
+        format!(
+            "Tile {} is not in the images of tileset {}",
+            gid, tileset.name
+        ),
+    ))
+}
+
+fn process_tiles(
+    state: &mut MapState,
+    map_path: &Path,
+    name: &str,
+    map: &Map,
+    tile_ids: &TileIds,
+) -> Result<(Vec<String>, Vec<String>), AssetError> {
+    let mut map_tiles = Vec::new();
+    let mut map_tile_refs = Vec::new();
+
+    for id in tile_ids.gids.iter() {
+        if *id == 0 {
+            continue;
+        }
+
+        let width = map.tile_width as i32;
+        let height = map.tile_height as i32;
+
+        let tile_path = state
+            .out_dir
+            .join(format!("{}_tile_{}", name.to_lowercase(), *id))
+            .with_extension("ntex");
+
+        let tileset = find_tileset_with_gid(*id, &map.tilesets, map_path)?;
+        let tile_image = load_tile_image(state, *id, map_path, tileset, width, height, false)?;
+
+        let tile_ident = format!("{}_TILE_{}", name, id);
+
+        let tile = format!(
+            TILE_TEMPLATE!(),
+            tile_ident = tile_ident,
+            width = width,
+            height = height,
+            tile_path = tile_path,
+        );
+
+        let tile_ref = format!(TILE_IDENT_TEMPLATE!(), tile_ident = tile_ident);
+
+        state.file(tile_path, "map tiles", &tile_ident, tile_image, "data");
+        map_tiles.push(tile);
+        map_tile_refs.push(tile_ref);
+    }
+
+    Ok((map_tiles, map_tile_refs))
+}
+
+/// The object and tileset to draw `object` with, when it is a tile object
+/// itself or through its template.
+fn object_sprite<'a>(
+    object: &'a Object,
+    map: &'a Map,
+    map_path: &Path,
+) -> Result<Option<(&'a Object, &'a Tileset)>, AssetError> {
+    if let Some(ObjectTemplate {
+        object: Some(template_object),
+        tileset: Some(tileset),
+    }) = object.template.as_deref()
+    {
+        if template_object.gid != 0 {
+            return Ok(Some((template_object, tileset)));
+        }
+    }
+
+    if object.gid != 0 {
+        let tileset = find_tileset_with_gid(object.gid, &map.tilesets, map_path)?;
+        return Ok(Some((object, tileset)));
+    }
+
+    Ok(None)
+}
+
+fn process_objects(
+    state: &mut MapState,
+    map: &Map,
+    map_path: &Path,
+) -> Result<(Vec<String>, Vec<String>), AssetError> {
+    let mut objects = Vec::new();
+    let mut object_textures = Vec::new();
+
+    for object_group in &map.object_groups {
+        for object in &object_group.objects {
+            let template_object = object
+                .template
+                .as_deref()
+                .and_then(|template| template.object.as_ref());
+
+            // The object overrides the type and properties of its template.
+            let kind = match template_object {
+                Some(template_object) if object.obj_type.is_empty() => &template_object.obj_type,
+                _ => &object.obj_type,
+            };
+
+            let mut properties = BTreeMap::new();
+            properties.extend(template_object.iter().flat_map(|t| t.properties.iter()));
+            properties.extend(object.properties.iter());
+
+            let properties: String = properties
+                .iter()
+                .map(|(name, value)| property_code(name, value))
+                .collect();
+
+            let (x, y, height, shape) = match object_sprite(object, map, map_path)? {
+                Some((sprite_object, tileset)) => {
+                    let object_texture_ident = format!(
+                        "OBJECT_TEXTURE_{}_{}{}_{}X{}",
+                        tileset.name.to_uppercase(),
+                        sprite_object.gid,
+                        if sprite_object.rotation == 180.0 {
+                            "_ROT_180"
+                        } else {
+                            ""
+                        },
+                        sprite_object.width,
+                        sprite_object.height,
+                    );
+
+                    if !state
+                        .emitted_object_textures
+                        .contains(&object_texture_ident)
+                    {
+                        let object_texture_path = state
+                            .out_dir
+                            .join(object_texture_ident.to_lowercase())
+                            .with_extension("ntex");
+
+                        let texture_image = load_tile_image(
+                            state,
+                            sprite_object.gid,
+                            map_path,
+                            tileset,
+                            sprite_object.width as i32,
+                            sprite_object.height as i32,
+                            sprite_object.rotation == 180.0,
+                        )?;
+
+                        let expected_len =
+                            2 * sprite_object.width as usize * sprite_object.height as usize;
+
+                        if texture_image.len() != expected_len {
+                            return Err(AssetError::new(
+                                map_path,
+                                format!(
+                                    "Object texture {} is {} bytes, not {}",
+                                    object_texture_ident,
+                                    texture_image.len(),
+                                    expected_len
+                                ),
+                            ));
+                        }
+
+                        object_textures.push(format!(
+                            OBJECT_TEXTURE_TEMPLATE!(),
+                            object_texture_ident = object_texture_ident,
+                            width = sprite_object.width as i32,
+                            height = sprite_object.height as i32,
+                            object_texture_path = object_texture_path,
+                        ));
+
+                        state.file(
+                            object_texture_path,
+                            "map objects",
+                            &object_texture_ident,
+                            texture_image,
+                            "data",
+                        );
+                        state
+                            .emitted_object_textures
+                            .insert(object_texture_ident.clone());
+                    }
+
+                    (
+                        object.x - sprite_object.width / 2.0,
+                        object.y - sprite_object.height / 2.0,
+                        sprite_object.height,
+                        format!("StaticObjectShape::Sprite(&{})", object_texture_ident),
+                    )
+                }
+                None => match &object.shape {
+                    ObjectShape::Rect { width, height }
+                    | ObjectShape::Ellipse { width, height } => (
+                        object.x + width / 2.0,
+                        object.y + height / 2.0,
+                        *height,
+                        "StaticObjectShape::Region".to_string(),
+                    ),
+                    ObjectShape::Point(_, _) => (
+                        object.x,
+                        object.y,
+                        0.0,
+                        "StaticObjectShape::Region".to_string(),
+                    ),
+                    ObjectShape::Polyline { points } | ObjectShape::Polygon { points } => {
+                        let points: String = points
+                            .iter()
+                            .map(|(x, y)| {
+                                format!(
+                                    "n64_math::Vec2::new({}_f32, {}_f32), ",
+                                    object.x + x,
+                                    object.y + y
+                                )
+                            })
+                            .collect();
+
+                        (
+                            object.x,
+                            object.y,
+                            0.0,
+                            format!("StaticObjectShape::Path(&[{}])", points),
+                        )
+                    }
+                },
+            };
+
+            objects.push(format!(
+                OBJECT_TEMPLATE!(),
+                kind = kind,
+                name = object.name,
+                x = x,
+                y = y,
+                height = height,
+                shape = shape,
+                properties = properties,
+            ));
+        }
+    }
+
+    Ok((objects, object_textures))
+}
+
+/// Every file in the directories of `inputs` and below, where tilesets,
+/// templates and tile images usually are.
+fn files_below(dirs: &[&Path]) -> Result<Vec<PathBuf>, AssetError> {
+    let mut files = Vec::new();
+    let mut dirs: Vec<PathBuf> = dirs.iter().map(|dir| dir.to_path_buf()).collect();
+    dirs.sort();
+    dirs.dedup();
+
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir).in_file(&dir)? {
+            let path = entry.in_file(&dir)?.path();
+
+            if path.is_dir() {
+                dirs.push(path);
+            } else {
+                files.push(path);
+            }
+        }
+    }
+
+    files.sort();
+
+    Ok(files)
+}
+
+/// Tiled maps become a `StaticMapData` each, with their tiles, layers and
+/// objects. Objects keep their type, name and custom properties, tile
+/// objects become sprites, polylines and polygons paths and other shapes
+/// regions. Maps are processed together since their layers share tile ids.
+pub struct MapProcessor;
+
+impl Processor for MapProcessor {
+    fn header(&self) -> &str {
+        "#[allow(unused_imports)]
+use crate::map::{
+    StaticMapData, StaticMapLayer, StaticObject, StaticObjectShape, StaticProperty, TileAnimation,
+    TileFrame,
+};
+use n64_math::Color;
+use n64::gfx::StaticTexture;
+use n64::include_bytes_align_as;
+"
+    }
+
+    fn version(&self) -> String {
+        "map 6".to_string()
+    }
+
+    fn batch(&self) -> bool {
+        true
+    }
+
+    fn dependencies(&self, inputs: &[PathBuf]) -> Result<Vec<PathBuf>, AssetError> {
+        let dirs: Vec<&Path> = inputs.iter().filter_map(|input| input.parent()).collect();
+
+        Ok(files_below(&dirs)?
+            .into_iter()
+            .filter(|file| !inputs.contains(file))
+            .collect())
+    }
+
+    fn process(&self, inputs: &[PathBuf], out_dir: &Path) -> Result<Output, AssetError> {
+        let mut state = MapState {
+            out_dir,
+            tileset_images: HashMap::new(),
+            emitted_object_textures: HashSet::new(),
+            output: Output::default(),
+        };
+
+        let mut maps = Vec::new();
+        let mut tiles = Vec::new();
+
+        let mut tile_ids = TileIds::new();
+
+        for path in inputs {
+            let name = asset_ident(path)?;
+
+            let map = {
+                let file = File::open(path)
+                    .map_err(|e| AssetError::new(path, format!("Unable to open: {}", e)))?;
+                let reader = BufReader::new(file);
+                tiled::parse_with_path(reader, path).in_file(path)?
+            };
+
+            let mut cells: Vec<u8> = Vec::new();
+            let mut layers = Vec::new();
+            let mut collision: Option<Vec<u8>> = None;
+            let mut map_tile_ids = HashSet::new();
+            let mut filled_cells = 0;
+
+            for layer in map.layers.iter() {
+                let rows = match &layer.tiles {
+                    LayerData::Finite(rows) => rows,
+                    _ => {
+                        return Err(AssetError::new(
+                            path,
+                            format!(
+                                "Layer {} is infinite, only finite maps are supported",
+                                layer.name
+                            ),
+                        ))
+                    }
+                };
+
+                // Collision layers are not drawn, any tile in them is solid.
+                if bool_property(&layer.properties, "collision", path, &layer.name)? {
+                    let collision =
+                        collision.get_or_insert_with(|| vec![0; (map.width * map.height) as usize]);
+
+                    for (solid, tile) in collision.iter_mut().zip(rows.iter().flatten()) {
+                        if tile.gid != 0 {
+                            *solid = 1;
+                        }
+                    }
+
+                    continue;
+                }
+
+                layers.push(format!(
+                    LAYER_TEMPLATE!(),
+                    parallax_x =
+                        float_property(&layer.properties, "parallax_x", 1.0, path, &layer.name)?,
+                    parallax_y =
+                        float_property(&layer.properties, "parallax_y", 1.0, path, &layer.name)?,
+                    visible = layer.visible,
+                ));
+
+                for tile in rows.iter().flatten() {
+                    let index = if tile.gid != 0 {
+                        map_tile_ids.insert(tile.gid);
+                        filled_cells += 1;
+                        tile_ids.index(tile.gid, path)?
+                    } else {
+                        0
+                    };
+
+                    cells.extend_from_slice(
+                        &cell(index, tile.flip_h, tile.flip_v, tile.flip_d).to_be_bytes(),
+                    );
+                }
+            }
+
+            let mut animated_tiles: Vec<u32> = map_tile_ids.iter().copied().collect();
+            animated_tiles.sort_by_key(|gid| tile_ids.by_gid[gid]);
+
+            let mut animations = Vec::new();
+
+            for gid in animated_tiles {
+                if let Some(frames) = tile_animation(gid, &map, path)? {
+                    let mut frame_refs = Vec::new();
+
+                    for (frame_gid, duration_ms) in frames {
+                        frame_refs.push(format!(
+                            FRAME_TEMPLATE!(),
+                            tile = tile_ids.index(frame_gid, path)?,
+                            duration_ms = duration_ms,
+                        ));
+                    }
+
+                    animations.push(format!(
+                        ANIMATION_TEMPLATE!(),
+                        tile = tile_ids.by_gid[&gid],
+                        frames = frame_refs.join(""),
+                    ));
+                }
+            }
+
+            let (map_tiles, map_tile_refs) =
+                process_tiles(&mut state, path, &name, &map, &tile_ids)?;
+
+            tiles.extend_from_slice(&map_tiles);
+
+            let map_data_path = out_dir.join(name.to_lowercase()).with_extension("nmap");
+
+            // Every map includes the tiles of the maps before it as well as
+            // its own, the report shows how many of them it actually uses.
+            state.output.records.push(format!(
+                "map\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                name,
+                map.width,
+                map.height,
+                layers.len(),
+                filled_cells,
+                map_tile_ids.len(),
+                map_tiles.len()
+            ));
+
+            // Cells only index the tiles as they are numbered now, new or
+            // reordered tiles mean the compiled tile table no longer fits.
+            // Written before the cells so a reload never sees new cells
+            // next to the old table.
+            let tile_gids_path = out_dir
+                .join(format!("{}_tile_gids", name.to_lowercase()))
+                .with_extension("ngid");
+            let mut tile_gids = Vec::new();
+            for gid in tile_ids.gids.iter() {
+                tile_gids.extend_from_slice(&gid.to_be_bytes());
+            }
+            state.output.files.push(OutputFile {
+                path: tile_gids_path.clone(),
+                category: "map tile ids".to_string(),
+                asset: name.clone(),
+                data: tile_gids,
+                included_as: None,
+                requires: None,
+            });
+
+            state
+                .file(map_data_path.clone(), "map layers", &name, cells, "cells")
+                .requires = Some(tile_gids_path);
+
+            let collision = match collision {
+                Some(collision) => {
+                    let collision_path = out_dir
+                        .join(format!("{}_collision", name.to_lowercase()))
+                        .with_extension("ncol");
+                    let code = format!("include_bytes!({:?})", collision_path);
+                    state.file(
+                        collision_path,
+                        "map collision",
+                        &name,
+                        collision,
+                        "collision",
+                    );
+                    code
+                }
+                None => "&[]".to_string(),
+            };
+
+            let (objects, object_textures) = process_objects(&mut state, &map, path)?;
+
+            let map_name_ident = name.to_string();
+            let tiles_name_ident = format!("{}_TILES", &name);
+            let layers_name_ident = format!("{}_LAYERS", &name);
+            let animations_name_ident = format!("{}_ANIMATIONS", &name);
+            let objects_name_ident = format!("{}_OBJECTS", &name);
+            let map_width = map.width as i32;
+            let map_height = map.height as i32;
+            let tile_width = map.tile_width as i32;
+            let tile_height = map.tile_height as i32;
+
+            let map = format!(
+                MAP_TEMPLATE!(),
+                map_name_ident = map_name_ident,
+                tiles_name_ident = tiles_name_ident,
+                map_tile_refs = map_tile_refs.join(""),
+                layers_name_ident = layers_name_ident,
+                layers = layers.join(""),
+                animations_name_ident = animations_name_ident,
+                animations = animations.join(""),
+                collision = collision,
+                map_width = map_width,
+                map_height = map_height,
+                tile_width = tile_width,
+                tile_height = tile_height,
+                map_data_path = map_data_path,
+                object_textures = object_textures.join(""),
+                objects = objects.join(""),
+                objects_name_ident = objects_name_ident,
+            );
+
+            maps.push(map);
+        }
+
+        state.output.code = format!("{}\n{}\n", tiles.join(""), maps.join(""));
+
+        Ok(state.output)
+    }
+}
+
+#[test]
+fn cells_keep_the_flips_of_tiled() {
+    assert_eq!(cell(5, false, false, false), 5);
+    assert_eq!(cell(5, true, false, false), 0x8005);
+    assert_eq!(cell(5, false, true, true), 0x6005);
+    assert_eq!(cell(0x1fff, true, true, true), 0xffff);
+}
+
+#[test]
+fn tile_ids_are_shared_and_limited() {
+    let path = Path::new("map_1.tmx");
+    let mut ids = TileIds::new();
+
+    assert_eq!(ids.index(0, path).unwrap(), 0);
+    assert_eq!(ids.index(300, path).unwrap(), 1);
+    assert_eq!(ids.index(7, path).unwrap(), 2);
+    assert_eq!(ids.index(300, path).unwrap(), 1);
+
+    for gid in 1000..(1000 + MAX_TILES as u32 - 2) {
+        ids.index(gid, path).unwrap();
+    }
+
+    assert_eq!(ids.index(7, path).unwrap(), 2);
+    assert_eq!(
+        ids.index(1, path).err().unwrap().to_string(),
+        "map_1.tmx: The maps use more than 8191 tiles"
+    );
+}
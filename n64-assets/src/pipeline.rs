@@ -0,0 +1,265 @@
+use crate::cache::{Cache, ContentHash};
+use crate::error::InFile;
+use crate::{
+    AssetError, BulletPatternProcessor, Manifest, MapProcessor, Output, Processor, SoundProcessor,
+    TextureProcessor,
+};
+use rayon::prelude::*;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Registers the included files of a module for hot reload in the PC build.
+#[rustfmt::skip]
+macro_rules! WATCH_TEMPLATE { () => {
+r##"#[cfg(not(target_vendor = "nintendo64"))]
+pub fn watch() {{
+{watch}}}
+"##
+}; }
+
+pub struct Pipeline {
+    out_dir: PathBuf,
+    processors: Vec<(String, Box<dyn Processor>)>,
+}
+
+/// What a run did.
+pub struct Report {
+    pub processed: usize,
+    pub cached: usize,
+    /// Every input and dependency of the stages.
+    pub files: Vec<PathBuf>,
+}
+
+struct Job<'a> {
+    stage: usize,
+    processor: &'a dyn Processor,
+    inputs: Vec<PathBuf>,
+    out_dir: PathBuf,
+}
+
+struct Done {
+    stage: usize,
+    output: Output,
+    key: u64,
+    cached: bool,
+    files: Vec<PathBuf>,
+}
+
+impl Pipeline {
+    /// With the `texture`, `map`, `sound` and `bullets` processors. Binary files and
+    /// the cache go under `out_dir`.
+    pub fn new(out_dir: impl Into<PathBuf>) -> Pipeline {
+        Pipeline {
+            out_dir: out_dir.into(),
+            processors: Vec::new(),
+        }
+        .processor("texture", TextureProcessor)
+        .processor("map", MapProcessor)
+        .processor("sound", SoundProcessor::default())
+        .processor("bullets", BulletPatternProcessor)
+    }
+
+    /// Adds the processor stages refer to as `name`, replacing any before.
+    pub fn processor(mut self, name: &str, processor: impl Processor + 'static) -> Pipeline {
+        self.processors.retain(|(existing, _)| existing != name);
+        self.processors
+            .push((name.to_string(), Box::new(processor)));
+        self
+    }
+
+    pub fn run(&self, manifest: &Manifest) -> Result<Report, AssetError> {
+        let mut jobs = Vec::new();
+        let mut processors = Vec::new();
+
+        for (index, stage) in manifest.stages.iter().enumerate() {
+            let processor = self
+                .processors
+                .iter()
+                .find(|(name, _)| *name == stage.processor)
+                .map(|(_, processor)| processor.as_ref())
+                .ok_or_else(|| {
+                    AssetError::at_line(
+                        &manifest.path,
+                        stage.line,
+                        format!("Unknown processor `{}`", stage.processor),
+                    )
+                })?;
+
+            processors.push(processor);
+
+            let inputs = manifest.inputs(stage)?;
+            let out_dir = self.out_dir.join(&stage.name);
+
+            if processor.batch() {
+                jobs.push(Job {
+                    stage: index,
+                    processor,
+                    inputs,
+                    out_dir,
+                });
+            } else {
+                for input in inputs {
+                    jobs.push(Job {
+                        stage: index,
+                        processor,
+                        inputs: vec![input],
+                        out_dir: out_dir.clone(),
+                    });
+                }
+            }
+        }
+
+        let cache = Cache::new(self.out_dir.join("cache"));
+        let done = jobs
+            .par_iter()
+            .map(|job| run_job(job, &cache))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut modules = vec![String::new(); manifest.stages.len()];
+        let mut watches = vec![String::new(); manifest.stages.len()];
+        let mut assets = vec![Vec::new(); manifest.stages.len()];
+        let mut sizes = String::from("# Generated by n64-assets, read by n64-rom size\n");
+        let mut keys = HashSet::new();
+        let mut report = Report {
+            processed: 0,
+            cached: 0,
+            files: Vec::new(),
+        };
+
+        for done in done {
+            for file in done.output.files.iter() {
+                write_if_changed(&file.path, &file.data)?;
+                assets[done.stage].push(file.asset.clone());
+
+                // Only the PC build includes required files, to check
+                // reloads against, they are not in the ROM.
+                let required = done
+                    .output
+                    .files
+                    .iter()
+                    .any(|other| other.requires.as_ref() == Some(&file.path));
+
+                if !required {
+                    sizes.push_str(&format!(
+                        "asset\t{}\t{}\t{}\n",
+                        file.category,
+                        file.asset,
+                        file.data.len()
+                    ));
+                }
+
+                match (&file.included_as, &file.requires) {
+                    (Some(included_as), None) => watches[done.stage].push_str(&format!(
+                        "    n64::hot_reload::watch({}, {:?});\n",
+                        included_as, file.path
+                    )),
+                    (Some(included_as), Some(required)) => {
+                        watches[done.stage].push_str(&format!(
+                            "    n64::hot_reload::watch_requiring({}, {:?}, include_bytes!({:?}), {:?});\n",
+                            included_as, file.path, required, required
+                        ))
+                    }
+                    (None, _) => (),
+                }
+            }
+
+            for record in done.output.records.iter() {
+                sizes.push_str(record);
+                sizes.push('\n');
+            }
+
+            modules[done.stage].push_str(&done.output.code);
+            keys.insert(done.key);
+
+            if done.cached {
+                report.cached += 1;
+            } else {
+                report.processed += 1;
+            }
+
+            report.files.extend(done.files);
+        }
+
+        for ((((stage, processor), code), watch), assets) in manifest
+            .stages
+            .iter()
+            .zip(processors)
+            .zip(modules)
+            .zip(watches)
+            .zip(assets)
+        {
+            let watch = format!(WATCH_TEMPLATE!(), watch = watch);
+            let module = format!(
+                "// This file is generated\n\n#![cfg_attr(rustfmt, rustfmt::skip)]\n\n{}\n{}{}\n{}",
+                processor.header(),
+                code,
+                processor.footer(&assets),
+                watch
+            );
+            write_if_changed(&stage.output, module.as_bytes())?;
+        }
+
+        if let Some(path) = &manifest.sizes {
+            write_if_changed(path, sizes.as_bytes())?;
+        }
+
+        cache.retain(&keys);
+
+        Ok(report)
+    }
+}
+
+fn run_job(job: &Job, cache: &Cache) -> Result<Done, AssetError> {
+    let mut files = job.inputs.clone();
+    files.extend(job.processor.dependencies(&job.inputs)?);
+
+    // The out directory is part of the key since the generated code
+    // includes files from it by path.
+    let mut hash = ContentHash::new();
+    hash.write(job.processor.version().as_bytes());
+    hash.write(job.out_dir.to_string_lossy().as_bytes());
+
+    for file in files.iter() {
+        hash.write(file.to_string_lossy().as_bytes());
+        hash.write(&fs::read(file).in_file(file)?);
+    }
+
+    let key = hash.finish();
+
+    if let Some(output) = cache.get(key) {
+        return Ok(Done {
+            stage: job.stage,
+            output,
+            key,
+            cached: true,
+            files,
+        });
+    }
+
+    fs::create_dir_all(&job.out_dir).in_file(&job.out_dir)?;
+    let output = job.processor.process(&job.inputs, &job.out_dir)?;
+    cache.put(key, &output)?;
+
+    Ok(Done {
+        stage: job.stage,
+        output,
+        key,
+        cached: false,
+        files,
+    })
+}
+
+/// Leaves files that are already up to date alone, so cargo does not see
+/// them as changed.
+pub(crate) fn write_if_changed(path: &Path, content: &[u8]) -> Result<(), AssetError> {
+    if fs::read(path).ok().as_deref() == Some(content) {
+        return Ok(());
+    }
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).in_file(dir)?;
+    }
+
+    fs::write(path, content).map_err(|e| AssetError::new(path, format!("Unable to write: {}", e)))
+}
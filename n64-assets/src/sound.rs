@@ -0,0 +1,933 @@
+use crate::error::InFile;
+use crate::{asset_ident, AssetError, Output, OutputFile, Processor};
+use n64_types::{ADPCM_COEFFICIENT_SHIFT, ADPCM_FRAME_BYTES, ADPCM_FRAME_SAMPLES};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use zerocopy::AsBytes;
+
+/// The rate the game mixes at by default, see `n64::AudioConfig`.
+const DEFAULT_SAMPLE_RATE: u32 = 22050;
+const DEFAULT_SILENCE_THRESHOLD: f32 = 1.0 / 1024.0;
+const RESAMPLE_HALF_TAPS: i64 = 32;
+
+struct Wav {
+    channels: usize,
+    samples: Vec<i16>,
+    looping: Option<(usize, usize)>,
+}
+
+/// Per sound import settings, read from an optional `<name>.toml` next to
+/// the wav file.
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+struct SoundSettings {
+    normalize: bool,
+    trim_silence: bool,
+    silence_threshold: f32,
+    loop_start: Option<usize>,
+    loop_end: Option<usize>,
+}
+
+impl Default for SoundSettings {
+    fn default() -> Self {
+        Self {
+            normalize: false,
+            trim_silence: false,
+            silence_threshold: DEFAULT_SILENCE_THRESHOLD,
+            loop_start: None,
+            loop_end: None,
+        }
+    }
+}
+
+fn load_sound_settings(path: &Path) -> Result<SoundSettings, AssetError> {
+    let settings_path = path.with_extension("toml");
+
+    let content = match fs::read_to_string(&settings_path) {
+        Ok(content) => content,
+        Err(_) => return Ok(SoundSettings::default()),
+    };
+
+    toml::from_str(&content).in_file(&settings_path)
+}
+
+fn read_wav_channels(path: &Path) -> Result<(u32, Vec<Vec<f32>>), AssetError> {
+    let reader = hound::WavReader::open(path)
+        .map_err(|e| AssetError::new(path, format!("Unable to load: {}", e)))?;
+
+    let spec = reader.spec();
+    let channel_count = spec.channels as usize;
+
+    if channel_count == 0 {
+        return Err(AssetError::new(path, "Wav file has no channels"));
+    }
+
+    let read_error = |e: hound::Error| AssetError::new(path, e.to_string());
+
+    let samples: Vec<f32> = match (spec.sample_format, spec.bits_per_sample) {
+        (hound::SampleFormat::Float, 32) => reader
+            .into_samples::<f32>()
+            .collect::<Result<_, _>>()
+            .map_err(read_error)?,
+        (hound::SampleFormat::Int, 8) => reader
+            .into_samples::<i8>()
+            .map(|s| s.map(|s| s as f32 / 128.0))
+            .collect::<Result<_, _>>()
+            .map_err(read_error)?,
+        (hound::SampleFormat::Int, 16) => reader
+            .into_samples::<i16>()
+            .map(|s| s.map(|s| s as f32 / 32768.0))
+            .collect::<Result<_, _>>()
+            .map_err(read_error)?,
+        (hound::SampleFormat::Int, bits @ 24) | (hound::SampleFormat::Int, bits @ 32) => {
+            let scale = (1u64 << (bits - 1)) as f32;
+            reader
+                .into_samples::<i32>()
+                .map(|s| s.map(|s| s as f32 / scale))
+                .collect::<Result<_, _>>()
+                .map_err(read_error)?
+        }
+        (format, bits) => {
+            return Err(AssetError::new(
+                path,
+                format!(
+                    "Unsupported sample format {:?} with {} bits per sample",
+                    format, bits
+                ),
+            ))
+        }
+    };
+
+    let channels = (0..channel_count)
+        .map(|c| {
+            samples
+                .iter()
+                .skip(c)
+                .step_by(channel_count)
+                .copied()
+                .collect()
+        })
+        .collect();
+
+    Ok((spec.sample_rate, channels))
+}
+
+// Mono and stereo are kept as is, anything wider is folded into stereo with
+// even channels going left and odd channels going right.
+fn downmix(mut channels: Vec<Vec<f32>>) -> Vec<Vec<f32>> {
+    if channels.len() <= 2 {
+        return channels;
+    }
+
+    let len = channels[0].len();
+    let mut left = vec![0.0; len];
+    let mut right = vec![0.0; len];
+    let left_count = (channels.len() - channels.len() / 2) as f32;
+    let right_count = (channels.len() / 2) as f32;
+
+    for (i, channel) in channels.drain(..).enumerate() {
+        let (out, count) = if i % 2 == 0 {
+            (&mut left, left_count)
+        } else {
+            (&mut right, right_count)
+        };
+
+        for (o, s) in out.iter_mut().zip(channel) {
+            *o += s / count;
+        }
+    }
+
+    vec![left, right]
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-9 {
+        1.0
+    } else {
+        let x = std::f64::consts::PI * x;
+        x.sin() / x
+    }
+}
+
+fn blackman(x: f64) -> f64 {
+    let x = std::f64::consts::PI * x;
+    0.42 + 0.5 * x.cos() + 0.08 * (2.0 * x).cos()
+}
+
+// Windowed sinc resampler. When downsampling the cutoff follows the target
+// rate to avoid aliasing.
+fn resample(channel: &[f32], from_rate: u32, to_rate: u32) -> Vec<f32> {
+    if from_rate == to_rate {
+        return channel.to_vec();
+    }
+
+    let ratio = to_rate as f64 / from_rate as f64;
+    let cutoff = ratio.min(1.0) * 0.95;
+    let out_len = (channel.len() as f64 * ratio).round() as usize;
+
+    (0..out_len)
+        .map(|i| {
+            let center = i as f64 / ratio;
+            let first = center.floor() as i64 - RESAMPLE_HALF_TAPS + 1;
+
+            let mut accumulator = 0.0;
+
+            for j in first..(first + 2 * RESAMPLE_HALF_TAPS) {
+                if j < 0 || j >= channel.len() as i64 {
+                    continue;
+                }
+
+                let x = center - j as f64;
+                accumulator += channel[j as usize] as f64
+                    * cutoff
+                    * sinc(cutoff * x)
+                    * blackman(x / RESAMPLE_HALF_TAPS as f64);
+            }
+
+            accumulator as f32
+        })
+        .collect()
+}
+
+fn load_wav(path: &Path, sample_rate: u32) -> Result<Wav, AssetError> {
+    let settings = load_sound_settings(path)?;
+    let (source_rate, channels) = read_wav_channels(path)?;
+    let mut channels = downmix(channels);
+
+    let source_len = channels[0].len();
+    let mut range = (0, source_len);
+
+    if settings.trim_silence {
+        let is_silent = |i: usize| {
+            channels
+                .iter()
+                .all(|c| c[i].abs() < settings.silence_threshold)
+        };
+
+        while range.0 < range.1 && is_silent(range.0) {
+            range.0 += 1;
+        }
+
+        while range.1 > range.0 && is_silent(range.1 - 1) {
+            range.1 -= 1;
+        }
+    }
+
+    let looping = match (settings.loop_start, settings.loop_end) {
+        (None, None) => None,
+        (start, end) => {
+            let start = start.unwrap_or(0);
+            let end = end.unwrap_or(source_len);
+
+            if start >= end || end > source_len {
+                return Err(AssetError::new(
+                    path.with_extension("toml"),
+                    format!(
+                        "Loop {}..{} is outside of the sound (0..{})",
+                        start, end, source_len
+                    ),
+                ));
+            }
+
+            // Never trim away the looping part.
+            range = (range.0.min(start), range.1.max(end));
+
+            Some((start, end))
+        }
+    };
+
+    for channel in channels.iter_mut() {
+        *channel = resample(&channel[range.0..range.1], source_rate, sample_rate);
+    }
+
+    if settings.normalize {
+        let peak = channels
+            .iter()
+            .flat_map(|c| c.iter())
+            .fold(0.0f32, |peak, s| peak.max(s.abs()));
+
+        if peak > 0.0 {
+            for sample in channels.iter_mut().flat_map(|c| c.iter_mut()) {
+                *sample /= peak;
+            }
+        }
+    }
+
+    let len = channels[0].len();
+    let to_output = |i: usize| {
+        let scaled = (i - range.0) as f64 * sample_rate as f64 / source_rate as f64;
+        (scaled.round() as usize).min(len)
+    };
+    let looping = looping.map(|(start, end)| (to_output(start), to_output(end)));
+
+    if let Some((start, end)) = looping {
+        if start >= end {
+            return Err(AssetError::new(
+                path.with_extension("toml"),
+                format!("Loop is empty after resampling to {} Hz", sample_rate),
+            ));
+        }
+    }
+
+    let mut samples = Vec::with_capacity(len * channels.len());
+
+    for i in 0..len {
+        for channel in channels.iter() {
+            samples.push((channel[i] * 32768.0).round().clamp(-32768.0, 32767.0) as i16);
+        }
+    }
+
+    Ok(Wav {
+        channels: channels.len(),
+        samples,
+        looping,
+    })
+}
+
+fn wav_to_pcm(wav: &Wav) -> Vec<i16> {
+    let mut data = Vec::with_capacity(2 * wav.samples.len() / wav.channels);
+
+    for sample in wav.samples.iter() {
+        data.push(sample.swap_bytes());
+
+        if wav.channels == 1 {
+            data.push(sample.swap_bytes());
+        }
+    }
+
+    data
+}
+
+// The frame format is in `n64_types::adpcm`, shared with the decoder.
+const ADPCM_PREDICTOR_COUNT: usize = 4;
+const ADPCM_MAX_SCALE: i32 = 12;
+
+struct Adpcm {
+    channels: usize,
+    sample_count: usize,
+    codebook: Vec<[i16; 2]>,
+    data: Vec<u8>,
+    loop_history: Vec<[i32; 2]>,
+}
+
+fn adpcm_frames(channel: &[i16]) -> impl Iterator<Item = [i32; ADPCM_FRAME_SAMPLES]> + '_ {
+    channel.chunks(ADPCM_FRAME_SAMPLES).map(|chunk| {
+        let mut frame = [0; ADPCM_FRAME_SAMPLES];
+        for (dst, src) in frame.iter_mut().zip(chunk) {
+            *dst = *src as i32;
+        }
+        frame
+    })
+}
+
+// Least squares fit of a second order predictor for one frame, using the
+// source signal as history.
+fn adpcm_fit_predictor(history: [i32; 2], frame: &[i32; ADPCM_FRAME_SAMPLES]) -> [f64; 2] {
+    let mut r = [[0.0f64; 2]; 2];
+    let mut p = [0.0f64; 2];
+    let mut h = [history[0] as f64, history[1] as f64];
+
+    for sample in frame.iter().map(|s| *s as f64) {
+        r[0][0] += h[0] * h[0];
+        r[0][1] += h[0] * h[1];
+        r[1][1] += h[1] * h[1];
+        p[0] += sample * h[0];
+        p[1] += sample * h[1];
+        h = [sample, h[0]];
+    }
+
+    let det = r[0][0] * r[1][1] - r[0][1] * r[0][1];
+
+    if det.abs() > 1e-6 {
+        [
+            (p[0] * r[1][1] - p[1] * r[0][1]) / det,
+            (p[1] * r[0][0] - p[0] * r[0][1]) / det,
+        ]
+    } else if r[0][0] > 1e-6 {
+        [p[0] / r[0][0], 0.0]
+    } else {
+        [0.0, 0.0]
+    }
+}
+
+fn adpcm_quantize_coefficient(coefficient: f64) -> i16 {
+    (coefficient * (1 << ADPCM_COEFFICIENT_SHIFT) as f64)
+        .round()
+        .max(-4.0 * (1 << ADPCM_COEFFICIENT_SHIFT) as f64)
+        .min(4.0 * (1 << ADPCM_COEFFICIENT_SHIFT) as f64 - 1.0) as i16
+}
+
+// Clusters the per frame predictors of the sound into a small codebook with
+// k-means. Entry 0 is always the "no prediction" predictor.
+fn adpcm_design_codebook(channels: &[Vec<i16>]) -> Vec<[i16; 2]> {
+    let mut candidates = Vec::new();
+
+    for channel in channels {
+        let mut history = [0, 0];
+
+        for frame in adpcm_frames(channel) {
+            if frame.iter().any(|s| *s != 0) {
+                candidates.push(adpcm_fit_predictor(history, &frame));
+            }
+            history = [
+                frame[ADPCM_FRAME_SAMPLES - 1],
+                frame[ADPCM_FRAME_SAMPLES - 2],
+            ];
+        }
+    }
+
+    let mut centroids: Vec<[f64; 2]> = (0..(ADPCM_PREDICTOR_COUNT - 1))
+        .filter_map(|i| {
+            candidates
+                .get(i * candidates.len() / (ADPCM_PREDICTOR_COUNT - 1))
+                .copied()
+        })
+        .collect();
+
+    for _ in 0..16 {
+        let mut sums = vec![([0.0, 0.0], 0usize); centroids.len()];
+
+        for candidate in candidates.iter() {
+            let closest = centroids
+                .iter()
+                .map(|c| (c[0] - candidate[0]).powi(2) + (c[1] - candidate[1]).powi(2))
+                .enumerate()
+                .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+                .map(|(i, _)| i)
+                .unwrap();
+
+            sums[closest].0[0] += candidate[0];
+            sums[closest].0[1] += candidate[1];
+            sums[closest].1 += 1;
+        }
+
+        for (centroid, (sum, count)) in centroids.iter_mut().zip(sums) {
+            if count > 0 {
+                *centroid = [sum[0] / count as f64, sum[1] / count as f64];
+            }
+        }
+    }
+
+    let mut codebook = vec![[0, 0]];
+
+    for centroid in centroids {
+        let entry = [
+            adpcm_quantize_coefficient(centroid[0]),
+            adpcm_quantize_coefficient(centroid[1]),
+        ];
+
+        if !codebook.contains(&entry) {
+            codebook.push(entry);
+        }
+    }
+
+    codebook
+}
+
+fn adpcm_encode_frame(
+    frame: &[i32; ADPCM_FRAME_SAMPLES],
+    codebook: &[[i16; 2]],
+    history: &mut [i32; 2],
+    out: &mut Vec<u8>,
+) {
+    let mut best: Option<(i64, u8, [i32; 2], [u8; ADPCM_FRAME_BYTES - 1])> = None;
+
+    for (predictor_index, predictor) in codebook.iter().enumerate() {
+        for scale in 0..=ADPCM_MAX_SCALE {
+            let mut h = *history;
+            let mut error = 0i64;
+            let mut residuals = [0u8; ADPCM_FRAME_BYTES - 1];
+
+            for (i, sample) in frame.iter().enumerate() {
+                let prediction = (predictor[0] as i32 * h[0] + predictor[1] as i32 * h[1])
+                    >> ADPCM_COEFFICIENT_SHIFT;
+                let residual = ((*sample - prediction) as f64 / (1 << scale) as f64)
+                    .round()
+                    .clamp(-8.0, 7.0) as i32;
+                let decoded = (prediction + (residual << scale))
+                    .max(i16::MIN as i32)
+                    .min(i16::MAX as i32);
+
+                error += ((*sample - decoded) as i64).pow(2);
+                residuals[i / 2] |= ((residual as u8) & 0xf) << if i % 2 == 0 { 4 } else { 0 };
+                h = [decoded, h[0]];
+            }
+
+            if best.map(|b| error < b.0).unwrap_or(true) {
+                best = Some((
+                    error,
+                    ((scale as u8) << 4) | predictor_index as u8,
+                    h,
+                    residuals,
+                ));
+            }
+        }
+    }
+
+    let (_, header, new_history, residuals) = best.unwrap();
+
+    *history = new_history;
+    out.push(header);
+    out.extend_from_slice(&residuals);
+}
+
+fn wav_to_adpcm(wav: &Wav) -> Adpcm {
+    let channels = (0..wav.channels)
+        .map(|c| {
+            wav.samples
+                .iter()
+                .skip(c)
+                .step_by(wav.channels)
+                .copied()
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    let sample_count = channels[0].len();
+    let codebook = adpcm_design_codebook(&channels);
+
+    let frame_count = channels[0].chunks(ADPCM_FRAME_SAMPLES).len();
+    let mut data = Vec::with_capacity(frame_count * wav.channels * ADPCM_FRAME_BYTES);
+    let mut histories = vec![[0, 0]; wav.channels];
+    let mut loop_history = vec![[0, 0]; wav.channels];
+    let loop_frame = wav.looping.map(|(start, _)| start / ADPCM_FRAME_SAMPLES);
+    let mut frames = channels
+        .iter()
+        .map(|channel| adpcm_frames(channel))
+        .collect::<Vec<_>>();
+
+    for frame_index in 0..frame_count {
+        // The decoder needs the predictor state at the frame the loop starts
+        // in to be able to jump back to it.
+        if loop_frame == Some(frame_index) {
+            loop_history.clone_from(&histories);
+        }
+
+        for (channel_frames, history) in frames.iter_mut().zip(histories.iter_mut()) {
+            let frame = channel_frames.next().unwrap();
+            adpcm_encode_frame(&frame, &codebook, history, &mut data);
+        }
+    }
+
+    Adpcm {
+        channels: wav.channels,
+        sample_count,
+        codebook,
+        data,
+        loop_history,
+    }
+}
+
+#[rustfmt::skip]
+macro_rules! SOUND_TEMPLATE { () => {
+r##"pub static {name}: StaticSoundData = StaticSoundData::Pcm {{ data: include_bytes_align_as!(i16, {path:?}), looping: {looping} }};
+"##
+}; }
+
+#[rustfmt::skip]
+macro_rules! ADPCM_SOUND_TEMPLATE { () => {
+r##"pub static {name}: StaticSoundData = StaticSoundData::Adpcm {{ data: include_bytes!({path:?}), codebook: &[{codebook}], channels: {channels}, sample_count: {sample_count}, looping: {looping}, loop_history: [{loop_history}] }};
+"##
+}; }
+
+#[rustfmt::skip]
+macro_rules! BY_NAME_TEMPLATE { () => {
+r##"
+/// The sound of the file with `name`, without extension, in lower case.
+pub fn by_name(name: &str) -> Option<&'static StaticSoundData> {{
+    match name {{
+{arms}        _ => None,
+    }}
+}}
+"##
+}; }
+
+fn format_sound_loop(looping: Option<(usize, usize)>) -> String {
+    match looping {
+        Some((start, end)) => format!("Some(SoundLoop {{ start: {}, end: {} }})", start, end),
+        None => "None".to_string(),
+    }
+}
+
+/// Each wav becomes a `StaticSoundData` named after the file, resampled to
+/// `sample_rate` and trimmed as the optional `<name>.toml` next to it says.
+/// Sounds are 16 bit PCM unless `adpcm` is set.
+pub struct SoundProcessor {
+    pub adpcm: bool,
+    /// Must be the rate the game mixes at, or sounds play at the wrong pitch.
+    pub sample_rate: u32,
+}
+
+impl Default for SoundProcessor {
+    fn default() -> Self {
+        Self {
+            adpcm: false,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+        }
+    }
+}
+
+impl Processor for SoundProcessor {
+    fn header(&self) -> &str {
+        "use crate::sound::{SoundLoop, StaticSoundData};
+use n64::include_bytes_align_as;
+"
+    }
+
+    fn version(&self) -> String {
+        format!("sound 3 adpcm {} rate {}", self.adpcm, self.sample_rate)
+    }
+
+    fn dependencies(&self, inputs: &[PathBuf]) -> Result<Vec<PathBuf>, AssetError> {
+        Ok(inputs
+            .iter()
+            .map(|input| input.with_extension("toml"))
+            .filter(|settings| settings.is_file())
+            .collect())
+    }
+
+    fn process(&self, inputs: &[PathBuf], out_dir: &Path) -> Result<Output, AssetError> {
+        let mut output = Output::default();
+
+        for input in inputs {
+            let name = asset_ident(input)?;
+            let wav = load_wav(input, self.sample_rate)?;
+
+            if self.adpcm {
+                let path = out_dir.join(name.to_lowercase()).with_extension("nadpcm");
+                let adpcm = wav_to_adpcm(&wav);
+
+                output.code.push_str(&format!(
+                    ADPCM_SOUND_TEMPLATE!(),
+                    name = name,
+                    path = path,
+                    codebook = adpcm
+                        .codebook
+                        .iter()
+                        .map(|p| format!("[{}, {}]", p[0], p[1]))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    channels = adpcm.channels,
+                    sample_count = adpcm.sample_count,
+                    looping = format_sound_loop(wav.looping),
+                    loop_history = (0..2)
+                        .map(|c| adpcm.loop_history[c.min(adpcm.channels - 1)])
+                        .map(|h| format!("[{}, {}]", h[0], h[1]))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                ));
+
+                output.files.push(OutputFile {
+                    path,
+                    category: "sounds".to_string(),
+                    asset: name,
+                    data: adpcm.data,
+                    // The codebook and sample count change with the data.
+                    included_as: None,
+                    requires: None,
+                });
+            } else {
+                let path = out_dir.join(name.to_lowercase()).with_extension("nsnd");
+                let pcm = wav_to_pcm(&wav);
+
+                output.code.push_str(&format!(
+                    SOUND_TEMPLATE!(),
+                    name = name,
+                    path = path,
+                    looping = format_sound_loop(wav.looping),
+                ));
+
+                output.files.push(OutputFile {
+                    path,
+                    category: "sounds".to_string(),
+                    included_as: Some(format!("{}.data()", name)),
+                    requires: None,
+                    asset: name,
+                    data: pcm.as_bytes().to_vec(),
+                });
+            }
+        }
+
+        Ok(output)
+    }
+
+    fn footer(&self, assets: &[String]) -> String {
+        let arms: String = assets
+            .iter()
+            .map(|name| format!("        {:?} => Some(&{}),\n", name.to_lowercase(), name))
+            .collect();
+
+        format!(BY_NAME_TEMPLATE!(), arms = arms)
+    }
+}
+
+#[cfg(test)]
+fn decode_adpcm(adpcm: &Adpcm, first_frame: usize, history: &[[i32; 2]]) -> Vec<Vec<i16>> {
+    let mut history = history.to_vec();
+    let mut channels = vec![Vec::new(); adpcm.channels];
+    let mut frame = [0; ADPCM_FRAME_SAMPLES];
+
+    let first_byte = first_frame * adpcm.channels * ADPCM_FRAME_BYTES;
+
+    for (i, bytes) in adpcm.data[first_byte..]
+        .chunks(ADPCM_FRAME_BYTES)
+        .enumerate()
+    {
+        let channel = i % adpcm.channels;
+        n64_types::decode_adpcm_frame(bytes, &adpcm.codebook, &mut history[channel], &mut frame);
+        channels[channel].extend_from_slice(&frame);
+    }
+
+    channels
+}
+
+#[cfg(test)]
+fn snr_db(source: &[i16], decoded: &[i16]) -> f64 {
+    let signal: f64 = source.iter().map(|s| (*s as f64).powi(2)).sum();
+    let noise: f64 = source
+        .iter()
+        .zip(decoded)
+        .map(|(s, d)| (*s as f64 - *d as f64).powi(2))
+        .sum();
+
+    10.0 * (signal / noise).log10()
+}
+
+#[test]
+fn adpcm_round_trips_a_sine_and_a_noise_burst() {
+    let mut rng = n64_math::rand::Rng::new(1);
+    let len = 4000;
+
+    // A sine on the left and a noise burst in the middle of the right.
+    let sine: Vec<i16> = (0..len)
+        .map(|i| (12000.0 * (i as f64 * 440.0 * 2.0 * std::f64::consts::PI / 22050.0).sin()) as i16)
+        .collect();
+    let noise: Vec<i16> = (0..len)
+        .map(|i| {
+            if (1000..3000).contains(&i) {
+                ((rng.next_f32() - 0.5) * 16000.0) as i16
+            } else {
+                0
+            }
+        })
+        .collect();
+
+    let wav = Wav {
+        channels: 2,
+        samples: sine
+            .iter()
+            .zip(&noise)
+            .flat_map(|(l, r)| [*l, *r])
+            .collect(),
+        looping: Some((1000, 3000)),
+    };
+    let adpcm = wav_to_adpcm(&wav);
+
+    assert_eq!(adpcm.sample_count, len);
+    assert_eq!(adpcm.data.len(), 2 * 250 * ADPCM_FRAME_BYTES);
+
+    let decoded = decode_adpcm(&adpcm, 0, &[[0, 0]; 2]);
+    let sine_snr = snr_db(&sine, &decoded[0]);
+    let noise_snr = snr_db(&noise[1000..3000], &decoded[1][1000..3000]);
+    assert!(sine_snr > 50.0, "sine SNR {} dB", sine_snr);
+    assert!(noise_snr > 18.0, "noise SNR {} dB", noise_snr);
+
+    // Jumping back to the loop with the loop history decodes the same as
+    // playing straight through, from the middle of the loop frame.
+    let loop_frame = 1000 / ADPCM_FRAME_SAMPLES;
+    let looped = decode_adpcm(&adpcm, loop_frame, &adpcm.loop_history);
+    let skip = 1000 - loop_frame * ADPCM_FRAME_SAMPLES;
+
+    for (straight, looped) in decoded.iter().zip(&looped) {
+        assert_eq!(straight[1000..3000], looped[skip..(skip + 2000)]);
+    }
+}
+
+#[cfg(test)]
+fn temp_dir(name: &str) -> std::path::PathBuf {
+    let dir =
+        std::env::temp_dir().join(format!("n64-assets-sound-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[cfg(test)]
+fn write_wav<S: hound::Sample + Copy>(
+    path: &Path,
+    spec: hound::WavSpec,
+    samples: impl IntoIterator<Item = S>,
+) {
+    let mut writer = hound::WavWriter::create(path, spec).unwrap();
+
+    for sample in samples {
+        writer.write_sample(sample).unwrap();
+    }
+
+    writer.finalize().unwrap();
+}
+
+#[test]
+fn wav_formats_are_read_as_floats() {
+    let dir = temp_dir("formats");
+    let spec = |bits_per_sample, sample_format| hound::WavSpec {
+        channels: 2,
+        sample_rate: 11025,
+        bits_per_sample,
+        sample_format,
+    };
+    let path = |name: &str| dir.join(name).with_extension("wav");
+
+    write_wav(
+        &path("8"),
+        spec(8, hound::SampleFormat::Int),
+        [-128i8, 64, 0, -64],
+    );
+    write_wav(
+        &path("24"),
+        spec(24, hound::SampleFormat::Int),
+        [-(1 << 23), 1 << 22, 0, -(1 << 22)],
+    );
+    write_wav(
+        &path("32"),
+        spec(32, hound::SampleFormat::Int),
+        [i32::MIN, 1 << 30, 0, -(1 << 30)],
+    );
+    write_wav(
+        &path("float"),
+        spec(32, hound::SampleFormat::Float),
+        [-1.0f32, 0.5, 0.0, -0.5],
+    );
+
+    for name in ["8", "24", "32", "float"].iter() {
+        let (rate, channels) = read_wav_channels(&path(name)).unwrap();
+        assert_eq!(rate, 11025);
+        assert_eq!(channels, [[-1.0, 0.0], [0.5, -0.5]], "{} bit", name);
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn wider_than_stereo_is_folded_into_stereo() {
+    let mono = vec![vec![0.5, -0.5]];
+    assert_eq!(downmix(mono.clone()), mono);
+
+    let surround = vec![
+        vec![0.3, 0.0],
+        vec![0.2, 0.0],
+        vec![0.6, 0.0],
+        vec![0.4, 0.0],
+        vec![0.0, 0.9],
+    ];
+    let stereo = downmix(surround);
+    assert_eq!(stereo.len(), 2);
+    assert!((stereo[0][0] - 0.3).abs() < 1e-6);
+    assert!((stereo[0][1] - 0.3).abs() < 1e-6);
+    assert!((stereo[1][0] - 0.3).abs() < 1e-6);
+    assert_eq!(stereo[1][1], 0.0);
+}
+
+#[test]
+fn resampling_keeps_the_pitch_and_filters_what_does_not_fit() {
+    let sine = |frequency: f64, rate: u32, len: usize| -> Vec<f32> {
+        (0..len)
+            .map(|i| {
+                (0.5 * (2.0 * std::f64::consts::PI * frequency * i as f64 / rate as f64).sin())
+                    as f32
+            })
+            .collect()
+    };
+    let rms = |samples: &[f32]| {
+        (samples.iter().map(|s| (*s as f64).powi(2)).sum::<f64>() / samples.len() as f64).sqrt()
+    };
+    // The filter is cut off at the ends, where the samples around are missing.
+    let error = |a: &[f32], b: &[f32]| {
+        let diff: Vec<f32> = a[100..a.len() - 100]
+            .iter()
+            .zip(&b[100..])
+            .map(|(a, b)| a - b)
+            .collect();
+        rms(&diff)
+    };
+
+    assert_eq!(resample(&[0.25, 0.5], 22050, 22050), [0.25, 0.5]);
+
+    let down = resample(&sine(1000.0, 44100, 4410), 44100, 22050);
+    assert_eq!(down.len(), 2205);
+    assert!(error(&down, &sine(1000.0, 22050, 2205)) < 0.001);
+
+    let up = resample(&sine(1000.0, 11025, 1102), 11025, 22050);
+    assert_eq!(up.len(), 2204);
+    assert!(error(&up, &sine(1000.0, 22050, 2204)) < 0.001);
+
+    // Above half the new rate, it would alias to a lower tone.
+    let high = resample(&sine(15000.0, 44100, 4410), 44100, 22050);
+    assert!(rms(&high[100..2105]) < 0.001);
+}
+
+#[test]
+fn silence_is_trimmed_but_never_the_loop() {
+    let dir = temp_dir("trim");
+    let path = dir.join("sound.wav");
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate: DEFAULT_SAMPLE_RATE,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+
+    // 10 silent samples, 5 loud, 10 silent.
+    let samples = (0..25).map(|i| if (10..15).contains(&i) { 16384i16 } else { 1 });
+    write_wav(&path, spec, samples);
+
+    fs::write(dir.join("sound.toml"), "trim_silence = true\n").unwrap();
+    let wav = load_wav(&path, DEFAULT_SAMPLE_RATE).unwrap();
+    assert_eq!(wav.channels, 1);
+    assert_eq!(wav.samples, [16384; 5]);
+    assert_eq!(wav.looping, None);
+
+    // Loop points are in source samples and move with the trimmed start.
+    fs::write(
+        dir.join("sound.toml"),
+        "trim_silence = true\nloop_start = 12\nloop_end = 20\n",
+    )
+    .unwrap();
+    let wav = load_wav(&path, DEFAULT_SAMPLE_RATE).unwrap();
+    assert_eq!(wav.samples.len(), 10);
+    assert_eq!(wav.looping, Some((2, 10)));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn settings_are_read_from_the_toml_next_to_the_sound() {
+    let dir = temp_dir("settings");
+    let path = dir.join("sound.wav");
+
+    assert_eq!(
+        load_sound_settings(&path).unwrap(),
+        SoundSettings::default()
+    );
+
+    fs::write(
+        dir.join("sound.toml"),
+        "# Loops # forever\nnormalize = true\nloop_start = 100 # in source samples\n",
+    )
+    .unwrap();
+    let settings = load_sound_settings(&path).unwrap();
+    assert!(settings.normalize);
+    assert_eq!(settings.loop_start, Some(100));
+    assert_eq!(settings.loop_end, None);
+
+    let error = |toml: &str| {
+        fs::write(dir.join("sound.toml"), toml).unwrap();
+        load_sound_settings(&path).err().unwrap().to_string()
+    };
+
+    assert!(error("rate = 44100\n").contains("unknown field `rate`"));
+    assert!(error("normalize = \"yes\"\n").contains("line 1"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,166 @@
+use crate::error::InFile;
+use crate::{asset_ident, AssetError, Output, OutputFile, Processor};
+use image::{imageops::FilterType, DynamicImage};
+use n64_math::Color;
+use std::convert::TryInto;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+/// 16 bit RGBA5551 pixels, big endian.
+pub(crate) struct Image {
+    pub(crate) width: i32,
+    pub(crate) height: i32,
+    pub(crate) data: Vec<u8>,
+}
+
+/// Loads an 8 bit RGBA png, optionally rotated and resized to `size`. Color
+/// is resized smoothly while alpha keeps hard edges.
+pub(crate) fn load_png(
+    path: &Path,
+    rotate_180: bool,
+    size: Option<(i32, i32)>,
+) -> Result<Image, AssetError> {
+    let file =
+        File::open(path).map_err(|e| AssetError::new(path, format!("Unable to open: {}", e)))?;
+    let decoder = png::Decoder::new(file);
+    let (info, mut reader) = decoder.read_info().in_file(path)?;
+    let mut buf = vec![0; info.buffer_size()];
+    reader.next_frame(&mut buf).in_file(path)?;
+
+    if info.color_type != png::ColorType::RGBA
+        || info.bit_depth != png::BitDepth::Eight
+        || info.buffer_size() != (4 * info.width * info.height) as usize
+    {
+        return Err(AssetError::new(
+            path,
+            format!(
+                "Image format {:?} with {:?} bits is not supported, it must be 8 bit RGBA",
+                info.color_type, info.bit_depth
+            ),
+        ));
+    }
+
+    let mut image = image::ImageBuffer::from_raw(info.width, info.height, buf)
+        .ok_or_else(|| AssetError::new(path, "Image data is cut short"))?;
+
+    if rotate_180 {
+        let rotated_image = DynamicImage::ImageRgba8(image).rotate180();
+        image = rotated_image.into_rgba8();
+    }
+
+    if let Some((width, height)) = size {
+        if info.width != width as u32 || info.height != height as u32 {
+            let buf = image.into_raw();
+
+            let mut color_in = Vec::with_capacity((3 * info.width * info.height) as usize);
+            let mut alpha_in = Vec::with_capacity((info.width * info.height) as usize);
+
+            for p in buf.chunks_exact(4) {
+                color_in.push(p[0]);
+                color_in.push(p[1]);
+                color_in.push(p[2]);
+                alpha_in.push(p[3]);
+            }
+
+            let color_image = image::ImageBuffer::from_raw(info.width, info.height, color_in)
+                .ok_or_else(|| AssetError::new(path, "Image data is cut short"))?;
+            let alpha_image = image::ImageBuffer::from_raw(info.width, info.height, alpha_in)
+                .ok_or_else(|| AssetError::new(path, "Image data is cut short"))?;
+
+            let scaled_color_image = DynamicImage::ImageRgb8(color_image).resize_exact(
+                width as u32,
+                height as u32,
+                FilterType::Gaussian,
+            );
+
+            let scaled_alpha_image = DynamicImage::ImageLuma8(alpha_image).resize_exact(
+                width as u32,
+                height as u32,
+                FilterType::Nearest,
+            );
+
+            let color_out = scaled_color_image.into_rgb8();
+            let alpha_out = scaled_alpha_image.into_luma8();
+
+            let mut out_buf = Vec::with_capacity((4 * width * height) as usize);
+
+            for (color, alpha) in color_out.chunks_exact(3).zip(alpha_out.iter()) {
+                out_buf.push(color[0]);
+                out_buf.push(color[1]);
+                out_buf.push(color[2]);
+                out_buf.push(*alpha);
+            }
+
+            image = image::ImageBuffer::from_raw(width as u32, height as u32, out_buf)
+                .ok_or_else(|| AssetError::new(path, "Resized image is cut short"))?;
+        }
+    }
+
+    let image_width = image.width() as i32;
+    let image_height = image.height() as i32;
+    let buf = image.into_raw();
+
+    let mut data = Vec::with_capacity((2 * image_width * image_height) as usize);
+
+    for pixel in buf.chunks_exact(4) {
+        let color = Color::from_bytes(pixel.try_into().in_file(path)?);
+        data.extend(&color.value().to_be_bytes());
+    }
+
+    Ok(Image {
+        width: image_width,
+        height: image_height,
+        data,
+    })
+}
+
+#[rustfmt::skip]
+macro_rules! TEXTURE_TEMPLATE { () => {
+r##"pub static {name}: StaticTexture = StaticTexture::from_static({width}, {height}, include_bytes_align_as!(Color, {path:?}));
+"##
+}; }
+
+/// Each png becomes a `StaticTexture` named after the file.
+pub struct TextureProcessor;
+
+impl Processor for TextureProcessor {
+    fn header(&self) -> &str {
+        "use n64_math::Color;
+use n64::gfx::StaticTexture;
+use n64::include_bytes_align_as;
+"
+    }
+
+    fn version(&self) -> String {
+        "texture 2".to_string()
+    }
+
+    fn process(&self, inputs: &[PathBuf], out_dir: &Path) -> Result<Output, AssetError> {
+        let mut output = Output::default();
+
+        for input in inputs {
+            let name = asset_ident(input)?;
+            let image = load_png(input, false, None)?;
+            let path = out_dir.join(name.to_lowercase()).with_extension("ntex");
+
+            output.code.push_str(&format!(
+                TEXTURE_TEMPLATE!(),
+                name = name,
+                width = image.width,
+                height = image.height,
+                path = path
+            ));
+
+            output.files.push(OutputFile {
+                path,
+                category: "textures".to_string(),
+                included_as: Some(format!("{}.data", name)),
+                requires: None,
+                asset: name,
+                data: image.data,
+            });
+        }
+
+        Ok(output)
+    }
+}
@@ -0,0 +1,186 @@
+//! Runs the pipeline over the small assets in `tests/data`, copied to a
+//! temporary directory so the generated files stay out of the tree.
+
+use n64_assets::{Manifest, Pipeline};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+fn copy_dir(from: &Path, to: &Path) {
+    fs::create_dir_all(to).unwrap();
+
+    for entry in fs::read_dir(from).unwrap() {
+        let path = entry.unwrap().path();
+        let target = to.join(path.file_name().unwrap());
+
+        if path.is_dir() {
+            copy_dir(&path, &target);
+        } else {
+            fs::copy(&path, &target).unwrap();
+        }
+    }
+}
+
+fn project(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("n64-assets-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    copy_dir(
+        &Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data"),
+        &dir,
+    );
+    dir
+}
+
+#[test]
+fn second_run_comes_from_the_cache() {
+    let dir = project("cache");
+    let manifest = Manifest::load(dir.join("assets.toml")).unwrap();
+    let pipeline = Pipeline::new(dir.join("out"));
+
+    let first = pipeline.run(&manifest).unwrap();
+    assert_eq!((first.processed, first.cached), (3, 0));
+
+    let textures = fs::read_to_string(dir.join("src/textures.rs")).unwrap();
+    assert!(textures.contains("pub static SHIP: StaticTexture = StaticTexture::from_static(4, 4,"));
+    assert!(textures.contains("pub static SHOT: StaticTexture = StaticTexture::from_static(2, 2,"));
+    assert!(textures.contains("    n64::hot_reload::watch(SHIP.data, "));
+    assert_eq!(
+        fs::read(dir.join("out/textures/ship.ntex")).unwrap().len(),
+        2 * 4 * 4
+    );
+
+    let sounds = fs::read_to_string(dir.join("src/sounds.rs")).unwrap();
+    assert!(sounds.contains("pub static BEEP: StaticSoundData = StaticSoundData::Pcm"));
+    assert!(sounds.contains("    n64::hot_reload::watch(BEEP.data(), "));
+    assert!(sounds.contains("        \"beep\" => Some(&BEEP),\n"));
+
+    let sizes = fs::read_to_string(dir.join("asset_sizes.tsv")).unwrap();
+    assert!(sizes.contains("asset\ttextures\tSHIP\t32\n"));
+    assert!(sizes.contains("asset\tsounds\tBEEP\t"));
+
+    let second = pipeline.run(&manifest).unwrap();
+    assert_eq!((second.processed, second.cached), (0, 3));
+    assert_eq!(
+        fs::read_to_string(dir.join("src/textures.rs")).unwrap(),
+        textures
+    );
+
+    fs::copy(dir.join("textures/shot.png"), dir.join("textures/ship.png")).unwrap();
+
+    let third = pipeline.run(&manifest).unwrap();
+    assert_eq!((third.processed, third.cached), (1, 2));
+    assert!(fs::read_to_string(dir.join("src/textures.rs"))
+        .unwrap()
+        .contains("pub static SHIP: StaticTexture = StaticTexture::from_static(2, 2,"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn errors_name_the_broken_file() {
+    let dir = project("error");
+    let broken = dir.join("textures/shot.png");
+    fs::write(&broken, b"not a png").unwrap();
+
+    let manifest = Manifest::load(dir.join("assets.toml")).unwrap();
+    let error = Pipeline::new(dir.join("out")).run(&manifest).err().unwrap();
+
+    assert_eq!(error.path, broken);
+    assert!(error
+        .to_string()
+        .starts_with(&format!("{}: ", broken.display())));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn maps_keep_flips_animations_layer_properties_and_collision() {
+    let dir = project("maps");
+    let manifest = Manifest::load(dir.join("maps.toml")).unwrap();
+    Pipeline::new(dir.join("out")).run(&manifest).unwrap();
+
+    // Big endian cells, the index of the tile plus one with the flips on
+    // top. The collision layer is left out.
+    let cells = fs::read(dir.join("out/maps/level.nmap")).unwrap();
+    assert_eq!(
+        cells,
+        [
+            0x00, 0x01, 0x00, 0x02, 0x00, 0x00, //
+            0x80, 0x02, 0x00, 0x00, 0x00, 0x03, //
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x04, //
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, //
+        ]
+    );
+
+    // The gid of each tile index, what hot reload checks new cells against.
+    let tile_gids = fs::read(dir.join("out/maps/level_tile_gids.ngid")).unwrap();
+    assert_eq!(
+        tile_gids,
+        [0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 4, 0, 0, 0, 3]
+    );
+
+    let collision = fs::read(dir.join("out/maps/level_collision.ncol")).unwrap();
+    assert_eq!(collision, [1, 0, 0, 0, 0, 1]);
+
+    let tile_1 = fs::read(dir.join("out/maps/level_tile_1.ntex")).unwrap();
+    let tile_4 = fs::read(dir.join("out/maps/level_tile_4.ntex")).unwrap();
+    assert_eq!(tile_1.len(), 2 * 2 * 2);
+    assert_ne!(tile_1, tile_4);
+
+    let maps = fs::read_to_string(dir.join("src/maps.rs")).unwrap();
+    assert!(maps.contains(
+        "    StaticMapLayer { parallax_x: 0.5_f32, parallax_y: 1_f32, visible: true },\n    \
+         StaticMapLayer { parallax_x: 1_f32, parallax_y: 1_f32, visible: false },\n"
+    ));
+    assert!(maps.contains(
+        "    TileAnimation { tile: 1, frames: &[TileFrame { tile: 1, duration_ms: 100 }, \
+         TileFrame { tile: 3, duration_ms: 200 }, ] },\n"
+    ));
+    assert!(maps.contains("    n64::hot_reload::watch_requiring(LEVEL.cells, "));
+    assert!(maps.contains("    n64::hot_reload::watch(LEVEL.collision, "));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn map_objects_keep_their_type_name_properties_and_shape() {
+    let dir = project("objects");
+    let manifest = Manifest::load(dir.join("maps.toml")).unwrap();
+    Pipeline::new(dir.join("out")).run(&manifest).unwrap();
+
+    let maps = fs::read_to_string(dir.join("src/maps.rs")).unwrap();
+
+    // Tile objects are drawn with the tile, centered on the object.
+    assert!(maps.contains(
+        "        kind: \"enemy\",
+        name: \"leader\",
+        x: 1_f32,
+        y: 3_f32,
+        height: 2_f32,
+        shape: StaticObjectShape::Sprite(&OBJECT_TEXTURE_TILES_4_2X2),
+        properties: &[(\"ai\", StaticProperty::String(\"sine\")), \
+         (\"fire_rate\", StaticProperty::Float(2.5_f32)), (\"health\", StaticProperty::Int(300)), ],
+"
+    ));
+    assert!(fs::read(dir.join("out/maps/object_texture_tiles_4_2x2.ntex")).is_ok());
+
+    assert!(maps.contains(
+        "        kind: \"music\",
+        name: \"boss\",
+        x: 3_f32,
+        y: 0.5_f32,
+        height: 1_f32,
+        shape: StaticObjectShape::Region,
+        properties: &[(\"sound\", StaticProperty::String(\"boss\")), ],
+"
+    ));
+
+    // Path points are in map pixels.
+    assert!(maps.contains(
+        "        shape: StaticObjectShape::Path(&[n64_math::Vec2::new(1_f32, 1_f32), \
+         n64_math::Vec2::new(3_f32, 2_f32), n64_math::Vec2::new(5_f32, 1_f32), ]),
+"
+    ));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
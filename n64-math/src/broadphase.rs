@@ -0,0 +1,137 @@
+use crate::Aabb2;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+
+/// Sort and sweep broadphase. The boxes are sorted by their left edge, so a
+/// query only looks at the boxes starting between its left edge, less the
+/// widest box, and its right edge.
+pub struct Broadphase<T> {
+    entries: Vec<(Aabb2, T)>,
+    max_width: f32,
+}
+
+impl<T: Copy> Broadphase<T> {
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+            max_width: 0.0,
+        }
+    }
+
+    /// Replaces the boxes, meant to be called once a frame.
+    pub fn rebuild(&mut self, boxes: impl IntoIterator<Item = (Aabb2, T)>) {
+        self.entries.clear();
+        self.entries.extend(boxes);
+        self.entries.sort_unstable_by(|(a, _), (b, _)| {
+            a.left().partial_cmp(&b.left()).unwrap_or(Ordering::Equal)
+        });
+
+        self.max_width = self
+            .entries
+            .iter()
+            .map(|(aabb, _)| aabb.right() - aabb.left())
+            .fold(0.0, f32::max);
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Calls `f` with every box overlapping `aabb`.
+    pub fn query(&self, aabb: &Aabb2, mut f: impl FnMut(&Aabb2, T)) {
+        let from = aabb.left() - self.max_width;
+        let first = match self.entries.binary_search_by(|(entry, _)| {
+            if entry.left() < from {
+                Ordering::Less
+            } else {
+                Ordering::Greater
+            }
+        }) {
+            Ok(index) | Err(index) => index,
+        };
+
+        for (entry, value) in &self.entries[first..] {
+            if entry.left() > aabb.right() {
+                break;
+            }
+
+            if entry.collides(aabb) {
+                f(entry, *value);
+            }
+        }
+    }
+}
+
+impl<T: Copy> Default for Broadphase<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+fn overlapping(broadphase: &Broadphase<u32>, aabb: Aabb2) -> Vec<u32> {
+    let mut found = Vec::new();
+    broadphase.query(&aabb, |_, value| found.push(value));
+    found.sort_unstable();
+    found
+}
+
+#[test]
+fn queries_find_the_same_boxes_as_checking_them_all() {
+    use crate::{rand::Rng, Vec2};
+
+    let mut rng = Rng::new(3);
+    let mut boxes = Vec::new();
+
+    for i in 0..200 {
+        let center = Vec2::new(rng.next_f32(), rng.next_f32());
+        let size = Vec2::new(rng.next_f32() * 0.1, rng.next_f32() * 0.1);
+        boxes.push((Aabb2::from_center_size(center, size), i));
+    }
+
+    let mut broadphase = Broadphase::new();
+    broadphase.rebuild(boxes.iter().copied());
+    assert_eq!(broadphase.len(), 200);
+
+    for _ in 0..50 {
+        let center = Vec2::new(rng.next_f32(), rng.next_f32());
+        let query = Aabb2::from_center_size(center, Vec2::new(0.05, 0.05));
+
+        let expected: Vec<u32> = boxes
+            .iter()
+            .filter(|(aabb, _)| aabb.collides(&query))
+            .map(|&(_, i)| i)
+            .collect();
+
+        assert_eq!(overlapping(&broadphase, query), expected);
+    }
+}
+
+#[test]
+fn a_wide_box_is_found_far_from_its_left_edge() {
+    use crate::Vec2;
+
+    let mut broadphase = Broadphase::new();
+    broadphase.rebuild(
+        [
+            (Aabb2::new(Vec2::new(0.0, 0.0), Vec2::new(1.0, 0.1)), 1),
+            (Aabb2::new(Vec2::new(0.5, 0.0), Vec2::new(0.6, 0.1)), 2),
+            (Aabb2::new(Vec2::new(0.95, 0.5), Vec2::new(1.0, 0.6)), 3),
+        ]
+        .iter()
+        .copied(),
+    );
+
+    let query = Aabb2::new(Vec2::new(0.9, 0.05), Vec2::new(0.95, 0.55));
+    assert_eq!(overlapping(&broadphase, query), [1, 3]);
+
+    broadphase.rebuild(core::iter::empty());
+    assert!(broadphase.is_empty());
+    assert_eq!(overlapping(&broadphase, query), []);
+}
@@ -1,6 +1,9 @@
 #![no_std]
 
+extern crate alloc;
+
 mod aabb2;
+mod broadphase;
 mod color;
 mod hash;
 mod vec2;
@@ -9,6 +12,7 @@ mod vec3;
 pub mod rand;
 
 pub use aabb2::Aabb2;
+pub use broadphase::Broadphase;
 pub use color::Color;
 pub use hash::{BuildFnvHasher, FnvHasher};
 pub use rand::{random_f32, random_f64, random_u32, random_u64};
@@ -15,6 +15,14 @@ impl Rng {
         }
     }
 
+    /// The same `seed` gives the same numbers.
+    #[inline]
+    pub const fn new(seed: u32) -> Rng {
+        Rng {
+            seed: Wrapping(seed),
+        }
+    }
+
     #[inline]
     pub fn next_u32(&mut self) -> u32 {
         self.seed = self.seed * Wrapping(214013) + Wrapping(2531011);
@@ -0,0 +1,210 @@
+//! Turns the linked game into a ROM: the header, the IPL3 boot code, the
+//! program loaded to its link address and the file system, padded and
+//! checksummed.
+
+use crate::{
+    encode_fs, fix_checksum, Cic, Elf, FsFile, BOOTCODE_END, BOOTCODE_START, MIN_ROM_SIZE,
+    TITLE_LEN,
+};
+use std::error::Error;
+use std::fmt;
+
+/// The program follows the boot code.
+pub const PROGRAM_START: usize = BOOTCODE_END;
+
+/// The boot code only copies this much of the program to RAM.
+pub const BOOT_LOAD_SIZE: usize = 0x10_0000;
+
+const CART_ROM_BASE: u32 = 0xB000_0000;
+const FS_ALIGN: usize = 8;
+
+/// The defaults of the boot code for every retail game.
+const PI_SETTINGS: u32 = 0x8037_1240;
+const CLOCK_RATE: u32 = 0x0000_000F;
+const RELEASE: u32 = 0x0000_144C;
+
+pub struct RomOptions<'a> {
+    /// IPL3 from a retail ROM, as extracted by `n64-rom extract-ipl3`.
+    pub bootcode: &'a [u8],
+    pub title: &'a str,
+    pub files: &'a [FsFile],
+}
+
+pub struct SectionSize {
+    pub name: String,
+    pub address: u32,
+    pub size: u32,
+    /// Only in RAM, not in the ROM.
+    pub nobits: bool,
+}
+
+/// What went where, shown by `n64-rom build`.
+pub struct BuildReport {
+    pub sections: Vec<SectionSize>,
+    pub load_address: u32,
+    pub program_size: usize,
+    pub fs_offset: usize,
+    pub fs_size: usize,
+    pub fs_files: usize,
+    pub rom_size: usize,
+    /// The chip the boot code is for, or `None` when it is unknown and the
+    /// checksum is made for the 6102.
+    pub cic: Option<Cic>,
+}
+
+pub fn build_rom(
+    elf: &[u8],
+    options: &RomOptions,
+) -> Result<(Vec<u8>, BuildReport), Box<dyn Error>> {
+    if options.bootcode.len() != BOOTCODE_END - BOOTCODE_START {
+        return Err(format!(
+            "Boot code is {} bytes, not {}",
+            options.bootcode.len(),
+            BOOTCODE_END - BOOTCODE_START
+        )
+        .into());
+    }
+
+    if !options.title.is_ascii() || options.title.len() > TITLE_LEN {
+        return Err(format!("Title must be at most {} ASCII characters", TITLE_LEN).into());
+    }
+
+    let elf = Elf::parse(elf)?;
+    let segments: Vec<_> = elf
+        .segments()?
+        .into_iter()
+        .filter(|segment| !segment.data.is_empty())
+        .collect();
+
+    let load_address = segments
+        .iter()
+        .map(|segment| segment.address)
+        .min()
+        .ok_or("ELF has nothing to load")?;
+
+    // The boot code jumps to where it loaded the program.
+    if elf.entry != load_address {
+        return Err(format!(
+            "Entry point {:#010x} is not at the start of the program {:#010x}",
+            elf.entry, load_address
+        )
+        .into());
+    }
+
+    let mut program = Vec::new();
+    for segment in segments.iter() {
+        let offset = (segment.address - load_address) as usize;
+        let end = offset + segment.data.len();
+
+        if program.len() < end {
+            program.resize(end, 0);
+        }
+
+        program[offset..end].copy_from_slice(segment.data);
+    }
+
+    let mut rom = vec![0; PROGRAM_START];
+    rom[0x00..0x04].copy_from_slice(&PI_SETTINGS.to_be_bytes());
+    rom[0x04..0x08].copy_from_slice(&CLOCK_RATE.to_be_bytes());
+    rom[0x08..0x0C].copy_from_slice(&load_address.to_be_bytes());
+    rom[0x0C..0x10].copy_from_slice(&RELEASE.to_be_bytes());
+    rom[0x20..0x20 + TITLE_LEN].copy_from_slice(&[b' '; TITLE_LEN]);
+    rom[0x20..0x20 + options.title.len()].copy_from_slice(options.title.as_bytes());
+    rom[0x3B] = b'N';
+    rom[0x3E] = b'E';
+    rom[BOOTCODE_START..BOOTCODE_END].copy_from_slice(options.bootcode);
+    rom.extend_from_slice(&program);
+
+    // The entry point hands `__rom_end` to the game as where the file
+    // system is, so it has to go exactly there.
+    let fs_offset = match elf.symbol("__rom_end")? {
+        Some(address) => address.wrapping_sub(CART_ROM_BASE) as usize,
+        None => (rom.len() + FS_ALIGN - 1) & !(FS_ALIGN - 1),
+    };
+
+    if fs_offset < rom.len() || fs_offset > rom.len() + BOOT_LOAD_SIZE {
+        return Err(format!(
+            "__rom_end points at ROM offset {:#x}, not after the program",
+            fs_offset
+        )
+        .into());
+    }
+
+    let fs = encode_fs(options.files);
+    rom.resize(fs_offset, 0);
+    rom.extend_from_slice(&fs);
+    rom.resize(((rom.len() + 3) & !3).max(MIN_ROM_SIZE), 0);
+
+    let cic = Cic::identify(options.bootcode);
+    fix_checksum(&mut rom, cic.unwrap_or(Cic::Cic6102))?;
+
+    let sections = elf
+        .sections()?
+        .into_iter()
+        .filter(|section| section.alloc && section.size > 0)
+        .map(|section| SectionSize {
+            name: section.name.to_string(),
+            address: section.address,
+            size: section.size,
+            nobits: section.nobits,
+        })
+        .collect();
+
+    let report = BuildReport {
+        sections,
+        load_address,
+        program_size: program.len(),
+        fs_offset,
+        fs_size: fs.len(),
+        fs_files: options.files.len(),
+        rom_size: rom.len(),
+        cic,
+    };
+
+    Ok((rom, report))
+}
+
+impl fmt::Display for BuildReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{:<16} {:>10} {:>10}", "Section", "Address", "Bytes")?;
+
+        for section in self.sections.iter() {
+            writeln!(
+                f,
+                "{:<16} {:#010x} {:>10}{}",
+                section.name,
+                section.address,
+                section.size,
+                if section.nobits { " (RAM only)" } else { "" }
+            )?;
+        }
+
+        writeln!(f)?;
+        writeln!(
+            f,
+            "Program     {:>10} bytes at {:#010x}",
+            self.program_size, self.load_address
+        )?;
+        writeln!(
+            f,
+            "Files       {:>10} bytes in {} files at ROM offset {:#x}",
+            self.fs_size, self.fs_files, self.fs_offset
+        )?;
+        writeln!(f, "ROM         {:>10} bytes", self.rom_size)?;
+
+        match self.cic {
+            Some(cic) => writeln!(f, "CIC         {:>10}", cic.name())?,
+            None => writeln!(f, "CIC            unknown, checksummed for the 6102")?,
+        }
+
+        if self.program_size > BOOT_LOAD_SIZE {
+            writeln!(
+                f,
+                "Warning: the boot code only loads the first {} bytes of the program",
+                BOOT_LOAD_SIZE
+            )?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,88 @@
+use std::error::Error;
+
+/// The three ways ROM dumps are stored, named after their usual extensions.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ByteOrder {
+    /// Big endian, the order the console reads.
+    Z64,
+    /// 16 bit words byte swapped.
+    V64,
+    /// 32 bit words byte swapped.
+    N64,
+}
+
+impl ByteOrder {
+    pub const ALL: [ByteOrder; 3] = [ByteOrder::Z64, ByteOrder::V64, ByteOrder::N64];
+
+    /// Tells the order from the first word of the header, which is always
+    /// 0x80371240.
+    #[inline]
+    pub fn detect(rom: &[u8]) -> Option<ByteOrder> {
+        match rom.get(..4)? {
+            [0x80, 0x37, 0x12, 0x40] => Some(ByteOrder::Z64),
+            [0x37, 0x80, 0x40, 0x12] => Some(ByteOrder::V64),
+            [0x40, 0x12, 0x37, 0x80] => Some(ByteOrder::N64),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn extension(self) -> &'static str {
+        match self {
+            ByteOrder::Z64 => "z64",
+            ByteOrder::V64 => "v64",
+            ByteOrder::N64 => "n64",
+        }
+    }
+
+    #[inline]
+    pub fn from_extension(extension: &str) -> Option<ByteOrder> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|order| order.extension().eq_ignore_ascii_case(extension))
+    }
+
+    /// Swapping is its own inverse, it converts both to and from big endian.
+    fn swap(self, rom: &mut [u8]) {
+        match self {
+            ByteOrder::Z64 => {}
+            ByteOrder::V64 => rom.chunks_exact_mut(2).for_each(|word| word.swap(0, 1)),
+            ByteOrder::N64 => rom.chunks_exact_mut(4).for_each(|word| word.reverse()),
+        }
+    }
+}
+
+/// Converts `rom` in place, its size must be a whole number of words.
+pub fn convert(rom: &mut [u8], from: ByteOrder, to: ByteOrder) -> Result<(), Box<dyn Error>> {
+    if !rom.chunks_exact(4).remainder().is_empty() {
+        return Err("ROM size is not a whole number of words".into());
+    }
+
+    from.swap(rom);
+    to.swap(rom);
+
+    Ok(())
+}
+
+#[test]
+fn byte_orders_are_detected_and_converted() {
+    let z64 = [0x80, 0x37, 0x12, 0x40, 1, 2, 3, 4];
+
+    for &order in ByteOrder::ALL.iter() {
+        let mut rom = z64;
+        convert(&mut rom, ByteOrder::Z64, order).unwrap();
+        assert_eq!(ByteOrder::detect(&rom), Some(order));
+        assert_eq!(ByteOrder::from_extension(order.extension()), Some(order));
+
+        convert(&mut rom, order, ByteOrder::Z64).unwrap();
+        assert_eq!(rom, z64);
+    }
+
+    let mut rom = z64;
+    convert(&mut rom, ByteOrder::Z64, ByteOrder::N64).unwrap();
+    assert_eq!(rom, [0x40, 0x12, 0x37, 0x80, 4, 3, 2, 1]);
+
+    assert_eq!(ByteOrder::detect(&[0; 4]), None);
+    assert!(convert(&mut [0; 6], ByteOrder::V64, ByteOrder::Z64).is_err());
+}
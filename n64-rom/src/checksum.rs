@@ -0,0 +1,117 @@
+//! The header checksum the boot code checks before it starts a ROM. It
+//! covers the first megabyte after the boot code.
+
+use crate::{Cic, BOOTCODE_START};
+use std::convert::TryInto;
+use std::error::Error;
+
+const CHECKSUM_START: usize = 0x1000;
+const CHECKSUM_LEN: usize = 0x10_0000;
+const CRC1_OFFSET: usize = 0x10;
+const CRC2_OFFSET: usize = 0x14;
+
+/// Smallest ROM that can be checksummed.
+pub const MIN_ROM_SIZE: usize = CHECKSUM_START + CHECKSUM_LEN;
+
+/// The two checksum words for the header of a big endian ROM.
+pub fn checksum(rom: &[u8], cic: Cic) -> Result<(u32, u32), Box<dyn Error>> {
+    let data = rom
+        .get(CHECKSUM_START..CHECKSUM_START + CHECKSUM_LEN)
+        .ok_or("ROM is too small to checksum")?;
+    let word = |offset: usize| u32::from_be_bytes(rom[offset..offset + 4].try_into().unwrap());
+
+    let seed = cic.seed();
+    let mut t1 = seed;
+    let mut t2 = seed;
+    let mut t3 = seed;
+    let mut t4 = seed;
+    let mut t5 = seed;
+    let mut t6 = seed;
+
+    for (i, d) in data.chunks(4).enumerate() {
+        let d = u32::from_be_bytes(d.try_into()?);
+
+        if t6.wrapping_add(d) < t6 {
+            t4 = t4.wrapping_add(1);
+        }
+
+        t6 = t6.wrapping_add(d);
+        t3 ^= d;
+
+        let r = d.rotate_left(d & 0x1f);
+        t5 = t5.wrapping_add(r);
+
+        if t2 > d {
+            t2 ^= r;
+        } else {
+            t2 ^= t6 ^ d;
+        }
+
+        if cic == Cic::Cic6105 {
+            // Mixes in a table from the 6105 boot code.
+            let offset = BOOTCODE_START + 0x0710 + ((CHECKSUM_START + i * 4) & 0xff);
+            t1 = t1.wrapping_add(word(offset) ^ d);
+        } else {
+            t1 = t1.wrapping_add(t5 ^ d);
+        }
+    }
+
+    Ok(match cic {
+        Cic::Cic6103 => ((t6 ^ t4).wrapping_add(t3), (t5 ^ t2).wrapping_add(t1)),
+        Cic::Cic6106 => (
+            t6.wrapping_mul(t4).wrapping_add(t3),
+            t5.wrapping_mul(t2).wrapping_add(t1),
+        ),
+        _ => (t6 ^ t4 ^ t3, t5 ^ t2 ^ t1),
+    })
+}
+
+/// Writes the checksum to the header, returns false if it was already right.
+pub fn fix_checksum(rom: &mut [u8], cic: Cic) -> Result<bool, Box<dyn Error>> {
+    let (crc1, crc2) = checksum(rom, cic)?;
+    let mut header = [0; 8];
+    header[..4].copy_from_slice(&crc1.to_be_bytes());
+    header[4..].copy_from_slice(&crc2.to_be_bytes());
+
+    if rom[CRC1_OFFSET..CRC2_OFFSET + 4] == header {
+        return Ok(false);
+    }
+
+    rom[CRC1_OFFSET..CRC2_OFFSET + 4].copy_from_slice(&header);
+    Ok(true)
+}
+
+#[test]
+fn checksum_of_an_empty_rom() {
+    let mut rom = vec![0; MIN_ROM_SIZE];
+
+    // With only zeros every state but t1 keeps the seed.
+    for &cic in [Cic::Cic6102, Cic::Cic6103].iter() {
+        let seed = cic.seed();
+        assert_eq!(
+            checksum(&rom, cic).unwrap(),
+            (seed, seed.wrapping_mul(0x4_0001))
+        );
+    }
+
+    assert!(fix_checksum(&mut rom, Cic::Cic6102).unwrap());
+    assert!(!fix_checksum(&mut rom, Cic::Cic6102).unwrap());
+    assert!(fix_checksum(&mut rom, Cic::Cic6106).unwrap());
+    assert!(checksum(&rom[..MIN_ROM_SIZE - 4], Cic::Cic6102).is_err());
+}
+
+#[test]
+fn the_6105_checksum_reads_its_boot_code() {
+    let mut rom: Vec<u8> = (0..MIN_ROM_SIZE).map(|i| (i * 7 + i / 251) as u8).collect();
+    let before = checksum(&rom, Cic::Cic6105).unwrap();
+
+    rom[BOOTCODE_START + 0x0710] ^= 1;
+    let after = checksum(&rom, Cic::Cic6105).unwrap();
+
+    assert_eq!(before.0, after.0);
+    assert_ne!(before.1, after.1);
+    assert_eq!(
+        checksum(&rom, Cic::Cic6102).unwrap(),
+        checksum(&rom, Cic::Cic6101).unwrap()
+    );
+}
@@ -0,0 +1,144 @@
+use crate::{BOOTCODE_END, BOOTCODE_START};
+
+/// The lockout chips. Each has its own IPL3 boot code in the ROM, which
+/// checks the header checksum in its own way.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Cic {
+    Cic6101,
+    Cic6102,
+    Cic6103,
+    Cic6105,
+    Cic6106,
+    Cic7102,
+}
+
+impl Cic {
+    pub const ALL: [Cic; 6] = [
+        Cic::Cic6101,
+        Cic::Cic6102,
+        Cic::Cic6103,
+        Cic::Cic6105,
+        Cic::Cic6106,
+        Cic::Cic7102,
+    ];
+
+    #[inline]
+    pub fn name(self) -> &'static str {
+        match self {
+            Cic::Cic6101 => "6101",
+            Cic::Cic6102 => "6102",
+            Cic::Cic6103 => "6103",
+            Cic::Cic6105 => "6105",
+            Cic::Cic6106 => "6106",
+            Cic::Cic7102 => "7102",
+        }
+    }
+
+    #[inline]
+    pub fn from_name(name: &str) -> Option<Cic> {
+        Self::ALL.iter().copied().find(|cic| cic.name() == name)
+    }
+
+    /// Initial value of the checksum state.
+    #[inline]
+    pub fn seed(self) -> u32 {
+        match self {
+            Cic::Cic6101 | Cic::Cic6102 | Cic::Cic7102 => 0xF8CA_4DDC,
+            Cic::Cic6103 => 0xA388_6759,
+            Cic::Cic6105 => 0xDF26_F436,
+            Cic::Cic6106 => 0x1FEA_617A,
+        }
+    }
+
+    /// CRC32 of the retail IPL3 boot code for the chip.
+    #[inline]
+    pub fn bootcode_crc(self) -> u32 {
+        match self {
+            Cic::Cic6101 => 0x6170_A4A1,
+            Cic::Cic6102 => 0x90BB_6CB5,
+            Cic::Cic6103 => 0x0B05_0EE0,
+            Cic::Cic6105 => 0x98BC_2C86,
+            Cic::Cic6106 => 0xACC8_580A,
+            Cic::Cic7102 => 0x009E_9EA3,
+        }
+    }
+
+    /// The chip an extracted IPL3 boot code is for.
+    #[inline]
+    pub fn identify(bootcode: &[u8]) -> Option<Cic> {
+        if bootcode.len() != BOOTCODE_END - BOOTCODE_START {
+            return None;
+        }
+
+        let crc = crc32(bootcode);
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|cic| cic.bootcode_crc() == crc)
+    }
+
+    /// The chip the boot code in a big endian ROM is for.
+    #[inline]
+    pub fn identify_rom(rom: &[u8]) -> Option<Cic> {
+        Self::identify(rom.get(BOOTCODE_START..BOOTCODE_END)?)
+    }
+}
+
+/// The CRC32 of zip and PNG.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+
+    for &byte in data {
+        crc ^= byte as u32;
+
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+
+    !crc
+}
+
+/// Boot code that is all zeros except for the last word, which is picked so
+/// the CRC32 is `crc`.
+#[cfg(test)]
+pub(crate) fn forge_bootcode(crc: u32) -> Vec<u8> {
+    let table = |index: u32| {
+        (0..8).fold(index, |crc, _| {
+            (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg())
+        })
+    };
+
+    let mut bootcode = vec![0; BOOTCODE_END - BOOTCODE_START];
+    let len = bootcode.len();
+    let state = !crc32(&bootcode[..len - 4]);
+
+    // Runs the last four steps of the CRC backwards from the wanted state,
+    // the top byte of each table entry is unique.
+    let mut wanted = !crc;
+    for _ in 0..4 {
+        let index = (0..256).find(|&i| table(i) >> 24 == wanted >> 24).unwrap();
+        wanted = ((wanted ^ table(index)) << 8) | index;
+    }
+
+    bootcode[len - 4..].copy_from_slice(&(wanted ^ state).to_le_bytes());
+    bootcode
+}
+
+#[test]
+fn bootcode_is_identified_by_crc() {
+    assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+
+    let bootcode = [0; BOOTCODE_END - BOOTCODE_START];
+    assert_eq!(Cic::identify(&bootcode), None);
+    assert_eq!(Cic::identify(&bootcode[1..]), None);
+
+    for &cic in Cic::ALL.iter() {
+        assert_eq!(Cic::from_name(cic.name()), Some(cic));
+        assert_eq!(
+            Cic::identify(&forge_bootcode(cic.bootcode_crc())),
+            Some(cic)
+        );
+    }
+}
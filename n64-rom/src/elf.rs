@@ -0,0 +1,193 @@
+//! Just enough of big endian 32 bit ELF to turn the linked game into a ROM.
+
+use std::convert::TryInto;
+use std::error::Error;
+use std::str;
+
+const PT_LOAD: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_NOBITS: u32 = 8;
+const SHF_ALLOC: u32 = 2;
+const STT_FUNC: u8 = 2;
+
+const PROGRAM_HEADER_SIZE: usize = 32;
+const SECTION_HEADER_SIZE: usize = 40;
+const SYMBOL_SIZE: usize = 16;
+
+pub struct Elf<'a> {
+    data: &'a [u8],
+    pub entry: u32,
+}
+
+/// Bytes loaded at `address`, followed by `mem_size - data.len()` zeros.
+pub struct Segment<'a> {
+    pub address: u32,
+    pub data: &'a [u8],
+    pub mem_size: u32,
+}
+
+pub struct Section<'a> {
+    pub name: &'a str,
+    pub address: u32,
+    pub size: u32,
+    /// Takes up memory when the program runs.
+    pub alloc: bool,
+    /// Takes up no space in the file, like `.bss`.
+    pub nobits: bool,
+}
+
+pub struct Symbol<'a> {
+    pub name: &'a str,
+    pub address: u32,
+    pub size: u32,
+    info: u8,
+}
+
+impl<'a> Symbol<'a> {
+    #[inline]
+    pub fn is_function(&self) -> bool {
+        self.info & 0xf == STT_FUNC
+    }
+}
+
+impl<'a> Elf<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Elf<'a>, Box<dyn Error>> {
+        if data.len() < 0x34 || &data[..4] != b"\x7fELF" || data[4] != 1 || data[5] != 2 {
+            return Err("Not a big endian 32 bit ELF file".into());
+        }
+
+        Ok(Elf {
+            data,
+            entry: read_u32(data, 0x18)?,
+        })
+    }
+
+    pub fn segments(&self) -> Result<Vec<Segment<'a>>, Box<dyn Error>> {
+        let offset = read_u32(self.data, 0x1C)? as usize;
+        let count = read_u16(self.data, 0x2C)? as usize;
+        let mut segments = Vec::new();
+
+        for header in (0..count).map(|i| offset + i * PROGRAM_HEADER_SIZE) {
+            if read_u32(self.data, header)? != PT_LOAD {
+                continue;
+            }
+
+            let file_offset = read_u32(self.data, header + 4)? as usize;
+            let file_size = read_u32(self.data, header + 16)? as usize;
+
+            segments.push(Segment {
+                address: read_u32(self.data, header + 8)?,
+                data: self
+                    .data
+                    .get(file_offset..file_offset + file_size)
+                    .ok_or("Segment is outside the file")?,
+                mem_size: read_u32(self.data, header + 20)?,
+            });
+        }
+
+        Ok(segments)
+    }
+
+    pub fn sections(&self) -> Result<Vec<Section<'a>>, Box<dyn Error>> {
+        let names = self.section_header(read_u16(self.data, 0x32)? as usize)?;
+        let names_offset = read_u32(self.data, names + 16)? as usize;
+        let mut sections = Vec::new();
+
+        for index in 1..read_u16(self.data, 0x30)? as usize {
+            let header = self.section_header(index)?;
+            let kind = read_u32(self.data, header + 4)?;
+            let flags = read_u32(self.data, header + 8)?;
+
+            sections.push(Section {
+                name: read_str(
+                    self.data,
+                    names_offset + read_u32(self.data, header)? as usize,
+                )?,
+                address: read_u32(self.data, header + 12)?,
+                size: read_u32(self.data, header + 20)?,
+                alloc: flags & SHF_ALLOC != 0,
+                nobits: kind == SHT_NOBITS,
+            });
+        }
+
+        Ok(sections)
+    }
+
+    pub fn symbols(&self) -> Result<Vec<Symbol<'a>>, Box<dyn Error>> {
+        let mut symtab = None;
+
+        for index in 0..read_u16(self.data, 0x30)? as usize {
+            let header = self.section_header(index)?;
+
+            if read_u32(self.data, header + 4)? == SHT_SYMTAB {
+                symtab = Some(header);
+                break;
+            }
+        }
+
+        let symtab = match symtab {
+            Some(symtab) => symtab,
+            None => return Ok(Vec::new()),
+        };
+
+        let offset = read_u32(self.data, symtab + 16)? as usize;
+        let size = read_u32(self.data, symtab + 20)? as usize;
+        let strtab = self.section_header(read_u32(self.data, symtab + 24)? as usize)?;
+        let strings_offset = read_u32(self.data, strtab + 16)? as usize;
+        let mut symbols = Vec::new();
+
+        for symbol in (offset..offset + size).step_by(SYMBOL_SIZE).skip(1) {
+            symbols.push(Symbol {
+                name: read_str(
+                    self.data,
+                    strings_offset + read_u32(self.data, symbol)? as usize,
+                )?,
+                address: read_u32(self.data, symbol + 4)?,
+                size: read_u32(self.data, symbol + 8)?,
+                info: *self
+                    .data
+                    .get(symbol + 12)
+                    .ok_or("Symbol table is cut short")?,
+            });
+        }
+
+        Ok(symbols)
+    }
+
+    /// Address of the symbol called `name`.
+    pub fn symbol(&self, name: &str) -> Result<Option<u32>, Box<dyn Error>> {
+        Ok(self
+            .symbols()?
+            .iter()
+            .find(|symbol| symbol.name == name)
+            .map(|symbol| symbol.address))
+    }
+
+    fn section_header(&self, index: usize) -> Result<usize, Box<dyn Error>> {
+        Ok(read_u32(self.data, 0x20)? as usize + index * SECTION_HEADER_SIZE)
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, Box<dyn Error>> {
+    let bytes = data
+        .get(offset..offset + 4)
+        .ok_or("Read past the end of the ELF")?;
+    Ok(u32::from_be_bytes(bytes.try_into()?))
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16, Box<dyn Error>> {
+    let bytes = data
+        .get(offset..offset + 2)
+        .ok_or("Read past the end of the ELF")?;
+    Ok(u16::from_be_bytes(bytes.try_into()?))
+}
+
+fn read_str(data: &[u8], offset: usize) -> Result<&str, Box<dyn Error>> {
+    let bytes = data.get(offset..).ok_or("Read past the end of the ELF")?;
+    let len = bytes
+        .iter()
+        .position(|b| *b == 0)
+        .ok_or("Unterminated string in the ELF")?;
+
+    Ok(str::from_utf8(&bytes[..len])?)
+}
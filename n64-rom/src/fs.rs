@@ -0,0 +1,170 @@
+//! A read only file system appended to the ROM after the program, where
+//! `__rom_end` points. Everything is big endian: the magic, the file count,
+//! then per file its name offset, data offset and size, all relative to the
+//! start of the file system, then the names, each ended by a zero. File
+//! data is 8 byte aligned so it can be read with DMA.
+
+use std::convert::TryInto;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::str;
+
+pub const FS_MAGIC: u32 = 0x4E36_3446; // "N64F"
+
+const HEADER_SIZE: usize = 8;
+const ENTRY_SIZE: usize = 12;
+const DATA_ALIGN: usize = 8;
+
+/// Name and contents of each file.
+type Files<'a> = Vec<(&'a str, &'a [u8])>;
+
+pub struct FsFile {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+/// Files are stored sorted by name.
+pub fn encode_fs(files: &[FsFile]) -> Vec<u8> {
+    let mut files: Vec<_> = files.iter().collect();
+    files.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut names = Vec::new();
+    let mut name_offsets = Vec::new();
+    let names_start = HEADER_SIZE + files.len() * ENTRY_SIZE;
+
+    for file in files.iter() {
+        name_offsets.push(names_start + names.len());
+        names.extend_from_slice(file.name.as_bytes());
+        names.push(0);
+    }
+
+    let mut data = Vec::new();
+    let mut data_offsets = Vec::new();
+    let data_start = align(names_start + names.len());
+
+    for file in files.iter() {
+        data_offsets.push(data_start + data.len());
+        data.extend_from_slice(&file.data);
+        data.resize(align(data.len()), 0);
+    }
+
+    let mut out = Vec::new();
+    out.extend_from_slice(&FS_MAGIC.to_be_bytes());
+    out.extend_from_slice(&(files.len() as u32).to_be_bytes());
+
+    for (i, file) in files.iter().enumerate() {
+        out.extend_from_slice(&(name_offsets[i] as u32).to_be_bytes());
+        out.extend_from_slice(&(data_offsets[i] as u32).to_be_bytes());
+        out.extend_from_slice(&(file.data.len() as u32).to_be_bytes());
+    }
+
+    out.extend_from_slice(&names);
+    out.resize(data_start, 0);
+    out.extend_from_slice(&data);
+
+    out
+}
+
+/// The names and contents of the files in an encoded file system.
+pub fn decode_fs(fs: &[u8]) -> Result<Files<'_>, Box<dyn Error>> {
+    let word = |offset: usize| -> Result<usize, Box<dyn Error>> {
+        let bytes = fs
+            .get(offset..offset + 4)
+            .ok_or("File system is cut short")?;
+        Ok(u32::from_be_bytes(bytes.try_into()?) as usize)
+    };
+
+    if word(0)? != FS_MAGIC as usize {
+        return Err("Not a file system".into());
+    }
+
+    let mut files = Vec::new();
+
+    for entry in (0..word(4)?).map(|i| HEADER_SIZE + i * ENTRY_SIZE) {
+        let name = fs
+            .get(word(entry)?..)
+            .ok_or("File name is outside the file system")?;
+        let name = &name[..name.iter().position(|b| *b == 0).unwrap_or(name.len())];
+        let start = word(entry + 4)?;
+        let data = fs
+            .get(start..start + word(entry + 8)?)
+            .ok_or("File data is outside the file system")?;
+
+        files.push((str::from_utf8(name)?, data));
+    }
+
+    Ok(files)
+}
+
+/// Every file under `dir`, named by their path in it with `/` between
+/// directories.
+pub fn read_fs_dir(dir: &Path) -> Result<Vec<FsFile>, Box<dyn Error>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+
+    while let Some(current) = dirs.pop() {
+        for entry in fs::read_dir(&current)? {
+            let path = entry?.path();
+
+            if path.is_dir() {
+                dirs.push(path);
+                continue;
+            }
+
+            let name = path
+                .strip_prefix(dir)?
+                .components()
+                .map(|part| part.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+
+            files.push(FsFile {
+                name,
+                data: fs::read(&path)?,
+            });
+        }
+    }
+
+    Ok(files)
+}
+
+#[inline]
+fn align(offset: usize) -> usize {
+    (offset + DATA_ALIGN - 1) & !(DATA_ALIGN - 1)
+}
+
+#[test]
+fn files_survive_encoding() {
+    let files = [
+        FsFile {
+            name: "maps/level1.map".into(),
+            data: vec![1, 2, 3],
+        },
+        FsFile {
+            name: "a.bin".into(),
+            data: vec![4; 17],
+        },
+        FsFile {
+            name: "empty".into(),
+            data: Vec::new(),
+        },
+    ];
+
+    let fs = encode_fs(&files);
+    let decoded = decode_fs(&fs).unwrap();
+
+    assert_eq!(
+        decoded,
+        [
+            ("a.bin", &[4; 17][..]),
+            ("empty", &[][..]),
+            ("maps/level1.map", &[1, 2, 3][..]),
+        ]
+    );
+    assert_eq!(fs.len() % DATA_ALIGN, 0);
+    assert!(decoded
+        .iter()
+        .all(|(_, data)| (data.as_ptr() as usize - fs.as_ptr() as usize) & (DATA_ALIGN - 1) == 0));
+    assert!(decode_fs(&fs[..fs.len() - 8]).is_err());
+}
@@ -0,0 +1,138 @@
+use std::convert::TryInto;
+use std::error::Error;
+
+pub const TITLE_LEN: usize = 20;
+
+const HEADER_SIZE: usize = 0x40;
+const TITLE_OFFSET: usize = 0x20;
+const GAME_CODE_OFFSET: usize = 0x3B;
+const VERSION_OFFSET: usize = 0x3F;
+
+/// The fields of the 64 byte header at the start of a big endian ROM.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub clock_rate: u32,
+    pub entry_point: u32,
+    pub release: u32,
+    pub crc1: u32,
+    pub crc2: u32,
+    pub title: String,
+    /// Media type, two letter id and region, `NSME` is Super Mario 64 in
+    /// North America.
+    pub game_code: [u8; 4],
+    pub version: u8,
+}
+
+impl Header {
+    pub fn read(rom: &[u8]) -> Result<Header, Box<dyn Error>> {
+        let header = rom
+            .get(..HEADER_SIZE)
+            .ok_or("ROM is too small to have a header")?;
+        let word =
+            |offset: usize| u32::from_be_bytes(header[offset..offset + 4].try_into().unwrap());
+
+        let title = &header[TITLE_OFFSET..TITLE_OFFSET + TITLE_LEN];
+        let title = String::from_utf8_lossy(title)
+            .trim_end_matches(&[' ', '\0'][..])
+            .to_string();
+
+        Ok(Header {
+            clock_rate: word(0x04),
+            entry_point: word(0x08),
+            release: word(0x0C),
+            crc1: word(0x10),
+            crc2: word(0x14),
+            title,
+            game_code: header[GAME_CODE_OFFSET..GAME_CODE_OFFSET + 4]
+                .try_into()
+                .unwrap(),
+            version: header[VERSION_OFFSET],
+        })
+    }
+
+    /// Writes everything but the checksum, which is only right for the rest
+    /// of the ROM.
+    pub fn write(&self, rom: &mut [u8]) -> Result<(), Box<dyn Error>> {
+        if rom.len() < HEADER_SIZE {
+            return Err("ROM is too small to have a header".into());
+        }
+
+        if !self.title.is_ascii() || self.title.len() > TITLE_LEN {
+            return Err(format!("Title must be at most {} ASCII characters", TITLE_LEN).into());
+        }
+
+        if !self.game_code.iter().all(u8::is_ascii_alphanumeric) {
+            return Err("Game code must be 4 letters or digits".into());
+        }
+
+        rom[0x04..0x08].copy_from_slice(&self.clock_rate.to_be_bytes());
+        rom[0x08..0x0C].copy_from_slice(&self.entry_point.to_be_bytes());
+        rom[0x0C..0x10].copy_from_slice(&self.release.to_be_bytes());
+
+        let title = &mut rom[TITLE_OFFSET..TITLE_OFFSET + TITLE_LEN];
+        title.iter_mut().for_each(|c| *c = b' ');
+        title[..self.title.len()].copy_from_slice(self.title.as_bytes());
+
+        rom[GAME_CODE_OFFSET..GAME_CODE_OFFSET + 4].copy_from_slice(&self.game_code);
+        rom[VERSION_OFFSET] = self.version;
+
+        Ok(())
+    }
+
+    #[inline]
+    pub fn region(&self) -> u8 {
+        self.game_code[3]
+    }
+}
+
+/// Where a region code in the game code is sold.
+pub fn region_name(region: u8) -> &'static str {
+    match region {
+        b'A' => "All regions",
+        b'B' => "Brazil",
+        b'C' => "China",
+        b'D' => "Germany",
+        b'E' => "North America",
+        b'F' => "France",
+        b'I' => "Italy",
+        b'J' => "Japan",
+        b'P' => "Europe",
+        b'S' => "Spain",
+        b'U' => "Australia",
+        b'X' | b'Y' => "Europe",
+        _ => "Unknown",
+    }
+}
+
+#[test]
+fn header_fields_are_read_and_written() {
+    let mut rom = vec![0; 0x1000];
+    rom[..4].copy_from_slice(&[0x80, 0x37, 0x12, 0x40]);
+    rom[0x08..0x0C].copy_from_slice(&0x8000_0400u32.to_be_bytes());
+    rom[0x10..0x14].copy_from_slice(&0x1234_5678u32.to_be_bytes());
+
+    let mut header = Header::read(&rom).unwrap();
+    assert_eq!(header.entry_point, 0x8000_0400);
+    assert_eq!(header.crc1, 0x1234_5678);
+    assert_eq!(header.title, "");
+
+    header.title = "LOKA".into();
+    header.game_code = *b"NLKE";
+    header.version = 1;
+    header.crc1 = 0;
+    header.write(&mut rom).unwrap();
+
+    let read = Header::read(&rom).unwrap();
+    assert_eq!(read.title, "LOKA");
+    assert_eq!(&rom[0x20..0x34], b"LOKA                ");
+    assert_eq!(read.game_code, *b"NLKE");
+    assert_eq!(region_name(read.region()), "North America");
+    assert_eq!(read.version, 1);
+    assert_eq!(read.crc1, 0x1234_5678, "the checksum is left alone");
+
+    header.title = "A TITLE THAT IS TOO LONG".into();
+    assert!(header.write(&mut rom).is_err());
+    header.title = "LOKA".into();
+    header.game_code = *b"N K ";
+    assert!(header.write(&mut rom).is_err());
+}
@@ -0,0 +1,25 @@
+//! Reading, converting, building and fixing up N64 ROM images.
+
+pub use builder::{build_rom, BuildReport, RomOptions, SectionSize, BOOT_LOAD_SIZE, PROGRAM_START};
+pub use byte_order::{convert, ByteOrder};
+pub use checksum::{checksum, fix_checksum, MIN_ROM_SIZE};
+pub use cic::{crc32, Cic};
+pub use elf::{Elf, Section, Segment, Symbol};
+pub use fs::{decode_fs, encode_fs, read_fs_dir, FsFile, FS_MAGIC};
+pub use header::{region_name, Header, TITLE_LEN};
+pub use size::{
+    parse_size, AssetSize, AssetSizes, Budget, MapUsage, SizeReport, DEFAULT_RDRAM_SIZE,
+};
+
+mod builder;
+mod byte_order;
+mod checksum;
+mod cic;
+mod elf;
+mod fs;
+mod header;
+mod size;
+
+/// The IPL3 boot code follows the 64 byte header.
+pub const BOOTCODE_START: usize = 0x40;
+pub const BOOTCODE_END: usize = 0x1000;
@@ -0,0 +1,274 @@
+use n64_rom::{
+    build_rom, convert, fix_checksum, parse_size, read_fs_dir, region_name, AssetSizes, Budget,
+    ByteOrder, Cic, Header, RomOptions, SizeReport, BOOTCODE_END, BOOTCODE_START,
+    DEFAULT_RDRAM_SIZE,
+};
+use std::convert::TryInto;
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+const USAGE: &str = "Usage: n64-rom COMMAND
+
+Commands:
+    build ELF --ipl3 FILE [--fs DIR] [--title TITLE] [--out ROM]
+                              Build a z64 ROM from the linked game, next to the ELF by default,
+                              with the files under DIR in its file system
+    size ELF [--assets FILE] [--rom ROM] [--rdram SIZE] [--json]
+             [--max-rom SIZE] [--max-program SIZE] [--max-assets SIZE] [--min-heap SIZE]
+                              Show code, data and asset sizes and the heap left on a console with
+                              SIZE RDRAM, 4M by default. FILE is game/asset_sizes.tsv from the
+                              asset build. Fails when over one of the limits
+    info ROM                  Show the header, byte order, boot code and checksum
+    convert ROM OUT           Convert to the byte order of the extension of OUT, z64, v64 or n64
+    set ROM [--title TITLE] [--game-code CODE] [--region REGION] [--version VERSION]
+                              Edit header fields
+    fix-crc ROM [--cic CIC]   Fix the header checksum, for the boot code in the ROM unless given
+    extract-ipl3 ROM [OUT]    Write the boot code to OUT, bootcode.bin by default
+    identify-ipl3 FILE        Tell the CIC an extracted boot code is for
+
+ROMs are written back in the byte order they were read in. Sizes are in bytes or with a K or M
+suffix.";
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    match args.as_slice() {
+        ["build", elf, options @ ..] => build(elf, options),
+        ["size", elf, options @ ..] => size(elf, options),
+        ["info", rom] => info(rom),
+        ["convert", rom, out] => convert_rom(rom, out),
+        ["set", rom, options @ ..] => set(rom, options),
+        ["fix-crc", rom] => fix_crc(rom, None),
+        ["fix-crc", rom, "--cic", cic] => fix_crc(rom, Some(cic)),
+        ["extract-ipl3", rom] => extract_ipl3(rom, "bootcode.bin"),
+        ["extract-ipl3", rom, out] => extract_ipl3(rom, out),
+        ["identify-ipl3", file] => identify_ipl3(file),
+        _ => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+    }
+}
+
+fn build(elf_path: &str, options: &[&str]) -> Result<(), Box<dyn Error>> {
+    let mut bootcode = None;
+    let mut fs_dir = None;
+    let mut title = "";
+    let mut out = Path::new(elf_path).with_extension("z64");
+
+    for option in options.chunks(2) {
+        match option {
+            ["--ipl3", path] => bootcode = Some(fs::read(path)?),
+            ["--fs", dir] => fs_dir = Some(Path::new(dir)),
+            ["--title", value] => title = value,
+            ["--out", path] => out = path.into(),
+            _ => return Err(format!("Bad option {}", option.join(" ")).into()),
+        }
+    }
+
+    let bootcode = bootcode.ok_or("The boot code must be given with --ipl3")?;
+    let files = match fs_dir {
+        Some(dir) => read_fs_dir(dir)?,
+        None => Vec::new(),
+    };
+
+    let (rom, report) = build_rom(
+        &fs::read(elf_path)?,
+        &RomOptions {
+            bootcode: &bootcode,
+            title,
+            files: &files,
+        },
+    )?;
+
+    fs::write(&out, rom)?;
+    print!("{}", report);
+    println!("Wrote {}", out.display());
+
+    Ok(())
+}
+
+fn size(elf_path: &str, options: &[&str]) -> Result<(), Box<dyn Error>> {
+    let mut assets = AssetSizes::default();
+    let mut rom_size = None;
+    let mut rdram_size = DEFAULT_RDRAM_SIZE;
+    let mut json = false;
+    let mut budget = Budget::default();
+    let mut options = options.iter();
+
+    while let Some(option) = options.next() {
+        let mut value = || {
+            options
+                .next()
+                .ok_or_else(|| format!("{} needs a value", option))
+        };
+
+        match *option {
+            "--assets" => assets = AssetSizes::parse(&fs::read_to_string(value()?)?)?,
+            "--rom" => rom_size = Some(fs::metadata(value()?)?.len() as usize),
+            "--rdram" => rdram_size = parse_size(value()?)?,
+            "--json" => json = true,
+            "--max-rom" => budget.max_rom = Some(parse_size(value()?)?),
+            "--max-program" => budget.max_program = Some(parse_size(value()?)?),
+            "--max-assets" => budget.max_assets = Some(parse_size(value()?)?),
+            "--min-heap" => budget.min_heap = Some(parse_size(value()?)?),
+            _ => return Err(format!("Bad option {}", option).into()),
+        }
+    }
+
+    let report = SizeReport::new(&fs::read(elf_path)?, assets, rom_size, rdram_size)?;
+    let over_budget = report.over_budget(&budget)?;
+
+    if json {
+        print!("{}", report.to_json(&over_budget));
+    } else {
+        print!("{}", report);
+    }
+
+    if !over_budget.is_empty() {
+        return Err(format!("Over budget: {}", over_budget.join(", ")).into());
+    }
+
+    Ok(())
+}
+
+/// Reads a ROM and converts it to big endian.
+fn load(path: &str) -> Result<(Vec<u8>, ByteOrder), Box<dyn Error>> {
+    let mut rom = fs::read(path)?;
+    let order = ByteOrder::detect(&rom).ok_or_else(|| format!("{} is not an N64 ROM", path))?;
+    convert(&mut rom, order, ByteOrder::Z64)?;
+
+    Ok((rom, order))
+}
+
+fn save(path: &str, mut rom: Vec<u8>, order: ByteOrder) -> Result<(), Box<dyn Error>> {
+    convert(&mut rom, ByteOrder::Z64, order)?;
+    fs::write(path, rom)?;
+
+    Ok(())
+}
+
+fn info(path: &str) -> Result<(), Box<dyn Error>> {
+    let (rom, order) = load(path)?;
+    let header = Header::read(&rom)?;
+    let cic = Cic::identify_rom(&rom);
+
+    println!("Byte order:  {}", order.extension());
+    println!("Size:        {} KiB", rom.len() / 1024);
+    println!("Title:       {}", header.title);
+    println!(
+        "Game code:   {}",
+        String::from_utf8_lossy(&header.game_code)
+    );
+    println!(
+        "Region:      {} ({})",
+        header.region() as char,
+        region_name(header.region())
+    );
+    println!("Version:     {}", header.version);
+    println!("Entry point: {:#010x}", header.entry_point);
+    println!("CIC:         {}", cic.map_or("Unknown", Cic::name));
+    println!("Checksum:    {:08x} {:08x}", header.crc1, header.crc2);
+
+    if let Some(cic) = cic {
+        match n64_rom::checksum(&rom, cic) {
+            Ok(crc) if crc == (header.crc1, header.crc2) => println!("             correct"),
+            Ok((crc1, crc2)) => println!("             wrong, should be {:08x} {:08x}", crc1, crc2),
+            Err(e) => println!("             {}", e),
+        }
+    }
+
+    Ok(())
+}
+
+fn convert_rom(path: &str, out: &str) -> Result<(), Box<dyn Error>> {
+    let extension = Path::new(out)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or("");
+    let to = ByteOrder::from_extension(extension)
+        .ok_or("The output must end with .z64, .v64 or .n64")?;
+
+    let (rom, _) = load(path)?;
+    save(out, rom, to)
+}
+
+fn set(path: &str, options: &[&str]) -> Result<(), Box<dyn Error>> {
+    let (mut rom, order) = load(path)?;
+    let mut header = Header::read(&rom)?;
+
+    for option in options.chunks(2) {
+        match option {
+            ["--title", title] => header.title = title.to_string(),
+            ["--game-code", code] => {
+                header.game_code = code
+                    .as_bytes()
+                    .try_into()
+                    .map_err(|_| "Game code must be 4 characters")?
+            }
+            ["--region", region] if region.len() == 1 => header.game_code[3] = region.as_bytes()[0],
+            ["--version", version] => header.version = version.parse()?,
+            _ => return Err(format!("Bad option {}", option.join(" ")).into()),
+        }
+    }
+
+    header.write(&mut rom)?;
+    save(path, rom, order)
+}
+
+fn fix_crc(path: &str, cic: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let (mut rom, order) = load(path)?;
+
+    let cic = match cic {
+        Some(name) => Cic::from_name(name).ok_or_else(|| format!("Unknown CIC {}", name))?,
+        None => Cic::identify_rom(&rom).ok_or("Unknown boot code, pass the CIC with --cic")?,
+    };
+
+    if fix_checksum(&mut rom, cic)? {
+        save(path, rom, order)?;
+        println!("Fixed the checksum for CIC-{}", cic.name());
+    } else {
+        println!("The checksum is already correct for CIC-{}", cic.name());
+    }
+
+    Ok(())
+}
+
+fn extract_ipl3(path: &str, out: &str) -> Result<(), Box<dyn Error>> {
+    let (rom, _) = load(path)?;
+    let bootcode = rom
+        .get(BOOTCODE_START..BOOTCODE_END)
+        .ok_or("ROM is too small to have boot code")?;
+
+    match Cic::identify(bootcode) {
+        Some(cic) => println!("Boot code for CIC-{}", cic.name()),
+        None => println!("Warning: the boot code is not one of the known ones"),
+    }
+
+    fs::write(out, bootcode)?;
+
+    Ok(())
+}
+
+fn identify_ipl3(path: &str) -> Result<(), Box<dyn Error>> {
+    let bootcode = fs::read(path)?;
+
+    if bootcode.len() != BOOTCODE_END - BOOTCODE_START {
+        return Err(format!(
+            "Boot code is {} bytes, not {}",
+            bootcode.len(),
+            BOOTCODE_END - BOOTCODE_START
+        )
+        .into());
+    }
+
+    match Cic::identify(&bootcode) {
+        Some(cic) => println!("CIC-{}", cic.name()),
+        None => println!("Unknown, CRC32 {:08x}", n64_rom::crc32(&bootcode)),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,537 @@
+//! Where the ROM and RAM go: the sections of the linked game, the assets the
+//! asset build included, how the maps use their tiles and how much heap is
+//! left at boot.
+
+use crate::Elf;
+use n64_alloc::SCRATCH_LEN_BYTES;
+use n64_types::STACK_SIZE;
+use std::error::Error;
+use std::fmt::{self, Write};
+
+const RDRAM_START: u32 = 0x8000_0000;
+
+/// RDRAM without an Expansion Pak.
+pub const DEFAULT_RDRAM_SIZE: usize = 4 * 1024 * 1024;
+
+pub struct AssetSize {
+    pub category: String,
+    pub name: String,
+    pub bytes: usize,
+}
+
+/// How a map uses its tiles. Every map includes the tiles of the maps
+/// before it as well, `included_tiles` counts all of them.
+pub struct MapUsage {
+    pub name: String,
+    pub width: usize,
+    pub height: usize,
+    pub layers: usize,
+    /// Cells that are not empty, over all layers.
+    pub filled_cells: usize,
+    /// Different tiles in the cells.
+    pub used_tiles: usize,
+    pub included_tiles: usize,
+}
+
+/// What `game/build.rs` writes to `asset_sizes.tsv`.
+#[derive(Default)]
+pub struct AssetSizes {
+    pub assets: Vec<AssetSize>,
+    pub maps: Vec<MapUsage>,
+}
+
+impl AssetSizes {
+    pub fn parse(text: &str) -> Result<AssetSizes, Box<dyn Error>> {
+        let mut sizes = AssetSizes::default();
+
+        for (index, line) in text.lines().enumerate() {
+            let fields: Vec<&str> = line.split('\t').collect();
+            let number = |field: &str| {
+                field
+                    .parse::<usize>()
+                    .map_err(|_| format!("Line {}: {} is not a number", index + 1, field))
+            };
+
+            match fields.as_slice() {
+                [""] => (),
+                [comment, ..] if comment.starts_with('#') => (),
+                ["asset", category, name, bytes] => sizes.assets.push(AssetSize {
+                    category: category.to_string(),
+                    name: name.to_string(),
+                    bytes: number(bytes)?,
+                }),
+                ["map", name, width, height, layers, filled_cells, used_tiles, included_tiles] => {
+                    sizes.maps.push(MapUsage {
+                        name: name.to_string(),
+                        width: number(width)?,
+                        height: number(height)?,
+                        layers: number(layers)?,
+                        filled_cells: number(filled_cells)?,
+                        used_tiles: number(used_tiles)?,
+                        included_tiles: number(included_tiles)?,
+                    })
+                }
+                _ => return Err(format!("Line {}: unknown record {}", index + 1, line).into()),
+            }
+        }
+
+        Ok(sizes)
+    }
+
+    /// Name, asset count and bytes of each category, in the order they
+    /// first appear.
+    pub fn categories(&self) -> Vec<(&str, usize, usize)> {
+        let mut categories: Vec<(&str, usize, usize)> = Vec::new();
+
+        for asset in self.assets.iter() {
+            match categories
+                .iter_mut()
+                .find(|(name, _, _)| *name == asset.category)
+            {
+                Some((_, count, bytes)) => {
+                    *count += 1;
+                    *bytes += asset.bytes;
+                }
+                None => categories.push((&asset.category, 1, asset.bytes)),
+            }
+        }
+
+        categories
+    }
+
+    pub fn total(&self) -> usize {
+        self.assets.iter().map(|asset| asset.bytes).sum()
+    }
+}
+
+/// Limits for `SizeReport::over_budget`, `None` is no limit.
+#[derive(Default)]
+pub struct Budget {
+    pub max_rom: Option<usize>,
+    /// Code, data and `.bss`, everything in RAM before the heap.
+    pub max_program: Option<usize>,
+    pub max_assets: Option<usize>,
+    pub min_heap: Option<usize>,
+}
+
+pub struct SizeReport {
+    /// `.boot` and `.text`.
+    pub code: usize,
+    pub rodata: usize,
+    /// `.data`, `.sdata` and `.got`.
+    pub data: usize,
+    pub bss: usize,
+    /// Where the heap starts.
+    pub program_end: u32,
+    pub rdram_size: usize,
+    /// RDRAM between the end of the program and the stack.
+    pub heap: usize,
+    /// The heap on PC, where the game is usually tried out first.
+    pub scratch_heap: usize,
+    pub rom_size: Option<usize>,
+    pub assets: AssetSizes,
+}
+
+impl SizeReport {
+    pub fn new(
+        elf: &[u8],
+        assets: AssetSizes,
+        rom_size: Option<usize>,
+        rdram_size: usize,
+    ) -> Result<SizeReport, Box<dyn Error>> {
+        let mut report = SizeReport {
+            code: 0,
+            rodata: 0,
+            data: 0,
+            bss: 0,
+            program_end: RDRAM_START,
+            rdram_size,
+            heap: 0,
+            scratch_heap: SCRATCH_LEN_BYTES,
+            rom_size,
+            assets,
+        };
+
+        for section in Elf::parse(elf)?.sections()? {
+            if !section.alloc || section.size == 0 {
+                continue;
+            }
+
+            let size = section.size as usize;
+            let name = section.name;
+
+            if section.nobits {
+                report.bss += size;
+            } else if name.starts_with(".text") || name.starts_with(".boot") {
+                report.code += size;
+            } else if name.starts_with(".rodata") {
+                report.rodata += size;
+            } else {
+                report.data += size;
+            }
+
+            report.program_end = report
+                .program_end
+                .max(section.address.wrapping_add(section.size));
+        }
+
+        let program = report.program_size();
+        report.heap = rdram_size.saturating_sub(program + STACK_SIZE);
+
+        Ok(report)
+    }
+
+    /// RAM taken by the program, from the start of RDRAM to the heap.
+    #[inline]
+    pub fn program_size(&self) -> usize {
+        self.program_end.saturating_sub(RDRAM_START) as usize
+    }
+
+    /// A line for each limit in `budget` that is exceeded. A ROM limit
+    /// needs the ROM size.
+    pub fn over_budget(&self, budget: &Budget) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut over = Vec::new();
+
+        if let Some(max) = budget.max_rom {
+            let rom_size = self
+                .rom_size
+                .ok_or("A ROM budget needs the size of the ROM, pass --rom")?;
+
+            if rom_size > max {
+                over.push(format!("ROM is {} bytes, over {}", rom_size, max));
+            }
+        }
+
+        if let Some(max) = budget.max_program {
+            if self.program_size() > max {
+                over.push(format!(
+                    "Program is {} bytes, over {}",
+                    self.program_size(),
+                    max
+                ));
+            }
+        }
+
+        if let Some(max) = budget.max_assets {
+            if self.assets.total() > max {
+                over.push(format!(
+                    "Assets are {} bytes, over {}",
+                    self.assets.total(),
+                    max
+                ));
+            }
+        }
+
+        if let Some(min) = budget.min_heap {
+            if self.heap < min {
+                over.push(format!("Heap is {} bytes, under {}", self.heap, min));
+            }
+        }
+
+        Ok(over)
+    }
+
+    pub fn to_json(&self, over_budget: &[String]) -> String {
+        let mut json = String::new();
+        let strings = |items: &[String]| {
+            items
+                .iter()
+                .map(|item| json_string(item))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        // Writing to a string can not fail.
+        let _ = writeln!(
+            json,
+            "{{\n  \"program\": {{\"code\": {}, \"rodata\": {}, \"data\": {}, \"bss\": {}, \"end\": {}, \"size\": {}}},",
+            self.code,
+            self.rodata,
+            self.data,
+            self.bss,
+            self.program_end,
+            self.program_size()
+        );
+        let _ = writeln!(
+            json,
+            "  \"heap\": {{\"rdram\": {}, \"stack\": {}, \"available\": {}, \"scratch\": {}}},",
+            self.rdram_size, STACK_SIZE, self.heap, self.scratch_heap
+        );
+        let _ = writeln!(
+            json,
+            "  \"rom_size\": {},",
+            self.rom_size
+                .map_or("null".to_string(), |size| size.to_string())
+        );
+
+        let categories: Vec<_> = self
+            .assets
+            .categories()
+            .iter()
+            .map(|(name, count, bytes)| {
+                format!(
+                    "{{\"name\": {}, \"count\": {}, \"bytes\": {}}}",
+                    json_string(name),
+                    count,
+                    bytes
+                )
+            })
+            .collect();
+        let assets: Vec<_> = self
+            .assets
+            .assets
+            .iter()
+            .map(|asset| {
+                format!(
+                    "{{\"category\": {}, \"name\": {}, \"bytes\": {}}}",
+                    json_string(&asset.category),
+                    json_string(&asset.name),
+                    asset.bytes
+                )
+            })
+            .collect();
+        let _ = writeln!(
+            json,
+            "  \"assets\": {{\n    \"total\": {},\n    \"categories\": [{}],\n    \"items\": [\n      {}\n    ]\n  }},",
+            self.assets.total(),
+            categories.join(", "),
+            assets.join(",\n      ")
+        );
+
+        let maps: Vec<_> = self
+            .assets
+            .maps
+            .iter()
+            .map(|map| {
+                format!(
+                    "{{\"name\": {}, \"width\": {}, \"height\": {}, \"layers\": {}, \"filled_cells\": {}, \"used_tiles\": {}, \"included_tiles\": {}}}",
+                    json_string(&map.name),
+                    map.width,
+                    map.height,
+                    map.layers,
+                    map.filled_cells,
+                    map.used_tiles,
+                    map.included_tiles
+                )
+            })
+            .collect();
+        let _ = writeln!(json, "  \"maps\": [{}],", maps.join(", "));
+        let _ = writeln!(json, "  \"over_budget\": [{}]\n}}", strings(over_budget));
+
+        json
+    }
+}
+
+impl fmt::Display for SizeReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let assets = self.assets.total();
+
+        writeln!(f, "Code        {:>10} bytes", self.code)?;
+        write!(f, "Rodata      {:>10} bytes", self.rodata)?;
+        if assets > 0 {
+            write!(f, ", {} of them assets", assets.min(self.rodata))?;
+        }
+        writeln!(f)?;
+        writeln!(f, "Data        {:>10} bytes", self.data)?;
+        writeln!(f, "Bss         {:>10} bytes", self.bss)?;
+        writeln!(
+            f,
+            "Program     {:>10} bytes, up to {:#010x}",
+            self.program_size(),
+            self.program_end
+        )?;
+
+        if let Some(rom_size) = self.rom_size {
+            writeln!(f, "ROM         {:>10} bytes", rom_size)?;
+        }
+
+        writeln!(f)?;
+        writeln!(
+            f,
+            "Heap        {:>10} bytes of {} KiB RDRAM, after the program and {} KiB stack",
+            self.heap,
+            self.rdram_size / 1024,
+            STACK_SIZE / 1024
+        )?;
+        writeln!(
+            f,
+            "PC heap     {:>10} bytes (SCRATCH_LEN_BYTES)",
+            self.scratch_heap
+        )?;
+
+        if self.heap < self.scratch_heap {
+            writeln!(
+                f,
+                "Warning: the N64 has {} bytes less heap than PC",
+                self.scratch_heap - self.heap
+            )?;
+        }
+
+        if self.assets.assets.is_empty() {
+            return Ok(());
+        }
+
+        writeln!(f)?;
+        writeln!(f, "{:<16} {:>6} {:>10}", "Assets", "Count", "Bytes")?;
+
+        for (name, count, bytes) in self.assets.categories() {
+            writeln!(f, "{:<16} {:>6} {:>10}", name, count, bytes)?;
+        }
+
+        writeln!(
+            f,
+            "{:<16} {:>6} {:>10}",
+            "total",
+            self.assets.assets.len(),
+            assets
+        )?;
+
+        let mut largest: Vec<_> = self.assets.assets.iter().collect();
+        largest.sort_by_key(|asset| core::cmp::Reverse(asset.bytes));
+
+        writeln!(f)?;
+        writeln!(f, "Largest assets")?;
+
+        for asset in largest.iter().take(10) {
+            writeln!(
+                f,
+                "{:<32} {:<16} {:>10}",
+                asset.name, asset.category, asset.bytes
+            )?;
+        }
+
+        if self.assets.maps.is_empty() {
+            return Ok(());
+        }
+
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{:<16} {:>9} {:>6} {:>12} {:>10} {:>10}",
+            "Map", "Size", "Layers", "Filled", "Used", "Included"
+        )?;
+
+        for map in self.assets.maps.iter() {
+            writeln!(
+                f,
+                "{:<16} {:>9} {:>6} {:>12} {:>10} {:>10}",
+                map.name,
+                format!("{}x{}", map.width, map.height),
+                map.layers,
+                format!(
+                    "{}/{}",
+                    map.filled_cells,
+                    map.width * map.height * map.layers
+                ),
+                map.used_tiles,
+                map.included_tiles
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Bytes, or kibibytes and mebibytes with a `K` or `M` suffix.
+pub fn parse_size(size: &str) -> Result<usize, Box<dyn Error>> {
+    let (number, unit) = match size.as_bytes().last() {
+        Some(b'K') | Some(b'k') => (&size[..size.len() - 1], 1024),
+        Some(b'M') | Some(b'm') => (&size[..size.len() - 1], 1024 * 1024),
+        _ => (size, 1),
+    };
+
+    number
+        .parse::<usize>()
+        .map(|number| number * unit)
+        .map_err(|_| format!("{} is not a size", size).into())
+}
+
+fn json_string(text: &str) -> String {
+    let mut json = String::from("\"");
+
+    for c in text.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(json, "\\u{:04x}", c as u32);
+            }
+            c => json.push(c),
+        }
+    }
+
+    json.push('"');
+    json
+}
+
+#[cfg(test)]
+const SAMPLE_SIZES: &str = "# Generated by game/build.rs, read by n64-rom size
+asset\ttextures\tSHIP_1\t2048
+asset\tsounds\tSHOOT_0\t4000
+asset\ttextures\tFONT_1_A\t512
+map\tMAP_1\t20\t100\t2\t1500\t12\t12
+";
+
+#[test]
+fn sample_sections_and_assets_add_up() {
+    let assets = AssetSizes::parse(SAMPLE_SIZES).unwrap();
+    assert_eq!(
+        assets.categories(),
+        [("textures", 2, 2560), ("sounds", 1, 4000)]
+    );
+    assert_eq!(assets.maps[0].included_tiles, 12);
+
+    let elf = include_bytes!("../tests/data/sample.elf");
+    let report = SizeReport::new(elf, assets, Some(0x10_1000), DEFAULT_RDRAM_SIZE).unwrap();
+
+    assert_eq!(
+        (report.code, report.rodata, report.data, report.bss),
+        (44, 22, 24, 64)
+    );
+    assert_eq!(report.program_end, 0x8000_04B0);
+    assert_eq!(report.heap, DEFAULT_RDRAM_SIZE - 0x4B0 - STACK_SIZE);
+
+    assert!(report.over_budget(&Budget::default()).unwrap().is_empty());
+    assert_eq!(
+        report
+            .over_budget(&Budget {
+                max_rom: Some(0x10_0000),
+                max_assets: Some(6000),
+                min_heap: Some(4 * 1024 * 1024),
+                ..Budget::default()
+            })
+            .unwrap(),
+        [
+            "ROM is 1052672 bytes, over 1048576",
+            "Assets are 6560 bytes, over 6000",
+            "Heap is 3930960 bytes, under 4194304"
+        ]
+    );
+
+    let json = report.to_json(&["A \"quoted\" line".to_string()]);
+    assert!(json.contains("\"program\": {\"code\": 44, \"rodata\": 22, \"data\": 24, \"bss\": 64"));
+    assert!(json.contains("{\"name\": \"sounds\", \"count\": 1, \"bytes\": 4000}"));
+    assert!(json.contains("\"over_budget\": [\"A \\\"quoted\\\" line\"]"));
+
+    let without_rom = SizeReport {
+        rom_size: None,
+        ..report
+    };
+    assert!(without_rom
+        .over_budget(&Budget {
+            max_rom: Some(0x10_0000),
+            ..Budget::default()
+        })
+        .is_err());
+}
+
+#[test]
+fn sizes_and_records_are_checked() {
+    assert_eq!(parse_size("300").unwrap(), 300);
+    assert_eq!(parse_size("2K").unwrap(), 2048);
+    assert_eq!(parse_size("4M").unwrap(), 4 * 1024 * 1024);
+    assert!(parse_size("4G").is_err());
+
+    assert!(AssetSizes::parse("asset\ttextures\tSHIP\tbig").is_err());
+    assert!(AssetSizes::parse("sprite\tSHIP\t10").is_err());
+}
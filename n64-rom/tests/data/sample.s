@@ -0,0 +1,41 @@
+# Source of sample.elf, rebuild it from the repository root with
+#
+#     llvm-mc -triple=mips -mcpu=mips3 -filetype=obj n64-rom/tests/data/sample.s -o sample.o
+#     rust-lld -flavor gnu -T n64.ld sample.o -o n64-rom/tests/data/sample.elf
+#
+# and the golden files with `UPDATE_GOLDEN=1 cargo test -p n64-rom`.
+
+.set noreorder
+
+.section .boot, "ax"
+.global _start
+_start:
+    la $t0, counter
+1:
+    lw $t1, 0($t0)
+    addiu $t1, $t1, 1
+    sw $t1, 0($t0)
+    jal add_one
+    nop
+    b 1b
+    nop
+
+.section .text.add_one, "ax"
+.global add_one
+.type add_one, @function
+add_one:
+    jr $ra
+    addiu $v0, $a0, 1
+
+.section .rodata.message, "a"
+message:
+    .asciz "HELLO FROM THE SAMPLE"
+
+.section .data.table, "aw"
+table:
+    .word 1, 2, 3, 4
+
+.section .bss.counter, "aw", @nobits
+.align 3
+counter:
+    .space 64
@@ -0,0 +1,100 @@
+//! Builds a ROM from `tests/data/sample.elf` and compares it and the report
+//! with the golden files next to it. Run with `UPDATE_GOLDEN=1` to rewrite
+//! them after an intended change.
+
+use n64_rom::{build_rom, checksum, decode_fs, Cic, FsFile, Header, RomOptions, MIN_ROM_SIZE};
+use std::env;
+use std::fs;
+use std::path::Path;
+
+fn data(name: &str) -> std::path::PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/data")
+        .join(name)
+}
+
+fn check_golden(name: &str, actual: &[u8]) {
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(data(name), actual).unwrap();
+    }
+
+    let golden = fs::read(data(name)).unwrap();
+    assert!(golden == actual, "{} differs from the golden file", name);
+}
+
+fn files() -> Vec<FsFile> {
+    ["hello.txt", "numbers.bin"]
+        .iter()
+        .map(|name| FsFile {
+            name: name.to_string(),
+            data: fs::read(data("files").join(name)).unwrap(),
+        })
+        .collect()
+}
+
+#[test]
+fn sample_rom_matches_the_golden_files() {
+    let elf = fs::read(data("sample.elf")).unwrap();
+    let bootcode: Vec<u8> = (0..4032).map(|i| (i * 31 % 251) as u8).collect();
+    let files = files();
+
+    let (rom, report) = build_rom(
+        &elf,
+        &RomOptions {
+            bootcode: &bootcode,
+            title: "SAMPLE",
+            files: &files,
+        },
+    )
+    .unwrap();
+
+    let used = report.fs_offset + report.fs_size;
+    check_golden("sample.z64", &rom[..used]);
+    check_golden("sample.report.txt", report.to_string().as_bytes());
+
+    assert_eq!(rom.len(), MIN_ROM_SIZE);
+    assert!(rom[used..].iter().all(|b| *b == 0));
+
+    let header = Header::read(&rom).unwrap();
+    assert_eq!(header.title, "SAMPLE");
+    assert_eq!(header.entry_point, 0x8000_0400);
+    assert_eq!(
+        checksum(&rom, Cic::Cic6102).unwrap(),
+        (header.crc1, header.crc2)
+    );
+
+    let decoded = decode_fs(&rom[report.fs_offset..]).unwrap();
+    assert_eq!(
+        decoded[0],
+        ("hello.txt", &b"Hello from the file system\n"[..])
+    );
+    assert_eq!(decoded[1].0, "numbers.bin");
+}
+
+#[test]
+fn a_bad_elf_or_boot_code_is_an_error() {
+    let elf = fs::read(data("sample.elf")).unwrap();
+    let options = RomOptions {
+        bootcode: &[0; 4032],
+        title: "SAMPLE",
+        files: &[],
+    };
+
+    assert!(build_rom(&elf[..100], &options).is_err());
+    assert!(build_rom(
+        &elf,
+        &RomOptions {
+            bootcode: &[0; 100],
+            ..options
+        }
+    )
+    .is_err());
+    assert!(build_rom(
+        &elf,
+        &RomOptions {
+            title: "A TITLE THAT IS TOO LONG",
+            ..options
+        }
+    )
+    .is_err());
+}
@@ -19,10 +19,8 @@ const AI_STATUS_FULL: usize = 1 << 31;
 
 const TV_TYPE_LOC: usize = 0x80000300;
 
-const FREQUENCY: usize = 22050;
-
 #[inline]
-pub fn init() {
+pub fn init(frequency: usize) {
     unsafe {
         let clockrate = match read_volatile(TV_TYPE_LOC as *const usize) {
             0 => AI_PAL_DACRATE,
@@ -30,7 +28,7 @@ pub fn init() {
             _ => AI_NTSC_DACRATE,
         };
 
-        write_volatile(AI_DACRATE, (2 * clockrate / FREQUENCY) - 1);
+        write_volatile(AI_DACRATE, (2 * clockrate / frequency) - 1);
         write_volatile(AI_SAMPLESIZE, 15);
     }
 }
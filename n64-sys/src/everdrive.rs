@@ -0,0 +1,145 @@
+use crate::sys::{get_ticks, TICKS_PER_SECOND};
+use core::ptr::{read_volatile, write_volatile};
+use n64_types::{usb_packet_header, UsbDataType, MAX_USB_PACKET_DATA, USB_PACKET_FOOTER};
+
+const PI_STATUS: *const u32 = 0xA460_0010 as _;
+const PI_STATUS_BUSY: u32 = 0x0003;
+
+const ED_BASE: usize = 0xBF80_0000;
+
+const ED_USB_CFG: *mut u32 = (ED_BASE + 0x0004) as _;
+const ED_VERSION: *const u32 = (ED_BASE + 0x0014) as _;
+const ED_USB_DAT: usize = ED_BASE + 0x0400;
+const ED_KEY: *mut u32 = (ED_BASE + 0x8004) as _;
+
+const ED_KEY_UNLOCK: u32 = 0xAA55;
+const ED_VERSION_MASK: u32 = 0xFFFF_0000;
+const ED_VERSION_ED64: u32 = 0xED64_0000;
+
+const USB_CMD_WRITE_NOP: u32 = 0xC000;
+const USB_CMD_WRITE: u32 = 0xC200;
+const USB_STATUS_POWER: u32 = 0x1000;
+const USB_STATUS_BUSY: u32 = 0x2000;
+
+/// Size of the cartridge USB buffer, data is sent a block at a time.
+const USB_BLOCK_SIZE: usize = 512;
+
+const USB_TIMEOUT_TICKS: u32 = TICKS_PER_SECOND / 10;
+
+#[inline]
+fn pi_wait() {
+    while unsafe { read_volatile(PI_STATUS) } & PI_STATUS_BUSY > 0 {}
+}
+
+#[inline]
+fn read_reg(reg: *const u32) -> u32 {
+    pi_wait();
+    unsafe { read_volatile(reg) }
+}
+
+#[inline]
+fn write_reg(reg: *mut u32, value: u32) {
+    pi_wait();
+    unsafe { write_volatile(reg, value) };
+}
+
+/// Unlocks the EverDrive registers and checks that they are there, false
+/// on other cartridges and emulators.
+#[inline]
+pub fn is_present() -> bool {
+    write_reg(ED_KEY, ED_KEY_UNLOCK);
+    read_reg(ED_VERSION) & ED_VERSION_MASK == ED_VERSION_ED64
+}
+
+/// True when a host has the USB port open.
+#[inline]
+pub fn usb_connected() -> bool {
+    read_reg(ED_USB_CFG) & USB_STATUS_POWER != 0
+}
+
+fn usb_wait() -> bool {
+    let start = get_ticks();
+
+    while read_reg(ED_USB_CFG) & USB_STATUS_BUSY != 0 {
+        if get_ticks().wrapping_sub(start) > USB_TIMEOUT_TICKS {
+            return false;
+        }
+    }
+
+    true
+}
+
+/// Sends `block` from the end of the cartridge USB buffer.
+fn usb_write_block(block: &[u8]) -> bool {
+    let len = (block.len() + 3) & !3;
+    let offset = USB_BLOCK_SIZE - len;
+
+    write_reg(ED_USB_CFG, USB_CMD_WRITE_NOP);
+
+    for (i, word) in block.chunks(4).enumerate() {
+        let mut bytes = [0; 4];
+        bytes[..word.len()].copy_from_slice(word);
+        write_reg(
+            (ED_USB_DAT + offset + i * 4) as *mut u32,
+            u32::from_be_bytes(bytes),
+        );
+    }
+
+    write_reg(ED_USB_CFG, USB_CMD_WRITE | offset as u32);
+
+    usb_wait()
+}
+
+struct BlockWriter {
+    block: [u8; USB_BLOCK_SIZE],
+    len: usize,
+    ok: bool,
+}
+
+impl BlockWriter {
+    #[inline]
+    fn write(&mut self, mut data: &[u8]) {
+        while self.ok && !data.is_empty() {
+            let count = data.len().min(USB_BLOCK_SIZE - self.len);
+            self.block[self.len..self.len + count].copy_from_slice(&data[..count]);
+            self.len += count;
+            data = &data[count..];
+
+            if self.len == USB_BLOCK_SIZE {
+                self.flush();
+            }
+        }
+    }
+
+    #[inline]
+    fn flush(&mut self) {
+        if self.ok && self.len > 0 {
+            self.ok = usb_write_block(&self.block[..self.len]);
+        }
+
+        self.len = 0;
+    }
+}
+
+/// Sends `data` as one packet that UNFLoader and `usb_log` understand.
+/// Gives up and returns false when nobody reads it. Call `is_present` once
+/// before using this.
+pub fn usb_write(data_type: UsbDataType, data: &[u8]) -> bool {
+    if !usb_connected() || !usb_wait() {
+        return false;
+    }
+
+    let data = &data[..data.len().min(MAX_USB_PACKET_DATA)];
+    let mut writer = BlockWriter {
+        block: [0; USB_BLOCK_SIZE],
+        len: 0,
+        ok: true,
+    };
+
+    writer.write(&usb_packet_header(data_type, data.len()));
+    writer.write(data);
+    writer.write(&USB_PACKET_FOOTER);
+    writer.flush();
+
+    writer.ok
+}
@@ -0,0 +1,52 @@
+use crate::sys::{data_cache_hit_writeback, instruction_cache_hit_invalidate};
+use core::ptr;
+use n64_types::ExceptionFrame;
+
+global_asm!(include_str!("exception.s"));
+
+// TLB refill, 64 bit TLB refill, cache error and general exception.
+const EXCEPTION_VECTORS: [usize; 4] = [0x8000_0000, 0x8000_0080, 0x8000_0100, 0x8000_0180];
+
+extern "C" {
+    static n64_exception_stub: u32;
+    static n64_exception_stub_end: u32;
+}
+
+#[no_mangle]
+static mut N64_EXCEPTION_FRAME: ExceptionFrame = ExceptionFrame::ZERO;
+
+static mut HANDLER: Option<fn(&ExceptionFrame) -> !> = None;
+
+#[no_mangle]
+extern "C" fn n64_exception_handler(frame: &ExceptionFrame) -> ! {
+    if let Some(handler) = unsafe { HANDLER } {
+        handler(frame);
+    }
+
+    #[allow(clippy::empty_loop)]
+    loop {}
+}
+
+/// Points every exception vector at `handler`, which gets the saved CPU
+/// state and runs on a stack of its own with interrupts disabled.
+#[inline]
+pub fn install(handler: fn(&ExceptionFrame) -> !) {
+    unsafe {
+        HANDLER = Some(handler);
+
+        let stub = &n64_exception_stub as *const u32;
+        let stub_len = (&n64_exception_stub_end as *const u32).offset_from(stub) as usize;
+        let stub = core::slice::from_raw_parts(stub, stub_len);
+
+        for &vector in EXCEPTION_VECTORS.iter() {
+            let vector = core::slice::from_raw_parts_mut(vector as *mut u32, stub_len);
+
+            for (dst, src) in vector.iter_mut().zip(stub.iter()) {
+                ptr::write_volatile(dst, *src);
+            }
+
+            data_cache_hit_writeback(vector);
+            instruction_cache_hit_invalidate(vector);
+        }
+    }
+}
@@ -0,0 +1,132 @@
+.set noreorder
+.set noat
+
+// Copied to each exception vector, jumps to the common handler.
+.section .text.n64_exception_stub, "ax"
+.global n64_exception_stub
+.global n64_exception_stub_end
+n64_exception_stub:
+    lui $k0, %hi(n64_exception_entry)
+    addiu $k0, $k0, %lo(n64_exception_entry)
+    jr $k0
+    nop
+n64_exception_stub_end:
+
+// Saves the CPU state to N64_EXCEPTION_FRAME in the layout of
+// `ExceptionFrame` and calls `n64_exception_handler` on its own stack.
+// Does not return.
+.section .text.n64_exception_entry, "ax"
+.global n64_exception_entry
+n64_exception_entry:
+    lui $k0, %hi(N64_EXCEPTION_FRAME)
+    addiu $k0, $k0, %lo(N64_EXCEPTION_FRAME)
+
+    sw $0, 0($k0)
+    sw $1, 4($k0)
+    sw $2, 8($k0)
+    sw $3, 12($k0)
+    sw $4, 16($k0)
+    sw $5, 20($k0)
+    sw $6, 24($k0)
+    sw $7, 28($k0)
+    sw $8, 32($k0)
+    sw $9, 36($k0)
+    sw $10, 40($k0)
+    sw $11, 44($k0)
+    sw $12, 48($k0)
+    sw $13, 52($k0)
+    sw $14, 56($k0)
+    sw $15, 60($k0)
+    sw $16, 64($k0)
+    sw $17, 68($k0)
+    sw $18, 72($k0)
+    sw $19, 76($k0)
+    sw $20, 80($k0)
+    sw $21, 84($k0)
+    sw $22, 88($k0)
+    sw $23, 92($k0)
+    sw $24, 96($k0)
+    sw $25, 100($k0)
+    sw $26, 104($k0)
+    sw $27, 108($k0)
+    sw $28, 112($k0)
+    sw $29, 116($k0)
+    sw $30, 120($k0)
+    sw $31, 124($k0)
+
+    mfhi $k1
+    sw $k1, 128($k0)
+    mflo $k1
+    sw $k1, 132($k0)
+
+    swc1 $f0, 136($k0)
+    swc1 $f1, 140($k0)
+    swc1 $f2, 144($k0)
+    swc1 $f3, 148($k0)
+    swc1 $f4, 152($k0)
+    swc1 $f5, 156($k0)
+    swc1 $f6, 160($k0)
+    swc1 $f7, 164($k0)
+    swc1 $f8, 168($k0)
+    swc1 $f9, 172($k0)
+    swc1 $f10, 176($k0)
+    swc1 $f11, 180($k0)
+    swc1 $f12, 184($k0)
+    swc1 $f13, 188($k0)
+    swc1 $f14, 192($k0)
+    swc1 $f15, 196($k0)
+    swc1 $f16, 200($k0)
+    swc1 $f17, 204($k0)
+    swc1 $f18, 208($k0)
+    swc1 $f19, 212($k0)
+    swc1 $f20, 216($k0)
+    swc1 $f21, 220($k0)
+    swc1 $f22, 224($k0)
+    swc1 $f23, 228($k0)
+    swc1 $f24, 232($k0)
+    swc1 $f25, 236($k0)
+    swc1 $f26, 240($k0)
+    swc1 $f27, 244($k0)
+    swc1 $f28, 248($k0)
+    swc1 $f29, 252($k0)
+    swc1 $f30, 256($k0)
+    swc1 $f31, 260($k0)
+
+    cfc1 $k1, $31
+    sw $k1, 264($k0)
+    mfc0 $k1, $12
+    sw $k1, 268($k0)
+    mfc0 $k1, $13
+    sw $k1, 272($k0)
+    mfc0 $k1, $14
+    sw $k1, 276($k0)
+    mfc0 $k1, $8
+    sw $k1, 280($k0)
+
+    // Clear the FPU cause and enable bits so the handler can use floats.
+    ctc1 $0, $31
+
+    // Leave exception level with interrupts disabled.
+    mfc0 $k1, $12
+    li $t0, ~3
+    and $k1, $k1, $t0
+    mtc0 $k1, $12
+    nop
+    nop
+
+    lui $sp, %hi(n64_exception_stack_top)
+    addiu $sp, $sp, %lo(n64_exception_stack_top)
+    addiu $sp, $sp, -16
+
+    jal n64_exception_handler
+    move $a0, $k0
+
+1:
+    j 1b
+    nop
+
+.section .bss.n64_exception_stack, "aw", @nobits
+.align 4
+n64_exception_stack:
+    .space 16384
+n64_exception_stack_top:
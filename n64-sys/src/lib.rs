@@ -1,11 +1,14 @@
 #![no_std]
 #![feature(llvm_asm)]
 #![feature(core_intrinsics)]
+#![feature(global_asm)]
 #![allow(clippy::missing_safety_doc)]
 
 extern crate alloc;
 
 pub mod ai;
+pub mod everdrive;
+pub mod exception;
 pub mod rdp;
 pub mod si;
 pub mod sys;
@@ -16,6 +16,9 @@ const RDP_COMMAND_BUFFER_BUSY: *const usize = (RDP_BASE + 0x14) as _;
 const RDP_PIPE_BUSY: *const usize = (RDP_BASE + 0x18) as _;
 const RDP_TMEM_BUSY: *const usize = (RDP_BASE + 0x1C) as _;
 
+// The counters are 24 bits wide.
+const RDP_COUNTER_MASK: usize = 0x00FF_FFFF;
+
 // RDP Status Read Flags:
 const RDP_STATUS_XBS: usize = 0x001; // RDP_STATUS: Use XBUS DMEM DMA Or DRAM DMA (Bit 0)
 const RDP_STATUS_FRZ: usize = 0x002; // RDP_STATUS: RDP Frozen (Bit 1)
@@ -46,6 +49,32 @@ fn wait_for_done() {
     while unsafe { read_volatile(RDP_STATUS) & RDP_STATUS_CMB } == 0 {}
 }
 
+#[inline]
+fn wait_for_idle() {
+    while unsafe { read_volatile(RDP_STATUS) & (RDP_STATUS_CMB | RDP_STATUS_PLB) } != 0 {}
+}
+
+/// RDP performance counters, in cycles of the 62.5 MHz RCP clock.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct RdpCounters {
+    pub clock: u32,
+    pub command_busy: u32,
+    pub pipe_busy: u32,
+    pub tmem_busy: u32,
+}
+
+#[inline]
+pub fn read_counters() -> RdpCounters {
+    unsafe {
+        RdpCounters {
+            clock: (read_volatile(RDP_CLOCK_COUNTER) & RDP_COUNTER_MASK) as u32,
+            command_busy: (read_volatile(RDP_COMMAND_BUFFER_BUSY) & RDP_COUNTER_MASK) as u32,
+            pipe_busy: (read_volatile(RDP_PIPE_BUSY) & RDP_COUNTER_MASK) as u32,
+            tmem_busy: (read_volatile(RDP_TMEM_BUSY) & RDP_COUNTER_MASK) as u32,
+        }
+    }
+}
+
 static mut COMMANDS: Option<Vec<RdpCommand>> = None;
 
 #[inline]
@@ -62,18 +91,26 @@ pub unsafe fn swap_commands(commands: Vec<RdpCommand>) -> Vec<RdpCommand> {
     temp
 }
 
+/// Runs the current commands and waits for the RDP to finish them. Returns
+/// the counters for this command buffer alone.
 #[inline]
-pub unsafe fn run_command_buffer() {
+pub unsafe fn run_command_buffer() -> RdpCounters {
     if let Some(commands) = &COMMANDS {
         if commands.is_empty() {
-            return;
+            return RdpCounters::default();
         }
 
         data_cache_hit_writeback(&commands);
 
         write_volatile(
             RDP_STATUS,
-            RDP_STATUS_CLR_XBS | RDP_STATUS_CLR_FRZ | RDP_STATUS_CLR_FLS,
+            RDP_STATUS_CLR_XBS
+                | RDP_STATUS_CLR_FRZ
+                | RDP_STATUS_CLR_FLS
+                | RDP_STATUS_CLR_TMC
+                | RDP_STATUS_CLR_PLC
+                | RDP_STATUS_CLR_CMC
+                | RDP_STATUS_CLR_CLK,
         );
         memory_barrier();
 
@@ -89,5 +126,10 @@ pub unsafe fn run_command_buffer() {
         memory_barrier();
 
         wait_for_done();
+        wait_for_idle();
+
+        read_counters()
+    } else {
+        RdpCounters::default()
     }
 }
@@ -1,4 +1,14 @@
 use core::mem::size_of;
+use core::ptr::read_volatile;
+
+const OS_MEM_SIZE: usize = 0x8000_0318;
+const RDRAM_START: usize = 0x8000_0000;
+
+pub use n64_types::STACK_SIZE;
+
+extern "C" {
+    static __bss_end: u8;
+}
 
 #[inline]
 pub unsafe fn data_cache_hit_writeback_invalidate<T>(block: &[T]) {
@@ -54,6 +64,33 @@ pub unsafe fn data_cache_hit_invalidate<T>(block: &[T]) {
     }
 }
 
+#[inline]
+pub unsafe fn instruction_cache_hit_invalidate<T>(block: &[T]) {
+    let mut addr = (block.as_ptr() as usize) & 0xffff_fffc;
+    let mut len = block.len() * size_of::<T>();
+
+    while len > 0 {
+        llvm_asm!("cache $0, ($1)"
+        :
+        : "i" (0x10), "r" (addr)
+        :
+        : "volatile"
+        );
+
+        len -= 4;
+        addr += 4;
+    }
+}
+
+#[inline]
+pub fn stack_pointer() -> usize {
+    let sp: usize;
+    unsafe {
+        llvm_asm!("move $0, $$sp" : "=r" (sp));
+    }
+    sp
+}
+
 #[inline]
 pub fn uncached_addr<T>(address: *const T) -> *const T {
     ((address as usize) | 0x2000_0000) as *const T
@@ -79,13 +116,11 @@ pub unsafe fn memory_barrier() {
     llvm_asm!("" ::: "memory" : "volatile");
 }
 
-#[inline]
-fn get_tick_rate() -> f32 {
-    93750000.0 / 2.0
-}
+pub use n64_types::TICKS_PER_SECOND;
 
+/// Reads the COP0 Count register. It wraps around about every 92 seconds.
 #[inline]
-fn get_ticks() -> u32 {
+pub fn get_ticks() -> u32 {
     let res;
 
     unsafe {
@@ -97,23 +132,18 @@ fn get_ticks() -> u32 {
     res
 }
 
-static mut LAST_TICKS: u32 = 0;
-static mut TIME: i64 = 0;
-
+/// Size of RDRAM as reported by the boot code, 8 MB with an Expansion Pak
+/// and 4 MB without.
 #[inline]
-pub fn current_time_us() -> i64 {
-    unsafe {
-        let now_ticks = get_ticks();
-
-        if now_ticks < LAST_TICKS {
-            TIME += 0x1_0000_0000 - LAST_TICKS as i64;
-            TIME += now_ticks as i64;
-        } else {
-            TIME += (now_ticks - LAST_TICKS) as i64;
-        }
+pub fn memory_size() -> usize {
+    unsafe { read_volatile(OS_MEM_SIZE as *const usize) }
+}
 
-        LAST_TICKS = now_ticks;
+/// RDRAM between the end of .bss and the stack, free for the heap.
+#[inline]
+pub fn heap_memory() -> (*mut u8, usize) {
+    let start = unsafe { &__bss_end as *const u8 as usize };
+    let end = RDRAM_START + memory_size() - STACK_SIZE;
 
-        (TIME as f32 / (get_tick_rate() / 1_000_000.0)) as i64
-    }
+    (start as *mut u8, end.saturating_sub(start))
 }
@@ -121,3 +121,9 @@ pub unsafe fn set_vi_buffer(fb: &mut [Color]) {
 pub unsafe fn get_vi_buffer() -> *mut Color {
     LAST_BUFFER.unwrap()
 }
+
+/// The buffer VI shows, or `None` before `init`.
+#[inline]
+pub unsafe fn try_get_vi_buffer() -> Option<*mut Color> {
+    LAST_BUFFER
+}
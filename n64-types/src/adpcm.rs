@@ -0,0 +1,42 @@
+//! ADPCM sound frames, written by the asset pipeline and decoded while the
+//! game mixes. A frame holds 16 samples of one channel in 9 bytes. The first
+//! byte is `scale << 4 | predictor`, followed by 16 signed 4-bit residuals. A
+//! predictor is a pair of Q11 coefficients applied to the last two decoded
+//! samples.
+
+pub const ADPCM_FRAME_SAMPLES: usize = 16;
+pub const ADPCM_FRAME_BYTES: usize = 9;
+pub const ADPCM_COEFFICIENT_SHIFT: i32 = 11;
+
+/// Decodes one frame, carrying the last two samples over in `history`.
+#[inline]
+pub fn decode_adpcm_frame(
+    frame: &[u8],
+    codebook: &[[i16; 2]],
+    history: &mut [i32; 2],
+    out: &mut [i16; ADPCM_FRAME_SAMPLES],
+) {
+    let scale = (frame[0] >> 4) as i32;
+    let predictor = codebook
+        .get((frame[0] & 0xf) as usize)
+        .copied()
+        .unwrap_or([0, 0]);
+
+    for (i, out_sample) in out.iter_mut().enumerate() {
+        let nibble = if i % 2 == 0 {
+            frame[1 + i / 2] >> 4
+        } else {
+            frame[1 + i / 2] & 0xf
+        };
+        let residual = (((nibble << 4) as i8) >> 4) as i32;
+
+        let prediction = (predictor[0] as i32 * history[0] + predictor[1] as i32 * history[1])
+            >> ADPCM_COEFFICIENT_SHIFT;
+        let sample = (prediction + (residual << scale))
+            .max(i16::MIN as i32)
+            .min(i16::MAX as i32);
+
+        *history = [sample, history[0]];
+        *out_sample = sample as i16;
+    }
+}
@@ -0,0 +1,29 @@
+/// CPU state saved by the exception handler, in the order it stores it.
+/// Registers are saved as their low 32 bits.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ExceptionFrame {
+    pub gpr: [u32; 32],
+    pub hi: u32,
+    pub lo: u32,
+    pub fpr: [u32; 32],
+    pub fcr31: u32,
+    pub sr: u32,
+    pub cause: u32,
+    pub epc: u32,
+    pub badvaddr: u32,
+}
+
+impl ExceptionFrame {
+    pub const ZERO: ExceptionFrame = ExceptionFrame {
+        gpr: [0; 32],
+        hi: 0,
+        lo: 0,
+        fpr: [0; 32],
+        fcr31: 0,
+        sr: 0,
+        cause: 0,
+        epc: 0,
+        badvaddr: 0,
+    };
+}
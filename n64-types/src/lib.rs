@@ -1,7 +1,32 @@
 #![no_std]
 
+extern crate alloc;
+
+pub use adpcm::{
+    decode_adpcm_frame, ADPCM_COEFFICIENT_SHIFT, ADPCM_FRAME_BYTES, ADPCM_FRAME_SAMPLES,
+};
+pub use exception_frame::ExceptionFrame;
 pub use rdp_command::RdpCommand;
+pub use symbol_table::{
+    encode_symbol_table, Symbol, SymbolTable, MAX_SYMBOL_NAME_LEN, SYMBOL_TABLE_HEADER_OFFSET,
+    SYMBOL_TABLE_MAGIC,
+};
+pub use usb_packet::{
+    usb_packet_header, UsbDataType, UsbPacket, UsbPacketDecoder, MAX_DECODED_USB_PACKET_DATA,
+    MAX_USB_PACKET_DATA, USB_PACKET_FOOTER, USB_PACKET_HEADER,
+};
 pub use video_mode::VideoMode;
 
+/// Rate of the COP0 Count register, half the CPU clock. Time is kept in
+/// these ticks on both N64 and PC so conversions behave the same everywhere.
+pub const TICKS_PER_SECOND: u32 = 93_750_000 / 2;
+
+/// Room left for the stack, which the entry point puts at the top of RDRAM.
+pub const STACK_SIZE: usize = 256 * 1024;
+
+mod adpcm;
+mod exception_frame;
 mod rdp_command;
+mod symbol_table;
+mod usb_packet;
 mod video_mode;
@@ -0,0 +1,174 @@
+//! Function symbols appended to the ROM so a crash can name the functions
+//! addresses are in. Everything is big endian: the magic, the symbol count,
+//! then per symbol its address, size and name offset sorted by address, then
+//! the names, each a length byte followed by the name.
+
+use alloc::vec::Vec;
+use core::str;
+
+pub const SYMBOL_TABLE_MAGIC: u32 = 0x5359_4D54; // "SYMT"
+
+/// ROM header word holding the ROM offset of the symbol table, 0 when the
+/// ROM has none.
+pub const SYMBOL_TABLE_HEADER_OFFSET: usize = 0x18;
+
+pub const MAX_SYMBOL_NAME_LEN: usize = 255;
+
+const HEADER_SIZE: usize = 8;
+const ENTRY_SIZE: usize = 12;
+
+/// Sorts the symbols and encodes them, names longer than
+/// `MAX_SYMBOL_NAME_LEN` are cut.
+pub fn encode_symbol_table(symbols: &mut [(u32, u32, &str)]) -> Vec<u8> {
+    symbols.sort_by_key(|(address, _, _)| *address);
+
+    let mut out = Vec::new();
+    let mut names = Vec::new();
+    let names_start = HEADER_SIZE + symbols.len() * ENTRY_SIZE;
+
+    out.extend_from_slice(&SYMBOL_TABLE_MAGIC.to_be_bytes());
+    out.extend_from_slice(&(symbols.len() as u32).to_be_bytes());
+
+    for (address, size, name) in symbols.iter() {
+        let name = &name.as_bytes()[..name.len().min(MAX_SYMBOL_NAME_LEN)];
+
+        out.extend_from_slice(&address.to_be_bytes());
+        out.extend_from_slice(&size.to_be_bytes());
+        out.extend_from_slice(&((names_start + names.len()) as u32).to_be_bytes());
+
+        names.push(name.len() as u8);
+        names.extend_from_slice(name);
+    }
+
+    out.extend_from_slice(&names);
+
+    while out.len() % 4 != 0 {
+        out.push(0);
+    }
+
+    out
+}
+
+pub struct Symbol {
+    pub address: u32,
+    pub size: u32,
+    name: [u8; MAX_SYMBOL_NAME_LEN],
+    name_len: u8,
+}
+
+impl Symbol {
+    #[inline]
+    pub fn name(&self) -> &str {
+        str::from_utf8(&self.name[..self.name_len as usize]).unwrap_or("?")
+    }
+}
+
+/// Reads an encoded table through `read`, which returns the big endian word
+/// at a word aligned byte offset into the table. The cartridge can only be
+/// read a word at a time, so the table is never needed in RAM.
+pub struct SymbolTable<R> {
+    read: R,
+    len: u32,
+}
+
+impl<R: Fn(usize) -> u32> SymbolTable<R> {
+    #[inline]
+    pub fn new(read: R) -> Option<Self> {
+        if read(0) != SYMBOL_TABLE_MAGIC {
+            return None;
+        }
+
+        let len = read(4);
+        Some(Self { read, len })
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The symbol `address` is in.
+    pub fn lookup(&self, address: u32) -> Option<Symbol> {
+        let mut low = 0;
+        let mut high = self.len as usize;
+
+        while low < high {
+            let mid = (low + high) / 2;
+
+            if self.entry_address(mid) <= address {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+
+        let index = low.checked_sub(1)?;
+        let entry = HEADER_SIZE + index * ENTRY_SIZE;
+        let symbol_address = (self.read)(entry);
+        let size = (self.read)(entry + 4);
+
+        if size != 0 && address - symbol_address >= size {
+            return None;
+        }
+
+        let name_offset = (self.read)(entry + 8) as usize;
+        let name_len = self.byte(name_offset);
+        let mut name = [0; MAX_SYMBOL_NAME_LEN];
+
+        for (i, byte) in name[..name_len as usize].iter_mut().enumerate() {
+            *byte = self.byte(name_offset + 1 + i);
+        }
+
+        Some(Symbol {
+            address: symbol_address,
+            size,
+            name,
+            name_len,
+        })
+    }
+
+    #[inline]
+    fn entry_address(&self, index: usize) -> u32 {
+        (self.read)(HEADER_SIZE + index * ENTRY_SIZE)
+    }
+
+    #[inline]
+    fn byte(&self, offset: usize) -> u8 {
+        let word = (self.read)(offset & !3);
+        (word >> ((3 - (offset & 3)) * 8)) as u8
+    }
+}
+
+#[test]
+fn symbols_are_found_by_address() {
+    let mut symbols = [
+        (0x8000_1000, 0x40, "game::main"),
+        (0x8000_0400, 0x100, "_start"),
+        (0x8000_2000, 0, "n64::crash::show"),
+    ];
+    let table = encode_symbol_table(&mut symbols);
+    let read = |offset: usize| {
+        u32::from_be_bytes([
+            table[offset],
+            table[offset + 1],
+            table[offset + 2],
+            table[offset + 3],
+        ])
+    };
+    let symbols = SymbolTable::new(read).unwrap();
+
+    assert_eq!(symbols.len(), 3);
+    assert_eq!(symbols.lookup(0x8000_0400).unwrap().name(), "_start");
+    assert_eq!(symbols.lookup(0x8000_103c).unwrap().name(), "game::main");
+    assert_eq!(
+        symbols.lookup(0x8000_3000).unwrap().name(),
+        "n64::crash::show"
+    );
+    assert!(symbols.lookup(0x8000_1040).is_none());
+    assert!(symbols.lookup(0x8000_0000).is_none());
+}
@@ -0,0 +1,227 @@
+//! Packets sent over the EverDrive-64 USB port, framed the way UNFLoader
+//! frames them: `DMA@`, a big endian word with the data type in the top byte
+//! and the data length in the rest, the data and then `CMPH`.
+
+use alloc::vec::Vec;
+
+pub const USB_PACKET_HEADER: [u8; 4] = *b"DMA@";
+pub const USB_PACKET_FOOTER: [u8; 4] = *b"CMPH";
+pub const MAX_USB_PACKET_DATA: usize = 0xff_ffff;
+/// The longest packet `UsbPacketDecoder` takes, log records are far shorter.
+pub const MAX_DECODED_USB_PACKET_DATA: usize = 64 * 1024;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum UsbDataType {
+    Text,
+    Binary,
+    Header,
+    Screenshot,
+    Heartbeat,
+    Unknown(u8),
+}
+
+impl UsbDataType {
+    #[inline]
+    pub fn from_u8(value: u8) -> Self {
+        match value {
+            0x01 => UsbDataType::Text,
+            0x02 => UsbDataType::Binary,
+            0x03 => UsbDataType::Header,
+            0x04 => UsbDataType::Screenshot,
+            0x05 => UsbDataType::Heartbeat,
+            value => UsbDataType::Unknown(value),
+        }
+    }
+
+    #[inline]
+    pub fn to_u8(self) -> u8 {
+        match self {
+            UsbDataType::Text => 0x01,
+            UsbDataType::Binary => 0x02,
+            UsbDataType::Header => 0x03,
+            UsbDataType::Screenshot => 0x04,
+            UsbDataType::Heartbeat => 0x05,
+            UsbDataType::Unknown(value) => value,
+        }
+    }
+}
+
+/// The 8 bytes sent before `len` bytes of data.
+#[inline]
+pub fn usb_packet_header(data_type: UsbDataType, len: usize) -> [u8; 8] {
+    let info = (data_type.to_u8() as u32) << 24 | (len.min(MAX_USB_PACKET_DATA) as u32);
+    let info = info.to_be_bytes();
+
+    [
+        USB_PACKET_HEADER[0],
+        USB_PACKET_HEADER[1],
+        USB_PACKET_HEADER[2],
+        USB_PACKET_HEADER[3],
+        info[0],
+        info[1],
+        info[2],
+        info[3],
+    ]
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UsbPacket {
+    pub data_type: UsbDataType,
+    pub data: Vec<u8>,
+}
+
+enum State {
+    Header,
+    Info,
+    Data,
+    Footer,
+}
+
+/// Finds packets in a byte stream. Bytes between packets, like the padding
+/// the cartridge adds to fill its USB buffer, are skipped, as are packets
+/// without a footer or longer than `MAX_DECODED_USB_PACKET_DATA`.
+pub struct UsbPacketDecoder {
+    state: State,
+    window: [u8; 4],
+    filled: usize,
+    data_type: UsbDataType,
+    len: usize,
+    data: Vec<u8>,
+}
+
+impl UsbPacketDecoder {
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            state: State::Header,
+            window: [0; 4],
+            filled: 0,
+            data_type: UsbDataType::Text,
+            len: 0,
+            data: Vec::new(),
+        }
+    }
+
+    /// Feeds one byte, returns the packet it completes if any.
+    pub fn push(&mut self, byte: u8) -> Option<UsbPacket> {
+        match self.state {
+            State::Header => {
+                self.window = [self.window[1], self.window[2], self.window[3], byte];
+                self.filled = (self.filled + 1).min(4);
+
+                if self.filled == 4 && self.window == USB_PACKET_HEADER {
+                    self.state = State::Info;
+                    self.filled = 0;
+                }
+            }
+            State::Info => {
+                self.window[self.filled] = byte;
+                self.filled += 1;
+
+                if self.filled == 4 {
+                    let info = u32::from_be_bytes(self.window);
+                    self.data_type = UsbDataType::from_u8((info >> 24) as u8);
+                    self.len = (info & 0xff_ffff) as usize;
+                    self.data.clear();
+                    self.filled = 0;
+                    self.state = if self.len > MAX_DECODED_USB_PACKET_DATA {
+                        // Noise that looked like a header, look for the next
+                        // one from the bytes read as the length.
+                        self.filled = 4;
+                        State::Header
+                    } else if self.len == 0 {
+                        State::Footer
+                    } else {
+                        State::Data
+                    };
+                }
+            }
+            State::Data => {
+                self.data.push(byte);
+
+                if self.data.len() == self.len {
+                    self.state = State::Footer;
+                }
+            }
+            State::Footer => {
+                self.window[self.filled] = byte;
+                self.filled += 1;
+
+                if self.filled == 4 {
+                    let complete = self.window == USB_PACKET_FOOTER;
+                    self.state = State::Header;
+                    self.window = [0; 4];
+                    self.filled = 0;
+
+                    if complete {
+                        return Some(UsbPacket {
+                            data_type: self.data_type,
+                            data: core::mem::take(&mut self.data),
+                        });
+                    }
+                }
+            }
+        }
+
+        None
+    }
+}
+
+impl Default for UsbPacketDecoder {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[test]
+fn packets_are_found_between_padding() {
+    let mut stream = Vec::new();
+    stream.extend_from_slice(&[0, 0, b'D', b'M']);
+    stream.extend_from_slice(&usb_packet_header(UsbDataType::Text, 5));
+    stream.extend_from_slice(b"hello");
+    stream.extend_from_slice(&USB_PACKET_FOOTER);
+    stream.extend_from_slice(&usb_packet_header(UsbDataType::Binary, 2));
+    stream.extend_from_slice(&[1, 2, b'X', b'X', b'X', b'X']);
+    stream.extend_from_slice(&[0; 7]);
+    stream.extend_from_slice(&usb_packet_header(UsbDataType::Heartbeat, 0));
+    stream.extend_from_slice(&USB_PACKET_FOOTER);
+
+    let mut decoder = UsbPacketDecoder::new();
+    let packets: Vec<_> = stream.iter().filter_map(|b| decoder.push(*b)).collect();
+
+    assert_eq!(
+        packets,
+        [
+            UsbPacket {
+                data_type: UsbDataType::Text,
+                data: b"hello".to_vec(),
+            },
+            UsbPacket {
+                data_type: UsbDataType::Heartbeat,
+                data: Vec::new(),
+            },
+        ]
+    );
+}
+
+#[test]
+fn a_header_too_long_for_a_log_is_skipped() {
+    let mut stream = Vec::new();
+    stream.extend_from_slice(&USB_PACKET_HEADER);
+    stream.extend_from_slice(&[0x01, 0xff, 0xff, 0xff]);
+    stream.extend_from_slice(&usb_packet_header(UsbDataType::Text, 2));
+    stream.extend_from_slice(b"ok");
+    stream.extend_from_slice(&USB_PACKET_FOOTER);
+
+    let mut decoder = UsbPacketDecoder::new();
+    let packets: Vec<_> = stream.iter().filter_map(|b| decoder.push(*b)).collect();
+
+    assert_eq!(
+        packets,
+        [UsbPacket {
+            data_type: UsbDataType::Text,
+            data: b"ok".to_vec(),
+        }]
+    );
+}
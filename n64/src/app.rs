@@ -0,0 +1,417 @@
+use crate::{current_time_us, hot_reload, profiler, N64};
+
+pub const DEFAULT_STEP_US: i64 = 1_000_000 / 60;
+pub const DEFAULT_MAX_STEPS: u32 = 4;
+
+/// Source of time for `App`, replaceable in tests.
+pub trait Clock {
+    fn now_us(&mut self) -> i64;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    #[inline]
+    fn now_us(&mut self) -> i64 {
+        current_time_us()
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Control {
+    Continue,
+    Exit,
+}
+
+/// A game driven by `App`. Each frame `update` is called zero or more times
+/// with a fixed `dt`, then `audio` for every free audio buffer and last
+/// `render` once.
+pub trait Game {
+    fn update(&mut self, n64: &mut N64, dt: f32) -> Control;
+
+    #[inline]
+    fn audio(&mut self, _buffer: &mut [i16]) {}
+
+    /// `alpha` is how far the current time is between the last update and
+    /// the next, in 0..1, for interpolating positions.
+    fn render(&mut self, n64: &mut N64, alpha: f32, budget: &FrameBudget);
+}
+
+/// Turns variable frame times into a whole number of fixed size steps.
+pub struct FixedTimestep {
+    step_us: i64,
+    max_steps: u32,
+    accumulator_us: i64,
+    last_us: Option<i64>,
+}
+
+impl FixedTimestep {
+    #[inline]
+    pub fn new(step_us: i64, max_steps: u32) -> Self {
+        assert!(step_us > 0);
+
+        Self {
+            step_us,
+            max_steps,
+            accumulator_us: 0,
+            last_us: None,
+        }
+    }
+
+    #[inline]
+    pub fn step_us(&self) -> i64 {
+        self.step_us
+    }
+
+    #[inline]
+    pub fn dt(&self) -> f32 {
+        self.step_us as f32 / 1e6
+    }
+
+    /// Returns how many steps to run to catch up to `now_us`. At most
+    /// `max_steps` are returned, time beyond that is dropped so a long stall
+    /// does not make every following frame run late too.
+    #[inline]
+    pub fn advance(&mut self, now_us: i64) -> u32 {
+        let elapsed_us = match self.last_us {
+            Some(last_us) => (now_us - last_us).max(0),
+            None => 0,
+        };
+
+        self.last_us = Some(now_us);
+        self.accumulator_us += elapsed_us;
+
+        let steps = (self.accumulator_us / self.step_us).min(self.max_steps as i64);
+        self.accumulator_us -= steps * self.step_us;
+        self.accumulator_us = self.accumulator_us.min(self.step_us - 1);
+
+        steps as u32
+    }
+
+    #[inline]
+    pub fn alpha(&self) -> f32 {
+        self.accumulator_us as f32 / self.step_us as f32
+    }
+}
+
+/// Keeps track of how long frames take compared to a budget.
+#[derive(Copy, Clone, Debug)]
+pub struct FrameBudget {
+    pub budget_us: i64,
+    pub last_frame_us: i64,
+    pub average_frame_us: i64,
+    pub worst_frame_us: i64,
+    pub last_update_count: u32,
+    pub frames: u32,
+    pub over_budget_frames: u32,
+}
+
+impl FrameBudget {
+    #[inline]
+    pub fn new(budget_us: i64) -> Self {
+        Self {
+            budget_us,
+            last_frame_us: 0,
+            average_frame_us: 0,
+            worst_frame_us: 0,
+            last_update_count: 0,
+            frames: 0,
+            over_budget_frames: 0,
+        }
+    }
+
+    #[inline]
+    pub fn record(&mut self, frame_us: i64, update_count: u32) {
+        self.average_frame_us = if self.frames == 0 {
+            frame_us
+        } else {
+            (7 * self.average_frame_us + frame_us) / 8
+        };
+
+        self.last_frame_us = frame_us;
+        self.worst_frame_us = self.worst_frame_us.max(frame_us);
+        self.last_update_count = update_count;
+        self.frames += 1;
+
+        if frame_us > self.budget_us {
+            self.over_budget_frames += 1;
+        }
+    }
+
+    #[inline]
+    pub fn is_over_budget(&self) -> bool {
+        self.last_frame_us > self.budget_us
+    }
+}
+
+pub struct App<C: Clock = SystemClock> {
+    clock: C,
+    timestep: FixedTimestep,
+    budget: FrameBudget,
+}
+
+impl App<SystemClock> {
+    #[inline]
+    pub fn new() -> Self {
+        Self::with_clock(
+            SystemClock,
+            FixedTimestep::new(DEFAULT_STEP_US, DEFAULT_MAX_STEPS),
+        )
+    }
+}
+
+impl Default for App<SystemClock> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: Clock> App<C> {
+    #[inline]
+    pub fn with_clock(clock: C, timestep: FixedTimestep) -> Self {
+        let budget = FrameBudget::new(timestep.step_us());
+
+        Self {
+            clock,
+            timestep,
+            budget,
+        }
+    }
+
+    #[inline]
+    pub fn budget(&self) -> &FrameBudget {
+        &self.budget
+    }
+
+    /// Runs frames until the game returns `Control::Exit` from `update`.
+    pub fn run(&mut self, n64: &mut N64, game: &mut impl Game) {
+        while self.frame(n64, game) == Control::Continue {}
+    }
+
+    pub fn frame(&mut self, n64: &mut N64, game: &mut impl Game) -> Control {
+        self.run_frame(&mut GameHooks { n64, game })
+    }
+
+    fn run_frame(&mut self, hooks: &mut impl FrameHooks) -> Control {
+        let frame_begin_us = self.clock.now_us();
+
+        profiler::begin_frame();
+
+        hooks.input();
+
+        let steps = self.timestep.advance(frame_begin_us);
+        let dt = self.timestep.dt();
+
+        let mut control = Control::Continue;
+
+        {
+            let _zone = profiler::zone("update");
+
+            for _ in 0..steps {
+                control = hooks.update(dt);
+
+                if control == Control::Exit {
+                    break;
+                }
+            }
+        }
+
+        // The last frame is still recorded for the trace.
+        if control == Control::Exit {
+            profiler::end_frame();
+            return control;
+        }
+
+        {
+            let _zone = profiler::zone("audio");
+            hooks.audio();
+        }
+
+        {
+            let _zone = profiler::zone("render");
+            hooks.render(self.timestep.alpha(), &self.budget);
+        }
+
+        profiler::end_frame();
+
+        let frame_end_us = self.clock.now_us();
+        self.budget.record(frame_end_us - frame_begin_us, steps);
+
+        Control::Continue
+    }
+}
+
+trait FrameHooks {
+    fn input(&mut self);
+    fn update(&mut self, dt: f32) -> Control;
+    fn audio(&mut self);
+    fn render(&mut self, alpha: f32, budget: &FrameBudget);
+}
+
+struct GameHooks<'a, G: Game> {
+    n64: &'a mut N64,
+    game: &'a mut G,
+}
+
+impl<'a, G: Game> FrameHooks for GameHooks<'a, G> {
+    #[inline]
+    fn input(&mut self) {
+        self.n64.controllers.update(&self.n64.graphics);
+        profiler::update_toggle(&self.n64.controllers);
+        hot_reload::poll(&mut self.n64.graphics);
+    }
+
+    #[inline]
+    fn update(&mut self, dt: f32) -> Control {
+        self.game.update(self.n64, dt)
+    }
+
+    #[inline]
+    fn audio(&mut self) {
+        let game = &mut self.game;
+        self.n64.audio.update(|buffer| game.audio(buffer));
+    }
+
+    #[inline]
+    fn render(&mut self, alpha: f32, budget: &FrameBudget) {
+        self.game.render(self.n64, alpha, budget);
+        self.n64.graphics.swap_buffers(&mut self.n64.framebuffer);
+    }
+}
+
+#[cfg(test)]
+use alloc::{rc::Rc, vec::Vec};
+#[cfg(test)]
+use core::cell::{Cell, RefCell};
+
+#[cfg(test)]
+struct FakeClock(Rc<Cell<i64>>);
+
+#[cfg(test)]
+impl Clock for FakeClock {
+    fn now_us(&mut self) -> i64 {
+        self.0.get()
+    }
+}
+
+#[cfg(test)]
+struct RecordingHooks {
+    time: Rc<Cell<i64>>,
+    frame_cost_us: i64,
+    exit_after: Option<usize>,
+    calls: Rc<RefCell<Vec<&'static str>>>,
+    updates: usize,
+    alphas: Vec<f32>,
+}
+
+#[cfg(test)]
+impl FrameHooks for RecordingHooks {
+    fn input(&mut self) {
+        self.calls.borrow_mut().push("input");
+    }
+
+    fn update(&mut self, _dt: f32) -> Control {
+        self.calls.borrow_mut().push("update");
+        self.updates += 1;
+
+        if Some(self.updates) == self.exit_after {
+            Control::Exit
+        } else {
+            Control::Continue
+        }
+    }
+
+    fn audio(&mut self) {
+        self.calls.borrow_mut().push("audio");
+    }
+
+    fn render(&mut self, alpha: f32, _budget: &FrameBudget) {
+        self.calls.borrow_mut().push("render");
+        self.alphas.push(alpha);
+        self.time.set(self.time.get() + self.frame_cost_us);
+    }
+}
+
+#[cfg(test)]
+fn recording_app(step_us: i64, frame_cost_us: i64) -> (App<FakeClock>, RecordingHooks) {
+    let time = Rc::new(Cell::new(0));
+    let app = App::with_clock(
+        FakeClock(time.clone()),
+        FixedTimestep::new(step_us, DEFAULT_MAX_STEPS),
+    );
+    let hooks = RecordingHooks {
+        time,
+        frame_cost_us,
+        exit_after: None,
+        calls: Rc::new(RefCell::new(Vec::new())),
+        updates: 0,
+        alphas: Vec::new(),
+    };
+
+    (app, hooks)
+}
+
+#[test]
+fn hooks_run_in_order() {
+    let (mut app, mut hooks) = recording_app(1000, 0);
+
+    app.run_frame(&mut hooks);
+    hooks.time.set(2500);
+    app.run_frame(&mut hooks);
+
+    assert_eq!(
+        *hooks.calls.borrow(),
+        ["input", "audio", "render", "input", "update", "update", "audio", "render"]
+    );
+    assert_eq!(hooks.alphas, [0.0, 0.5]);
+}
+
+#[test]
+fn fixed_step_count_follows_clock() {
+    let (mut app, mut hooks) = recording_app(1000, 0);
+
+    for frame in 0..100 {
+        hooks.time.set(frame * 1500);
+        app.run_frame(&mut hooks);
+    }
+
+    // 99 frames of 1.5 ms each, minus the half step still accumulated.
+    assert_eq!(hooks.updates, 148);
+}
+
+#[test]
+fn long_stall_is_clamped() {
+    let mut timestep = FixedTimestep::new(1000, 4);
+
+    assert_eq!(timestep.advance(0), 0);
+    assert_eq!(timestep.advance(1_000_000), 4);
+    assert!(timestep.alpha() < 1.0);
+    assert_eq!(timestep.advance(1_001_000), 1);
+}
+
+#[test]
+fn exit_stops_frame() {
+    let (mut app, mut hooks) = recording_app(1000, 0);
+    hooks.exit_after = Some(1);
+
+    app.run_frame(&mut hooks);
+    hooks.time.set(3000);
+
+    assert_eq!(app.run_frame(&mut hooks), Control::Exit);
+    assert_eq!(hooks.updates, 1);
+    assert_eq!(hooks.calls.borrow().last(), Some(&"update"));
+}
+
+#[test]
+fn budget_counts_slow_frames() {
+    let (mut app, mut hooks) = recording_app(1000, 1500);
+
+    for _ in 0..3 {
+        app.run_frame(&mut hooks);
+    }
+
+    assert_eq!(app.budget().frames, 3);
+    assert_eq!(app.budget().over_budget_frames, 3);
+    assert_eq!(app.budget().last_frame_us, 1500);
+}
@@ -1,52 +1,115 @@
+use crate::{audio_stats::QueueDepthHistory, AudioConfig, AudioStats};
 use alloc::boxed::Box;
 use alloc::collections::VecDeque;
 use alloc::vec::Vec;
 use n64_sys::ai;
 
-const BUFFER_COUNT: usize = 4;
-const BUFFER_NO_SAMPLES: usize = 2 * 880;
-
 pub struct Audio {
+    config: AudioConfig,
     free_buffers: VecDeque<Box<[i16]>>,
     ready_buffers: VecDeque<Box<[i16]>>,
     playing_buffers: VecDeque<Box<[i16]>>,
+    started: bool,
+    /// Whether the DAC ran out of samples, so a starvation lasting several
+    /// updates counts as one underrun.
+    starved: bool,
+    underruns: u32,
+    queue_depth_history: QueueDepthHistory,
 }
 
 impl Audio {
     #[inline]
-    pub(crate) fn new() -> Self {
-        ai::init();
+    pub(crate) fn new(config: AudioConfig) -> Self {
+        config.assert_valid();
+
+        ai::init(config.sample_rate);
 
-        let mut free_buffers = VecDeque::with_capacity(BUFFER_COUNT);
-        let ready_buffers = VecDeque::with_capacity(BUFFER_COUNT);
-        let mut playing_buffers = VecDeque::with_capacity(BUFFER_COUNT);
+        let mut free_buffers = VecDeque::with_capacity(config.buffer_count);
+        let ready_buffers = VecDeque::with_capacity(config.buffer_count);
+        let mut playing_buffers = VecDeque::with_capacity(config.buffer_count);
 
-        for _ in 0..(BUFFER_COUNT / 2) {
+        for _ in 0..(config.buffer_count / 2) {
             let mut buffer = Vec::new();
-            buffer.resize_with(BUFFER_NO_SAMPLES, Default::default);
+            buffer.resize_with(config.buffer_len, Default::default);
             free_buffers.push_back(buffer.into_boxed_slice());
         }
 
-        for _ in 0..(BUFFER_COUNT / 2) {
+        for _ in 0..(config.buffer_count - config.buffer_count / 2) {
             let mut buffer = Vec::new();
-            buffer.resize_with(BUFFER_NO_SAMPLES, Default::default);
+            buffer.resize_with(config.buffer_len, Default::default);
             playing_buffers.push_back(buffer.into_boxed_slice());
         }
 
         Self {
+            config,
             free_buffers,
             ready_buffers,
             playing_buffers,
+            started: false,
+            starved: false,
+            underruns: 0,
+            queue_depth_history: QueueDepthHistory::new(),
         }
     }
 
+    #[inline]
+    pub fn config(&self) -> AudioConfig {
+        self.config
+    }
+
+    #[inline]
+    fn queued_buffers(&self) -> usize {
+        let dac_buffers = if ai::full() {
+            2
+        } else if ai::busy() {
+            1
+        } else {
+            0
+        };
+
+        self.ready_buffers.len() + dac_buffers
+    }
+
+    /// Time from a buffer being mixed until it has been played, in microseconds.
+    #[inline]
+    pub fn latency_us(&self) -> i64 {
+        self.queued_buffers() as i64 * self.config.buffer_duration_us()
+    }
+
+    /// Number of times the DAC has run out of samples since start.
+    #[inline]
+    pub fn underruns(&self) -> u32 {
+        self.underruns
+    }
+
+    #[inline]
+    pub fn stats(&self) -> AudioStats {
+        AudioStats::new(
+            self.underruns,
+            self.queued_buffers(),
+            self.latency_us(),
+            self.queue_depth_history,
+        )
+    }
+
     #[inline]
     pub fn update(&mut self, mut f: impl FnMut(&mut [i16])) {
+        let queued_buffers = self.queued_buffers();
+        self.queue_depth_history.push(queued_buffers);
+
         for mut buffer in self.free_buffers.drain(..) {
             f(&mut buffer);
             self.ready_buffers.push_back(buffer);
         }
 
+        let starved = self.started && !ai::busy();
+
+        if starved && !self.starved {
+            self.underruns += 1;
+        }
+
+        self.starved = starved;
+
         while !ai::full() && self.ready_buffers.len() > 0 && self.playing_buffers.len() > 0 {
             self.free_buffers
                 .push_back(self.playing_buffers.pop_front().unwrap());
@@ -56,6 +119,8 @@ impl Audio {
                 ai::submit_audio_data_to_dac(&next_buffer);
                 self.playing_buffers.push_back(next_buffer);
             }
+
+            self.started = true;
         }
     }
 }
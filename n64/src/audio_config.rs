@@ -0,0 +1,56 @@
+/// Output format and buffering for `Audio`. Buffers hold interleaved stereo
+/// samples, and the AI takes them in 8 byte steps, so `buffer_len` must be a
+/// multiple of 4.
+#[derive(Copy, Clone, Debug)]
+pub struct AudioConfig {
+    pub sample_rate: usize,
+    pub buffer_len: usize,
+    pub buffer_count: usize,
+}
+
+impl AudioConfig {
+    /// Panics on a config `Audio` can not play with.
+    #[inline]
+    pub(crate) fn assert_valid(&self) {
+        assert!(self.sample_rate > 0, "Audio sample rate must be positive");
+        assert!(self.buffer_len > 0, "Audio buffer length must be positive");
+        assert_eq!(
+            self.buffer_len % 4,
+            0,
+            "Audio buffer length must be a multiple of 4"
+        );
+        assert!(
+            self.buffer_count >= 2,
+            "Audio needs at least two buffers to double buffer"
+        );
+    }
+
+    #[inline]
+    pub fn buffer_duration_us(&self) -> i64 {
+        (self.buffer_len / 2) as i64 * 1_000_000 / self.sample_rate as i64
+    }
+}
+
+impl Default for AudioConfig {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            sample_rate: 22050,
+            #[cfg(target_vendor = "nintendo64")]
+            buffer_len: 2 * 880,
+            #[cfg(not(target_vendor = "nintendo64"))]
+            buffer_len: 2 * 512,
+            buffer_count: 4,
+        }
+    }
+}
+
+#[test]
+#[should_panic(expected = "multiple of 4")]
+fn a_buffer_of_half_stereo_frames_is_rejected() {
+    AudioConfig {
+        buffer_len: 2 * 512 + 2,
+        ..AudioConfig::default()
+    }
+    .assert_valid();
+}
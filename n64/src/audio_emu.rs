@@ -1,3 +1,4 @@
+use crate::{audio_stats::QueueDepthHistory, wav_writer::WavWriter, AudioConfig, AudioStats};
 use cpal::{
     self,
     traits::{DeviceTrait, HostTrait, StreamTrait},
@@ -6,69 +7,291 @@ use cpal::{
 use rubato::{InterpolationParameters, InterpolationType, Resampler, SincFixedIn, WindowFunction};
 use std::error::Error;
 use std::{
-    sync::mpsc::{channel, sync_channel, Receiver, Sender, SyncSender},
-    thread,
+    env,
+    fs::File,
+    io::{self, BufWriter},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU32, AtomicUsize, Ordering},
+        mpsc::{channel, sync_channel, Receiver, RecvTimeoutError, Sender, SyncSender},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
 
-const BUFFER_NO_SAMPLES: usize = 2 * 512;
-const BUFFER_COUNT: usize = 4;
-
-fn get_sample<T>(
-    resampler: &mut SincFixedIn<f32>,
-    current_index: &mut usize,
-    current_buffer: &mut Option<Vec<f32>>,
-    to_audio_receiver: &Receiver<Buffer>,
-    from_audio_sender: &Sender<Buffer>,
-    sample_convert: impl Fn(f32) -> T,
-) -> T {
-    if current_buffer.is_none() {
-        if let Ok(buffer) = to_audio_receiver.try_recv() {
-            let mut channels = Vec::new();
-            channels.push(Vec::with_capacity(BUFFER_NO_SAMPLES / 2));
-            channels.push(Vec::with_capacity(BUFFER_NO_SAMPLES / 2));
-
-            for frame in buffer.samples.chunks_exact(2) {
-                channels[0].push(frame[0] as f32 / (i16::MAX as f32));
-                channels[1].push(frame[1] as f32 / (i16::MAX as f32));
-            }
+struct AudioOutput {
+    resampler: SincFixedIn<f32>,
+    buffer_len: usize,
+    current_index: usize,
+    current_buffer: Option<Vec<f32>>,
+    to_audio_receiver: Receiver<Buffer>,
+    from_audio_sender: Sender<Buffer>,
+    counters: Arc<AudioCounters>,
+    starved: bool,
+}
 
-            let resampled_buffers = resampler.process(&channels).unwrap();
+impl AudioOutput {
+    fn next_sample(&mut self) -> f32 {
+        if self.current_buffer.is_none() {
+            if let Ok(buffer) = self.to_audio_receiver.try_recv() {
+                self.counters.queued_buffers.fetch_sub(1, Ordering::Relaxed);
+
+                let mut channels = Vec::new();
+                channels.push(Vec::with_capacity(self.buffer_len / 2));
+                channels.push(Vec::with_capacity(self.buffer_len / 2));
+
+                for frame in buffer.samples.chunks_exact(2) {
+                    channels[0].push(frame[0] as f32 / (i16::MAX as f32));
+                    channels[1].push(frame[1] as f32 / (i16::MAX as f32));
+                }
 
-            let mut converted_buffer =
-                Vec::with_capacity(resampled_buffers[0].len() + resampled_buffers[1].len());
+                let resampled_buffers = self.resampler.process(&channels).unwrap();
 
-            for samples in resampled_buffers[0].iter().zip(resampled_buffers[1].iter()) {
-                converted_buffer.push(*samples.0);
-                converted_buffer.push(*samples.1);
+                let mut converted_buffer =
+                    Vec::with_capacity(resampled_buffers[0].len() + resampled_buffers[1].len());
+
+                for samples in resampled_buffers[0].iter().zip(resampled_buffers[1].iter()) {
+                    converted_buffer.push(*samples.0);
+                    converted_buffer.push(*samples.1);
+                }
+
+                self.current_buffer = Some(converted_buffer);
+                self.current_index = 0;
+                self.starved = false;
+
+                self.from_audio_sender.send(buffer).ok();
+            } else if !self.starved {
+                self.starved = true;
+                self.counters.underruns.fetch_add(1, Ordering::Relaxed);
             }
+        }
+
+        if let Some(buffer) = &self.current_buffer {
+            let sample = buffer[self.current_index];
 
-            *current_buffer = Some(converted_buffer);
-            *current_index = 0;
+            self.current_index += 1;
 
-            from_audio_sender.send(buffer).ok();
+            if self.current_index >= buffer.len() {
+                self.current_buffer = None;
+            }
+
+            sample
+        } else {
+            0.0
         }
     }
+}
 
-    sample_convert(if let Some(buffer) = current_buffer {
-        let sample = buffer[*current_index];
+#[derive(Default)]
+struct AudioCounters {
+    queued_buffers: AtomicUsize,
+    underruns: AtomicU32,
+}
 
-        *current_index += 1;
+/// Where `Audio` sends mixed buffers on PC. Selected with the
+/// `N64_AUDIO_BACKEND` environment variable, see `AudioBackend::from_env`.
+#[derive(Clone, Debug)]
+pub enum AudioBackend {
+    /// Play on the default output device, falling back to `Null` if there is none.
+    Device,
+    /// Throw buffers away. With `realtime` they are consumed at the rate
+    /// they would have been played, otherwise as fast as they are mixed.
+    Null { realtime: bool },
+    /// Write the stream to a wav file as fast as it is mixed.
+    File(PathBuf),
+}
 
-        if *current_index >= buffer.len() {
-            *current_buffer = None;
+impl AudioBackend {
+    /// Parses `N64_AUDIO_BACKEND`: `device`, `null`, `null-simulated` or
+    /// `file:<path>`. Defaults to `Device`.
+    pub fn from_env() -> Self {
+        match env::var("N64_AUDIO_BACKEND") {
+            Ok(value) => Self::parse(&value).unwrap_or_else(|| {
+                println!("Unknown audio backend: {}, using device", value);
+                AudioBackend::Device
+            }),
+            Err(_) => AudioBackend::Device,
         }
+    }
 
-        sample
-    } else {
-        0.0
-    })
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "device" => Some(AudioBackend::Device),
+            "null" => Some(AudioBackend::Null { realtime: true }),
+            "null-simulated" => Some(AudioBackend::Null { realtime: false }),
+            _ if value.starts_with("file:") => {
+                Some(AudioBackend::File(PathBuf::from(&value["file:".len()..])))
+            }
+            _ => None,
+        }
+    }
 }
 
-fn audio_thread(
+struct AudioThread {
+    config: AudioConfig,
     to_audio_receiver: Receiver<Buffer>,
     from_audio_sender: Sender<Buffer>,
+    counters: Arc<AudioCounters>,
     exit_receiver: Receiver<()>,
-) -> Result<(), Box<dyn Error>> {
+}
+
+impl AudioThread {
+    fn run(self, backend: AudioBackend) -> Result<(), Box<dyn Error>> {
+        match backend {
+            AudioBackend::Device => match open_device() {
+                Ok((device, device_config)) => self.run_device(device, device_config),
+                Err(e) => {
+                    println!("Audio Error: {}, falling back to null audio", e);
+                    self.run_null(true, None)
+                }
+            },
+            AudioBackend::Null { realtime } => self.run_null(realtime, None),
+            AudioBackend::File(path) => {
+                let sink = WavWriter::create(&path, 2, self.config.sample_rate as u32)
+                    .map_err(|e| format!("Unable to create {}: {}", path.to_string_lossy(), e))?;
+                self.run_null(false, Some(sink))
+            }
+        }
+    }
+
+    fn run_device(
+        self,
+        device: cpal::Device,
+        config: cpal::SupportedStreamConfig,
+    ) -> Result<(), Box<dyn Error>> {
+        let params = InterpolationParameters {
+            sinc_len: 64,
+            f_cutoff: 0.95,
+            interpolation: InterpolationType::Nearest,
+            oversampling_factor: 160,
+            window: WindowFunction::BlackmanHarris2,
+        };
+
+        let resampler = SincFixedIn::<f32>::new(
+            config.sample_rate().0 as f64 / self.config.sample_rate as f64,
+            params,
+            self.config.buffer_len / 2,
+            2,
+        );
+
+        let mut output = AudioOutput {
+            resampler,
+            buffer_len: self.config.buffer_len,
+            current_index: 0,
+            current_buffer: None,
+            to_audio_receiver: self.to_audio_receiver,
+            from_audio_sender: self.from_audio_sender,
+            counters: self.counters,
+            // Not having any samples before the game has started mixing is not an underrun.
+            starved: true,
+        };
+
+        let sampel_format = config.sample_format();
+        let config = config.into();
+
+        let stream = match sampel_format {
+            SampleFormat::F32 => device.build_output_stream(
+                &config,
+                move |buffer: &mut [f32], _: &cpal::OutputCallbackInfo| {
+                    for elem in buffer.iter_mut() {
+                        *elem = output.next_sample();
+                    }
+                },
+                move |err| {
+                    println!("Audio Error: {}", err);
+                },
+            ),
+            SampleFormat::I16 => device.build_output_stream(
+                &config,
+                move |buffer: &mut [i16], _: &cpal::OutputCallbackInfo| {
+                    for elem in buffer.iter_mut() {
+                        *elem = (output.next_sample() / (i16::MAX as f32)) as i16;
+                    }
+                },
+                move |err| {
+                    println!("Audio Error: {}", err);
+                },
+            ),
+            SampleFormat::U16 => device.build_output_stream(
+                &config,
+                move |buffer: &mut [u16], _: &cpal::OutputCallbackInfo| {
+                    for elem in buffer.iter_mut() {
+                        *elem = (output.next_sample() + (u16::MAX / 2) as f32) as u16;
+                    }
+                },
+                move |err| {
+                    println!("Audio Error: {}", err);
+                },
+            ),
+        }
+        .unwrap();
+
+        stream.play().unwrap();
+
+        self.exit_receiver.recv().ok();
+
+        Ok(())
+    }
+
+    fn run_null(
+        self,
+        realtime: bool,
+        mut sink: Option<WavWriter<BufWriter<File>>>,
+    ) -> Result<(), Box<dyn Error>> {
+        let buffer_duration = Duration::from_micros(self.config.buffer_duration_us() as u64);
+        let mut playing_until: Option<Instant> = None;
+
+        while self.exit_receiver.try_recv().is_err() {
+            let buffer = match self
+                .to_audio_receiver
+                .recv_timeout(Duration::from_millis(10))
+            {
+                Ok(buffer) => buffer,
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+
+            self.counters.queued_buffers.fetch_sub(1, Ordering::Relaxed);
+
+            if let Some(sink) = &mut sink {
+                sink.write_samples(&buffer.samples)?;
+            }
+
+            if realtime {
+                let now = Instant::now();
+
+                let start = match playing_until {
+                    Some(end) if end >= now => end,
+                    Some(_) => {
+                        self.counters.underruns.fetch_add(1, Ordering::Relaxed);
+                        now
+                    }
+                    None => now,
+                };
+
+                let end = start + buffer_duration;
+                thread::sleep(end - now);
+                playing_until = Some(end);
+            }
+
+            self.from_audio_sender.send(buffer).ok();
+        }
+
+        if let Some(mut sink) = sink {
+            // Everything mixed before exit should end up in the file.
+            while let Ok(buffer) = self.to_audio_receiver.try_recv() {
+                sink.write_samples(&buffer.samples)?;
+            }
+
+            sink.finish()?;
+        }
+
+        Ok(())
+    }
+}
+
+fn open_device() -> Result<(cpal::Device, cpal::SupportedStreamConfig), Box<dyn Error>> {
     let host = cpal::default_host();
     let device = host
         .default_output_device()
@@ -77,95 +300,14 @@ fn audio_thread(
     let config = device
         .supported_output_configs()?
         .next()
-        .expect("no supported config?!")
+        .ok_or("No supported output config")?
         .with_max_sample_rate();
 
-    assert!(config.channels() == 2);
-
-    let params = InterpolationParameters {
-        sinc_len: 64,
-        f_cutoff: 0.95,
-        interpolation: InterpolationType::Nearest,
-        oversampling_factor: 160,
-        window: WindowFunction::BlackmanHarris2,
-    };
-
-    let mut resampler = SincFixedIn::<f32>::new(
-        config.sample_rate().0 as f64 / 22050.0,
-        params,
-        BUFFER_NO_SAMPLES / 2,
-        2,
-    );
-
-    let mut current_buffer = None;
-    let mut current_index = 0;
-
-    let sampel_format = config.sample_format();
-    let config = config.into();
-
-    let stream = match sampel_format {
-        SampleFormat::F32 => device.build_output_stream(
-            &config,
-            move |buffer: &mut [f32], _: &cpal::OutputCallbackInfo| {
-                for elem in buffer.iter_mut() {
-                    *elem = get_sample(
-                        &mut resampler,
-                        &mut current_index,
-                        &mut current_buffer,
-                        &to_audio_receiver,
-                        &from_audio_sender,
-                        |sample| sample as f32,
-                    );
-                }
-            },
-            move |err| {
-                println!("Audio Error: {}", err);
-            },
-        ),
-        SampleFormat::I16 => device.build_output_stream(
-            &config,
-            move |buffer: &mut [i16], _: &cpal::OutputCallbackInfo| {
-                for elem in buffer.iter_mut() {
-                    *elem = get_sample(
-                        &mut resampler,
-                        &mut current_index,
-                        &mut current_buffer,
-                        &to_audio_receiver,
-                        &from_audio_sender,
-                        |sample| (sample / (i16::MAX as f32)) as i16,
-                    );
-                }
-            },
-            move |err| {
-                println!("Audio Error: {}", err);
-            },
-        ),
-        SampleFormat::U16 => device.build_output_stream(
-            &config,
-            move |buffer: &mut [u16], _: &cpal::OutputCallbackInfo| {
-                for elem in buffer.iter_mut() {
-                    *elem = get_sample(
-                        &mut resampler,
-                        &mut current_index,
-                        &mut current_buffer,
-                        &to_audio_receiver,
-                        &from_audio_sender,
-                        |sample| (sample + (u16::MAX / 2) as f32) as u16,
-                    );
-                }
-            },
-            move |err| {
-                println!("Audio Error: {}", err);
-            },
-        ),
+    if config.channels() != 2 {
+        return Err(format!("Unsupported channel count: {}", config.channels()).into());
     }
-    .unwrap();
 
-    stream.play().unwrap();
-
-    exit_receiver.recv().ok();
-
-    Ok(())
+    Ok((device, config))
 }
 
 struct Buffer {
@@ -173,9 +315,9 @@ struct Buffer {
 }
 
 impl Buffer {
-    fn new() -> Self {
+    fn new(len: usize) -> Self {
         let mut samples = Vec::new();
-        samples.resize_with(BUFFER_NO_SAMPLES, Default::default);
+        samples.resize_with(len, Default::default);
         Self {
             samples: samples.into_boxed_slice(),
         }
@@ -183,50 +325,139 @@ impl Buffer {
 }
 
 pub struct Audio {
+    config: AudioConfig,
+    counters: Arc<AudioCounters>,
+    queue_depth_history: QueueDepthHistory,
+    dump: Option<WavWriter<BufWriter<File>>>,
     to_audio_sender: Sender<Buffer>,
     from_audio_receiver: Receiver<Buffer>,
     buffers: Vec<Buffer>,
     exit_sender: SyncSender<()>,
+    thread: Option<JoinHandle<()>>,
 }
 
 impl Audio {
     #[inline]
-    pub(crate) fn new() -> Self {
+    pub(crate) fn new(config: AudioConfig) -> Self {
+        Self::with_backend(config, AudioBackend::from_env())
+    }
+
+    pub fn with_backend(config: AudioConfig, backend: AudioBackend) -> Self {
+        config.assert_valid();
+
         let mut buffers = Vec::new();
 
-        for _ in 0..BUFFER_COUNT {
-            buffers.push(Buffer::new());
+        for _ in 0..config.buffer_count {
+            buffers.push(Buffer::new(config.buffer_len));
         }
 
+        let counters = Arc::new(AudioCounters::default());
+        let thread_counters = counters.clone();
+
         let (to_audio_sender, to_audio_receiver) = channel();
         let (from_audio_sender, from_audio_receiver) = channel();
         let (exit_sender, exit_receiver) = sync_channel(0);
 
-        thread::spawn(move || {
-            match audio_thread(to_audio_receiver, from_audio_sender, exit_receiver) {
-                Ok(()) => (),
-                Err(e) => {
-                    println!("Audio Error: {}", e);
-                }
+        let audio_thread = AudioThread {
+            config,
+            to_audio_receiver,
+            from_audio_sender,
+            counters: thread_counters,
+            exit_receiver,
+        };
+
+        let thread = thread::spawn(move || match audio_thread.run(backend) {
+            Ok(()) => (),
+            Err(e) => {
+                println!("Audio Error: {}", e);
             }
         });
 
-        Self {
+        let mut audio = Self {
+            config,
+            counters,
+            queue_depth_history: QueueDepthHistory::new(),
+            dump: None,
             to_audio_sender,
             from_audio_receiver,
             buffers,
             exit_sender,
+            thread: Some(thread),
+        };
+
+        if let Ok(path) = env::var("N64_AUDIO_DUMP") {
+            if let Err(e) = audio.start_dump(&path) {
+                println!("Failed to create audio dump {}: {}", path, e);
+            }
+        }
+
+        audio
+    }
+
+    /// Writes everything mixed from now on to a wav file, at the configured
+    /// sample rate before any resampling. Also enabled by setting
+    /// `N64_AUDIO_DUMP` to a path.
+    pub fn start_dump(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.dump = Some(WavWriter::create(path, 2, self.config.sample_rate as u32)?);
+        Ok(())
+    }
+
+    pub fn stop_dump(&mut self) -> io::Result<()> {
+        match self.dump.take() {
+            Some(dump) => dump.finish(),
+            None => Ok(()),
         }
     }
 
+    #[inline]
+    pub fn config(&self) -> AudioConfig {
+        self.config
+    }
+
+    /// Time from a buffer being mixed until it has been played, in microseconds.
+    /// Does not include the latency of the output device.
+    #[inline]
+    pub fn latency_us(&self) -> i64 {
+        self.counters.queued_buffers.load(Ordering::Relaxed) as i64
+            * self.config.buffer_duration_us()
+    }
+
+    /// Number of times the output device has run out of samples since start.
+    #[inline]
+    pub fn underruns(&self) -> u32 {
+        self.counters.underruns.load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    pub fn stats(&self) -> AudioStats {
+        AudioStats::new(
+            self.underruns(),
+            self.counters.queued_buffers.load(Ordering::Relaxed),
+            self.latency_us(),
+            self.queue_depth_history,
+        )
+    }
+
     #[inline]
     pub fn update(&mut self, mut f: impl FnMut(&mut [i16])) {
         while let Ok(buffer) = self.from_audio_receiver.try_recv() {
             self.buffers.push(buffer);
         }
 
+        self.queue_depth_history
+            .push(self.counters.queued_buffers.load(Ordering::Relaxed));
+
         for mut buffer in self.buffers.drain(..) {
             f(&mut buffer.samples);
+
+            if let Some(dump) = &mut self.dump {
+                if let Err(e) = dump.write_samples(&buffer.samples) {
+                    println!("Failed to write audio dump: {}", e);
+                    self.dump = None;
+                }
+            }
+
+            self.counters.queued_buffers.fetch_add(1, Ordering::Relaxed);
             self.to_audio_sender
                 .send(buffer)
                 .map_err(|_| println!("Failed to send buffer to audio system"))
@@ -237,6 +468,49 @@ impl Audio {
 
 impl Drop for Audio {
     fn drop(&mut self) {
-        self.exit_sender.send(()).unwrap();
+        // The audio thread is gone if it failed.
+        self.exit_sender.send(()).ok();
+
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+    }
+}
+
+#[test]
+fn file_backend_writes_everything_mixed() {
+    let path = env::temp_dir().join(format!("n64_audio_file_backend_{}.wav", std::process::id()));
+    let config = AudioConfig::default();
+    let mut mixed_buffers = 0;
+
+    {
+        let mut audio = Audio::with_backend(config, AudioBackend::File(path.clone()));
+
+        for _ in 0..10 {
+            audio.update(|buffer| {
+                buffer.iter_mut().for_each(|sample| *sample = 1);
+                mixed_buffers += 1;
+            });
+            thread::sleep(Duration::from_millis(1));
+        }
     }
+
+    let data = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).ok();
+
+    assert_eq!(data.len(), 44 + 2 * config.buffer_len * mixed_buffers);
+    assert!(data[44..].chunks(2).all(|s| s == [1, 0]));
+}
+
+#[test]
+fn parse_audio_backend() {
+    assert!(matches!(
+        AudioBackend::parse("null-simulated"),
+        Some(AudioBackend::Null { realtime: false })
+    ));
+    assert!(matches!(
+        AudioBackend::parse("file:out.wav"),
+        Some(AudioBackend::File(path)) if path == Path::new("out.wav")
+    ));
+    assert!(AudioBackend::parse("speaker").is_none());
 }
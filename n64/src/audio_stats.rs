@@ -0,0 +1,105 @@
+pub const AUDIO_STATS_HISTORY_LEN: usize = 64;
+
+/// Snapshot of the audio queue, taken with `Audio::stats`.
+#[derive(Copy, Clone)]
+pub struct AudioStats {
+    /// Number of times the output has run out of samples since start.
+    pub underruns: u32,
+    /// Buffers mixed but not yet played.
+    pub queued_buffers: usize,
+    /// Time from a buffer being mixed until it has been played, in microseconds.
+    pub latency_us: i64,
+    queue_depth_history: QueueDepthHistory,
+}
+
+impl AudioStats {
+    #[inline]
+    pub(crate) fn new(
+        underruns: u32,
+        queued_buffers: usize,
+        latency_us: i64,
+        queue_depth_history: QueueDepthHistory,
+    ) -> Self {
+        Self {
+            underruns,
+            queued_buffers,
+            latency_us,
+            queue_depth_history,
+        }
+    }
+
+    /// Queue depth at the last `AUDIO_STATS_HISTORY_LEN` updates, oldest first.
+    #[inline]
+    pub fn queue_depth_history(&self) -> impl Iterator<Item = usize> + '_ {
+        self.queue_depth_history.iter()
+    }
+
+    #[inline]
+    pub fn min_queue_depth(&self) -> usize {
+        self.queue_depth_history().min().unwrap_or(0)
+    }
+
+    #[inline]
+    pub fn max_queue_depth(&self) -> usize {
+        self.queue_depth_history().max().unwrap_or(0)
+    }
+
+    #[inline]
+    pub fn average_queue_depth(&self) -> f32 {
+        let len = self.queue_depth_history.len;
+
+        if len == 0 {
+            return 0.0;
+        }
+
+        self.queue_depth_history().sum::<usize>() as f32 / len as f32
+    }
+}
+
+#[derive(Copy, Clone)]
+pub(crate) struct QueueDepthHistory {
+    depths: [u8; AUDIO_STATS_HISTORY_LEN],
+    len: usize,
+    next: usize,
+}
+
+impl QueueDepthHistory {
+    #[inline]
+    pub(crate) fn new() -> Self {
+        Self {
+            depths: [0; AUDIO_STATS_HISTORY_LEN],
+            len: 0,
+            next: 0,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn push(&mut self, depth: usize) {
+        self.depths[self.next] = depth.min(u8::MAX as usize) as u8;
+        self.next = (self.next + 1) % AUDIO_STATS_HISTORY_LEN;
+        self.len = (self.len + 1).min(AUDIO_STATS_HISTORY_LEN);
+    }
+
+    #[inline]
+    fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        let first = (self.next + AUDIO_STATS_HISTORY_LEN - self.len) % AUDIO_STATS_HISTORY_LEN;
+
+        (0..self.len).map(move |i| self.depths[(first + i) % AUDIO_STATS_HISTORY_LEN] as usize)
+    }
+}
+
+#[test]
+fn queue_depth_history_keeps_latest() {
+    let mut history = QueueDepthHistory::new();
+
+    for depth in 0..(AUDIO_STATS_HISTORY_LEN + 3) {
+        history.push(depth);
+    }
+
+    let stats = AudioStats::new(0, 0, 0, history);
+
+    assert_eq!(stats.queue_depth_history().count(), AUDIO_STATS_HISTORY_LEN);
+    assert_eq!(stats.min_queue_depth(), 3);
+    assert_eq!(stats.max_queue_depth(), AUDIO_STATS_HISTORY_LEN + 2);
+    assert_eq!(stats.queue_depth_history().next(), Some(3));
+}
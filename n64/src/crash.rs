@@ -0,0 +1,646 @@
+//! Crash screen for CPU exceptions and panics. `install` points the
+//! exception vectors at it on N64, it shows the cause, registers and a best
+//! effort stack trace named from the symbol table the deploy tool appends to
+//! the ROM. Pages are flipped with A on the first controller.
+
+use core::fmt::{self, Write};
+use n64_types::{ExceptionFrame, Symbol};
+
+pub const MAX_STACK_TRACE: usize = 16;
+
+/// How far above the stack pointer return addresses are searched for.
+pub const STACK_SCAN_BYTES: usize = 4096;
+
+const MAX_LINE_LEN: usize = 40;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ExceptionCode {
+    Interrupt,
+    TlbModification,
+    TlbLoad,
+    TlbStore,
+    AddressErrorLoad,
+    AddressErrorStore,
+    BusErrorInstruction,
+    BusErrorData,
+    Syscall,
+    Breakpoint,
+    ReservedInstruction,
+    CoprocessorUnusable,
+    Overflow,
+    Trap,
+    FloatingPoint,
+    Watch,
+    Unknown(u8),
+}
+
+impl ExceptionCode {
+    #[inline]
+    pub fn from_cause(cause: u32) -> ExceptionCode {
+        match (cause >> 2) & 0x1f {
+            0 => ExceptionCode::Interrupt,
+            1 => ExceptionCode::TlbModification,
+            2 => ExceptionCode::TlbLoad,
+            3 => ExceptionCode::TlbStore,
+            4 => ExceptionCode::AddressErrorLoad,
+            5 => ExceptionCode::AddressErrorStore,
+            6 => ExceptionCode::BusErrorInstruction,
+            7 => ExceptionCode::BusErrorData,
+            8 => ExceptionCode::Syscall,
+            9 => ExceptionCode::Breakpoint,
+            10 => ExceptionCode::ReservedInstruction,
+            11 => ExceptionCode::CoprocessorUnusable,
+            12 => ExceptionCode::Overflow,
+            13 => ExceptionCode::Trap,
+            15 => ExceptionCode::FloatingPoint,
+            23 => ExceptionCode::Watch,
+            code => ExceptionCode::Unknown(code as u8),
+        }
+    }
+
+    #[inline]
+    pub fn name(self) -> &'static str {
+        match self {
+            ExceptionCode::Interrupt => "INTERRUPT",
+            ExceptionCode::TlbModification => "TLB MODIFICATION",
+            ExceptionCode::TlbLoad => "TLB MISS LOAD",
+            ExceptionCode::TlbStore => "TLB MISS STORE",
+            ExceptionCode::AddressErrorLoad => "ADDRESS ERROR LOAD",
+            ExceptionCode::AddressErrorStore => "ADDRESS ERROR STORE",
+            ExceptionCode::BusErrorInstruction => "BUS ERROR FETCH",
+            ExceptionCode::BusErrorData => "BUS ERROR DATA",
+            ExceptionCode::Syscall => "SYSCALL",
+            ExceptionCode::Breakpoint => "BREAKPOINT",
+            ExceptionCode::ReservedInstruction => "RESERVED INSTRUCTION",
+            ExceptionCode::CoprocessorUnusable => "COPROCESSOR UNUSABLE",
+            ExceptionCode::Overflow => "OVERFLOW",
+            ExceptionCode::Trap => "TRAP",
+            ExceptionCode::FloatingPoint => "FLOATING POINT",
+            ExceptionCode::Watch => "WATCH",
+            ExceptionCode::Unknown(_) => "UNKNOWN EXCEPTION",
+        }
+    }
+
+    /// Whether `BadVAddr` holds the address that caused the exception.
+    #[inline]
+    pub fn has_bad_address(self) -> bool {
+        matches!(
+            self,
+            ExceptionCode::TlbModification
+                | ExceptionCode::TlbLoad
+                | ExceptionCode::TlbStore
+                | ExceptionCode::AddressErrorLoad
+                | ExceptionCode::AddressErrorStore
+        )
+    }
+}
+
+/// The faulting instruction is the branch before `EPC` when this is set.
+#[inline]
+pub fn in_branch_delay_slot(cause: u32) -> bool {
+    cause & 0x8000_0000 != 0
+}
+
+const FPU_CAUSES: [(u32, &str); 6] = [
+    (1 << 12, "INEXACT"),
+    (1 << 13, "UNDERFLOW"),
+    (1 << 14, "OVERFLOW"),
+    (1 << 15, "DIVISION BY ZERO"),
+    (1 << 16, "INVALID OPERATION"),
+    (1 << 17, "UNIMPLEMENTED"),
+];
+
+/// Names of the cause bits set in the FPU control register.
+#[inline]
+pub fn fpu_causes(fcr31: u32) -> impl Iterator<Item = &'static str> {
+    FPU_CAUSES
+        .iter()
+        .filter(move |(bit, _)| fcr31 & bit != 0)
+        .map(|(_, name)| *name)
+}
+
+pub const GPR_NAMES: [&str; 32] = [
+    "ZR", "AT", "V0", "V1", "A0", "A1", "A2", "A3", "T0", "T1", "T2", "T3", "T4", "T5", "T6", "T7",
+    "S0", "S1", "S2", "S3", "S4", "S5", "S6", "S7", "T8", "T9", "K0", "K1", "GP", "SP", "FP", "RA",
+];
+
+/// `JAL`, `JALR` and the branch and link instructions, whose return address
+/// is two instructions after them.
+#[inline]
+pub fn is_call_instruction(instruction: u32) -> bool {
+    let opcode = instruction >> 26;
+
+    match opcode {
+        0 => instruction & 0x3f == 0x09,
+        1 => matches!((instruction >> 16) & 0x1f, 0x10..=0x13),
+        3 => true,
+        _ => false,
+    }
+}
+
+/// Fills `trace` with the words on the stack that look like return
+/// addresses, innermost first. Without frame pointers this can find stale
+/// addresses left by earlier calls, hence best effort.
+pub fn stack_trace(
+    stack: impl IntoIterator<Item = u32>,
+    is_return_address: impl Fn(u32) -> bool,
+    trace: &mut [u32],
+) -> usize {
+    let mut len = 0;
+
+    for word in stack {
+        if len == trace.len() {
+            break;
+        }
+
+        if is_return_address(word) && (len == 0 || trace[len - 1] != word) {
+            trace[len] = word;
+            len += 1;
+        }
+    }
+
+    len
+}
+
+/// A line of text, cut at `MAX_LINE_LEN` bytes.
+pub struct Line {
+    buf: [u8; MAX_LINE_LEN],
+    len: usize,
+}
+
+impl Line {
+    #[inline]
+    pub fn new() -> Line {
+        Line {
+            buf: [0; MAX_LINE_LEN],
+            len: 0,
+        }
+    }
+
+    #[inline]
+    pub fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.buf[..self.len]).unwrap_or("")
+    }
+}
+
+impl Default for Line {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Write for Line {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for ch in s.chars() {
+            if self.len + ch.len_utf8() > MAX_LINE_LEN {
+                break;
+            }
+
+            ch.encode_utf8(&mut self.buf[self.len..]);
+            self.len += ch.len_utf8();
+        }
+
+        Ok(())
+    }
+}
+
+/// Breaks what is written to it into lines of at most `width` bytes.
+struct Wrapper<F> {
+    width: usize,
+    line: Line,
+    f: F,
+}
+
+impl<F: FnMut(&str)> Wrapper<F> {
+    #[inline]
+    fn line(&mut self, args: fmt::Arguments) {
+        self.write_fmt(args).ok();
+        self.flush();
+    }
+
+    #[inline]
+    fn flush(&mut self) {
+        (self.f)(self.line.as_str());
+        self.line.len = 0;
+    }
+}
+
+impl<F: FnMut(&str)> Write for Wrapper<F> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for ch in s.chars() {
+            if self.line.len + ch.len_utf8() > self.width {
+                self.flush();
+            }
+
+            self.line.write_str(ch.encode_utf8(&mut [0; 4]))?;
+        }
+
+        Ok(())
+    }
+}
+
+pub enum Crash<'a> {
+    Exception(&'a ExceptionFrame),
+    Panic(&'a str),
+}
+
+pub struct CrashReport<'a> {
+    pub crash: Crash<'a>,
+    pub trace: &'a [u32],
+    pub symbol: &'a dyn Fn(u32) -> Option<Symbol>,
+}
+
+impl<'a> CrashReport<'a> {
+    /// Calls `f` with every line of the report, wrapped at `width` bytes.
+    pub fn write_lines(&self, width: usize, f: impl FnMut(&str)) {
+        let mut out = Wrapper {
+            width: width.clamp(1, MAX_LINE_LEN),
+            line: Line::new(),
+            f,
+        };
+
+        match self.crash {
+            Crash::Exception(frame) => {
+                let code = ExceptionCode::from_cause(frame.cause);
+
+                out.line(format_args!("{}", code.name()));
+                out.line(format_args!("PC {:08X}", frame.epc));
+
+                if in_branch_delay_slot(frame.cause) {
+                    out.line(format_args!("IN DELAY SLOT"));
+                }
+
+                self.write_symbol(frame.epc, &mut out);
+
+                if code.has_bad_address() {
+                    out.line(format_args!("ADDRESS {:08X}", frame.badvaddr));
+                }
+
+                out.line(format_args!("CAUSE {:08X}", frame.cause));
+                out.line(format_args!("SR {:08X}", frame.sr));
+
+                if code == ExceptionCode::FloatingPoint {
+                    for cause in fpu_causes(frame.fcr31) {
+                        out.line(format_args!("FPU {}", cause));
+                    }
+                }
+            }
+            Crash::Panic(message) => {
+                out.line(format_args!("PANIC"));
+                out.line(format_args!("{}", message));
+            }
+        }
+
+        out.line(format_args!(""));
+        out.line(format_args!("STACK"));
+
+        for &address in self.trace {
+            out.line(format_args!("{:08X}", address));
+            self.write_symbol(address, &mut out);
+        }
+
+        if let Crash::Exception(frame) = self.crash {
+            out.line(format_args!(""));
+            out.line(format_args!("GPR"));
+
+            for (name, value) in GPR_NAMES.iter().zip(frame.gpr.iter()) {
+                out.line(format_args!("{} {:08X}", name, value));
+            }
+
+            out.line(format_args!("HI {:08X}", frame.hi));
+            out.line(format_args!("LO {:08X}", frame.lo));
+            out.line(format_args!(""));
+            out.line(format_args!("FPR"));
+
+            for (i, value) in frame.fpr.iter().enumerate() {
+                out.line(format_args!("F{} {:08X}", i, value));
+            }
+
+            out.line(format_args!("FCR31 {:08X}", frame.fcr31));
+        }
+    }
+
+    fn write_symbol<F: FnMut(&str)>(&self, address: u32, out: &mut Wrapper<F>) {
+        if let Some(symbol) = (self.symbol)(address) {
+            out.line(format_args!(
+                "{}+{:X}",
+                symbol.name(),
+                address - symbol.address
+            ));
+        }
+    }
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(target_vendor = "nintendo64")] {
+        use crate::{gfx::TextureMut, ipl3font, slow_cpu_clear};
+        use core::ptr::read_volatile;
+        use n64_math::Color;
+        use n64_sys::{exception, si, sys, vi};
+        use n64_types::{SymbolTable, VideoMode, SYMBOL_TABLE_HEADER_OFFSET};
+
+        const ROM_BASE: usize = 0xB000_0000;
+        const TEXT_START: u32 = 0x8000_0400;
+        const MARGIN: i32 = 15;
+        const LINE_HEIGHT: i32 = ipl3font::GLYPH_HEIGHT + 3;
+        const BUTTON_A: u64 = 0x8000_0000;
+        const RED: Color = Color::new(0b10000_00011_00011_1);
+        const WHITE: Color = Color::new(0b11111_11111_11111_1);
+
+        static mut VIDEO_MODE: Option<VideoMode> = None;
+
+        /// Shows the crash screen on CPU exceptions.
+        #[inline]
+        pub fn install(video_mode: VideoMode) {
+            unsafe {
+                VIDEO_MODE = Some(video_mode);
+            }
+
+            exception::install(exception_handler);
+        }
+
+        fn exception_handler(frame: &ExceptionFrame) -> ! {
+            let mut trace = [0; MAX_STACK_TRACE];
+            let mut len = 0;
+
+            // A leaf function never stores its return address on the stack.
+            if is_return_address(frame.gpr[31]) {
+                trace[0] = frame.gpr[31];
+                len = 1;
+            }
+
+            len += scan_stack(frame.gpr[29], &mut trace[len..]);
+
+            show(Crash::Exception(frame), &trace[..len]);
+        }
+
+        /// Shows the crash screen for a panic, call from the `panic_handler`.
+        pub fn show_panic(info: &core::panic::PanicInfo) -> ! {
+            let mut message = MessageBuffer([0; 256], 0);
+            write!(message, "{}", info).ok();
+
+            let mut trace = [0; MAX_STACK_TRACE];
+            let len = scan_stack(sys::stack_pointer() as u32, &mut trace);
+
+            show(
+                Crash::Panic(core::str::from_utf8(&message.0[..message.1]).unwrap_or("")),
+                &trace[..len],
+            );
+        }
+
+        struct MessageBuffer([u8; 256], usize);
+
+        // Cut on a char boundary, so the message stays valid UTF-8.
+        impl Write for MessageBuffer {
+            fn write_str(&mut self, s: &str) -> fmt::Result {
+                for ch in s.chars() {
+                    if self.1 + ch.len_utf8() > self.0.len() {
+                        break;
+                    }
+
+                    ch.encode_utf8(&mut self.0[self.1..]);
+                    self.1 += ch.len_utf8();
+                }
+
+                Ok(())
+            }
+        }
+
+        fn scan_stack(sp: u32, trace: &mut [u32]) -> usize {
+            let stack_end = (0x8000_0000 + sys::memory_size()).min(sp as usize + STACK_SCAN_BYTES);
+            let words = (sp as usize..stack_end)
+                .step_by(4)
+                .map(|address| unsafe { read_volatile(address as *const u32) });
+
+            stack_trace(words, is_return_address, trace)
+        }
+
+        fn is_return_address(address: u32) -> bool {
+            let text_end = sys::heap_memory().0 as u32;
+
+            address % 4 == 0
+                && address >= TEXT_START + 8
+                && address < text_end
+                && is_call_instruction(unsafe { read_volatile((address - 8) as *const u32) })
+        }
+
+        fn rom_word(offset: usize) -> u32 {
+            unsafe { read_volatile((ROM_BASE + offset) as *const u32) }
+        }
+
+        fn lookup_symbol(address: u32) -> Option<Symbol> {
+            let table = rom_word(SYMBOL_TABLE_HEADER_OFFSET) as usize;
+
+            if table == 0 {
+                return None;
+            }
+
+            SymbolTable::new(|offset| rom_word(table + offset))?.lookup(address)
+        }
+
+        /// The buffer VI already shows, or when the crash comes before the
+        /// first frame, one at the end of the heap with VI set up for it.
+        /// Nothing runs after the crash screen, so the heap can be overwritten.
+        fn crash_framebuffer(video_mode: VideoMode) -> *mut Color {
+            if let Some(buffer) = unsafe { vi::try_get_vi_buffer() } {
+                return buffer;
+            }
+
+            let len = video_mode.size() as usize / 2;
+            let (heap_start, heap_len) = sys::heap_memory();
+            let start = (heap_start as usize + heap_len - len * 2) & !63;
+            let framebuffer = unsafe { core::slice::from_raw_parts_mut(start as *mut Color, len) };
+            vi::init(video_mode, framebuffer);
+
+            start as *mut Color
+        }
+
+        fn show(crash: Crash, trace: &[u32]) -> ! {
+            let video_mode = unsafe { VIDEO_MODE }.unwrap_or(VideoMode::Ntsc {
+                width: 320,
+                height: 240,
+            });
+            let width = ((video_mode.width() - 2 * MARGIN)
+                / (ipl3font::GLYPH_WIDTH + 1)) as usize;
+            let lines_per_page =
+                ((video_mode.height() - 2 * MARGIN) / LINE_HEIGHT - 1) as usize;
+
+            let report = CrashReport {
+                crash,
+                trace,
+                symbol: &lookup_symbol,
+            };
+
+            let mut line_count = 0;
+            report.write_lines(width, |text| {
+                crate::error!("{}", text);
+                line_count += 1;
+            });
+            let page_count = (line_count + lines_per_page - 1) / lines_per_page;
+
+            let framebuffer = crash_framebuffer(video_mode);
+            let mut page = 0;
+            let mut controllers = [0; 8];
+            let mut was_pressed = true;
+
+            loop {
+                let mut out_tex = TextureMut::new(video_mode.width(), video_mode.height(), unsafe {
+                    core::slice::from_raw_parts_mut(framebuffer, video_mode.size() as usize / 2)
+                });
+
+                slow_cpu_clear(out_tex.data);
+
+                let mut index = 0;
+                report.write_lines(width, |text| {
+                    if index / lines_per_page == page {
+                        let y = MARGIN + (index % lines_per_page) as i32 * LINE_HEIGHT;
+                        let color = if index == 0 { RED } else { WHITE };
+                        ipl3font::draw_str(&mut out_tex, MARGIN, y, color, text.as_bytes());
+                    }
+
+                    index += 1;
+                });
+
+                let mut footer = Line::new();
+                write!(footer, "A {}/{}", page + 1, page_count).ok();
+                let footer_y = MARGIN + lines_per_page as i32 * LINE_HEIGHT;
+                ipl3font::draw_str(&mut out_tex, MARGIN, footer_y, RED, footer.as_str().as_bytes());
+
+                unsafe {
+                    sys::data_cache_hit_writeback(out_tex.data);
+                    vi::set_vi_buffer(out_tex.data);
+                }
+
+                loop {
+                    vi::wait_for_vblank();
+                    si::read_controllers(&mut controllers);
+
+                    let pressed = controllers[0] & BUTTON_A != 0;
+                    let clicked = pressed && !was_pressed;
+                    was_pressed = pressed;
+
+                    if clicked {
+                        page = (page + 1) % page_count.max(1);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+fn report_lines(report: &CrashReport) -> std::vec::Vec<std::string::String> {
+    let mut lines = std::vec::Vec::new();
+    report.write_lines(20, |line| lines.push(line.into()));
+    lines
+}
+
+#[test]
+fn exception_codes_decode_from_cause() {
+    assert_eq!(
+        ExceptionCode::from_cause(4 << 2),
+        ExceptionCode::AddressErrorLoad
+    );
+    assert_eq!(
+        ExceptionCode::from_cause(0x8000_003c),
+        ExceptionCode::FloatingPoint
+    );
+    assert_eq!(
+        ExceptionCode::from_cause(14 << 2),
+        ExceptionCode::Unknown(14)
+    );
+    assert!(in_branch_delay_slot(0x8000_003c));
+    assert!(ExceptionCode::TlbLoad.has_bad_address());
+
+    let causes: std::vec::Vec<_> = fpu_causes((1 << 15) | (1 << 16) | 0x1f).collect();
+    assert_eq!(causes, ["DIVISION BY ZERO", "INVALID OPERATION"]);
+}
+
+#[test]
+fn call_instructions_are_recognized() {
+    // jal 0x80001000, jalr $t9, bgezal $zero, jr $ra and addiu $sp, $sp, -24
+    assert!(is_call_instruction(0x0c00_0400));
+    assert!(is_call_instruction(0x0320_f809));
+    assert!(is_call_instruction(0x0411_0004));
+    assert!(!is_call_instruction(0x03e0_0008));
+    assert!(!is_call_instruction(0x27bd_ffe8));
+}
+
+#[test]
+fn stack_trace_keeps_return_addresses() {
+    let stack = [
+        0x1234,
+        0x8000_1008,
+        0x8000_1008,
+        7,
+        0x8000_2010,
+        0x8000_3000,
+    ];
+    let mut trace = [0; 2];
+    let len = stack_trace(
+        stack.iter().copied(),
+        |word| word & 0xffff_0000 == 0x8000_0000,
+        &mut trace,
+    );
+
+    assert_eq!(&trace[..len], &[0x8000_1008, 0x8000_2010]);
+}
+
+#[test]
+fn exception_report_names_the_crash() {
+    let mut symbols = [(0x8000_1000, 0x100, "game::player::Player::update")];
+    let table = n64_types::encode_symbol_table(&mut symbols);
+    let read = |offset: usize| {
+        let bytes = [
+            table[offset],
+            table[offset + 1],
+            table[offset + 2],
+            table[offset + 3],
+        ];
+        u32::from_be_bytes(bytes)
+    };
+    let table = n64_types::SymbolTable::new(read).unwrap();
+    let lookup = |address| table.lookup(address);
+
+    let mut frame = ExceptionFrame::ZERO;
+    frame.cause = 5 << 2;
+    frame.epc = 0x8000_1010;
+    frame.badvaddr = 0x0000_0004;
+    frame.gpr[31] = 0x8000_1044;
+
+    let trace = [0x8000_1044];
+    let report = CrashReport {
+        crash: Crash::Exception(&frame),
+        trace: &trace,
+        symbol: &lookup,
+    };
+    let lines = report_lines(&report);
+
+    assert_eq!(lines[0], "ADDRESS ERROR STORE");
+    assert_eq!(lines[1], "PC 80001010");
+    assert_eq!(lines[2], "game::player::Player");
+    assert_eq!(lines[3], "::update+10");
+    assert_eq!(lines[4], "ADDRESS 00000004");
+    assert!(lines.contains(&"80001044".into()));
+    assert!(lines.contains(&"RA 80001044".into()));
+    assert!(lines.contains(&"FCR31 00000000".into()));
+    assert!(lines.iter().all(|line| line.len() <= 20));
+}
+
+#[test]
+fn panic_report_wraps_the_message() {
+    let lookup = |_| None;
+    let report = CrashReport {
+        crash: Crash::Panic("panicked at 'index out of bounds', game/src/map.rs:10:5"),
+        trace: &[],
+        symbol: &lookup,
+    };
+    let lines = report_lines(&report);
+
+    assert_eq!(lines[0], "PANIC");
+    assert_eq!(lines[1], "panicked at 'index o");
+    assert_eq!(lines.last().unwrap(), "STACK");
+    assert!(!lines.iter().any(|line| line.starts_with("GPR")));
+}
@@ -1,5 +1,6 @@
 pub use command_buffer::{CommandBuffer, CommandBufferCache};
-pub use texture::{StaticTexture, Texture, TextureMut};
+pub use frame_stats::{FrameStats, RDP_CLOCK_RATE};
+pub use texture::{StaticTexture, Texture, TextureFlip, TextureMut};
 
 cfg_if::cfg_if! {
     if #[cfg(target_vendor = "nintendo64")] {
@@ -11,4 +12,5 @@ cfg_if::cfg_if! {
     }
 }
 
+mod frame_stats;
 mod texture;
@@ -1,7 +1,8 @@
-use super::{Texture, TextureMut};
+use super::{FrameStats, Texture, TextureFlip, TextureMut};
 use crate::graphics::Graphics;
 use n64_math::{Color, Vec2, Vec3};
 use n64_sys::rdp;
+use n64_types::RdpCommand;
 use rdp_command_builder::*;
 
 mod rdp_command_builder;
@@ -166,6 +167,23 @@ impl<'a> CommandBuffer<'a> {
         lower_right: Vec2,
         texture: Texture<'static>,
         blend_color: Option<u32>,
+    ) -> &mut Self {
+        self.add_textured_rect_flipped(
+            upper_left,
+            lower_right,
+            texture,
+            blend_color,
+            TextureFlip::default(),
+        )
+    }
+
+    pub fn add_textured_rect_flipped(
+        &mut self,
+        upper_left: Vec2,
+        lower_right: Vec2,
+        texture: Texture<'static>,
+        blend_color: Option<u32>,
+        flip: TextureFlip,
     ) -> &mut Self {
         self.textured_rect_count += 1;
         self.cache.rdp.sync_tile().set_other_modes(
@@ -213,14 +231,45 @@ impl<'a> CommandBuffer<'a> {
                 Vec2::new((texture.width) as f32, (texture.height) as f32),
                 Vec2::new(0.0, 0.0),
                 0,
-            )
-            .texture_rectangle(
+            );
+
+        // Mirroring starts at the last texel and steps backwards. With a
+        // diagonal flip S follows y, so the vertical flip applies to it.
+        let last_s = (texture.width - 1) as f32;
+        let last_t = (texture.height - 1) as f32;
+        let (flip_s, flip_t) = if flip.diagonal {
+            (flip.vertical, flip.horizontal)
+        } else {
+            (flip.horizontal, flip.vertical)
+        };
+
+        let st_top_left = Vec2::new(
+            if flip_s { last_s } else { 0.0 },
+            if flip_t { last_t } else { 0.0 },
+        );
+        let d_xy_d_st = Vec2::new(
+            if flip_s { -32.0 } else { 32.0 },
+            if flip_t { -32.0 } else { 32.0 },
+        );
+
+        if flip.diagonal {
+            self.cache.rdp.texture_rectangle_flip(
                 upper_left,
                 lower_right,
                 0,
-                Vec2::new(0.0, 0.0),
-                Vec2::new(32.0, 32.0),
+                st_top_left,
+                d_xy_d_st,
+            );
+        } else {
+            self.cache.rdp.texture_rectangle(
+                upper_left,
+                lower_right,
+                0,
+                st_top_left,
+                d_xy_d_st,
             );
+        }
+
         self
     }
 
@@ -315,18 +364,26 @@ impl<'a> CommandBuffer<'a> {
         self
     }
 
-    pub fn run(mut self, _graphics: &mut Graphics) -> (i32, i32) {
+    pub fn run(mut self, _graphics: &mut Graphics) -> FrameStats {
         self.cache.rdp.sync_full();
 
-        unsafe {
+        let command_count = self.cache.rdp.commands.as_ref().unwrap().len() as u32;
+
+        let counters = unsafe {
             self.cache.rdp.commands =
                 Some(rdp::swap_commands(self.cache.rdp.commands.take().unwrap()));
-            rdp::run_command_buffer();
+            rdp::run_command_buffer()
+        };
+
+        FrameStats {
+            colored_rect_count: self.colored_rect_count,
+            textured_rect_count: self.textured_rect_count,
+            command_count,
+            command_bytes: command_count * core::mem::size_of::<RdpCommand>() as u32,
+            gpu_clocks: counters.clock,
+            command_busy_clocks: counters.command_busy,
+            pipe_busy_clocks: counters.pipe_busy,
+            tmem_busy_clocks: counters.tmem_busy,
         }
-
-        (
-            self.colored_rect_count as i32,
-            self.textured_rect_count as i32,
-        )
     }
 }
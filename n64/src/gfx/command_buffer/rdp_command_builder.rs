@@ -108,6 +108,7 @@ pub const COMMAND_LOAD_TILE: u64 = 0xf4;
 pub const COMMAND_EDGE_COEFFICIENTS: u64 = 0xc8;
 pub const COMMAND_FILL_RECTANGLE: u64 = 0xf6;
 pub const COMMAND_TEXTURE_RECTANGLE: u64 = 0xe4;
+pub const COMMAND_TEXTURE_RECTANGLE_FLIP: u64 = 0xe5;
 pub const COMMAND_SYNC_FULL: u64 = 0xe9;
 pub const COMMAND_SYNC_PIPE: u64 = 0xe7;
 pub const COMMAND_SYNC_TILE: u64 = 0xe8;
@@ -402,6 +403,47 @@ impl RdpCommandBuilder {
         tile_index: u8,
         st_top_left: Vec2,
         d_xy_d_st: Vec2,
+    ) -> &mut RdpCommandBuilder {
+        self.texture_rectangle_command(
+            COMMAND_TEXTURE_RECTANGLE,
+            top_left,
+            bottom_right,
+            tile_index,
+            st_top_left,
+            d_xy_d_st,
+        )
+    }
+
+    /// Like `texture_rectangle` but S steps along y and T along x, which
+    /// swaps the axes of the texture.
+    #[inline]
+    pub fn texture_rectangle_flip(
+        &mut self,
+        top_left: Vec2,
+        bottom_right: Vec2,
+        tile_index: u8,
+        st_top_left: Vec2,
+        d_xy_d_st: Vec2,
+    ) -> &mut RdpCommandBuilder {
+        self.texture_rectangle_command(
+            COMMAND_TEXTURE_RECTANGLE_FLIP,
+            top_left,
+            bottom_right,
+            tile_index,
+            st_top_left,
+            d_xy_d_st,
+        )
+    }
+
+    #[inline]
+    fn texture_rectangle_command(
+        &mut self,
+        command: u64,
+        top_left: Vec2,
+        bottom_right: Vec2,
+        tile_index: u8,
+        st_top_left: Vec2,
+        d_xy_d_st: Vec2,
     ) -> &mut RdpCommandBuilder {
         let mut l = top_left.x();
         let mut t = top_left.y();
@@ -416,18 +458,31 @@ impl RdpCommandBuilder {
         let mut st_l = st_top_left.x();
         let mut st_t = st_top_left.y();
 
+        // Texels per pixel, 32 is one.
+        let ds = d_xy_d_st.x() / 32.0;
+        let dt = d_xy_d_st.y() / 32.0;
+        let flip = command == COMMAND_TEXTURE_RECTANGLE_FLIP;
+
         if l < 0.0 {
-            st_l -= l;
+            if flip {
+                st_t -= l * dt;
+            } else {
+                st_l -= l * ds;
+            }
             l = 0.0;
         }
 
         if t < 0.0 {
-            st_t -= t;
+            if flip {
+                st_l -= t * ds;
+            } else {
+                st_t -= t * dt;
+            }
             t = 0.0;
         }
 
         self.commands.as_mut().unwrap().push(RdpCommand(
-            (COMMAND_TEXTURE_RECTANGLE << 56)
+            (command << 56)
                 | (to_fixpoint_10_2_as_integer(r) << (32 + 12))
                 | (to_fixpoint_10_2_as_integer(b) << 32)
                 | ((tile_index as u64) << 24)
@@ -484,5 +539,6 @@ fn to_fixpoint_s_11_2(val: f32) -> u64 {
 
 #[inline]
 fn to_fixpoint_s_10_5(val: f32) -> u64 {
-    ((val * (1 << 5) as f32) as i16) as u64
+    // Through u16 so negative values do not spill into the fields above.
+    ((val * (1 << 5) as f32) as i16) as u16 as u64
 }
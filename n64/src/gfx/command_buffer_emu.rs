@@ -1,5 +1,5 @@
 use super::TextureMut;
-use crate::gfx::Texture;
+use crate::gfx::{FrameStats, Texture, TextureFlip};
 use crate::{
     graphics::QUAD_INDEX_DATA,
     graphics_emu::{
@@ -15,6 +15,22 @@ use std::mem;
 use wgpu::util::DeviceExt;
 use zerocopy::AsBytes;
 
+// Number of 64-bit RDP commands the N64 command buffer emits for each part
+// of a frame, used to estimate `FrameStats`.
+const SETUP_COMMANDS: u32 = 3;
+const CLEAR_COMMANDS: u32 = 3;
+const SYNC_FULL_COMMANDS: u32 = 1;
+const COLORED_RECT_COMMANDS: u32 = 5;
+const TEXTURED_RECT_COMMANDS: u32 = 7;
+const BLEND_COLOR_COMMANDS: u32 = 1;
+
+// Rough RDP throughput: fill mode writes four 16-bit pixels per clock, one
+// cycle mode one pixel, and TMEM loads eight bytes.
+const COMMAND_CLOCKS: u32 = 4;
+const FILL_PIXELS_PER_CLOCK: u32 = 4;
+const TEXTURED_PIXELS_PER_CLOCK: u32 = 1;
+const TMEM_BYTES_PER_CLOCK: u32 = 8;
+
 enum Command {
     ColoredRect {
         upper_left: Vec2,
@@ -26,6 +42,7 @@ enum Command {
         lower_right: Vec2,
         texture: Texture<'static>,
         blend_color: u32,
+        flip: TextureFlip,
     },
 }
 
@@ -89,6 +106,23 @@ impl<'a> CommandBuffer<'a> {
         lower_right: Vec2,
         texture: Texture<'static>,
         blend_color: Option<u32>,
+    ) -> &mut Self {
+        self.add_textured_rect_flipped(
+            upper_left,
+            lower_right,
+            texture,
+            blend_color,
+            TextureFlip::default(),
+        )
+    }
+
+    pub fn add_textured_rect_flipped(
+        &mut self,
+        upper_left: Vec2,
+        lower_right: Vec2,
+        texture: Texture<'static>,
+        blend_color: Option<u32>,
+        flip: TextureFlip,
     ) -> &mut Self {
         self.textured_rect_count += 1;
         self.cache.commands.push(Command::TexturedRect {
@@ -100,6 +134,7 @@ impl<'a> CommandBuffer<'a> {
             } else {
                 0xff_ff_ff_ff
             },
+            flip,
         });
 
         self
@@ -117,7 +152,7 @@ impl<'a> CommandBuffer<'a> {
         self
     }
 
-    pub fn run(self, graphics: &mut Graphics) -> (i32, i32) {
+    pub fn run(self, graphics: &mut Graphics) -> FrameStats {
         let dst = DstTexture::new(&graphics.device, self.out_tex.width, self.out_tex.height);
         let window_size = Vec2::new(self.out_tex.width as f32, self.out_tex.height as f32);
 
@@ -158,6 +193,7 @@ impl<'a> CommandBuffer<'a> {
                             lower_right,
                             texture,
                             blend_color,
+                            flip,
                         } => {
                             graphics.textured_rect.upload_texture_data(
                                 &graphics.device,
@@ -180,6 +216,12 @@ impl<'a> CommandBuffer<'a> {
                                     ((blend_color >> 8) & 0xff) as f32 / 255.0,
                                     ((blend_color >> 0) & 0xff) as f32 / 255.0,
                                 ],
+                                flip: [
+                                    flip.horizontal as u32 as f32,
+                                    flip.vertical as u32 as f32,
+                                    flip.diagonal as u32 as f32,
+                                    0.0,
+                                ],
                             });
                         }
                     }
@@ -341,9 +383,102 @@ impl<'a> CommandBuffer<'a> {
             }
         }
 
-        (
-            self.colored_rect_count as i32,
-            self.textured_rect_count as i32,
+        estimate_stats(
+            &self.cache.commands,
+            self.clear,
+            self.out_tex.width,
+            self.out_tex.height,
         )
     }
 }
+
+/// Estimates what the commands would cost on the N64 RDP.
+fn estimate_stats(commands: &[Command], clear: bool, width: i32, height: i32) -> FrameStats {
+    let pixels = |upper_left: Vec2, lower_right: Vec2| {
+        let w = lower_right.x().min(width as f32) - upper_left.x().max(0.0);
+        let h = lower_right.y().min(height as f32) - upper_left.y().max(0.0);
+
+        (w.max(0.0) * h.max(0.0)) as u32
+    };
+
+    let mut stats = FrameStats {
+        command_count: SETUP_COMMANDS + SYNC_FULL_COMMANDS,
+        ..Default::default()
+    };
+
+    if clear {
+        stats.command_count += CLEAR_COMMANDS;
+        stats.pipe_busy_clocks += (width * height) as u32 / FILL_PIXELS_PER_CLOCK;
+    }
+
+    for command in commands {
+        match command {
+            Command::ColoredRect {
+                upper_left,
+                lower_right,
+                ..
+            } => {
+                stats.colored_rect_count += 1;
+                stats.command_count += COLORED_RECT_COMMANDS;
+                stats.pipe_busy_clocks += pixels(*upper_left, *lower_right) / FILL_PIXELS_PER_CLOCK;
+            }
+            Command::TexturedRect {
+                upper_left,
+                lower_right,
+                texture,
+                blend_color,
+                ..
+            } => {
+                let texture_bytes = (texture.width * texture.height) as u32 * 2;
+
+                stats.textured_rect_count += 1;
+                stats.command_count += TEXTURED_RECT_COMMANDS;
+                if *blend_color != 0xff_ff_ff_ff {
+                    stats.command_count += BLEND_COLOR_COMMANDS;
+                }
+                stats.pipe_busy_clocks +=
+                    pixels(*upper_left, *lower_right) / TEXTURED_PIXELS_PER_CLOCK;
+                stats.tmem_busy_clocks += texture_bytes / TMEM_BYTES_PER_CLOCK;
+            }
+        }
+    }
+
+    stats.command_bytes = stats.command_count * 8;
+    stats.command_busy_clocks = stats.command_count * COMMAND_CLOCKS;
+    stats.gpu_clocks = stats.command_busy_clocks + stats.pipe_busy_clocks + stats.tmem_busy_clocks;
+
+    stats
+}
+
+#[test]
+fn estimated_stats_count_commands_and_pixels() {
+    static TEXTURE_DATA: [Color; 16 * 16] = [Color::new(0); 16 * 16];
+
+    let commands = [
+        Command::ColoredRect {
+            upper_left: Vec2::new(0.0, 0.0),
+            lower_right: Vec2::new(8.0, 8.0),
+            color: Color::new(0),
+        },
+        Command::TexturedRect {
+            upper_left: Vec2::new(-8.0, 0.0),
+            lower_right: Vec2::new(8.0, 16.0),
+            texture: Texture::new(16, 16, &TEXTURE_DATA),
+            blend_color: 0xff_00_00_ff,
+            flip: TextureFlip::default(),
+        },
+    ];
+
+    let stats = estimate_stats(&commands, true, 32, 32);
+
+    assert_eq!(stats.colored_rect_count, 1);
+    assert_eq!(stats.textured_rect_count, 1);
+    assert_eq!(stats.command_count, 3 + 1 + 3 + 5 + 7 + 1);
+    assert_eq!(stats.command_bytes, 8 * stats.command_count);
+    assert_eq!(stats.pipe_busy_clocks, 32 * 32 / 4 + 8 * 8 / 4 + 8 * 16);
+    assert_eq!(stats.tmem_busy_clocks, 16 * 16 * 2 / 8);
+    assert_eq!(
+        stats.gpu_clocks,
+        stats.command_busy_clocks + stats.pipe_busy_clocks + stats.tmem_busy_clocks
+    );
+}
@@ -0,0 +1,45 @@
+use crate::time::Duration;
+
+/// Rate of the RCP clock the RDP counters count in.
+pub const RDP_CLOCK_RATE: u32 = 62_500_000;
+
+/// What running a `CommandBuffer` cost. On N64 the clock counts are read
+/// from the RDP counters, on PC they are estimated from the commands.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct FrameStats {
+    pub colored_rect_count: u32,
+    pub textured_rect_count: u32,
+    pub command_count: u32,
+    pub command_bytes: u32,
+    /// RDP clocks from starting the commands until they were done.
+    pub gpu_clocks: u32,
+    pub command_busy_clocks: u32,
+    pub pipe_busy_clocks: u32,
+    pub tmem_busy_clocks: u32,
+}
+
+impl FrameStats {
+    #[inline]
+    pub fn gpu_time(&self) -> Duration {
+        Duration::from_nanos(self.gpu_clocks as u64 * 1_000_000_000 / RDP_CLOCK_RATE as u64)
+    }
+
+    /// Fraction of `gpu_clocks` the pixel pipeline was busy.
+    #[inline]
+    pub fn pipe_utilization(&self) -> f32 {
+        self.pipe_busy_clocks as f32 / self.gpu_clocks.max(1) as f32
+    }
+}
+
+#[test]
+fn gpu_time_from_clocks() {
+    let stats = FrameStats {
+        gpu_clocks: RDP_CLOCK_RATE / 1000,
+        pipe_busy_clocks: RDP_CLOCK_RATE / 4000,
+        ..Default::default()
+    };
+
+    assert_eq!(stats.gpu_time().as_micros(), 1000);
+    assert_eq!(stats.pipe_utilization(), 0.25);
+    assert_eq!(FrameStats::default().pipe_utilization(), 0.0);
+}
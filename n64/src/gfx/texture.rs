@@ -1,3 +1,4 @@
+use crate::hot_reload;
 use n64_math::Color;
 use zerocopy::LayoutVerified;
 
@@ -45,6 +46,15 @@ impl<'a> TextureMut<'a> {
     }
 }
 
+/// Mirrors a textured rect like the tile flips of Tiled, `diagonal` swaps
+/// the x and y axes of the texture before the other two.
+#[derive(Copy, Clone, Default, PartialEq, Eq, Debug)]
+pub struct TextureFlip {
+    pub horizontal: bool,
+    pub vertical: bool,
+    pub diagonal: bool,
+}
+
 #[derive(Copy, Clone)]
 pub struct StaticTexture {
     pub width: i32,
@@ -64,9 +74,10 @@ impl StaticTexture {
 
     #[inline]
     pub fn as_texture(self) -> Texture<'static> {
-        let data = LayoutVerified::<_, [Color]>::new_slice_unaligned(self.data)
-            .unwrap()
-            .into_slice();
+        let data =
+            LayoutVerified::<_, [Color]>::new_slice_unaligned(hot_reload::current(self.data))
+                .unwrap()
+                .into_slice();
 
         Texture {
             width: self.width,
@@ -8,6 +8,7 @@ layout(location = 1) out vec4 v_blend_color;
 struct Uniforms {
     vec4 u_offset_and_scale;
     vec4 u_blend_color;
+    vec4 u_flip;
 };
 
 layout(std430, set = 0, binding = 0) buffer Locals {
@@ -16,7 +17,11 @@ layout(std430, set = 0, binding = 0) buffer Locals {
 
 void main() {
 
-    v_tex_coord = a_tex_coord;
+    // Like Tiled the diagonal flip is applied to the texture first, so it
+    // is the last step from screen to texture coordinates.
+    vec3 flip = uniforms[gl_InstanceIndex].u_flip.xyz;
+    vec2 tex_coord = mix(a_tex_coord, 1.0 - a_tex_coord, flip.xy);
+    v_tex_coord = flip.z > 0.5 ? tex_coord.yx : tex_coord;
     v_blend_color = uniforms[gl_InstanceIndex].u_blend_color;
 
     vec2 offset = uniforms[gl_InstanceIndex].u_offset_and_scale.xy;
@@ -11,6 +11,8 @@ pub(crate) struct TexturedRectUniforms {
     pub offset: [f32; 2],
     pub scale: [f32; 2],
     pub blend_color: [f32; 4],
+    /// Horizontal, vertical and diagonal as 0 or 1.
+    pub flip: [f32; 4],
 }
 
 pub(crate) struct UploadedTexture {
@@ -207,6 +209,12 @@ impl TexturedRect {
         }
     }
 
+    /// Drops the upload of texture data that is no longer used, so new data
+    /// at the same address is uploaded again.
+    pub(crate) fn forget_texture(&mut self, data: *const [Color]) {
+        self.texture_cache.remove(&data);
+    }
+
     pub(crate) fn upload_texture_data(
         &mut self,
         device: &wgpu::Device,
@@ -0,0 +1,336 @@
+//! Swaps included asset data for the processed files it was included from
+//! when they change, so art can be tweaked while the PC build runs. The
+//! generated asset modules have a `watch` function registering their files
+//! and the app polls them every frame. On N64 assets are always the
+//! included data.
+//!
+//! Sizes, loop points and everything else in the generated code stay as
+//! built, so a file that changes size is skipped until the next build. So
+//! is a file whose required file changed, like map cells indexing tiles
+//! the compiled tile table does not have.
+
+use crate::Graphics;
+
+cfg_if::cfg_if! {
+    if #[cfg(target_vendor = "nintendo64")] {
+        #[inline]
+        pub fn current(data: &'static [u8]) -> &'static [u8] {
+            data
+        }
+
+        #[inline]
+        pub fn poll(_graphics: &mut Graphics) {}
+    } else {
+        use core::sync::atomic::{AtomicBool, Ordering};
+        use n64_math::Color;
+        use spin::Mutex;
+        use std::collections::HashMap;
+        use std::fs;
+        use std::path::PathBuf;
+        use std::time::{Duration, Instant, SystemTime};
+        use zerocopy::AsBytes;
+
+        const SCAN_INTERVAL: Duration = Duration::from_millis(250);
+
+        struct WatchedFile {
+            path: PathBuf,
+            modified: Option<SystemTime>,
+            /// The included data or the latest version of the file.
+            current: &'static [u8],
+            /// A file that must still be this included data for the file
+            /// to reload.
+            required: Option<(PathBuf, &'static [u8])>,
+        }
+
+        struct Watcher {
+            /// By the address of the included data.
+            files: HashMap<usize, WatchedFile>,
+            last_scan: Option<Instant>,
+        }
+
+        impl Watcher {
+            fn new() -> Watcher {
+                Watcher {
+                    files: HashMap::new(),
+                    last_scan: None,
+                }
+            }
+
+            fn watch(&mut self, data: &'static [u8], path: &str) {
+                self.files.insert(
+                    data.as_ptr() as usize,
+                    WatchedFile {
+                        path: PathBuf::from(path),
+                        modified: None,
+                        current: data,
+                        required: None,
+                    },
+                );
+            }
+
+            fn watch_requiring(
+                &mut self,
+                data: &'static [u8],
+                path: &str,
+                required: &'static [u8],
+                required_path: &str,
+            ) {
+                self.watch(data, path);
+
+                if let Some(file) = self.files.get_mut(&(data.as_ptr() as usize)) {
+                    file.required = Some((PathBuf::from(required_path), required));
+                }
+            }
+
+            fn current(&self, data: &'static [u8]) -> &'static [u8] {
+                self.files
+                    .get(&(data.as_ptr() as usize))
+                    .map(|file| file.current)
+                    .unwrap_or(data)
+            }
+
+            /// Reads the files that changed and calls `replaced` with the
+            /// version each one replaces.
+            fn reload(&mut self, mut replaced: impl FnMut(&'static [u8])) {
+                for file in self.files.values_mut() {
+                    let modified = match fs::metadata(&file.path).and_then(|m| m.modified()) {
+                        Ok(modified) => modified,
+                        Err(_) => continue,
+                    };
+
+                    if file.modified == Some(modified) {
+                        continue;
+                    }
+
+                    file.modified = Some(modified);
+
+                    let data = match fs::read(&file.path) {
+                        Ok(data) => data,
+                        Err(e) => {
+                            crate::warn!("Unable to reload {}: {}", file.path.display(), e);
+                            continue;
+                        }
+                    };
+
+                    if data == file.current {
+                        continue;
+                    }
+
+                    if data.len() != file.current.len() {
+                        crate::warn!(
+                            "{} changed size from {} to {} bytes, rebuild to use it",
+                            file.path.display(),
+                            file.current.len(),
+                            data.len()
+                        );
+                        continue;
+                    }
+
+                    if let Some((required_path, required)) = &file.required {
+                        if fs::read(required_path).ok().as_deref() != Some(*required) {
+                            crate::warn!(
+                                "{} changed, {} no longer fits the build, rebuild to use it",
+                                required_path.display(),
+                                file.path.display()
+                            );
+                            continue;
+                        }
+                    }
+
+                    replaced(file.current);
+                    file.current = leak_aligned(&data);
+
+                    crate::info!("Reloaded {}", file.path.display());
+                }
+            }
+        }
+
+        static WATCHING: AtomicBool = AtomicBool::new(false);
+        static WATCHER: Mutex<Option<Watcher>> = Mutex::new(None);
+
+        /// Reloads `data` from `path` whenever the file changes, starting
+        /// with the next `poll`.
+        pub fn watch(data: &'static [u8], path: &str) {
+            WATCHER
+                .lock()
+                .get_or_insert_with(Watcher::new)
+                .watch(data, path);
+
+            WATCHING.store(true, Ordering::Relaxed);
+        }
+
+        /// Like `watch`, but only while the file at `required_path` is
+        /// still the included `required`.
+        pub fn watch_requiring(
+            data: &'static [u8],
+            path: &str,
+            required: &'static [u8],
+            required_path: &str,
+        ) {
+            WATCHER
+                .lock()
+                .get_or_insert_with(Watcher::new)
+                .watch_requiring(data, path, required, required_path);
+
+            WATCHING.store(true, Ordering::Relaxed);
+        }
+
+        /// The latest version of included `data`.
+        #[inline]
+        pub fn current(data: &'static [u8]) -> &'static [u8] {
+            if !WATCHING.load(Ordering::Relaxed) {
+                return data;
+            }
+
+            WATCHER
+                .lock()
+                .as_ref()
+                .map(|watcher| watcher.current(data))
+                .unwrap_or(data)
+        }
+
+        /// Reads the watched files that changed since the last scan and
+        /// drops the textures uploaded from their old data.
+        pub fn poll(graphics: &mut Graphics) {
+            if !WATCHING.load(Ordering::Relaxed) {
+                return;
+            }
+
+            let mut watcher = WATCHER.lock();
+            let watcher = match watcher.as_mut() {
+                Some(watcher) => watcher,
+                None => return,
+            };
+
+            if let Some(last_scan) = watcher.last_scan {
+                if last_scan.elapsed() < SCAN_INTERVAL {
+                    return;
+                }
+            }
+
+            watcher.last_scan = Some(Instant::now());
+
+            watcher.reload(|old| {
+                graphics.textured_rect.forget_texture(core::ptr::slice_from_raw_parts(
+                    old.as_ptr() as *const Color,
+                    old.len() / 2,
+                ));
+            });
+        }
+
+        /// Old versions may still be in use, by a sound that is playing
+        /// for example, so they are never freed. Aligned like the most
+        /// aligned included data.
+        fn leak_aligned(data: &[u8]) -> &'static [u8] {
+            let words = Box::leak(vec![0u64; data.len() / 8 + 1].into_boxed_slice());
+            let bytes = &mut words.as_bytes_mut()[..data.len()];
+            bytes.copy_from_slice(data);
+            bytes
+        }
+
+        #[cfg(test)]
+        fn temp_file(name: &str, data: &[u8]) -> PathBuf {
+            let path = std::env::temp_dir().join(format!(
+                "n64-hot-reload-{}-{}",
+                name,
+                std::process::id()
+            ));
+            fs::write(&path, data).unwrap();
+            path
+        }
+
+        /// Writes `data` and forgets when the files were modified, the times
+        /// can be too coarse to tell writes in a test apart.
+        #[cfg(test)]
+        fn rewrite(watcher: &mut Watcher, path: &std::path::Path, data: &[u8]) {
+            fs::write(path, data).unwrap();
+
+            for file in watcher.files.values_mut() {
+                file.modified = None;
+            }
+        }
+
+        #[test]
+        fn current_is_the_reloaded_data() {
+            static DATA: [u8; 4] = [1, 2, 3, 4];
+            let path = temp_file("current", &DATA);
+            let mut watcher = Watcher::new();
+            let mut replaced = Vec::new();
+
+            watcher.watch(&DATA, path.to_str().unwrap());
+            watcher.reload(|old| replaced.push(old));
+            assert_eq!(watcher.current(&DATA).as_ptr(), DATA.as_ptr());
+            assert!(replaced.is_empty());
+
+            rewrite(&mut watcher, &path, &[5, 6, 7, 8]);
+            watcher.reload(|old| replaced.push(old));
+            assert_eq!(watcher.current(&DATA), [5, 6, 7, 8]);
+            assert_eq!(replaced, [&DATA[..]]);
+
+            fs::remove_file(path).ok();
+        }
+
+        #[test]
+        fn a_file_changing_size_is_skipped() {
+            static DATA: [u8; 4] = [1, 2, 3, 4];
+            let path = temp_file("size", &DATA);
+            let mut watcher = Watcher::new();
+
+            watcher.watch(&DATA, path.to_str().unwrap());
+            rewrite(&mut watcher, &path, &[5, 6, 7, 8, 9, 10]);
+            watcher.reload(|_| panic!("Reloaded a file of another size"));
+            assert_eq!(watcher.current(&DATA).as_ptr(), DATA.as_ptr());
+
+            fs::remove_file(path).ok();
+        }
+
+        #[test]
+        fn unchanged_content_is_not_reloaded() {
+            static DATA: [u8; 4] = [1, 2, 3, 4];
+            let path = temp_file("unchanged", &DATA);
+            let mut watcher = Watcher::new();
+
+            watcher.watch(&DATA, path.to_str().unwrap());
+            rewrite(&mut watcher, &path, &[5, 6, 7, 8]);
+            watcher.reload(|_| {});
+            let reloaded = watcher.current(&DATA);
+
+            rewrite(&mut watcher, &path, &[5, 6, 7, 8]);
+            watcher.reload(|_| panic!("Reloaded the same content"));
+            assert_eq!(watcher.current(&DATA).as_ptr(), reloaded.as_ptr());
+
+            fs::remove_file(path).ok();
+        }
+
+        #[test]
+        fn cells_for_an_unknown_tile_are_skipped() {
+            // Two tiles, then cells using a third one the tile table of the
+            // build does not have.
+            static TILE_GIDS: [u8; 12] = [0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 2];
+            static CELLS: [u8; 4] = [0, 1, 0, 2];
+            let gids_path = temp_file("tile-gids", &TILE_GIDS);
+            let cells_path = temp_file("cells", &CELLS);
+            let mut watcher = Watcher::new();
+
+            watcher.watch_requiring(
+                &CELLS,
+                cells_path.to_str().unwrap(),
+                &TILE_GIDS,
+                gids_path.to_str().unwrap(),
+            );
+            fs::write(&gids_path, [0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 7]).unwrap();
+            rewrite(&mut watcher, &cells_path, &[0, 1, 0, 3]);
+            watcher.reload(|_| panic!("Reloaded cells for another tile table"));
+            assert_eq!(watcher.current(&CELLS).as_ptr(), CELLS.as_ptr());
+
+            // Cells only using the tiles of the build reload.
+            fs::write(&gids_path, TILE_GIDS).unwrap();
+            rewrite(&mut watcher, &cells_path, &[0, 2, 0, 1]);
+            watcher.reload(|_| {});
+            assert_eq!(watcher.current(&CELLS), [0, 2, 0, 1]);
+
+            fs::remove_file(gids_path).ok();
+            fs::remove_file(cells_path).ok();
+        }
+    }
+}
@@ -3,15 +3,25 @@
 extern crate alloc;
 
 pub use audio::Audio;
+pub use audio_config::AudioConfig;
+pub use audio_stats::{AudioStats, AUDIO_STATS_HISTORY_LEN};
 pub use controllers::Controllers;
 pub use framebuffer::{slow_cpu_clear, Framebuffer};
 pub use graphics::Graphics;
 pub use n64_types::VideoMode;
 
+pub mod app;
+pub mod crash;
 pub mod gfx;
+pub mod hot_reload;
 pub mod ipl3font;
+pub mod log;
+pub mod profiler;
+pub mod time;
 pub mod utils;
 
+mod audio_config;
+mod audio_stats;
 mod framebuffer;
 
 cfg_if::cfg_if! {
@@ -23,6 +33,7 @@ cfg_if::cfg_if! {
         pub mod audio_emu;
         pub mod graphics_emu;
         pub mod controllers_emu;
+        pub mod wav_writer;
 
         use audio_emu as audio;
         use graphics_emu as graphics;
@@ -40,7 +51,12 @@ pub struct N64 {
 impl N64 {
     #[inline]
     pub fn new(video_mode: VideoMode) -> N64 {
-        let audio = Audio::new();
+        Self::with_audio_config(video_mode, AudioConfig::default())
+    }
+
+    #[inline]
+    pub fn with_audio_config(video_mode: VideoMode, audio_config: AudioConfig) -> N64 {
+        let audio = Audio::new(audio_config);
         let mut framebuffer = Framebuffer::new(video_mode);
         let graphics = Graphics::new(video_mode, &mut framebuffer);
         let controllers = Controllers::new();
@@ -54,24 +70,7 @@ impl N64 {
     }
 }
 
-cfg_if::cfg_if! {
-    if #[cfg(target_vendor = "nintendo64")] {
-        #[inline]
-        pub fn current_time_us() -> i64 {
-            n64_sys::sys::current_time_us()
-        }
-    } else {
-
-        use lazy_static::lazy_static;
-        use std::time::Instant;
-
-        lazy_static! {
-            static ref BEGINNING: Instant = Instant::now();
-        }
-
-        #[inline]
-        pub fn current_time_us() -> i64 {
-            (BEGINNING.elapsed().as_secs_f64() * 1000.0 * 1000.0) as i64
-        }
-    }
+#[inline]
+pub fn current_time_us() -> i64 {
+    time::Instant::now().since_start().as_micros() as i64
 }
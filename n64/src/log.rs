@@ -0,0 +1,202 @@
+//! `error!`, `warn!`, `info!`, `debug!` and `trace!`. On N64 records go to
+//! the USB port of an EverDrive-64, where `usb_log` or UNFLoader shows
+//! them, and are dropped on other cartridges. On PC they go to stdout.
+
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicU8, Ordering};
+
+/// Longer records are cut.
+pub const MAX_RECORD_LEN: usize = 256;
+
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(Level::Debug as u8);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    #[inline]
+    pub fn name(self) -> &'static str {
+        match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        }
+    }
+
+    #[inline]
+    fn from_u8(value: u8) -> Level {
+        match value {
+            0 => Level::Error,
+            1 => Level::Warn,
+            2 => Level::Info,
+            3 => Level::Debug,
+            _ => Level::Trace,
+        }
+    }
+}
+
+/// Records less severe than `level` are skipped, `Debug` by default.
+#[inline]
+pub fn set_max_level(level: Level) {
+    MAX_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+#[inline]
+pub fn max_level() -> Level {
+    Level::from_u8(MAX_LEVEL.load(Ordering::Relaxed))
+}
+
+#[inline]
+pub fn enabled(level: Level) -> bool {
+    level <= max_level()
+}
+
+/// One formatted record, `LEVEL module: message` and a newline.
+pub struct Record {
+    buf: [u8; MAX_RECORD_LEN],
+    len: usize,
+}
+
+impl Record {
+    pub fn new(level: Level, module: &str, args: fmt::Arguments) -> Record {
+        let mut record = Record {
+            buf: [0; MAX_RECORD_LEN],
+            len: 0,
+        };
+
+        write!(record, "{:<5} {}: {}", level.name(), module, args).ok();
+
+        // Always room for the newline.
+        record.len = record.len.min(MAX_RECORD_LEN - 1);
+        record.buf[record.len] = b'\n';
+        record.len += 1;
+
+        record
+    }
+
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+impl Write for Record {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for ch in s.chars() {
+            if self.len + ch.len_utf8() > MAX_RECORD_LEN - 1 {
+                break;
+            }
+
+            ch.encode_utf8(&mut self.buf[self.len..]);
+            self.len += ch.len_utf8();
+        }
+
+        Ok(())
+    }
+}
+
+/// Used by the macros, which check `enabled` first.
+#[doc(hidden)]
+#[inline]
+pub fn log(level: Level, module: &str, args: fmt::Arguments) {
+    write_record(&Record::new(level, module, args));
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(target_vendor = "nintendo64")] {
+        use n64_sys::everdrive;
+        use n64_types::UsbDataType;
+        use spin::Mutex;
+
+        static EVERDRIVE_PRESENT: Mutex<Option<bool>> = Mutex::new(None);
+
+        fn write_record(record: &Record) {
+            // A record logged while another is being sent, from the crash
+            // handler, is dropped rather than waited on forever.
+            if let Some(mut present) = EVERDRIVE_PRESENT.try_lock() {
+                if *present.get_or_insert_with(everdrive::is_present) {
+                    everdrive::usb_write(UsbDataType::Text, record.as_bytes());
+                }
+            }
+        }
+    } else {
+        use std::io::Write as _;
+
+        fn write_record(record: &Record) {
+            let stdout = std::io::stdout();
+            stdout.lock().write_all(record.as_bytes()).ok();
+        }
+    }
+}
+
+#[macro_export]
+macro_rules! log {
+    ($level:expr, $($arg:tt)+) => {
+        if $crate::log::enabled($level) {
+            $crate::log::log($level, module_path!(), format_args!($($arg)+));
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)+) => {
+        $crate::log!($crate::log::Level::Error, $($arg)+)
+    };
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)+) => {
+        $crate::log!($crate::log::Level::Warn, $($arg)+)
+    };
+}
+
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)+) => {
+        $crate::log!($crate::log::Level::Info, $($arg)+)
+    };
+}
+
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)+) => {
+        $crate::log!($crate::log::Level::Debug, $($arg)+)
+    };
+}
+
+#[macro_export]
+macro_rules! trace {
+    ($($arg:tt)+) => {
+        $crate::log!($crate::log::Level::Trace, $($arg)+)
+    };
+}
+
+#[test]
+fn records_are_formatted_and_cut() {
+    let record = Record::new(Level::Info, "game", format_args!("score {}", 42));
+    assert_eq!(record.as_bytes(), b"INFO  game: score 42\n");
+
+    let long = "x".repeat(2 * MAX_RECORD_LEN);
+    let record = Record::new(Level::Error, "game", format_args!("{}", long));
+    assert_eq!(record.as_bytes().len(), MAX_RECORD_LEN);
+    assert_eq!(record.as_bytes().last(), Some(&b'\n'));
+}
+
+#[test]
+fn levels_below_the_max_are_enabled() {
+    assert!(enabled(Level::Error));
+    assert!(enabled(Level::Debug));
+    assert!(!enabled(Level::Trace));
+
+    crate::trace!("skipped {}", 1);
+}
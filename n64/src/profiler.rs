@@ -0,0 +1,490 @@
+use crate::{
+    gfx::CommandBuffer,
+    time::{Duration, Instant},
+    Controllers, VideoMode,
+};
+use alloc::string::String;
+use core::fmt::Write;
+use n64_math::{Color, Vec2};
+use spin::Mutex;
+
+pub const MAX_ZONE_NAMES: usize = 32;
+pub const MAX_ZONES_PER_FRAME: usize = 16;
+pub const PROFILER_HISTORY_LEN: usize = 32;
+
+const ZONE_COLORS: [Color; 6] = [
+    Color::new(0b11111_01000_01000_1),
+    Color::new(0b01000_11111_01000_1),
+    Color::new(0b01000_01000_11111_1),
+    Color::new(0b11111_11111_01000_1),
+    Color::new(0b11111_01000_11111_1),
+    Color::new(0b01000_11111_11111_1),
+];
+const FRAME_COLOR: Color = Color::new(0b01000_01000_01000_1);
+const BUDGET_COLOR: Color = Color::new(0b11111_11111_11111_1);
+const GPU_COLOR: Color = Color::new(0b11111_10000_00000_1);
+
+static PROFILER: Mutex<Profiler> = Mutex::new(Profiler::new());
+
+#[derive(Copy, Clone, Debug)]
+pub struct ZoneSample {
+    pub name: u8,
+    pub depth: u8,
+    /// Ticks from the start of the frame.
+    pub start: u32,
+    pub duration: u32,
+}
+
+impl ZoneSample {
+    const EMPTY: ZoneSample = ZoneSample {
+        name: 0,
+        depth: 0,
+        start: 0,
+        duration: 0,
+    };
+}
+
+#[derive(Copy, Clone)]
+pub struct FrameProfile {
+    pub start: Instant,
+    pub duration: Duration,
+    /// Time the RDP spent on the frame, see `record_gpu_time`.
+    pub gpu_duration: Duration,
+    zones: [ZoneSample; MAX_ZONES_PER_FRAME],
+    zone_count: usize,
+}
+
+impl FrameProfile {
+    const EMPTY: FrameProfile = FrameProfile {
+        start: Instant::from_ticks(0),
+        duration: Duration::ZERO,
+        gpu_duration: Duration::ZERO,
+        zones: [ZoneSample::EMPTY; MAX_ZONES_PER_FRAME],
+        zone_count: 0,
+    };
+
+    #[inline]
+    pub fn zones(&self) -> &[ZoneSample] {
+        &self.zones[..self.zone_count]
+    }
+
+    /// Summed time of all zones with the name index `name`, a zone can run
+    /// several times per frame.
+    #[inline]
+    pub fn total(&self, name: u8) -> Duration {
+        self.zones()
+            .iter()
+            .filter(|zone| zone.name == name)
+            .fold(Duration::ZERO, |total, zone| {
+                total + Duration::from_ticks(zone.duration as u64)
+            })
+    }
+}
+
+/// Collects named zones per frame into a ring buffer. Used through the free
+/// functions in this module, which share one global instance.
+pub struct Profiler {
+    names: [&'static str; MAX_ZONE_NAMES],
+    name_count: usize,
+    frames: [FrameProfile; PROFILER_HISTORY_LEN],
+    frame_count: usize,
+    next_frame: usize,
+    current: FrameProfile,
+    depth: u8,
+    overlay_enabled: bool,
+    combo_down: bool,
+}
+
+impl Profiler {
+    pub const fn new() -> Self {
+        Self {
+            names: [""; MAX_ZONE_NAMES],
+            name_count: 0,
+            frames: [FrameProfile::EMPTY; PROFILER_HISTORY_LEN],
+            frame_count: 0,
+            next_frame: 0,
+            current: FrameProfile::EMPTY,
+            depth: 0,
+            overlay_enabled: false,
+            combo_down: false,
+        }
+    }
+
+    #[inline]
+    pub fn name(&self, index: u8) -> &'static str {
+        self.names[index as usize]
+    }
+
+    /// Every zone name seen so far, indexed by `ZoneSample::name`.
+    #[inline]
+    pub fn names(&self) -> &[&'static str] {
+        &self.names[..self.name_count]
+    }
+
+    /// Finished frames, oldest first.
+    #[inline]
+    pub fn frames(&self) -> impl Iterator<Item = &FrameProfile> + '_ {
+        let first =
+            (self.next_frame + PROFILER_HISTORY_LEN - self.frame_count) % PROFILER_HISTORY_LEN;
+
+        (0..self.frame_count).map(move |i| &self.frames[(first + i) % PROFILER_HISTORY_LEN])
+    }
+
+    #[inline]
+    pub fn last_frame(&self) -> Option<&FrameProfile> {
+        self.frames().last()
+    }
+
+    pub fn begin_frame(&mut self, now: Instant) {
+        self.current = FrameProfile::EMPTY;
+        self.current.start = now;
+        self.depth = 0;
+    }
+
+    #[inline]
+    pub fn record_gpu_time(&mut self, duration: Duration) {
+        self.current.gpu_duration += duration;
+    }
+
+    pub fn end_frame(&mut self, now: Instant) {
+        self.current.duration = now.duration_since(self.current.start);
+        self.frames[self.next_frame] = self.current;
+        self.next_frame = (self.next_frame + 1) % PROFILER_HISTORY_LEN;
+        self.frame_count = (self.frame_count + 1).min(PROFILER_HISTORY_LEN);
+    }
+
+    /// Returns the slot of the new zone, or `None` if the frame is full.
+    pub fn begin_zone(&mut self, name: &'static str, now: Instant) -> Option<usize> {
+        let name = self.name_index(name)?;
+        let slot = self.current.zone_count;
+
+        if slot >= MAX_ZONES_PER_FRAME {
+            return None;
+        }
+
+        self.current.zones[slot] = ZoneSample {
+            name,
+            depth: self.depth,
+            start: now.duration_since(self.current.start).as_ticks() as u32,
+            duration: 0,
+        };
+        self.current.zone_count += 1;
+        self.depth += 1;
+
+        Some(slot)
+    }
+
+    pub fn end_zone(&mut self, slot: usize, now: Instant) {
+        let zone = &mut self.current.zones[slot];
+        let end = now.duration_since(self.current.start).as_ticks() as u32;
+
+        zone.duration = end.saturating_sub(zone.start);
+        self.depth = self.depth.saturating_sub(1);
+    }
+
+    fn name_index(&mut self, name: &'static str) -> Option<u8> {
+        if let Some(index) = self.names[..self.name_count]
+            .iter()
+            .position(|n| *n == name)
+        {
+            return Some(index as u8);
+        }
+
+        if self.name_count >= MAX_ZONE_NAMES {
+            return None;
+        }
+
+        self.names[self.name_count] = name;
+        self.name_count += 1;
+
+        Some((self.name_count - 1) as u8)
+    }
+
+    /// Chrome trace event JSON for all frames in the history, load it in
+    /// `chrome://tracing` or Perfetto.
+    pub fn chrome_trace(&self) -> String {
+        let mut out = String::from("{\"traceEvents\":[");
+        let mut first = true;
+
+        for frame in self.frames() {
+            let frame_start_us = frame.start.since_start().as_micros();
+
+            let mut event = |name: &str, start_us: u64, duration_us: u64| {
+                if !first {
+                    out.push(',');
+                }
+                first = false;
+
+                write!(
+                    out,
+                    "{{\"name\":\"{}\",\"ph\":\"X\",\"ts\":{},\"dur\":{},\"pid\":0,\"tid\":0}}",
+                    name, start_us, duration_us
+                )
+                .ok();
+            };
+
+            event("frame", frame_start_us, frame.duration.as_micros());
+
+            for zone in frame.zones() {
+                event(
+                    self.name(zone.name),
+                    frame_start_us + Duration::from_ticks(zone.start as u64).as_micros(),
+                    Duration::from_ticks(zone.duration as u64).as_micros(),
+                );
+            }
+        }
+
+        out.push_str("]}");
+        out
+    }
+
+    fn draw_overlay(&self, cb: &mut CommandBuffer, video_mode: VideoMode, budget: Duration) {
+        let budget_ticks = budget.as_ticks().max(1) as f32;
+
+        // History, one bar per frame with top level zones stacked on the
+        // total frame time. The line marks the budget.
+        {
+            let bar_width = 2.0;
+            let budget_height = 32.0;
+            let left = 16.0;
+            let bottom = video_mode.height() as f32 - 16.0;
+            let height = |ticks: u64| (ticks as f32 / budget_ticks * budget_height).min(64.0);
+
+            for (i, frame) in self.frames().enumerate() {
+                let x = left + i as f32 * bar_width;
+
+                cb.add_colored_rect(
+                    Vec2::new(x, bottom - height(frame.duration.as_ticks())),
+                    Vec2::new(x + bar_width, bottom),
+                    FRAME_COLOR,
+                );
+
+                let mut y = bottom;
+
+                for zone in frame.zones().iter().filter(|z| z.depth == 0) {
+                    let h = height(zone.duration as u64);
+
+                    if h >= 1.0 {
+                        cb.add_colored_rect(
+                            Vec2::new(x, y - h),
+                            Vec2::new(x + bar_width, y),
+                            zone_color(zone.name),
+                        );
+                    }
+
+                    y -= h;
+                }
+            }
+
+            cb.add_colored_rect(
+                Vec2::new(left, bottom - budget_height - 1.0),
+                Vec2::new(
+                    left + PROFILER_HISTORY_LEN as f32 * bar_width,
+                    bottom - budget_height,
+                ),
+                BUDGET_COLOR,
+            );
+        }
+
+        // Timeline of the last frame, one row per nesting depth with the GPU
+        // time above. The full width is two frame budgets.
+        if let Some(frame) = self.last_frame() {
+            let left = 16.0;
+            let top = 48.0;
+            let row_height = 6.0;
+            let width = video_mode.width() as f32 - 32.0;
+            let x = |ticks: u64| left + (ticks as f32 / budget_ticks * width / 2.0).min(width);
+
+            if frame.gpu_duration > Duration::ZERO {
+                cb.add_colored_rect(
+                    Vec2::new(left, top - row_height - 2.0),
+                    Vec2::new(x(frame.gpu_duration.as_ticks()).max(left + 1.0), top - 3.0),
+                    GPU_COLOR,
+                );
+            }
+
+            for zone in frame.zones() {
+                let y = top + zone.depth as f32 * row_height;
+                let start = x(zone.start as u64);
+                let end = x(zone.start as u64 + zone.duration as u64).max(start + 1.0);
+
+                cb.add_colored_rect(
+                    Vec2::new(start, y),
+                    Vec2::new(end, y + row_height - 1.0),
+                    zone_color(zone.name),
+                );
+            }
+
+            cb.add_colored_rect(
+                Vec2::new(x(budget.as_ticks()), top - row_height - 2.0),
+                Vec2::new(x(budget.as_ticks()) + 1.0, top + 4.0 * row_height),
+                BUDGET_COLOR,
+            );
+        }
+    }
+}
+
+impl Default for Profiler {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Color the overlay uses for a zone name index.
+#[inline]
+pub fn zone_color(name: u8) -> Color {
+    ZONE_COLORS[name as usize % ZONE_COLORS.len()]
+}
+
+/// Times the rest of the scope, see `zone`.
+pub struct Zone {
+    slot: Option<usize>,
+}
+
+impl Drop for Zone {
+    #[inline]
+    fn drop(&mut self) {
+        if let Some(slot) = self.slot {
+            let now = Instant::now();
+            PROFILER.lock().end_zone(slot, now);
+        }
+    }
+}
+
+/// Starts a named zone which lasts until the returned guard is dropped.
+///
+/// ```ignore
+/// let _zone = profiler::zone("bullets");
+/// ```
+#[inline]
+pub fn zone(name: &'static str) -> Zone {
+    let now = Instant::now();
+
+    Zone {
+        slot: PROFILER.lock().begin_zone(name, now),
+    }
+}
+
+#[inline]
+pub fn begin_frame() {
+    let now = Instant::now();
+    PROFILER.lock().begin_frame(now);
+}
+
+/// Adds RDP time to the current frame, for example `FrameStats::gpu_time`.
+#[inline]
+pub fn record_gpu_time(duration: Duration) {
+    PROFILER.lock().record_gpu_time(duration);
+}
+
+#[inline]
+pub fn end_frame() {
+    let now = Instant::now();
+    PROFILER.lock().end_frame(now);
+}
+
+/// Runs `f` with the global profiler, for reading the recorded frames.
+#[inline]
+pub fn with_profiler<R>(f: impl FnOnce(&Profiler) -> R) -> R {
+    f(&PROFILER.lock())
+}
+
+/// Toggles the overlay when L, R and Start are pressed together.
+#[inline]
+pub fn update_toggle(controllers: &Controllers) {
+    let mut profiler = PROFILER.lock();
+    let combo_down = controllers.l() && controllers.r() && controllers.start();
+
+    if combo_down && !profiler.combo_down {
+        profiler.overlay_enabled = !profiler.overlay_enabled;
+    }
+
+    profiler.combo_down = combo_down;
+}
+
+#[inline]
+pub fn overlay_enabled() -> bool {
+    PROFILER.lock().overlay_enabled
+}
+
+/// Draws the frame history and a timeline of the last frame, if the overlay
+/// is enabled. `budget` is the target frame time.
+#[inline]
+pub fn draw_overlay(cb: &mut CommandBuffer, video_mode: VideoMode, budget: Duration) {
+    let profiler = PROFILER.lock();
+
+    if profiler.overlay_enabled {
+        profiler.draw_overlay(cb, video_mode, budget);
+    }
+}
+
+#[cfg(not(target_vendor = "nintendo64"))]
+pub fn write_chrome_trace(path: impl AsRef<std::path::Path>) -> std::io::Result<()> {
+    let trace = with_profiler(|profiler| profiler.chrome_trace());
+    std::fs::write(path, trace)
+}
+
+#[test]
+fn zones_nest_and_history_wraps() {
+    let mut profiler = Profiler::new();
+
+    for frame in 0..(PROFILER_HISTORY_LEN as u64 + 2) {
+        let start = frame * 1000;
+
+        profiler.begin_frame(Instant::from_ticks(start));
+        let update = profiler.begin_zone("update", Instant::from_ticks(start + 10));
+        let bullets = profiler.begin_zone("bullets", Instant::from_ticks(start + 20));
+        profiler.end_zone(bullets.unwrap(), Instant::from_ticks(start + 50));
+        profiler.end_zone(update.unwrap(), Instant::from_ticks(start + 100));
+        profiler.record_gpu_time(Duration::from_ticks(400));
+        profiler.end_frame(Instant::from_ticks(start + 900));
+    }
+
+    assert_eq!(profiler.frames().count(), PROFILER_HISTORY_LEN);
+    assert_eq!(profiler.frames().next().unwrap().start.as_ticks(), 2000);
+
+    let last = profiler.last_frame().unwrap();
+    assert_eq!(last.duration.as_ticks(), 900);
+    assert_eq!(last.gpu_duration.as_ticks(), 400);
+    assert_eq!(last.zones().len(), 2);
+    assert_eq!(profiler.name(last.zones()[1].name), "bullets");
+    assert_eq!(last.zones()[1].depth, 1);
+    assert_eq!(last.zones()[1].start, 20);
+    assert_eq!(last.zones()[1].duration, 30);
+    assert_eq!(profiler.names(), ["update", "bullets"]);
+    assert_eq!(last.total(1), Duration::from_ticks(30));
+}
+
+#[test]
+fn zones_past_frame_capacity_are_dropped() {
+    let mut profiler = Profiler::new();
+    profiler.begin_frame(Instant::from_ticks(0));
+
+    for i in 0..MAX_ZONES_PER_FRAME {
+        let slot = profiler.begin_zone("zone", Instant::from_ticks(i as u64));
+        profiler.end_zone(slot.unwrap(), Instant::from_ticks(i as u64 + 1));
+    }
+
+    assert!(profiler
+        .begin_zone("zone", Instant::from_ticks(100))
+        .is_none());
+}
+
+#[test]
+fn chrome_trace_json() {
+    let mut profiler = Profiler::new();
+
+    profiler.begin_frame(Instant::from_ticks(3 * 1_000_000));
+    let slot = profiler.begin_zone("mix", Instant::from_ticks(3 * 1_000_000 + 3 * 100));
+    profiler.end_zone(slot.unwrap(), Instant::from_ticks(3 * 1_000_000 + 3 * 300));
+    profiler.end_frame(Instant::from_ticks(3 * 1_000_000 + 3 * 1000));
+
+    assert_eq!(
+        profiler.chrome_trace(),
+        "{\"traceEvents\":[\
+         {\"name\":\"frame\",\"ph\":\"X\",\"ts\":64000,\"dur\":64,\"pid\":0,\"tid\":0},\
+         {\"name\":\"mix\",\"ph\":\"X\",\"ts\":64006,\"dur\":12,\"pid\":0,\"tid\":0}\
+         ]}"
+    );
+}
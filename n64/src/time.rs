@@ -0,0 +1,356 @@
+use core::ops::{Add, AddAssign, Sub, SubAssign};
+
+pub use n64_types::TICKS_PER_SECOND;
+
+// 1e9 / TICKS_PER_SECOND reduced to 64 / 3, which keeps tick <-> ns
+// conversions in integers without overflowing for centuries.
+const NANOS_PER_TICK_NUM: u64 = 64;
+const NANOS_PER_TICK_DEN: u64 = 3;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Duration {
+    ticks: u64,
+}
+
+impl Duration {
+    pub const ZERO: Duration = Duration { ticks: 0 };
+
+    #[inline]
+    pub const fn from_ticks(ticks: u64) -> Self {
+        Self { ticks }
+    }
+
+    #[inline]
+    pub const fn from_nanos(nanos: u64) -> Self {
+        Self {
+            ticks: nanos / NANOS_PER_TICK_NUM * NANOS_PER_TICK_DEN
+                + nanos % NANOS_PER_TICK_NUM * NANOS_PER_TICK_DEN / NANOS_PER_TICK_NUM,
+        }
+    }
+
+    #[inline]
+    pub const fn from_micros(micros: u64) -> Self {
+        Self::from_nanos(micros * 1_000)
+    }
+
+    #[inline]
+    pub const fn from_millis(millis: u64) -> Self {
+        Self::from_nanos(millis * 1_000_000)
+    }
+
+    #[inline]
+    pub const fn from_secs(secs: u64) -> Self {
+        Self {
+            ticks: secs * TICKS_PER_SECOND as u64,
+        }
+    }
+
+    #[inline]
+    pub const fn as_ticks(self) -> u64 {
+        self.ticks
+    }
+
+    #[inline]
+    pub const fn as_nanos(self) -> u64 {
+        self.ticks / NANOS_PER_TICK_DEN * NANOS_PER_TICK_NUM
+            + self.ticks % NANOS_PER_TICK_DEN * NANOS_PER_TICK_NUM / NANOS_PER_TICK_DEN
+    }
+
+    #[inline]
+    pub const fn as_micros(self) -> u64 {
+        self.as_nanos() / 1_000
+    }
+
+    #[inline]
+    pub const fn as_millis(self) -> u64 {
+        self.as_nanos() / 1_000_000
+    }
+
+    #[inline]
+    pub fn as_secs_f32(self) -> f32 {
+        let ticks_per_second = TICKS_PER_SECOND as u64;
+
+        (self.ticks / ticks_per_second) as f32
+            + (self.ticks % ticks_per_second) as f32 / ticks_per_second as f32
+    }
+
+    #[inline]
+    pub fn checked_sub(self, other: Duration) -> Option<Duration> {
+        self.ticks
+            .checked_sub(other.ticks)
+            .map(Duration::from_ticks)
+    }
+
+    #[inline]
+    pub fn saturating_sub(self, other: Duration) -> Duration {
+        Duration::from_ticks(self.ticks.saturating_sub(other.ticks))
+    }
+}
+
+impl Add for Duration {
+    type Output = Duration;
+
+    #[inline]
+    fn add(self, other: Duration) -> Duration {
+        Duration::from_ticks(self.ticks + other.ticks)
+    }
+}
+
+impl AddAssign for Duration {
+    #[inline]
+    fn add_assign(&mut self, other: Duration) {
+        self.ticks += other.ticks;
+    }
+}
+
+impl Sub for Duration {
+    type Output = Duration;
+
+    #[inline]
+    fn sub(self, other: Duration) -> Duration {
+        Duration::from_ticks(self.ticks - other.ticks)
+    }
+}
+
+impl SubAssign for Duration {
+    #[inline]
+    fn sub_assign(&mut self, other: Duration) {
+        self.ticks -= other.ticks;
+    }
+}
+
+/// A point in time, as 64-bit ticks since time was first read.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Instant {
+    ticks: u64,
+}
+
+impl Instant {
+    #[inline]
+    pub fn now() -> Self {
+        Self { ticks: now_ticks() }
+    }
+
+    #[inline]
+    pub const fn from_ticks(ticks: u64) -> Self {
+        Self { ticks }
+    }
+
+    #[inline]
+    pub const fn as_ticks(self) -> u64 {
+        self.ticks
+    }
+
+    /// Time since start, saturating at zero if `earlier` is later than `self`.
+    #[inline]
+    pub fn duration_since(self, earlier: Instant) -> Duration {
+        Duration::from_ticks(self.ticks.saturating_sub(earlier.ticks))
+    }
+
+    #[inline]
+    pub fn elapsed(self) -> Duration {
+        Instant::now().duration_since(self)
+    }
+
+    #[inline]
+    pub fn since_start(self) -> Duration {
+        Duration::from_ticks(self.ticks)
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    #[inline]
+    fn add(self, other: Duration) -> Instant {
+        Instant::from_ticks(self.ticks + other.as_ticks())
+    }
+}
+
+impl Sub<Duration> for Instant {
+    type Output = Instant;
+
+    #[inline]
+    fn sub(self, other: Duration) -> Instant {
+        Instant::from_ticks(self.ticks - other.as_ticks())
+    }
+}
+
+impl Sub for Instant {
+    type Output = Duration;
+
+    #[inline]
+    fn sub(self, other: Instant) -> Duration {
+        self.duration_since(other)
+    }
+}
+
+/// Extends the 32-bit Count register, which wraps about every 92 seconds,
+/// to 64 bits. Has to be fed at least once per wrap to stay correct.
+#[derive(Copy, Clone, Debug)]
+pub struct TickExtender {
+    last: u32,
+    ticks: u64,
+}
+
+impl TickExtender {
+    #[inline]
+    pub const fn new(start: u32) -> Self {
+        Self {
+            last: start,
+            ticks: 0,
+        }
+    }
+
+    #[inline]
+    pub fn extend(&mut self, now: u32) -> u64 {
+        self.ticks += now.wrapping_sub(self.last) as u64;
+        self.last = now;
+        self.ticks
+    }
+}
+
+/// Measures time from creation. With `scoped` the result is written to a
+/// `Duration` when the stopwatch is dropped.
+pub struct Stopwatch<'a> {
+    start: Instant,
+    out: Option<&'a mut Duration>,
+}
+
+impl Stopwatch<'static> {
+    #[inline]
+    pub fn start() -> Self {
+        Self {
+            start: Instant::now(),
+            out: None,
+        }
+    }
+}
+
+impl<'a> Stopwatch<'a> {
+    #[inline]
+    pub fn scoped(out: &'a mut Duration) -> Self {
+        Self {
+            start: Instant::now(),
+            out: Some(out),
+        }
+    }
+
+    #[inline]
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    #[inline]
+    pub fn restart(&mut self) -> Duration {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.start);
+        self.start = now;
+        elapsed
+    }
+}
+
+impl<'a> Drop for Stopwatch<'a> {
+    #[inline]
+    fn drop(&mut self) {
+        if let Some(out) = self.out.take() {
+            *out = self.start.elapsed();
+        }
+    }
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(target_vendor = "nintendo64")] {
+        use spin::Mutex;
+
+        static TICKS: Mutex<Option<TickExtender>> = Mutex::new(None);
+
+        #[inline]
+        fn now_ticks() -> u64 {
+            let now = n64_sys::sys::get_ticks();
+            let mut ticks = TICKS.lock();
+
+            ticks.get_or_insert_with(|| TickExtender::new(now)).extend(now)
+        }
+    } else {
+        use lazy_static::lazy_static;
+
+        lazy_static! {
+            static ref BEGINNING: std::time::Instant = std::time::Instant::now();
+        }
+
+        #[inline]
+        fn now_ticks() -> u64 {
+            Duration::from_nanos(BEGINNING.elapsed().as_nanos() as u64).as_ticks()
+        }
+    }
+}
+
+#[test]
+fn tick_extender_handles_wraparound() {
+    let mut extender = TickExtender::new(u32::MAX - 10);
+
+    assert_eq!(extender.extend(u32::MAX - 5), 5);
+    assert_eq!(extender.extend(4), 15);
+    assert_eq!(extender.extend(4), 15);
+    assert_eq!(extender.extend(u32::MAX), 15 + u32::MAX as u64 - 4);
+    assert_eq!(extender.extend(0), 16 + u32::MAX as u64 - 4);
+}
+
+#[test]
+fn tick_extender_counts_past_32_bits() {
+    let mut extender = TickExtender::new(0);
+    let mut now = 0u32;
+
+    for _ in 0..10 {
+        now = now.wrapping_add(0x8000_0000);
+        extender.extend(now);
+    }
+
+    assert_eq!(extender.extend(now), 5 * 0x1_0000_0000);
+}
+
+#[test]
+fn duration_conversions() {
+    assert_eq!(Duration::from_secs(1).as_ticks(), TICKS_PER_SECOND as u64);
+    assert_eq!(Duration::from_secs(1).as_nanos(), 1_000_000_000);
+    assert_eq!(Duration::from_millis(1500).as_micros(), 1_500_000);
+    assert_eq!(Duration::from_ticks(3).as_nanos(), 64);
+    assert_eq!(Duration::from_nanos(64).as_ticks(), 3);
+    assert_eq!(Duration::from_micros(16_667).as_micros(), 16_666);
+    assert_eq!(
+        Duration::from_ticks(TICKS_PER_SECOND as u64 / 2).as_secs_f32(),
+        0.5
+    );
+
+    // A day of ticks, far past where f32 would have lost precision.
+    let day = Duration::from_secs(24 * 60 * 60);
+    assert_eq!(
+        (day + Duration::from_ticks(1)).as_ticks() - day.as_ticks(),
+        1
+    );
+    assert_eq!(day.as_micros(), 24 * 60 * 60 * 1_000_000);
+}
+
+#[test]
+fn instant_arithmetic() {
+    let start = Instant::from_ticks(1000);
+    let later = start + Duration::from_ticks(500);
+
+    assert_eq!(later - start, Duration::from_ticks(500));
+    assert_eq!(start.duration_since(later), Duration::ZERO);
+    assert!(Instant::now() <= Instant::now());
+}
+
+#[test]
+fn scoped_stopwatch_writes_on_drop() {
+    let mut elapsed = Duration::from_secs(1000);
+
+    {
+        let _stopwatch = Stopwatch::scoped(&mut elapsed);
+        std::thread::sleep(std::time::Duration::from_millis(2));
+    }
+
+    assert!(elapsed >= Duration::from_millis(2));
+    assert!(elapsed < Duration::from_secs(1000));
+}
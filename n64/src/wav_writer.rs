@@ -0,0 +1,110 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+const HEADER_LEN: u32 = 44;
+
+/// Writes interleaved 16-bit samples to a wav file. The header is patched
+/// with the final length when the writer is finished or dropped.
+pub struct WavWriter<W: Write + Seek> {
+    out: W,
+    channels: u16,
+    data_len: u32,
+    finished: bool,
+}
+
+impl WavWriter<BufWriter<File>> {
+    pub fn create(path: impl AsRef<Path>, channels: u16, sample_rate: u32) -> io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?), channels, sample_rate)
+    }
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut out: W, channels: u16, sample_rate: u32) -> io::Result<Self> {
+        let block_align = 2 * channels;
+
+        out.write_all(b"RIFF")?;
+        out.write_all(&(HEADER_LEN - 8).to_le_bytes())?;
+        out.write_all(b"WAVE")?;
+        out.write_all(b"fmt ")?;
+        out.write_all(&16u32.to_le_bytes())?;
+        out.write_all(&1u16.to_le_bytes())?;
+        out.write_all(&channels.to_le_bytes())?;
+        out.write_all(&sample_rate.to_le_bytes())?;
+        out.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+        out.write_all(&block_align.to_le_bytes())?;
+        out.write_all(&16u16.to_le_bytes())?;
+        out.write_all(b"data")?;
+        out.write_all(&0u32.to_le_bytes())?;
+
+        Ok(Self {
+            out,
+            channels,
+            data_len: 0,
+            finished: false,
+        })
+    }
+
+    pub fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    pub fn write_samples(&mut self, samples: &[i16]) -> io::Result<()> {
+        for sample in samples {
+            self.out.write_all(&sample.to_le_bytes())?;
+        }
+
+        self.data_len += 2 * samples.len() as u32;
+
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.finished = true;
+        self.write_lengths()
+    }
+
+    fn write_lengths(&mut self) -> io::Result<()> {
+        let position = self.out.seek(SeekFrom::Current(0))?;
+
+        self.out.seek(SeekFrom::Start(4))?;
+        self.out
+            .write_all(&(HEADER_LEN - 8 + self.data_len).to_le_bytes())?;
+        self.out.seek(SeekFrom::Start(40))?;
+        self.out.write_all(&self.data_len.to_le_bytes())?;
+        self.out.seek(SeekFrom::Start(position))?;
+        self.out.flush()
+    }
+}
+
+impl<W: Write + Seek> Drop for WavWriter<W> {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.write_lengths().ok();
+        }
+    }
+}
+
+#[test]
+fn header_is_patched_on_finish() {
+    let mut cursor = io::Cursor::new(Vec::new());
+    let mut writer = WavWriter::new(&mut cursor, 2, 22050).unwrap();
+    writer.write_samples(&[1, -1, 2, -2]).unwrap();
+    writer.finish().unwrap();
+    let data = cursor.into_inner();
+
+    assert_eq!(data.len(), HEADER_LEN as usize + 8);
+    assert_eq!(&data[0..4], b"RIFF");
+    assert_eq!(u32::from_le_bytes([data[4], data[5], data[6], data[7]]), 44);
+    assert_eq!(
+        u32::from_le_bytes([data[24], data[25], data[26], data[27]]),
+        22050
+    );
+    assert_eq!(
+        u32::from_le_bytes([data[40], data[41], data[42], data[43]]),
+        8
+    );
+    assert_eq!(&data[44..46], &1i16.to_le_bytes());
+}
@@ -0,0 +1,24 @@
+use everdrive_port::{find_everdrive, BAUD_RATE};
+use std::env;
+use std::error::Error;
+use std::io;
+use std::time::Duration;
+
+mod viewer;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let port_name = match env::args().nth(1) {
+        Some(name) => name,
+        None => find_everdrive("pass its port as the first argument")?,
+    };
+
+    println!("Reading log from {}", port_name);
+
+    let port = serialport::new(&port_name, BAUD_RATE)
+        .timeout(Duration::from_millis(100))
+        .open()?;
+
+    viewer::show_packets(port, io::stdout())?;
+
+    Ok(())
+}
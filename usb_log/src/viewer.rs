@@ -0,0 +1,103 @@
+use n64_types::{UsbDataType, UsbPacketDecoder};
+use std::io::{self, ErrorKind, Read, Write};
+
+/// Shows the packets read from `input` on `out` until `input` ends. Text is
+/// written as is, other packets as a line saying what they were.
+pub fn show_packets(mut input: impl Read, mut out: impl Write) -> io::Result<()> {
+    let mut decoder = UsbPacketDecoder::new();
+    let mut buf = [0; 512];
+
+    loop {
+        let len = match input.read(&mut buf) {
+            Ok(0) => return Ok(()),
+            Ok(len) => len,
+            // A serial port times out when the game is quiet.
+            Err(e) if e.kind() == ErrorKind::TimedOut || e.kind() == ErrorKind::Interrupted => {
+                continue
+            }
+            Err(e) => return Err(e),
+        };
+
+        for &byte in &buf[..len] {
+            let packet = match decoder.push(byte) {
+                Some(packet) => packet,
+                None => continue,
+            };
+
+            match packet.data_type {
+                UsbDataType::Text => out.write_all(&packet.data)?,
+                UsbDataType::Heartbeat => {}
+                data_type => {
+                    writeln!(out, "[{:?} packet, {} bytes]", data_type, packet.data.len())?
+                }
+            }
+        }
+
+        out.flush()?;
+    }
+}
+
+/// Stands in for the cartridge: packets are sent the way `n64_sys::everdrive`
+/// sends them, in 512 byte blocks where the last one is padded to a word, and
+/// read back a few bytes at a time.
+#[cfg(test)]
+struct Loopback {
+    sent: std::collections::VecDeque<u8>,
+    read_size: usize,
+}
+
+#[cfg(test)]
+impl Loopback {
+    fn send(&mut self, data_type: UsbDataType, data: &[u8]) {
+        let mut packet = n64_types::usb_packet_header(data_type, data.len()).to_vec();
+        packet.extend_from_slice(data);
+        packet.extend_from_slice(&n64_types::USB_PACKET_FOOTER);
+
+        for block in packet.chunks(512) {
+            self.sent.extend(block);
+
+            for _ in block.len()..(block.len() + 3) & !3 {
+                self.sent.push_back(0);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+impl Read for Loopback {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf.len().min(self.read_size).min(self.sent.len());
+
+        for byte in &mut buf[..len] {
+            *byte = self.sent.pop_front().unwrap();
+        }
+
+        Ok(len)
+    }
+}
+
+#[test]
+fn log_records_survive_the_loopback() {
+    let long = "y".repeat(1000) + "\n";
+    let mut loopback = Loopback {
+        sent: Default::default(),
+        read_size: 7,
+    };
+
+    loopback.send(UsbDataType::Text, b"INFO  game: 1024 KiB heap\n");
+    loopback.send(UsbDataType::Heartbeat, &[]);
+    loopback.send(UsbDataType::Text, long.as_bytes());
+    loopback.send(UsbDataType::Screenshot, &[0; 12]);
+    loopback.send(UsbDataType::Text, b"WARN  game: odd\n");
+
+    let mut out = Vec::new();
+    show_packets(loopback, &mut out).unwrap();
+
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        format!(
+            "INFO  game: 1024 KiB heap\n{}[Screenshot packet, 12 bytes]\nWARN  game: odd\n",
+            long
+        )
+    );
+}